@@ -0,0 +1,32 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tinyscript::vm::value::Value;
+use tinyscript::{load_with_config, CompilerOptions, VmConfig};
+
+const SCRIPT: &str = r#"
+class Bench {
+    function main(count) {
+        var total = 0;
+        for (var i = 0; i < count; i = i + 1) {
+            var row = { "index": i, "tags": [i, i + 1, i + 2] };
+            total = total + row.index;
+        }
+        return total;
+    }
+}
+"#;
+
+fn arena(c: &mut Criterion) {
+    let mut group = c.benchmark_group("arena");
+    for (name, config) in [("plain", VmConfig::new()), ("arena", VmConfig::new().arena(true))] {
+        let mut vm = load_with_config(SCRIPT, CompilerOptions::default(), config).expect("script should compile");
+        for count in [10, 100, 1000] {
+            group.bench_with_input(BenchmarkId::new(name, count), &count, |b, count| {
+                b.iter(|| vm.exec("Bench.main", Some(vec![Value::Integer(*count)])).expect("script should run"))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, arena);
+criterion_main!(benches);
@@ -0,0 +1,38 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tinyscript::vm::value::Value;
+use tinyscript::{load, CompilerOptions};
+
+const SCRIPT: &str = r#"
+class Bench {
+    function score(a, b) {
+        return a * 2 + b;
+    }
+}
+"#;
+
+fn batch_calls(c: &mut Criterion) {
+    let mut vm = load(SCRIPT, CompilerOptions::default()).expect("script should compile");
+    let mut group = c.benchmark_group("batch_calls");
+    for calls in [100, 1000, 10000] {
+        group.bench_with_input(BenchmarkId::new("exec", calls), &calls, |b, calls| {
+            b.iter(|| {
+                for i in 0..*calls {
+                    vm.exec("Bench.score", Some(vec![Value::Integer(i), Value::Integer(1)])).expect("script should run");
+                }
+            })
+        });
+
+        let batch: Vec<(&str, Vec<Value>)> = (0..calls).map(|i| ("Bench.score", vec![Value::Integer(i), Value::Integer(1)])).collect();
+        group.bench_with_input(BenchmarkId::new("batch", calls), &batch, |b, batch| {
+            b.iter(|| {
+                for result in vm.call_batch(batch) {
+                    result.expect("script should run");
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, batch_calls);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tinyscript::vm::value::Value;
+use tinyscript::{load, CompilerOptions};
+
+// calls between script functions passing 0 to 3 args
+const SCRIPT: &str = r#"
+class Bench {
+    function none() {
+        return 1;
+    }
+
+    function one(a) {
+        return a;
+    }
+
+    function three(a, b, c) {
+        return a + b + c;
+    }
+
+    function run(n) {
+        var total = 0;
+        for (var i = 0; i < n; i = i + 1) {
+            total = total + Bench.none() + Bench.one(i) + Bench.three(i, 1, 2);
+        }
+        return total;
+    }
+}
+"#;
+
+fn calls(c: &mut Criterion) {
+    let mut vm = load(SCRIPT, CompilerOptions::default()).expect("script should compile");
+    let mut group = c.benchmark_group("calls");
+    for calls in [100, 1000, 10000] {
+        group.bench_with_input(BenchmarkId::from_parameter(calls * 3), &calls, |b, calls| {
+            b.iter(|| vm.exec("Bench.run", Some(vec![Value::Integer(*calls)])).expect("script should run"))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, calls);
+criterion_main!(benches);
@@ -0,0 +1,38 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tinyscript::vm::value::Value;
+use tinyscript::{load, CompilerOptions};
+
+const SCRIPT: &str = r#"
+class Bench {
+    function fib(n) {
+        if (n < 2) {
+            return n;
+        }
+        return Bench.fib(n - 1) + Bench.fib(n - 2);
+    }
+
+    function loop(n) {
+        var total = 0;
+        for (var i = 0; i < n; i = i + 1) {
+            total = total + i * 2;
+        }
+        return total;
+    }
+}
+"#;
+
+fn interpreter(c: &mut Criterion) {
+    let mut vm = load(SCRIPT, CompilerOptions::default()).expect("script should compile");
+    let mut group = c.benchmark_group("interpreter");
+    for (name, entry, sizes) in [("fib", "Bench.fib", [15, 20]), ("loop", "Bench.loop", [10000, 40000])] {
+        for n in sizes {
+            group.bench_with_input(BenchmarkId::new(name, n), &n, |b, n| {
+                b.iter(|| vm.exec(entry, Some(vec![Value::Integer(*n)])).expect("script should run"))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, interpreter);
+criterion_main!(benches);
@@ -0,0 +1,43 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tinyscript::vm::value::Value;
+use tinyscript::{load, CompilerOptions};
+
+const CONCAT_SCRIPT: &str = r#"
+class Bench {
+    function main(count) {
+        var s = "";
+        for (var i = 0; i < count; i = i + 1) {
+            s = s + "abcdefghij";
+        }
+        return s;
+    }
+}
+"#;
+
+const BUILDER_SCRIPT: &str = r#"
+class Bench {
+    function main(count) {
+        var sb = new StringBuilder();
+        for (var i = 0; i < count; i = i + 1) {
+            sb.append("abcdefghij");
+        }
+        return sb.build();
+    }
+}
+"#;
+
+fn string_concat(c: &mut Criterion) {
+    let mut group = c.benchmark_group("string_concat");
+    for (name, script) in [("concat", CONCAT_SCRIPT), ("builder", BUILDER_SCRIPT)] {
+        let mut vm = load(script, CompilerOptions::default()).expect("script should compile");
+        for count in [1000, 5000, 20000] {
+            group.bench_with_input(BenchmarkId::new(name, count), &count, |b, count| {
+                b.iter(|| vm.exec("Bench.main", Some(vec![Value::Integer(*count)])).expect("script should run"))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, string_concat);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use tinyscript::vm::value::Value;
+use tinyscript::{compile, load, CompilerOptions};
+
+// name, script and the argument its Bench.main is run with
+const SCRIPTS: &[(&str, &str, i32)] = &[
+    ("fib(25)", include_str!("scripts/fib.tny"), 25),
+    ("arithmetic", include_str!("scripts/arithmetic.tny"), 200_000),
+    ("strings", include_str!("scripts/strings.tny"), 20_000),
+    ("dictionaries", include_str!("scripts/dictionaries.tny"), 20_000),
+    ("methods", include_str!("scripts/methods.tny"), 50_000),
+];
+
+fn compile_scripts(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compile");
+    for (name, script, _) in SCRIPTS {
+        group.bench_function(*name, |b| b.iter(|| compile(script).expect("script should compile")));
+    }
+    group.finish();
+}
+
+fn run_scripts(c: &mut Criterion) {
+    let mut group = c.benchmark_group("run");
+    group.sample_size(15);
+    for (name, script, arg) in SCRIPTS {
+        let mut vm = load(script, CompilerOptions::default()).expect("script should compile");
+        group.bench_function(*name, |b| b.iter(|| vm.exec("Bench.main", Some(vec![Value::Integer(*arg)])).expect("script should run")));
+    }
+    group.finish();
+}
+
+// compare against a saved run with cargo bench --bench suite -- --save-baseline name and then
+// --baseline name
+criterion_group!(benches, compile_scripts, run_scripts);
+criterion_main!(benches);
@@ -0,0 +1,127 @@
+#![no_main]
+
+use std::rc::Rc;
+
+use libfuzzer_sys::fuzz_target;
+use tinyscript::vm::config::VmConfig;
+use tinyscript::vm::instruction::{Comparison, Instruction};
+use tinyscript::vm::program::{FunctionMetadata, Program};
+use tinyscript::vm::VM;
+use tinyscript::{Sandbox, Value};
+
+const FUNCTIONS: [&str; 2] = ["A.main", "A.f"];
+
+// a value for StackPush picked by one byte
+fn value(byte: u8) -> Value {
+    match byte % 8 {
+        0 => Value::Null,
+        1 => Value::Bool(byte > 127),
+        2 => Value::Integer(byte as i32 - 128),
+        3 => Value::Float(byte as f32 / 7.0),
+        4 => Value::from(byte.to_string()),
+        5 => Value::FunctionRef(Rc::new(FUNCTIONS[byte as usize % 2].to_string())),
+        6 => Value::Integer(i32::MAX),
+        _ => Value::Integer(0)
+    }
+}
+
+fn comparison(byte: u8) -> Comparison {
+    match byte % 6 {
+        0 => Comparison::Equal,
+        1 => Comparison::NotEqual,
+        2 => Comparison::LessThan,
+        3 => Comparison::LessThanOrEqual,
+        4 => Comparison::GreaterThan,
+        _ => Comparison::GreaterThanOrEqual
+    }
+}
+
+// an instruction picked by an opcode and its operand, operands are kept small so that
+// slots and jumps are often in range
+fn instruction(opcode: u8, operand: u8) -> Instruction {
+    let small = operand as usize % 16;
+    match opcode % 42 {
+        0 => Instruction::Assert,
+        1 => Instruction::Print,
+        2 => Instruction::StackPush(value(operand)),
+        3 => Instruction::Pop,
+        4 => Instruction::Dup,
+        5 => Instruction::Swap,
+        6 => Instruction::MoveToLocalVariable(small % 4),
+        7 => Instruction::CopyToLocalVariable(small % 4),
+        8 => Instruction::LoadLocalVariable(small % 4),
+        9 => Instruction::StoreGlobal(small % 2),
+        10 => Instruction::LoadGlobal(small % 2),
+        11 => Instruction::CreateObject,
+        12 => Instruction::CreateBuiltinObject(String::from("StringBuilder"), small % 3),
+        13 => Instruction::DictionaryAdd,
+        14 => Instruction::ArrayLength,
+        15 => Instruction::ArrayAdd,
+        16 => Instruction::IterNext,
+        17 => Instruction::Yield,
+        18 => Instruction::GetCollectionItemByKey,
+        19 => Instruction::SetCollectionItemByKey,
+        20 => Instruction::Call(small % 3),
+        21 => Instruction::CallMethod(String::from("length"), small % 3),
+        22 => Instruction::CallBuiltin(String::from("format"), small % 3),
+        23 => Instruction::Jump(small),
+        24 => Instruction::JumpIfFalse(small),
+        25 => Instruction::Return(operand % 2 == 0),
+        26 => Instruction::Equal,
+        27 => Instruction::NotEqual,
+        28 => Instruction::Add,
+        29 => Instruction::Sub,
+        30 => Instruction::Multiply,
+        31 => Instruction::Divide,
+        32 => Instruction::Pow,
+        33 => Instruction::LessThan,
+        34 => Instruction::LessThanOrEqual,
+        35 => Instruction::GreaterThan,
+        36 => Instruction::GreaterThanOrEqual,
+        37 => Instruction::AddLocals(small % 4, operand as usize / 16 % 4, small / 4),
+        38 => Instruction::IncrementLocal(small % 4, operand as i32 - 128),
+        39 => Instruction::CompareLocalConstJump(comparison(operand), small % 4, value(operand), small),
+        40 => Instruction::FloorDivide,
+        _ => Instruction::CompareLocalsJump(comparison(operand), small % 4, operand as usize / 64, small)
+    }
+}
+
+// the first bytes describe the two functions and the rest are pairs of opcode and operand
+fn program(data: &[u8]) -> Option<Program> {
+    let [split, slots, depth, generator, code @ ..] = data else { return None };
+
+    let mut program = Program::new();
+    program.instructions = code.chunks_exact(2).map(|pair| instruction(pair[0], pair[1])).collect();
+    program.insert_global(Value::Null);
+    program.insert_global(Value::Integer(1));
+
+    let split = *split as usize % (program.instructions.len() + 1);
+    for (name, start) in FUNCTIONS.iter().zip([0, split]) {
+        program.insert_into_symbols(name.to_string(), start);
+        program.metadata.insert(name.to_string(), FunctionMetadata {
+            local_slots: *slots as usize % 5,
+            max_stack_depth: *depth as usize % 16,
+            generator: start != 0 && generator % 2 == 1,
+            cleanup: None,
+        });
+    }
+
+    Some(program)
+}
+
+// bytecode must either be rejected by the verifier or run without panicking
+fuzz_target!(|data: &[u8]| {
+    let Some(program) = program(data) else { return };
+    if program.verify().is_err() {
+        return;
+    }
+
+    let sandbox = Sandbox {
+        max_instructions: Some(10_000),
+        max_memory: Some(1024 * 1024),
+        max_call_depth: Some(32),
+        allowed_builtins: None,
+    };
+    let mut vm = VM::with_config(program, VmConfig::new().sandbox(sandbox));
+    let _ = vm.exec("A.main", Some(vec![Value::Integer(1)]));
+});
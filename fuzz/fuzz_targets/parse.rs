@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tinyscript::{compile_with_options, CompilerOptions};
+
+// any text must either compile or be rejected with an error
+fuzz_target!(|source: &str| {
+    let _ = compile_with_options(source, CompilerOptions { no_std: true, ..Default::default() });
+});
@@ -0,0 +1,170 @@
+#ifndef TINYSCRIPT_H
+#define TINYSCRIPT_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum TsType {
+  TsType_Null,
+  TsType_Bool,
+  TsType_Integer,
+  TsType_Float,
+  TsType_String,
+  TsType_Array,
+  TsType_Dictionary,
+  TsType_Other,
+} TsType;
+
+typedef struct TsProgram TsProgram;
+
+typedef struct TsValue TsValue;
+
+typedef struct TsVm TsVm;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Compiles a script, returning null if it does not compile.
+ *
+ * # Safety
+ * `source` must be a nul terminated string and `error` either null or writable.
+ */
+struct TsProgram *ts_compile(const char *source, char **error);
+
+/**
+ * Frees a program that was not loaded into a vm.
+ *
+ * # Safety
+ * `program` must be null or returned by `ts_compile` and not yet freed.
+ */
+void ts_program_free(struct TsProgram *program);
+
+/**
+ * Loads a program into a new vm, taking ownership of the program.
+ *
+ * # Safety
+ * `program` must be returned by `ts_compile` and not yet freed or loaded.
+ */
+struct TsVm *ts_vm_new(struct TsProgram *program);
+
+/**
+ * # Safety
+ * `vm` must be null or returned by `ts_vm_new` and not yet freed.
+ */
+void ts_vm_free(struct TsVm *vm);
+
+/**
+ * Calls a function such as "Game.update", returning null if the call fails.
+ *
+ * # Safety
+ * `vm` must be a live vm, `entry` a nul terminated string, `args` null or `count` live values,
+ * and `error` either null or writable. the arguments stay owned by the caller.
+ */
+struct TsValue *ts_call(struct TsVm *vm,
+                        const char *entry,
+                        const struct TsValue *const *args,
+                        uintptr_t count,
+                        char **error);
+
+struct TsValue *ts_value_null(void);
+
+struct TsValue *ts_value_bool(bool value);
+
+struct TsValue *ts_value_integer(int32_t value);
+
+struct TsValue *ts_value_float(float value);
+
+/**
+ * Returns null if the text is not utf-8.
+ *
+ * # Safety
+ * `value` must be a nul terminated string.
+ */
+struct TsValue *ts_value_string(const char *value);
+
+/**
+ * # Safety
+ * `value` must be null or a live value.
+ */
+enum TsType ts_value_type(const struct TsValue *value);
+
+/**
+ * Writes the bool to out, returning false if the value is not a bool.
+ *
+ * # Safety
+ * `value` must be null or a live value and `out` writable.
+ */
+bool ts_value_as_bool(const struct TsValue *value, bool *out);
+
+/**
+ * Writes the integer to out, returning false if the value is not an integer.
+ *
+ * # Safety
+ * `value` must be null or a live value and `out` writable.
+ */
+bool ts_value_as_integer(const struct TsValue *value, int32_t *out);
+
+/**
+ * Writes the number to out, giving integers as floats, returning false if the value is not a number.
+ *
+ * # Safety
+ * `value` must be null or a live value and `out` writable.
+ */
+bool ts_value_as_float(const struct TsValue *value, float *out);
+
+/**
+ * The value as text, the way print shows it. free it with `ts_string_free`.
+ *
+ * # Safety
+ * `value` must be null or a live value.
+ */
+char *ts_value_to_string(const struct TsValue *value);
+
+/**
+ * Number of items in an array, or zero for any other value.
+ *
+ * # Safety
+ * `value` must be null or a live value.
+ */
+uintptr_t ts_value_array_len(const struct TsValue *value);
+
+/**
+ * A copy of an item of an array, or null if it is not an array or the index is out of range.
+ *
+ * # Safety
+ * `value` must be null or a live value.
+ */
+struct TsValue *ts_value_array_get(const struct TsValue *value, uintptr_t index);
+
+/**
+ * A copy of an entry of a dictionary or object, or null if there is no such key.
+ *
+ * # Safety
+ * `value` must be null or a live value and `key` a nul terminated string.
+ */
+struct TsValue *ts_value_get(const struct TsValue *value, const char *key);
+
+/**
+ * # Safety
+ * `value` must be null or a value returned by this api and not yet freed.
+ */
+void ts_value_free(struct TsValue *value);
+
+/**
+ * Frees text returned by this api, such as an error.
+ *
+ * # Safety
+ * `text` must be null or text returned by this api and not yet freed.
+ */
+void ts_string_free(char *text);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* TINYSCRIPT_H */
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::IntoPyObjectExt;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+
+use tinyscript::vm::VM;
+use tinyscript::{compile_with_options, CompilerOptions, TinyscriptError, Value};
+
+create_exception!(tinyscript, CompileError, PyException);
+create_exception!(tinyscript, RuntimeError, PyException);
+
+fn error(e: TinyscriptError) -> PyErr {
+    match e {
+        TinyscriptError::Compile(e) => CompileError::new_err(e.to_string()),
+        TinyscriptError::Runtime(e) => RuntimeError::new_err(e.to_string()),
+    }
+}
+
+// a compiled script whose functions can be called more than once, keeping its state between calls
+#[pyclass(unsendable)]
+struct Program {
+    vm: VM,
+}
+
+#[pymethods]
+impl Program {
+
+    // call a function such as "Plugin.main" with python values as its arguments
+    #[pyo3(signature = (entry, *args))]
+    fn call(&mut self, py: Python<'_>, entry: &str, args: &Bound<'_, PyTuple>) -> PyResult<Py<PyAny>> {
+        let params = args.iter().map(|arg| to_value(&arg, &mut vec![])).collect::<PyResult<Vec<Value>>>()?;
+        let params = (!params.is_empty()).then_some(params);
+        let value = self.vm.exec(entry, params).map_err(|e| error(e.into()))?;
+        to_python(py, &value, &mut vec![])
+    }
+
+}
+
+#[pyfunction]
+fn compile(source: &str) -> PyResult<Program> {
+    let program = compile_with_options(source, CompilerOptions::default()).map_err(|e| error(e.into()))?;
+    Ok(Program { vm: VM::new(program) })
+}
+
+// compile a script and call one function of it
+#[pyfunction]
+#[pyo3(signature = (source, entry, *args))]
+fn run(py: Python<'_>, source: &str, entry: &str, args: &Bound<'_, PyTuple>) -> PyResult<Py<PyAny>> {
+    compile(source)?.call(py, entry, args)
+}
+
+// convert the contents of a list or dictionary, where seen holds those being converted around
+// it, as one that holds itself has no value on the other side
+fn enter<P: PartialEq, T>(seen: &mut Vec<P>, collection: P, convert: impl FnOnce(&mut Vec<P>) -> PyResult<T>) -> PyResult<T> {
+    if seen.contains(&collection) {
+        return Err(PyValueError::new_err("can not convert a value that contains itself"));
+    }
+    seen.push(collection);
+    let result = convert(seen);
+    seen.pop();
+    result
+}
+
+// None, bool, int, float, str, list, tuple and dict with string keys, nested in any way
+fn to_value(object: &Bound<'_, PyAny>, seen: &mut Vec<*mut pyo3::ffi::PyObject>) -> PyResult<Value> {
+    if object.is_none() {
+        return Ok(Value::Null);
+    }
+    // bool is a subclass of int so it is checked first
+    if let Ok(b) = object.cast::<PyBool>() {
+        return Ok(Value::Bool(b.is_true()));
+    }
+    if object.is_instance_of::<PyInt>() {
+        return Ok(Value::Integer(object.extract::<i32>()?));
+    }
+    if object.is_instance_of::<PyFloat>() {
+        return Ok(Value::Float(object.extract::<f32>()?));
+    }
+    if let Ok(s) = object.cast::<PyString>() {
+        return Ok(Value::from(s.to_str()?));
+    }
+    if let Ok(list) = object.cast::<PyList>() {
+        return enter(seen, object.as_ptr(), |seen| list.iter().map(|item| to_value(&item, seen)).collect::<PyResult<Vec<Value>>>()).map(Value::from);
+    }
+    if let Ok(tuple) = object.cast::<PyTuple>() {
+        return enter(seen, object.as_ptr(), |seen| tuple.iter().map(|item| to_value(&item, seen)).collect::<PyResult<Vec<Value>>>()).map(Value::from);
+    }
+    if let Ok(dict) = object.cast::<PyDict>() {
+        return enter(seen, object.as_ptr(), |seen| {
+            let mut entries = HashMap::new();
+            for (key, value) in dict.iter() {
+                entries.insert(key.extract::<String>()?, to_value(&value, seen)?);
+            }
+            Ok(Value::from(entries))
+        });
+    }
+    Err(PyTypeError::new_err(format!("can not pass {} to a script", object.get_type().name()?)))
+}
+
+// values without a python equivalent, such as generators, are given as the text a script would print
+fn to_python(py: Python<'_>, value: &Value, seen: &mut Vec<*const ()>) -> PyResult<Py<PyAny>> {
+    let object = match value {
+        Value::Null => py.None(),
+        Value::Bool(b) => b.into_py_any(py)?,
+        Value::Integer(i) => i.into_py_any(py)?,
+        Value::Float(f) => f.into_py_any(py)?,
+        Value::String(s) => s.as_str().into_py_any(py)?,
+        Value::Array(items) => {
+            let items = enter(seen, Rc::as_ptr(items) as *const (), |seen| items.borrow().iter().map(|item| to_python(py, item, seen)).collect::<PyResult<Vec<Py<PyAny>>>>())?;
+            PyList::new(py, items)?.into_any().unbind()
+        }
+        Value::Dictionary(entries) | Value::Object(entries) => {
+            let dict = PyDict::new(py);
+            enter(seen, Rc::as_ptr(entries) as *const (), |seen| {
+                for (key, entry) in entries.borrow().iter() {
+                    dict.set_item(key, to_python(py, entry, seen)?)?;
+                }
+                Ok(())
+            })?;
+            dict.into_any().unbind()
+        }
+        value => value.to_string().into_py_any(py)?,
+    };
+    Ok(object)
+}
+
+#[pymodule]
+#[pyo3(name = "tinyscript")]
+fn tinyscript_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(compile, m)?)?;
+    m.add_function(wrap_pyfunction!(run, m)?)?;
+    m.add_class::<Program>()?;
+    m.add("CompileError", m.py().get_type::<CompileError>())?;
+    m.add("RuntimeError", m.py().get_type::<RuntimeError>())?;
+    Ok(())
+}
@@ -0,0 +1,300 @@
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::{env, fs, io};
+
+use tinyscript::vm::debugger::{DebugSession, Debugger, Resume};
+use tinyscript::vm::lint::LintRules;
+use tinyscript::vm::program::Program;
+use tinyscript::vm::value::Value;
+use tinyscript::vm::VM;
+use tinyscript::{build_project, compile_with_options, CompilerOptions, DiskResolver};
+
+const USAGE: &str = "\
+usage: tinyscript debug <script> <Class.function>
+       tinyscript build <project> <bundle.tnb> [--strip]
+       tinyscript analyze <script> [--dot]
+       tinyscript lint <script>";
+
+const HELP: &str = "\
+break <Class.function>    pause when the function is called
+delete <Class.function>   remove a breakpoint
+step                      run one instruction, going into calls
+next                      run one instruction of this function, running calls
+finish                    run until this function returns
+continue                  run until the next breakpoint
+locals                    show the variables of this function
+stack                     show the calls that are running
+print <expression>        show an expression of the variables, such as 'this.count * 2'
+watch <expression>        print an expression at every pause
+quit                      stop debugging";
+
+// Command Line
+//
+// tinyscript debug runs a function of a script under a debugger driven from the terminal.
+// it pauses before the first instruction so breakpoints can be set before anything runs.
+//
+// tinyscript build compiles the project in a directory, or described by a tinyscript.toml,
+// into a bundle. --strip links it into one module without what its entry points can not reach
+//
+// tinyscript analyze reports the calls a script can make, its cycles and how deep its calls go,
+// or with --dot gives the call graph for graphviz
+//
+// tinyscript lint checks a script that is not trusted against the default lint rules, printing
+// a line for each finding and failing if there are any
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.as_slice() {
+        [command, script, entry] if command == "debug" => debug(Path::new(script), entry).map(|value| format!("{} returned {:?}", entry, value)),
+        [command, project, output] if command == "build" => build(Path::new(project), Path::new(output), false),
+        [command, project, output, strip] if command == "build" && strip == "--strip" => build(Path::new(project), Path::new(output), true),
+        [command, script] if command == "analyze" => analyze(Path::new(script), false),
+        [command, script, dot] if command == "analyze" && dot == "--dot" => analyze(Path::new(script), true),
+        [command, script] if command == "lint" => lint(Path::new(script)),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match result {
+        Ok(message) => {
+            println!("{}", message);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn build(project: &Path, output: &Path, strip: bool) -> Result<String, String> {
+    let mut bundle = build_project(project).map_err(|e| e.to_string())?;
+    if strip {
+        bundle = bundle.stripped().map_err(|e| e.to_string())?;
+    }
+    fs::write(output, bundle.to_bytes()).map_err(|e| format!("unable to write {}: {}", output.display(), e))?;
+    Ok(format!("built {} with {} modules into {}", bundle.name, bundle.modules.len(), output.display()))
+}
+
+fn analyze(script: &Path, dot: bool) -> Result<String, String> {
+    let graph = compile_script(script)?.call_graph();
+    Ok(match dot {
+        true => graph.to_dot(),
+        false => graph.to_string(),
+    }.trim_end().to_string())
+}
+
+fn lint(script: &Path) -> Result<String, String> {
+    let report = compile_script(script)?.lint(&LintRules::default());
+    match report.is_clean() {
+        true => Ok(format!("{} has nothing to report", script.display())),
+        false => Err(report.to_string().trim_end().to_string()),
+    }
+}
+
+fn debug(script: &Path, entry: &str) -> Result<Value, String> {
+    let program = compile_script(script)?;
+
+    let mut vm = VM::new(program);
+    vm.attach_debugger(Terminal::new(io::stdin().lock(), io::stdout()));
+    vm.pause_next();
+    vm.exec(entry, None).map_err(|e| e.to_string())
+}
+
+fn compile_script(script: &Path) -> Result<Program, String> {
+    let source = fs::read_to_string(script).map_err(|e| format!("unable to read {}: {}", script.display(), e))?;
+
+    // imports are found next to the script
+    let root = script.parent().unwrap_or(Path::new("."));
+    let options = CompilerOptions { resolver: Arc::new(DiskResolver::new(root)), ..Default::default() };
+    compile_with_options(&source, options).map_err(|e| e.to_string())
+}
+
+// a debugger that reads commands from one stream and writes what it shows to another
+struct Terminal<R, W> {
+    input: R,
+    output: W,
+    // once the input runs out the script runs to the end
+    finished: bool,
+    // expressions printed at every pause
+    watches: Vec<String>,
+}
+
+impl<R: BufRead, W: Write> Terminal<R, W> {
+
+    fn new(input: R, output: W) -> Self {
+        Terminal { input, output, finished: false, watches: vec![] }
+    }
+
+    // the next command, or None once there are no more
+    fn read_command(&mut self) -> Option<String> {
+        write!(self.output, "(debug) ").ok()?;
+        self.output.flush().ok()?;
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim().to_string())
+        }
+    }
+
+    // run a command, giving back how to resume if it is one that does
+    fn command(&mut self, line: &str, session: &mut DebugSession) -> io::Result<Option<Resume>> {
+        let (command, argument) = line.split_once(char::is_whitespace).map(|(c, a)| (c, a.trim())).unwrap_or((line, ""));
+        let innermost = session.frames().len().saturating_sub(1);
+
+        match command {
+            "step" | "s" => return Ok(Some(Resume::Step)),
+            "next" | "n" => return Ok(Some(Resume::Next)),
+            "finish" | "f" => return Ok(Some(Resume::Finish)),
+            "continue" | "c" => return Ok(Some(Resume::Continue)),
+            "quit" | "q" => std::process::exit(0),
+
+            "break" | "b" => match session.break_at(argument) {
+                Ok(()) => writeln!(self.output, "breakpoint at {}", argument)?,
+                Err(e) => writeln!(self.output, "{}", e)?
+            },
+            "delete" | "d" => match session.clear_breakpoint(argument) {
+                true => writeln!(self.output, "removed breakpoint at {}", argument)?,
+                false => writeln!(self.output, "there is no breakpoint at {}", argument)?
+            },
+
+            "locals" | "l" => {
+                for (name, value) in session.locals(innermost) {
+                    writeln!(self.output, "{} = {:?}", name, value)?;
+                }
+            }
+            "stack" | "bt" => {
+                for (index, frame) in session.frames().iter().enumerate().rev() {
+                    match frame.position {
+                        Some(position) => writeln!(self.output, "#{} {} at {}", index, frame.function, position)?,
+                        None => writeln!(self.output, "#{} {}", index, frame.function)?
+                    }
+                }
+            }
+            "print" | "p" => match session.eval(innermost, argument) {
+                Ok(value) => writeln!(self.output, "{:?}", value)?,
+                Err(e) => writeln!(self.output, "{}", e)?
+            },
+            "watch" | "w" => self.watches.push(argument.to_string()),
+
+            "help" | "h" => writeln!(self.output, "{}", HELP)?,
+            "" => {}
+            _ => writeln!(self.output, "unknown command '{}', try help", command)?
+        }
+        Ok(None)
+    }
+
+}
+
+impl<R: BufRead, W: Write> Debugger for Terminal<R, W> {
+    fn paused(&mut self, session: &mut DebugSession) -> Resume {
+        if self.finished {
+            return Resume::Continue;
+        }
+
+        let function = session.frames().last().map(|frame| frame.function.clone()).unwrap_or_default();
+        let _ = writeln!(self.output, "paused in {} at {}: {:?}", function, session.position(), session.instruction());
+        let innermost = session.frames().len().saturating_sub(1);
+        for watch in self.watches.iter() {
+            let _ = match session.eval(innermost, watch) {
+                Ok(value) => writeln!(self.output, "{} = {:?}", watch, value),
+                Err(e) => writeln!(self.output, "{}: {}", watch, e)
+            };
+        }
+
+        loop {
+            let Some(line) = self.read_command() else {
+                self.finished = true;
+                return Resume::Continue;
+            };
+            match self.command(&line, session) {
+                Ok(Some(resume)) => return resume,
+                Ok(None) => {}
+                Err(_) => {
+                    self.finished = true;
+                    return Resume::Continue;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{Cursor, Write};
+    use std::rc::Rc;
+
+    use tinyscript::compile;
+    use tinyscript::vm::value::Value;
+    use tinyscript::vm::VM;
+
+    use crate::Terminal;
+
+    // output that can be read back once the vm has finished with it
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn debug(commands: &str) -> String {
+        let script = r#"
+            class Test {
+                var count = 3;
+
+                function main() {
+                    var scale = 2;
+                    var test = new Test();
+                    return test.times(scale);
+                }
+
+                function times(n) {
+                    var result = this.count * n;
+                    return result;
+                }
+            }
+        "#;
+        let output = Shared::default();
+        let mut vm = VM::new(compile(script).unwrap());
+        vm.attach_debugger(Terminal::new(Cursor::new(commands.to_string()), output.clone()));
+        vm.pause_next();
+        assert_eq!(vm.exec("Test.main", None).unwrap(), Value::Integer(6));
+        String::from_utf8(output.0.take()).unwrap()
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let output = debug("break Test.times\nbreak Test.missing\ncontinue\nlocals\nprint this.count * n\nstack\nprint nothing\ncontinue\n");
+        assert!(output.starts_with("paused in Test.main at"));
+        assert!(output.contains("(debug) breakpoint at Test.times\n"));
+        assert!(output.contains("function 'Test.missing' does not exist"));
+        assert!(output.contains("paused in Test.times at"));
+        assert!(output.contains("this = Object"));
+        assert!(output.contains("n = Integer(2)\nresult = Null\n"));
+        assert!(output.contains("(debug) Integer(6)\n"));
+        assert!(output.contains("#1 Test.times at") && output.contains("#0 Test.main at"));
+        assert!(output.contains("(debug) compile error: "));
+    }
+
+    #[test]
+    fn test_stepping() {
+        // running out of commands lets the script finish
+        let output = debug("watch scale + 1\nnext\nnext\nlocals\n");
+        assert_eq!(output.matches("paused in Test.main").count(), 3);
+        assert!(output.contains("scale = Integer(2)"));
+        assert!(output.contains("scale + 1 = Integer(3)"));
+        assert!(debug("oops\n").contains("unknown command 'oops', try help"));
+    }
+
+}
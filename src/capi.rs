@@ -0,0 +1,376 @@
+use core::ffi::{c_char, CStr};
+use core::ptr;
+use std::ffi::CString;
+
+use crate::compiler::{compile_with_options, CompilerOptions};
+use crate::prelude::*;
+use crate::vm::program::Program;
+use crate::vm::value::Value;
+use crate::vm::VM;
+
+// C API
+//
+// extern "C" functions for hosts written in C or C++. build the library with
+// cargo rustc --release --features capi --crate-type staticlib (or cdylib) and include
+// include/tinyscript.h, which is generated by cbindgen from this file.
+//
+// every pointer returned is owned by the caller and freed with the matching ts_*_free function.
+// errors are returned as null, with the message written to error when it is not null
+
+// a compiled program, used up by ts_vm_new
+pub struct TsProgram(Program);
+
+// a vm that a program has been loaded into
+pub struct TsVm(VM);
+
+// a value passed to or returned from a script
+pub struct TsValue(Value);
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TsType {
+    Null,
+    Bool,
+    Integer,
+    Float,
+    String,
+    Array,
+    Dictionary,
+    // any other value, such as an object, which C can only pass back to a script
+    Other,
+}
+
+fn boxed<T>(value: T) -> *mut T {
+    Box::into_raw(Box::new(value))
+}
+
+// nul bytes can not be passed to C, so they end the text
+fn c_string(text: String) -> *mut c_char {
+    let end = text.find('\0').unwrap_or(text.len());
+    CString::new(&text[..end]).expect("nul bytes should be removed").into_raw()
+}
+
+unsafe fn set_error(error: *mut *mut c_char, message: String) {
+    if !error.is_null() {
+        *error = c_string(message);
+    }
+}
+
+unsafe fn str_arg<'a>(text: *const c_char) -> Result<&'a str, String> {
+    if text.is_null() {
+        return Err(String::from("text is null"));
+    }
+    CStr::from_ptr(text).to_str().map_err(|e| e.to_string())
+}
+
+/// Compiles a script, returning null if it does not compile.
+///
+/// # Safety
+/// `source` must be a nul terminated string and `error` either null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn ts_compile(source: *const c_char, error: *mut *mut c_char) -> *mut TsProgram {
+    let result = str_arg(source).and_then(|source| compile_with_options(source, CompilerOptions::default()).map_err(|e| e.to_string()));
+    match result {
+        Ok(program) => boxed(TsProgram(program)),
+        Err(message) => {
+            set_error(error, message);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Frees a program that was not loaded into a vm.
+///
+/// # Safety
+/// `program` must be null or returned by `ts_compile` and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn ts_program_free(program: *mut TsProgram) {
+    if !program.is_null() {
+        drop(Box::from_raw(program));
+    }
+}
+
+/// Loads a program into a new vm, taking ownership of the program.
+///
+/// # Safety
+/// `program` must be returned by `ts_compile` and not yet freed or loaded.
+#[no_mangle]
+pub unsafe extern "C" fn ts_vm_new(program: *mut TsProgram) -> *mut TsVm {
+    if program.is_null() {
+        return ptr::null_mut();
+    }
+    let program = Box::from_raw(program);
+    boxed(TsVm(VM::new(program.0)))
+}
+
+/// # Safety
+/// `vm` must be null or returned by `ts_vm_new` and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn ts_vm_free(vm: *mut TsVm) {
+    if !vm.is_null() {
+        drop(Box::from_raw(vm));
+    }
+}
+
+/// Calls a function such as "Game.update", returning null if the call fails.
+///
+/// # Safety
+/// `vm` must be a live vm, `entry` a nul terminated string, `args` null or `count` live values,
+/// and `error` either null or writable. the arguments stay owned by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn ts_call(vm: *mut TsVm, entry: *const c_char, args: *const *const TsValue, count: usize, error: *mut *mut c_char) -> *mut TsValue {
+    let Some(vm) = vm.as_mut() else {
+        set_error(error, String::from("vm is null"));
+        return ptr::null_mut();
+    };
+    let params = match (args.is_null(), count) {
+        (_, 0) => None,
+        (true, _) => {
+            set_error(error, String::from("args is null"));
+            return ptr::null_mut();
+        }
+        (false, count) => Some(core::slice::from_raw_parts(args, count).iter().map(|arg| arg.as_ref().map(|arg| arg.0.clone()).unwrap_or_default()).collect())
+    };
+    let result = str_arg(entry).and_then(|entry| vm.0.exec(entry, params).map_err(|e| e.to_string()));
+    match result {
+        Ok(value) => boxed(TsValue(value)),
+        Err(message) => {
+            set_error(error, message);
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn ts_value_null() -> *mut TsValue {
+    boxed(TsValue(Value::Null))
+}
+
+#[no_mangle]
+pub extern "C" fn ts_value_bool(value: bool) -> *mut TsValue {
+    boxed(TsValue(Value::Bool(value)))
+}
+
+#[no_mangle]
+pub extern "C" fn ts_value_integer(value: i32) -> *mut TsValue {
+    boxed(TsValue(Value::Integer(value)))
+}
+
+#[no_mangle]
+pub extern "C" fn ts_value_float(value: f32) -> *mut TsValue {
+    boxed(TsValue(Value::Float(value)))
+}
+
+/// Returns null if the text is not utf-8.
+///
+/// # Safety
+/// `value` must be a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ts_value_string(value: *const c_char) -> *mut TsValue {
+    match str_arg(value) {
+        Ok(value) => boxed(TsValue(Value::from(value.to_string()))),
+        Err(_) => ptr::null_mut()
+    }
+}
+
+/// # Safety
+/// `value` must be null or a live value.
+#[no_mangle]
+pub unsafe extern "C" fn ts_value_type(value: *const TsValue) -> TsType {
+    match value.as_ref().map(|value| &value.0) {
+        None | Some(Value::Null) => TsType::Null,
+        Some(Value::Bool(_)) => TsType::Bool,
+        Some(Value::Integer(_)) => TsType::Integer,
+        Some(Value::Float(_)) => TsType::Float,
+        Some(Value::String(_)) => TsType::String,
+        Some(Value::Array(_)) => TsType::Array,
+        Some(Value::Dictionary(_)) => TsType::Dictionary,
+        Some(_) => TsType::Other
+    }
+}
+
+/// Writes the bool to out, returning false if the value is not a bool.
+///
+/// # Safety
+/// `value` must be null or a live value and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn ts_value_as_bool(value: *const TsValue, out: *mut bool) -> bool {
+    match value.as_ref().map(|value| &value.0) {
+        Some(Value::Bool(b)) if !out.is_null() => {
+            *out = *b;
+            true
+        }
+        _ => false
+    }
+}
+
+/// Writes the integer to out, returning false if the value is not an integer.
+///
+/// # Safety
+/// `value` must be null or a live value and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn ts_value_as_integer(value: *const TsValue, out: *mut i32) -> bool {
+    match value.as_ref().map(|value| &value.0) {
+        Some(Value::Integer(i)) if !out.is_null() => {
+            *out = *i;
+            true
+        }
+        _ => false
+    }
+}
+
+/// Writes the number to out, giving integers as floats, returning false if the value is not a number.
+///
+/// # Safety
+/// `value` must be null or a live value and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn ts_value_as_float(value: *const TsValue, out: *mut f32) -> bool {
+    let number = match value.as_ref().map(|value| &value.0) {
+        Some(Value::Float(f)) => *f,
+        Some(Value::Integer(i)) => *i as f32,
+        _ => return false
+    };
+    if out.is_null() {
+        return false;
+    }
+    *out = number;
+    true
+}
+
+/// The value as text, the way print shows it. free it with `ts_string_free`.
+///
+/// # Safety
+/// `value` must be null or a live value.
+#[no_mangle]
+pub unsafe extern "C" fn ts_value_to_string(value: *const TsValue) -> *mut c_char {
+    match value.as_ref() {
+        Some(value) => c_string(value.0.to_string()),
+        None => ptr::null_mut()
+    }
+}
+
+/// Number of items in an array, or zero for any other value.
+///
+/// # Safety
+/// `value` must be null or a live value.
+#[no_mangle]
+pub unsafe extern "C" fn ts_value_array_len(value: *const TsValue) -> usize {
+    match value.as_ref().map(|value| &value.0) {
+        Some(Value::Array(items)) => items.borrow().len(),
+        _ => 0
+    }
+}
+
+/// A copy of an item of an array, or null if it is not an array or the index is out of range.
+///
+/// # Safety
+/// `value` must be null or a live value.
+#[no_mangle]
+pub unsafe extern "C" fn ts_value_array_get(value: *const TsValue, index: usize) -> *mut TsValue {
+    match value.as_ref().map(|value| &value.0) {
+        Some(Value::Array(items)) => items.borrow().get(index).map_or(ptr::null_mut(), |item| boxed(TsValue(item.clone()))),
+        _ => ptr::null_mut()
+    }
+}
+
+/// A copy of an entry of a dictionary or object, or null if there is no such key.
+///
+/// # Safety
+/// `value` must be null or a live value and `key` a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ts_value_get(value: *const TsValue, key: *const c_char) -> *mut TsValue {
+    let Ok(key) = str_arg(key) else {
+        return ptr::null_mut();
+    };
+    match value.as_ref().map(|value| &value.0) {
+        Some(Value::Dictionary(entries) | Value::Object(entries)) => entries.borrow().get(key).map_or(ptr::null_mut(), |entry| boxed(TsValue(entry.clone()))),
+        _ => ptr::null_mut()
+    }
+}
+
+/// # Safety
+/// `value` must be null or a value returned by this api and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn ts_value_free(value: *mut TsValue) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
+
+/// Frees text returned by this api, such as an error.
+///
+/// # Safety
+/// `text` must be null or text returned by this api and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn ts_string_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+
+    use crate::capi::*;
+
+    #[test]
+    fn test_call() {
+        let source = CString::new("class Game { function add(a, b) { return [a + b, \"done\"]; } }").unwrap();
+        unsafe {
+            let program = ts_compile(source.as_ptr(), ptr::null_mut());
+            assert!(!program.is_null());
+            let vm = ts_vm_new(program);
+
+            let args = [ts_value_integer(2), ts_value_float(0.5)];
+            let entry = CString::new("Game.add").unwrap();
+            let result = ts_call(vm, entry.as_ptr(), args.as_ptr() as *const *const TsValue, args.len(), ptr::null_mut());
+            assert_eq!(ts_value_type(result), TsType::Array);
+            assert_eq!(ts_value_array_len(result), 2);
+
+            let sum = ts_value_array_get(result, 0);
+            let mut out = 0.0;
+            assert!(ts_value_as_float(sum, &mut out));
+            assert_eq!(out, 2.5);
+            assert!(!ts_value_as_integer(sum, &mut 0));
+
+            let done = ts_value_array_get(result, 1);
+            let text = ts_value_to_string(done);
+            assert_eq!(CStr::from_ptr(text).to_str(), Ok("done"));
+            assert!(ts_value_array_get(result, 2).is_null());
+
+            ts_string_free(text);
+            for value in [sum, done, result].into_iter().chain(args) {
+                ts_value_free(value);
+            }
+            ts_vm_free(vm);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let source = CString::new("class Game { function main() { return missing(); } }").unwrap();
+        let broken = CString::new("class {").unwrap();
+        let entry = CString::new("Game.main").unwrap();
+        unsafe {
+            let mut error = ptr::null_mut();
+            assert!(ts_compile(broken.as_ptr(), &mut error).is_null());
+            assert!(!error.is_null());
+            ts_string_free(error);
+
+            let vm = ts_vm_new(ts_compile(source.as_ptr(), ptr::null_mut()));
+            let mut error = ptr::null_mut();
+            assert!(ts_call(vm, entry.as_ptr(), ptr::null(), 0, &mut error).is_null());
+            assert!(CStr::from_ptr(error).to_str().unwrap().contains("missing"));
+            ts_string_free(error);
+            ts_vm_free(vm);
+
+            let mut error = ptr::null_mut();
+            assert!(ts_call(ptr::null_mut(), entry.as_ptr(), ptr::null(), 0, &mut error).is_null());
+            assert_eq!(CStr::from_ptr(error).to_str(), Ok("vm is null"));
+            ts_string_free(error);
+        }
+    }
+
+}
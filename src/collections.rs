@@ -0,0 +1,5 @@
+// maps and sets from std, or ordered ones from alloc without std since there is no hasher to seed
+#[cfg(feature = "std")]
+pub use std::collections::{HashMap, HashSet};
+#[cfg(not(feature = "std"))]
+pub use alloc::collections::{BTreeMap as HashMap, BTreeSet as HashSet};
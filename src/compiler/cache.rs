@@ -0,0 +1,293 @@
+use core::fmt;
+use core::fmt::{Debug, Formatter};
+#[cfg(feature = "fs")]
+use std::fs;
+#[cfg(feature = "fs")]
+use std::path::PathBuf;
+
+use log::warn;
+
+use crate::compiler::compiler::{Compiler, CompilerOptions};
+use crate::compiler::error::CompileError;
+use crate::logging::debug;
+use crate::prelude::*;
+use crate::vm::bytecode::BYTECODE_VERSION;
+use crate::vm::error::RuntimeError;
+use crate::vm::image::{Reader, Writer};
+use crate::vm::program::Program;
+
+// Program Cache
+//
+// somewhere compiled programs are kept between runs, by a key made from their source
+pub trait ProgramCache {
+    fn load(&self, key: &str) -> Option<Vec<u8>>;
+    fn store(&self, key: &str, bytes: &[u8]);
+}
+
+// keeps each program in a file named by its key
+#[cfg(feature = "fs")]
+#[derive(Clone, Debug)]
+pub struct DirectoryCache {
+    root: PathBuf,
+}
+
+#[cfg(feature = "fs")]
+impl DirectoryCache {
+
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        DirectoryCache { root: root.into() }
+    }
+
+}
+
+#[cfg(feature = "fs")]
+impl ProgramCache for DirectoryCache {
+
+    fn load(&self, key: &str) -> Option<Vec<u8>> {
+        fs::read(self.root.join(format!("{}.tsbc", key))).ok()
+    }
+
+    // a cache that can not be written only makes the next start slower
+    fn store(&self, key: &str, bytes: &[u8]) {
+        if let Err(e) = fs::create_dir_all(&self.root).and_then(|_| fs::write(self.root.join(format!("{}.tsbc", key)), bytes)) {
+            warn!("unable to cache program in {}: {}", self.root.display(), e);
+        }
+    }
+
+}
+
+type LoadCallback = Box<dyn Fn(&str) -> Option<Vec<u8>>>;
+type StoreCallback = Box<dyn Fn(&str, &[u8])>;
+
+// hands programs to the host to keep wherever it likes
+pub struct CallbackCache {
+    load: LoadCallback,
+    store: StoreCallback,
+}
+
+impl CallbackCache {
+
+    pub fn new(load: impl Fn(&str) -> Option<Vec<u8>> + 'static, store: impl Fn(&str, &[u8]) + 'static) -> Self {
+        CallbackCache { load: Box::new(load), store: Box::new(store) }
+    }
+
+}
+
+impl Debug for CallbackCache {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "CallbackCache")
+    }
+}
+
+impl ProgramCache for CallbackCache {
+
+    fn load(&self, key: &str) -> Option<Vec<u8>> {
+        (self.load)(key)
+    }
+
+    fn store(&self, key: &str, bytes: &[u8]) {
+        (self.store)(key, bytes)
+    }
+
+}
+
+// Cached Compiler
+//
+// compiles scripts once and loads them from the cache after that. the key covers the source,
+// the options and the version of the crate, so a new compiler never loads what an old one
+// wrote. imported files are looked up again on each load and compared with what was compiled
+pub struct CachedCompiler {
+    options: CompilerOptions,
+    cache: Box<dyn ProgramCache>,
+}
+
+impl CachedCompiler {
+
+    pub fn new(cache: impl ProgramCache + 'static) -> Self {
+        CachedCompiler::with_options(cache, CompilerOptions::default())
+    }
+
+    pub fn with_options(cache: impl ProgramCache + 'static, options: CompilerOptions) -> Self {
+        CachedCompiler { options, cache: Box::new(cache) }
+    }
+
+    pub fn compile(&self, source: &str) -> Result<Program, CompileError> {
+        let key = self.key(source);
+
+        if let Some(bytes) = self.cache.load(&key) {
+            match self.read_entry(&bytes) {
+                Ok(Some(program)) => {
+                    debug!("loaded program {} from the cache", key);
+                    return Ok(program);
+                }
+                Ok(None) => debug!("cached program {} has imports that changed", key),
+                Err(e) => warn!("ignoring cached program {}: {}", key, e)
+            }
+        }
+
+        let (program, imported) = Compiler::with_options(self.options.clone()).compile_with_imports(source.to_string())?;
+        self.cache.store(&key, &write_entry(&program, &imported));
+        Ok(program)
+    }
+
+    // the key a source is cached under, which changes with anything that changes the program
+    pub fn key(&self, source: &str) -> String {
+        let mut defines: Vec<&String> = self.options.defines.iter().collect();
+        defines.sort();
+
+        let mut hash = Fnv::default();
+        hash.write(env!("CARGO_PKG_VERSION").as_bytes());
+        hash.write(&BYTECODE_VERSION.major.to_le_bytes());
+        hash.write(&BYTECODE_VERSION.minor.to_le_bytes());
+        hash.write(&[self.options.strict as u8, self.options.checked as u8, self.options.no_std as u8]);
+        for define in defines {
+            hash.write(define.as_bytes());
+            hash.write(&[0]);
+        }
+        hash.write(source.as_bytes());
+        format!("{:016x}", hash.0)
+    }
+
+    // the program of an entry, or None if an import is no longer what it was compiled from
+    fn read_entry(&self, bytes: &[u8]) -> Result<Option<Program>, RuntimeError> {
+        let mut r = Reader::new(bytes, RuntimeError::InvalidBytecode);
+        for _ in 0..r.usize()? {
+            let path = r.string()?;
+            let hash = r.string()?;
+            match self.options.resolver.load(&path) {
+                Ok(source) if hash_of(&source) == hash => {}
+                _ => return Ok(None)
+            }
+        }
+        let len = r.usize()?;
+        let program = Program::from_bytes(r.take(len)?)?;
+        r.finish()?;
+        Ok(Some(program))
+    }
+
+}
+
+// the hash of each import followed by the bytecode
+fn write_entry(program: &Program, imported: &[(String, String)]) -> Vec<u8> {
+    let bytecode = program.to_bytes();
+    let mut w = Writer::default();
+    w.usize(imported.len());
+    for (path, source) in imported {
+        w.string(path);
+        w.string(&hash_of(source));
+    }
+    w.usize(bytecode.len());
+    w.bytes.extend_from_slice(&bytecode);
+    w.bytes
+}
+
+fn hash_of(source: &str) -> String {
+    let mut hash = Fnv::default();
+    hash.write(source.as_bytes());
+    format!("{:016x}", hash.0)
+}
+
+// 64 bit fnv-1a, which is quick and the same on every platform and run
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    use crate::collections::HashMap;
+    use crate::compiler::cache::{CachedCompiler, CallbackCache};
+    use crate::compiler::compiler::CompilerOptions;
+    use crate::compiler::resolver::MemoryResolver;
+    use crate::vm::value::Value;
+    use crate::vm::VM;
+
+    const SOURCE: &str = "import \"shapes.tny\";\nclass Test { function main() { var s = new Square(); return s.area(); } }";
+
+    // a cache held in memory that counts the programs it stores
+    fn cache(entries: &Rc<RefCell<HashMap<String, Vec<u8>>>>, stored: &Rc<Cell<usize>>) -> CallbackCache {
+        let (read, write, count) = (entries.clone(), entries.clone(), stored.clone());
+        CallbackCache::new(
+            move |key| read.borrow().get(key).cloned(),
+            move |key, bytes| {
+                count.set(count.get() + 1);
+                write.borrow_mut().insert(key.to_string(), bytes.to_vec());
+            }
+        )
+    }
+
+    fn compiler(cache: CallbackCache, side: i32) -> CachedCompiler {
+        let shapes = format!("class Square {{ var side = {}; function area() {{ return this.side * this.side; }} }}", side);
+        let options = CompilerOptions { resolver: Arc::new(MemoryResolver::new().module("shapes.tny", &shapes)), no_std: true, ..Default::default() };
+        CachedCompiler::with_options(cache, options)
+    }
+
+    fn run(compiler: &CachedCompiler) -> Value {
+        VM::new(compiler.compile(SOURCE).unwrap()).exec("Test.main", None).unwrap()
+    }
+
+    #[test]
+    fn test_cache() {
+        let (entries, stored) = (Rc::new(RefCell::new(HashMap::new())), Rc::new(Cell::new(0)));
+
+        let first = compiler(cache(&entries, &stored), 3);
+        assert_eq!(run(&first), Value::Integer(9));
+        assert_eq!(run(&first), Value::Integer(9));
+        assert_eq!(stored.get(), 1);
+
+        // a changed import is compiled again under the same key
+        let changed = compiler(cache(&entries, &stored), 4);
+        assert_eq!(run(&changed), Value::Integer(16));
+        assert_eq!(stored.get(), 2);
+        assert_eq!(entries.borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_keys() {
+        let compiler = compiler(CallbackCache::new(|_| None, |_, _| {}), 3);
+        assert_eq!(compiler.key(SOURCE), compiler.key(SOURCE));
+        assert_ne!(compiler.key(SOURCE), compiler.key("class Test { }"));
+
+        let strict = CachedCompiler::with_options(CallbackCache::new(|_| None, |_, _| {}), CompilerOptions { strict: true, no_std: true, ..Default::default() });
+        assert_ne!(strict.key(SOURCE), compiler.key(SOURCE));
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_directory() {
+        let root = std::env::temp_dir().join(format!("tinyscript-cache-{}", std::process::id()));
+        let compiler = CachedCompiler::new(crate::compiler::cache::DirectoryCache::new(&root));
+        let source = "class Test { function main() { return 42; } }";
+
+        let program = compiler.compile(source).unwrap();
+        let cached = std::fs::read(root.join(format!("{}.tsbc", compiler.key(source)))).unwrap();
+        assert!(cached.ends_with(&program.to_bytes()));
+        assert_eq!(VM::new(compiler.compile(source).unwrap()).exec("Test.main", None).unwrap(), Value::Integer(42));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_entry() {
+        let (entries, stored) = (Rc::new(RefCell::new(HashMap::new())), Rc::new(Cell::new(0)));
+        let compiler = compiler(cache(&entries, &stored), 3);
+        entries.borrow_mut().insert(compiler.key(SOURCE), vec![1, 2, 3]);
+        assert_eq!(run(&compiler), Value::Integer(9));
+        assert_eq!(stored.get(), 1);
+    }
+
+}
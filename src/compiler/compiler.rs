@@ -1,123 +1,572 @@
-use std::collections::HashMap;
-use std::fs;
-use log::{debug, trace};
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use log::warn;
+use crate::collections::{HashMap, HashSet};
+use crate::logging::{debug, trace};
+use crate::compiler::constant::{fold, literal, Defines};
+use crate::compiler::error::{CompileError, ParseError};
 use crate::compiler::frontend;
+use crate::compiler::preprocessor::preprocess;
 
-use crate::compiler::function::Function;
+use crate::compiler::function::{ClassFields, FunctionDecl, Named};
+use crate::compiler::resolver::ModuleResolver;
+use crate::compiler::stdlib;
+use crate::compiler::stdlib::STD_NAMESPACE;
 use crate::compiler::token::Token;
+use crate::prelude::*;
 use crate::vm::program::Program;
 use crate::vm::value::Value;
 
 pub const CLASS_CONSTRUCTOR_FUNCTION_NAME: &str = "constructor";
+pub const CLASS_SELF_VARIABLE_NAME: &str = "this";
+pub const STRICT_PRAGMA: &str = "strict";
+pub const CHECKED_PRAGMA: &str = "checked";
+// name of the script that was compiled rather than imported
+pub const MAIN_SCRIPT_NAME: &str = "main";
+// functions each thread is given at the least, below which threads cost more than they save
+#[cfg(feature = "parallel")]
+const PARALLEL_MIN_FUNCTIONS: usize = 64;
+// expect(value) makes an expectation from the standard library
+pub const EXPECT_FUNCTION_NAME: &str = "expect";
+pub const EXPECTATION_CLASS_NAME: &str = "Expectation";
+// methods the ? operator uses to tell a success apart and take its value
+pub const TRY_CHECK_METHOD_NAME: &str = "is_ok";
+pub const TRY_UNWRAP_METHOD_NAME: &str = "unwrap";
+
+// Compiler Options
+#[derive(Clone, Debug)]
+pub struct CompilerOptions {
+    // treat warnings as errors and reject implicit behaviour
+    pub strict: bool,
+    // fail instead of giving answers that are silently wrong, such as comparing with nan
+    pub checked: bool,
+    // where the sources of imported files come from
+    pub resolver: Arc<dyn ModuleResolver>,
+    // leave the standard library out of the program
+    pub no_std: bool,
+    // names that #if blocks in scripts test for
+    pub defines: HashSet<String>,
+    // threads functions are compiled on, large programs only use more than one
+    #[cfg(feature = "parallel")]
+    pub threads: usize,
+    // fuse common sequences of instructions into superinstructions
+    pub optimize: bool,
+    // time recorded in the program in seconds since the unix epoch, without it the same
+    // sources always compile to the same bytes
+    pub compiled_at: Option<u64>,
+}
+
+impl Default for CompilerOptions {
+    fn default() -> Self {
+        CompilerOptions {
+            strict: false,
+            checked: false,
+            resolver: default_resolver(),
+            no_std: false,
+            defines: HashSet::new(),
+            #[cfg(feature = "parallel")]
+            threads: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            optimize: true,
+            compiled_at: None,
+        }
+    }
+}
+
+// imports are read from the working directory, or must be given by the host without a filesystem
+#[cfg(feature = "fs")]
+fn default_resolver() -> Arc<dyn ModuleResolver> {
+    Arc::new(crate::compiler::resolver::DiskResolver::default())
+}
+
+#[cfg(not(feature = "fs"))]
+fn default_resolver() -> Arc<dyn ModuleResolver> {
+    Arc::new(crate::compiler::resolver::MemoryResolver::new())
+}
 
 // Compiler
 pub struct Compiler {
-    globals: HashMap<String, Value>,
-    global_lookup: HashMap<String, usize>
+    options: CompilerOptions,
+    global_lookup: HashMap<String, usize>,
+    // classes declared by each imported file
+    modules: HashMap<String, Vec<String>>,
+    // functions waiting to be compiled with the scope of the file they came from
+    units: Vec<(HashMap<String, usize>, Vec<FunctionDecl>)>,
+    // classes of the standard library visible in every file
+    prelude: Vec<String>,
+    // path and source of each imported file, so a cached program can tell when one changes
+    imported: Vec<(String, String)>,
+    // fields of each class in the order they are declared, by global
+    fields: ClassFields,
 }
 
 impl Compiler {
 
     pub fn new() -> Self {
+        Compiler::with_options(CompilerOptions::default())
+    }
+
+    pub fn with_options(options: CompilerOptions) -> Self {
         Compiler {
-            globals: Default::default(),
-            global_lookup: Default::default()
+            options,
+            global_lookup: Default::default(),
+            modules: Default::default(),
+            units: vec![],
+            prelude: vec![],
+            imported: vec![],
+            fields: Default::default(),
         }
     }
 
-    pub fn compile(mut self, program: String) -> Result<Program, String> {
+    pub fn compile(self, program: String) -> Result<Program, CompileError> {
+        self.compile_with_imports(program).map(|(program, _)| program)
+    }
+
+    // compile and give back the files that were imported along the way
+    pub fn compile_with_imports(mut self, program: String) -> Result<(Program, Vec<(String, String)>), CompileError> {
 
         // create a new program
         let mut p = Program::new();
 
+        // link the standard library in before anything that uses it
+        if !self.options.no_std {
+            self.link_stdlib(&mut p)?;
+        }
+
+        // declare the classes of the script and everything it imports
+        self.declare_file(&program, None, &mut p)?;
+        p.strict = self.options.strict;
+        p.checked = self.options.checked;
+        self.compile_units(&mut p)?;
+
+        // record how the program was built
+        p.build.compiler_version = env!("CARGO_PKG_VERSION").to_string();
+        p.build.compiled_at = self.options.compiled_at;
+        p.build.optimization_level = self.options.optimize as u8;
+        p.build.add_source(MAIN_SCRIPT_NAME, &program);
+        for (path, source) in self.imported.iter() {
+            p.build.add_source(path, source);
+        }
+
+        // log the program
+        debug!("Program compiled with {} instructions", p.instructions.len());
+        trace!("Program is {:?}", p.instructions);
+
+        // return the program
+        Ok((p, self.imported))
+    }
+
+    // compile the functions of every file declared so far and add them to the program
+    fn compile_units(&mut self, p: &mut Program) -> Result<(), CompileError> {
+
+        // files finish declaring after their imports, so reversing puts the script itself first
+        debug!("Compiling functions");
+        let fields = Arc::new(core::mem::take(&mut self.fields));
+        let units: Vec<(HashMap<String, usize>, FunctionDecl)> = core::mem::take(&mut self.units).into_iter().rev()
+            .flat_map(|(scope, functions)| functions.into_iter().map(move |func| (scope.clone(), func)))
+            .map(|(scope, mut func)| {
+                func.fields = fields.clone();
+                (scope, func)
+            })
+            .collect();
+
+        // functions are added in the order they were declared, however they were compiled, each
+        // followed by the anonymous functions written in it
+        for (fname, params, (mut ins, metadata, locals)) in compile_functions(units, &self.options)? {
+            p.params.insert(fname.clone(), params);
+            p.locals.insert(fname.clone(), locals);
+            ins.iter_mut().for_each(|i| i.relocate(p.instructions.len()));
+            trace!("function {} has metadata {:?}", fname, metadata);
+            p.metadata.insert(fname.clone(), metadata);
+            p.symbols.insert(fname, p.instructions.len());
+            p.instructions.extend(ins);
+        }
+
+        Ok(())
+    }
+
+    // link in the standard library and make its classes visible to every file
+    fn link_stdlib(&mut self, p: &mut Program) -> Result<(), CompileError> {
+        let stdlib = precompiled_stdlib(self.options.optimize)?;
+        p.link(stdlib.program).expect("the standard library should link into an empty program");
+        for (path, _) in stdlib::MODULES {
+            self.prelude.extend(stdlib.modules[*path].iter().cloned());
+        }
+        self.modules.extend(stdlib.modules);
+        self.global_lookup.extend(p.global_symbols.iter().map(|(name, global)| (name.clone(), *global)));
+        self.fields.extend(stdlib.fields);
+        Ok(())
+    }
+
+    // declare the classes of a file, each file only sees its own classes and what it imports
+    fn declare_file(&mut self, source: &str, path: Option<&str>, p: &mut Program) -> Result<(), CompileError> {
+
+        // strip the #if blocks that are not wanted
+        let source = &preprocess(source, &self.options.defines)?;
+
         // Tokenize Code
-        let script: Vec<Token> = frontend::parser::script(program.as_str()).map_err(|e| e.to_string())?;
+        let script: Vec<Token> = frontend::parser::script(source).map_err(|e| ParseError::new(source, e))?;
 
-        // loop through the imports of the script
-        debug!("Importing");
+        // apply pragmas before anything is compiled
         for token in script.iter() {
-            match token {
-                Token::Import(file) => {
-                    debug!("Importing {}", file);
-                    // let imported_script = fs::read_to_string(file).expect("Unable to read file");
-                    // let script: Vec<Token> = frontend::parser::script(&imported_script).map_err(|e| e.to_string()).expect("err");
-                },
-                _ => {}
+            if let Token::Pragma(name) = token {
+                debug!("Applying pragma {}", name);
+                match name.as_str() {
+                    STRICT_PRAGMA => self.options.strict = true,
+                    CHECKED_PRAGMA => self.options.checked = true,
+                    _ if self.options.strict => return Err(CompileError::UnknownPragma(name.clone())),
+                    _ => warn!("ignoring unknown pragma '{}'", name)
+                }
             }
         }
 
+        // namespaces imported anywhere in the script are in scope for every class
+        let imports: Vec<String> = script.iter().filter_map(|token| match token {
+            Token::ImportNamespace(name) => Some(name.clone()),
+            _ => None
+        }).collect();
 
         let mut functions = Vec::new();
+        let mut declared = Vec::new();
+        let script_name = path.unwrap_or(MAIN_SCRIPT_NAME);
 
         debug!("Declaring top level items");
+        let mut doc: Option<String> = None;
+        let mut namespace: Option<String> = None;
+
+        // every define is known up front so using one too early is an error rather than a variable
+        let mut defines: Defines = script.iter().filter_map(|token| match token {
+            Token::Define(name, _) => Some((name.clone(), None)),
+            _ => None
+        }).collect();
+
         for token in script.iter() {
-            match token {
-                Token::Class(class_name, items) => {
-
-                    // create a new object for the class
-                    let mut object = HashMap::new();
-                    let mut class_fields = vec![];
-
-                    // loop
-                    for item in items.iter() {
-                        match item {
-
-                            // add the function to the class
-                            Token::Function(func_name, params, statements) => {
-                                let func = Function::new(class_name, func_name, params.clone(), statements.clone());
-                                object.insert(func_name.to_string(), Value::FunctionRef(func.get_full_name().clone()));
-                                functions.push(func);
-                            },
-
-                            // add the variable to the class
-                            Token::Variable(name, ..) => {
-                                class_fields.push(item.clone());
-                                object.insert(name.to_string(), Value::Null);
-                            },
-
-                            _ => {}
+
+            // defines are folded when declared, so each one can use those above it
+            if let Token::Define(name, expression) = token {
+                if let Some(Some(_)) = defines.get(name) {
+                    return Err(CompileError::InvalidDefine(name.clone(), String::from("it is already declared")));
+                }
+                let value = fold(name, expression, &defines)?;
+                debug!("Defining {} as {:?}", name, value);
+                defines.insert(name.clone(), Some(literal(name, value)?));
+                continue;
+            }
+
+            // a namespace applies to the classes that follow it
+            if let Token::Namespace(name) = token {
+                debug!("Entering namespace {}", name);
+                namespace = Some(name.clone());
+                continue;
+            }
+
+            // doc comments belong to the class that follows them
+            if let Token::DocComment(text) = token {
+                append_doc(&mut doc, text);
+                continue;
+            }
+            let class_doc = doc.take();
+
+            if let Token::Class(name, items) = token {
+
+                // classes are known by their fully qualified name
+                let class_name = &match &namespace {
+                    Some(ns) => format!("{}.{}", ns, name),
+                    None => name.clone()
+                };
+                let class_defines = Arc::new(defines.clone());
+                let mut namespaces: Vec<String> = namespace.iter().cloned().collect();
+                namespaces.extend(imports.iter().cloned());
+                if !self.options.no_std {
+                    namespaces.push(STD_NAMESPACE.to_string());
+                }
+
+                if let Some(text) = class_doc {
+                    p.docs.insert(class_name.to_string(), text);
+                }
+
+                // create a new object for the class
+                let mut object = HashMap::new();
+                let mut class_fields = vec![];
+                let mut field_names = vec![];
+                let mut constructor = None;
+
+                // loop
+                let mut doc: Option<String> = None;
+                let mut attributes: Vec<String> = vec![];
+                for item in items.iter() {
+
+                    // doc comments and attributes belong to the member that follows them
+                    let member_name = match item {
+                        Token::DocComment(text) => {
+                            append_doc(&mut doc, text);
+                            continue;
+                        }
+                        Token::Attribute(name) => {
+                            attributes.push(name.to_string());
+                            continue;
+                        }
+                        Token::Function(func_name, _, _) | Token::Generator(func_name, _, _) => Some(func_name.to_string()),
+                        Token::Constructor(_, _) => Some(CLASS_CONSTRUCTOR_FUNCTION_NAME.to_string()),
+                        Token::Variable(name, _) => Some(name.to_string()),
+                        _ => None
+                    };
+                    match member_name {
+                        Some(member_name) => {
+                            let key = format!("{}.{}", class_name, member_name);
+                            if let Some(text) = doc.take() {
+                                p.docs.insert(key.clone(), text);
+                            }
+                            if !attributes.is_empty() {
+                                p.attributes.insert(key, core::mem::take(&mut attributes));
+                            }
+                        }
+                        None => {
+                            doc = None;
+                            attributes.clear();
                         }
                     }
 
-                    // add the default constructor if it doesn't exist
-                    if object.contains_key(CLASS_CONSTRUCTOR_FUNCTION_NAME) {
+                    match item {
+
+                        // keep hold of the constructor until the fields are known
+                        Token::Function(func_name, params, statements) if func_name == CLASS_CONSTRUCTOR_FUNCTION_NAME => {
+                            constructor = Some((params.clone(), statements.clone()));
+                        },
+                        Token::Constructor(params, statements) => {
+                            constructor = Some((params.clone(), statements.clone()));
+                        },
+
+                        // add the function to the class
+                        Token::Function(func_name, _, _) | Token::Generator(func_name, _, _) if object.contains_key(func_name.as_str()) => {
+                            return Err(CompileError::DuplicateFunction(format!("{}.{}", class_name, func_name)));
+                        },
+                        Token::Function(func_name, params, statements) => {
+                            let mut func = FunctionDecl::new(class_name, func_name, params.clone(), statements.clone());
+                            func.namespaces = namespaces.clone();
+                            func.defines = class_defines.clone();
+                            func.script = script_name.to_string();
+                            object.insert(func_name.to_string(), Value::FunctionRef(Rc::new(func.get_full_name())));
+                            functions.push(func);
+                        },
+
+                        // generators are functions whose calls are suspended at each yield
+                        Token::Generator(func_name, params, statements) => {
+                            let mut func = FunctionDecl::new(class_name, func_name, params.clone(), statements.clone());
+                            func.namespaces = namespaces.clone();
+                            func.defines = class_defines.clone();
+                            func.script = script_name.to_string();
+                            func.generator = true;
+                            object.insert(func_name.to_string(), Value::FunctionRef(Rc::new(func.get_full_name())));
+                            functions.push(func);
+                        },
 
-                    } else {
-                        let default_constructor = Function::new(class_name, CLASS_CONSTRUCTOR_FUNCTION_NAME, Default::default(), class_fields);
-                        let fname = default_constructor.get_full_name().clone();
-                        functions.push(default_constructor);
-                        object.insert(CLASS_CONSTRUCTOR_FUNCTION_NAME.to_string(), Value::FunctionRef(fname));
+                        // fields are initialised on 'this' at the start of the constructor
+                        Token::Variable(name, value) => {
+                            let field = Token::DotChain(Box::new(Token::Identifier(CLASS_SELF_VARIABLE_NAME.to_string())), vec![*name.clone()]);
+                            class_fields.push(Token::Assign(Box::new(field), value.clone()));
+                            field_names.push(name.to_string());
+                            object.insert(name.to_string(), Value::Null);
+                        },
+
+                        _ => {}
                     }
+                }
+
+                // add the constructor, or a default one if it doesn't exist
+                let (params, mut statements) = constructor.unwrap_or_default();
+                class_fields.append(&mut statements);
+                let mut constructor = FunctionDecl::new(class_name, CLASS_CONSTRUCTOR_FUNCTION_NAME, params, class_fields);
+                constructor.namespaces = namespaces;
+                constructor.defines = class_defines;
+                constructor.script = script_name.to_string();
+                object.insert(CLASS_CONSTRUCTOR_FUNCTION_NAME.to_string(), Value::FunctionRef(Rc::new(constructor.get_full_name())));
+                functions.push(constructor);
 
-                    // log class name and object
-                    trace!("storing class {:?} with object '{:?}'", class_name.to_string(), object);
+                // log class name and object
+                trace!("storing class {:?} with object '{:?}'", class_name, object);
 
-                    // insert the class into the globals
-                    let v = Value::Class(object);
-                    let global_index = p.insert_global(v.clone());
-                    self.global_lookup.insert(class_name.to_string(), global_index);
-                    self.globals.insert(class_name.to_string(), v.clone());
+                // insert the class into the globals
+                if self.global_lookup.contains_key(class_name) {
+                    return Err(CompileError::DuplicateClass(class_name.to_string()));
+                }
+                let v = Value::Class(Rc::new(object));
+                let global_index = p.insert_global(v);
+                p.global_symbols.insert(class_name.to_string(), global_index);
+                self.global_lookup.insert(class_name.to_string(), global_index);
+                self.fields.insert(global_index, field_names);
+                declared.push(class_name.to_string());
 
-                },
+            }
+        }
+
+        // register the file before following its imports so circular imports resolve
+        if let Some(path) = path {
+            self.modules.insert(path.to_string(), declared.clone());
+        }
+
+        // the file sees the standard library and its own classes
+        let mut scope: HashMap<String, usize> = self.prelude.iter().chain(declared.iter()).map(|name| (name.clone(), self.global_lookup[name])).collect();
+
+        // and the classes it imports
+        debug!("Importing");
+        for token in script.iter() {
+            match token {
+                Token::Import(file, alias) => {
+                    debug!("Importing {}", file);
+                    for name in self.import_module(file, p)? {
+                        let visible = match alias {
+                            Some(alias) => format!("{}.{}", alias, short_name(&name)),
+                            None => name.clone()
+                        };
+                        scope.insert(visible, self.global_lookup[&name]);
+                    }
+                }
+                Token::FromImport(file, names) => {
+                    debug!("Importing {:?} from {}", names, file);
+                    let classes = self.import_module(file, p)?;
+                    for wanted in names {
+                        let name = classes.iter().find(|name| *name == wanted || short_name(name) == wanted)
+                            .ok_or_else(|| CompileError::UnknownImport(file.clone(), wanted.clone()))?;
+                        scope.insert(wanted.clone(), self.global_lookup[name]);
+                    }
+                }
                 _ => {}
             }
         }
 
-        debug!("Compiling functions");
-        for func in functions {
-            let fname = func.get_full_name().clone();
-            debug!("Compiling function {}", fname);
-            let ins = func.compile(self.globals.clone(), self.global_lookup.clone());
-            p.symbols.insert(fname, p.instructions.len());
-            p.instructions.extend(ins);
+        self.units.push((scope, functions));
+        Ok(())
+    }
+
+    // declare an imported file once and get the classes it declares
+    fn import_module(&mut self, path: &str, p: &mut Program) -> Result<Vec<String>, CompileError> {
+        if let Some(classes) = self.modules.get(path) {
+            return Ok(classes.clone());
         }
+        let source = self.options.resolver.load(path).map_err(|e| CompileError::ImportFailed(path.to_string(), e))?;
+        self.imported.push((path.to_string(), source.clone()));
+        self.declare_file(&source, Some(path), p).map_err(|e| CompileError::Module(path.to_string(), Box::new(e)))?;
+        Ok(self.modules[path].clone())
+    }
 
-        // log the program
-        debug!("Program compiled with {} instructions", p.instructions.len());
-        trace!("Program is {:?}", p.instructions);
+}
 
-        // return the program
-        Ok(p)
+// the standard library compiled on its own, with the classes each of its files declares and the
+// fields of those classes, so a compile can link it in rather than compile it again
+#[derive(Clone)]
+struct Stdlib {
+    program: Program,
+    modules: HashMap<String, Vec<String>>,
+    fields: ClassFields,
+}
+
+fn compile_stdlib(optimize: bool) -> Result<Stdlib, CompileError> {
+    debug!("Compiling the standard library");
+    let mut compiler = Compiler::with_options(CompilerOptions { optimize, ..Default::default() });
+    let mut p = Program::new();
+    for (path, source) in stdlib::MODULES {
+        compiler.declare_file(source, Some(path), &mut p).map_err(|e| CompileError::Module(path.to_string(), Box::new(e)))?;
+        compiler.prelude.extend(compiler.modules[*path].iter().cloned());
+    }
+    let fields = compiler.fields.clone();
+    compiler.compile_units(&mut p)?;
+    Ok(Stdlib { program: p, modules: compiler.modules, fields })
+}
+
+// the standard library is compiled once for each thread and level of optimization, as the values
+// in a program can not be shared between threads
+#[cfg(feature = "std")]
+fn precompiled_stdlib(optimize: bool) -> Result<Stdlib, CompileError> {
+    use core::cell::OnceCell;
+
+    thread_local! {
+        static STDLIB: [OnceCell<Stdlib>; 2] = const { [OnceCell::new(), OnceCell::new()] };
+    }
+
+    STDLIB.with(|compiled| {
+        let compiled = &compiled[optimize as usize];
+        if let Some(stdlib) = compiled.get() {
+            return Ok(stdlib.clone());
+        }
+        let stdlib = compile_stdlib(optimize)?;
+        Ok(compiled.get_or_init(|| stdlib).clone())
+    })
+}
+
+// without std there is nowhere to keep it, so it is compiled each time
+#[cfg(not(feature = "std"))]
+fn precompiled_stdlib(optimize: bool) -> Result<Stdlib, CompileError> {
+    compile_stdlib(optimize)
+}
+
+fn compile_sequentially(units: Vec<(HashMap<String, usize>, FunctionDecl)>, options: &CompilerOptions) -> Result<Vec<Named>, CompileError> {
+    let mut compiled = vec![];
+    for (scope, func) in units {
+        debug!("Compiling function {}", func.get_full_name());
+        compiled.extend(func.compile(scope, options)?);
+    }
+    Ok(compiled)
+}
+
+#[cfg(not(feature = "parallel"))]
+fn compile_functions(units: Vec<(HashMap<String, usize>, FunctionDecl)>, options: &CompilerOptions) -> Result<Vec<Named>, CompileError> {
+    compile_sequentially(units, options)
+}
+
+// functions only need their declaration and the globals, so they are compiled on a pool of
+// threads. instructions hold values that can not leave the thread they were made on, so each
+// function comes back as bytecode and is decoded in the order it was declared
+#[cfg(feature = "parallel")]
+fn compile_functions(units: Vec<(HashMap<String, usize>, FunctionDecl)>, options: &CompilerOptions) -> Result<Vec<Named>, CompileError> {
+    use rayon::prelude::*;
+
+    use crate::vm::bytecode::{decode_instructions, encode_instructions};
+    use crate::vm::program::FunctionMetadata;
+
+    // a compiled function with its instructions as bytecode
+    type Encoded = (String, Vec<String>, Vec<u8>, FunctionMetadata, Vec<String>);
+
+    let threads = options.threads.min(units.len() / PARALLEL_MIN_FUNCTIONS);
+    if threads <= 1 {
+        return compile_sequentially(units, options);
     }
+    debug!("Compiling {} functions on {} threads", units.len(), threads);
+
+    let pool = match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => pool,
+        Err(_) => return compile_sequentially(units, options)
+    };
+    // every function is compiled so the error reported is the first in declaration order, as it
+    // is when compiling sequentially
+    let results: Vec<Result<Vec<Encoded>, CompileError>> = pool.install(|| {
+        units.into_par_iter().with_min_len(PARALLEL_MIN_FUNCTIONS).map(|(scope, func)| {
+            debug!("Compiling function {}", func.get_full_name());
+            let compiled = func.compile(scope, options)?;
+            Ok(compiled.into_iter().map(|(name, params, (ins, metadata, locals))| (name, params, encode_instructions(&ins), metadata, locals)).collect())
+        }).collect()
+    });
 
-}
\ No newline at end of file
+    let mut compiled = vec![];
+    for result in results {
+        for (name, params, bytes, metadata, locals) in result? {
+            compiled.push((name, params, (decode_instructions(&bytes).expect("instructions should decode as they were encoded"), metadata, locals)));
+        }
+    }
+    Ok(compiled)
+}
+
+// name of a class without its namespace
+fn short_name(name: &str) -> &str {
+    name.rsplit('.').next().unwrap_or(name)
+}
+
+// add a line to the documentation being collected
+fn append_doc(doc: &mut Option<String>, line: &str) {
+    match doc {
+        Some(text) => {
+            text.push('\n');
+            text.push_str(line);
+        }
+        None => *doc = Some(line.to_string())
+    }
+}
@@ -0,0 +1,124 @@
+use crate::collections::HashMap;
+use crate::compiler::error::CompileError;
+use crate::compiler::token::Token;
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
+use crate::vm::value::Value;
+
+// Constant Folding
+//
+// works out the value of a define when it is declared, so uses of it compile to a single push.
+// only literals, earlier defines and operators on them are constant
+
+// defines of a file as literals, None for those declared further down than where they are looked up
+pub type Defines = HashMap<String, Option<Token>>;
+
+pub fn fold(name: &str, expression: &Token, defines: &Defines) -> Result<Value, CompileError> {
+    let invalid = |reason: String| CompileError::InvalidDefine(name.to_string(), reason);
+    let binary = |a: &Token, b: &Token| Ok::<(Value, Value), CompileError>((fold(name, a, defines)?, fold(name, b, defines)?));
+    let evaluated = |result: Result<Value, RuntimeError>| result.map_err(|e| invalid(e.to_string()));
+    let compared = |a: &Token, b: &Token, test: fn(core::cmp::Ordering) -> bool| {
+        let (lhs, rhs) = binary(a, b)?;
+        evaluated(lhs.compare(&rhs).map(|ordering| Value::Bool(test(ordering))))
+    };
+
+    match expression {
+        Token::Null => Ok(Value::Null),
+        Token::Integer(v) => Ok(Value::Integer(*v)),
+        Token::Float(v) => Ok(Value::Float(*v)),
+        Token::Bool(v) => Ok(Value::Bool(*v)),
+        Token::String(v) => Ok(Value::from(v.to_string())),
+
+        Token::Identifier(other) => match defines.get(other) {
+            Some(Some(literal)) => fold(other, literal, defines),
+            Some(None) => Err(CompileError::DefineUsedBeforeDeclaration(other.to_string())),
+            None => Err(invalid(format!("'{}' is not a define", other)))
+        },
+
+        Token::Add(a, b) => binary(a, b).and_then(|(lhs, rhs)| evaluated(lhs + rhs)),
+        Token::Sub(a, b) => binary(a, b).and_then(|(lhs, rhs)| evaluated(lhs - rhs)),
+        Token::Mul(a, b) => binary(a, b).and_then(|(lhs, rhs)| evaluated(lhs * rhs)),
+        Token::Div(a, b) => binary(a, b).and_then(|(lhs, rhs)| evaluated(lhs / rhs)),
+        Token::FloorDiv(a, b) => binary(a, b).and_then(|(lhs, rhs)| evaluated(lhs.floor_div(rhs))),
+        Token::Pow(a, b) => binary(a, b).and_then(|(lhs, rhs)| evaluated(lhs.pow(rhs))),
+
+        Token::Eq(a, b) => binary(a, b).map(|(lhs, rhs)| Value::Bool(lhs == rhs)),
+        Token::Ne(a, b) => binary(a, b).map(|(lhs, rhs)| Value::Bool(lhs != rhs)),
+        Token::Identical(a, b) => binary(a, b).map(|(lhs, rhs)| Value::Bool(lhs.identical(&rhs))),
+        Token::Lt(a, b) => compared(a, b, |o| o.is_lt()),
+        Token::Le(a, b) => compared(a, b, |o| o.is_le()),
+        Token::Gt(a, b) => compared(a, b, |o| o.is_gt()),
+        Token::Ge(a, b) => compared(a, b, |o| o.is_ge()),
+        Token::And(a, b) => match fold(name, a, defines)? {
+            Value::Bool(false) => Ok(Value::Bool(false)),
+            Value::Bool(true) => fold(name, b, defines),
+            other => Err(invalid(format!("can not use {} with &&", other.type_name())))
+        },
+        Token::Chain(comparisons) => {
+            for comparison in comparisons {
+                if fold(name, comparison, defines)? == Value::Bool(false) {
+                    return Ok(Value::Bool(false));
+                }
+            }
+            Ok(Value::Bool(true))
+        }
+
+        _ => Err(invalid(String::from("value is not a constant expression")))
+    }
+}
+
+// the literal a folded value is written as
+pub fn literal(name: &str, value: Value) -> Result<Token, CompileError> {
+    match value {
+        Value::Null => Ok(Token::Null),
+        Value::Integer(v) => Ok(Token::Integer(v)),
+        Value::Float(v) => Ok(Token::Float(v)),
+        Value::Bool(v) => Ok(Token::Bool(v)),
+        Value::String(v) => Ok(Token::String(v.to_string())),
+        other => Err(CompileError::InvalidDefine(name.to_string(), format!("{} is not a constant", other.type_name())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collections::HashMap;
+    use crate::compiler::constant::{fold, literal, Defines};
+    use crate::compiler::error::CompileError;
+    use crate::compiler::frontend::parser;
+    use crate::compiler::token::Token;
+
+    // fold each define of a script in order, as the compiler does
+    fn defines(script: &str) -> Result<Defines, CompileError> {
+        let tokens = parser::script(script).unwrap();
+        let mut defines: Defines = HashMap::new();
+        for token in tokens.iter() {
+            if let Token::Define(name, _) = token {
+                defines.insert(name.to_string(), None);
+            }
+        }
+        for token in tokens.iter() {
+            if let Token::Define(name, expression) = token {
+                let value = fold(name, expression, &defines)?;
+                defines.insert(name.to_string(), Some(literal(name, value)?));
+            }
+        }
+        Ok(defines)
+    }
+
+    #[test]
+    fn test_fold() {
+        let defines = defines("define MAX_HP = 100;\ndefine HALF = MAX_HP // 2 + 1;\ndefine NAME = \"hp \" + MAX_HP;\ndefine BIG = HALF > 50 && MAX_HP == 100;").unwrap();
+        assert!(matches!(defines["HALF"], Some(Token::Integer(51))));
+        assert!(matches!(&defines["NAME"], Some(Token::String(name)) if name == "hp 100"));
+        assert!(matches!(defines["BIG"], Some(Token::Bool(true))));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(defines("define A = B + 1;\ndefine B = 2;").err(), Some(CompileError::DefineUsedBeforeDeclaration(String::from("B"))));
+        assert_eq!(defines("define A = x;").err(), Some(CompileError::InvalidDefine(String::from("A"), String::from("'x' is not a define"))));
+        assert_eq!(defines("define A = [1];").err(), Some(CompileError::InvalidDefine(String::from("A"), String::from("value is not a constant expression"))));
+        assert!(matches!(defines("define A = 1 // 0;"), Err(CompileError::InvalidDefine(_, _))));
+    }
+
+}
@@ -0,0 +1,166 @@
+use core::error::Error;
+use core::fmt;
+use core::fmt::{Display, Formatter};
+
+use peg::str::LineCol;
+
+use crate::prelude::*;
+
+// Parse Error
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub offset: usize,
+    pub line_text: String,
+    pub expected: Vec<String>,
+}
+
+impl ParseError {
+
+    // build from a peg error, keeping the line of source it happened on
+    pub fn new(source: &str, error: peg::error::ParseError<LineCol>) -> Self {
+        let line_text = source.lines().nth(error.location.line - 1).unwrap_or_default();
+        ParseError {
+            line: error.location.line,
+            column: error.location.column,
+            offset: error.location.offset,
+            line_text: line_text.trim_end().to_string(),
+            expected: error.expected.tokens().map(|t| t.to_string()).collect(),
+        }
+    }
+
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "parse error at line {}, column {}: expected {}", self.line, self.column, self.expected.join(", "))?;
+
+        // render the line with a caret under the column
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.line_text)?;
+        write!(f, "{} | {}^", gutter, " ".repeat(self.column.saturating_sub(1)))
+    }
+}
+
+impl Error for ParseError {}
+
+// Compile Error
+#[derive(Clone, Debug, PartialEq)]
+pub enum CompileError {
+    Parse(ParseError),
+    UnknownPragma(String),
+    // line and what is wrong with the #if, #else or #endif on it
+    InvalidDirective(usize, String),
+    // line and what is wrong with the placeholder on it
+    InvalidTemplate(usize, String),
+    UnknownClass(String),
+    DuplicateClass(String),
+    DuplicateFunction(String),
+    ImportFailed(String, String),
+    // what is wrong with a tinyscript.toml
+    InvalidManifest(String),
+    UnknownImport(String, String),
+    Module(String, Box<CompileError>),
+    UndeclaredVariable(String),
+    DefineUsedBeforeDeclaration(String),
+    // name of the define and why it is not a constant
+    InvalidDefine(String, String),
+    DuplicateVariable(String),
+    // variable used after the block it was declared in has ended
+    OutOfScope(String),
+    InvalidAssignment,
+    InvalidChain,
+    InvalidPattern(String),
+    YieldOutsideGenerator,
+    // defer somewhere it can not be used
+    InvalidDefer(String),
+    // break or continue outside of a loop
+    OutsideLoop(String),
+    // name given to break or continue that no loop around it has
+    UnknownLoop(String),
+    UnsupportedToken(String),
+    FeatureNotEnabled(String, String),
+    Strict(Vec<String>),
+    Function(String, Box<CompileError>),
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            CompileError::Parse(e) => write!(f, "{}", e),
+            CompileError::UnknownPragma(name) => write!(f, "unknown pragma '{}'", name),
+            CompileError::InvalidDirective(line, message) => write!(f, "invalid directive at line {}: {}", line, message),
+            CompileError::InvalidTemplate(line, message) => write!(f, "invalid template at line {}: {}", line, message),
+            CompileError::UnknownClass(name) => write!(f, "class '{}' does not exist", name),
+            CompileError::DuplicateClass(name) => write!(f, "class '{}' already exists", name),
+            CompileError::DuplicateFunction(name) => write!(f, "function '{}' already exists", name),
+            CompileError::ImportFailed(path, reason) => write!(f, "unable to import '{}': {}", path, reason),
+            CompileError::InvalidManifest(reason) => write!(f, "invalid project manifest: {}", reason),
+            CompileError::UnknownImport(path, name) => write!(f, "'{}' has no class '{}'", path, name),
+            CompileError::Module(path, e) => write!(f, "in '{}': {}", path, e),
+            CompileError::UndeclaredVariable(name) => write!(f, "variable '{}' does not exist", name),
+            CompileError::DefineUsedBeforeDeclaration(name) => write!(f, "define '{}' is used before it is declared", name),
+            CompileError::InvalidDefine(name, reason) => write!(f, "define '{}' is invalid: {}", name, reason),
+            CompileError::DuplicateVariable(name) => write!(f, "variable '{}' already exists", name),
+            CompileError::OutOfScope(name) => write!(f, "variable '{}' is used outside the block it is declared in", name),
+            CompileError::InvalidAssignment => write!(f, "left side of assignment is not a variable, member or index"),
+            CompileError::InvalidChain => write!(f, "chain item is not a member, index or call"),
+            CompileError::InvalidPattern(reason) => write!(f, "invalid pattern: {}", reason),
+            CompileError::YieldOutsideGenerator => write!(f, "yield can only be used in a generator function"),
+            CompileError::InvalidDefer(reason) => write!(f, "invalid defer: {}", reason),
+            CompileError::OutsideLoop(statement) => write!(f, "{} can only be used in a loop", statement),
+            CompileError::UnknownLoop(name) => write!(f, "there is no loop named '{}' around this", name),
+            CompileError::UnsupportedToken(token) => write!(f, "unsupported token {}", token),
+            CompileError::FeatureNotEnabled(feature, literal) => write!(f, "'{}' needs the {} feature", literal, feature),
+            CompileError::Strict(messages) => write!(f, "strict mode: {}", messages.join("; ")),
+            CompileError::Function(name, e) => write!(f, "in {}: {}", name, e),
+        }
+    }
+}
+
+impl Error for CompileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CompileError::Parse(e) => Some(e),
+            CompileError::Function(_, e) => Some(e.as_ref()),
+            CompileError::Module(_, e) => Some(e.as_ref()),
+            _ => None
+        }
+    }
+}
+
+impl From<ParseError> for CompileError {
+    fn from(e: ParseError) -> Self {
+        CompileError::Parse(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::error::ParseError;
+    use crate::compiler::frontend::parser;
+
+    #[test]
+    fn test_parse_error_location() {
+        let source = "class Test {\n    function main() {\n        var x = ;\n    }\n}";
+        let error = ParseError::new(source, parser::script(source).unwrap_err());
+        assert_eq!(error.line, 3);
+        assert_eq!(error.line_text, "        var x = ;");
+        assert!(!error.expected.is_empty());
+    }
+
+    #[test]
+    fn test_parse_error_display() {
+        let error = ParseError {
+            line: 12,
+            column: 5,
+            offset: 100,
+            line_text: String::from("var = 1;"),
+            expected: vec![String::from("identifier")],
+        };
+        assert_eq!(error.to_string(), "parse error at line 12, column 5: expected identifier\n   |\n12 | var = 1;\n   |     ^");
+    }
+
+}
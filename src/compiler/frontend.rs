@@ -1,16 +1,27 @@
 use peg::parser;
 
 use crate::compiler::token::Token;
+use crate::prelude::*;
 
 parser!(pub grammar parser() for str {
 
     // top level rule
     pub rule script() -> Vec<Token>
-        = WHITESPACE() f:(import()
+        = WHITESPACE() f:(pragma()
+        / define()
+        / namespace()
+        / import_namespace()
+        / import()
+        / from_import()
+        / doc_comment()
         / class()
         / comment()
     )* WHITESPACE() { f }
 
+    // an expression on its own, such as one a debugger evaluates
+    pub rule lone_expression() -> Token
+        = WHITESPACE() e:expression() WHITESPACE() { e }
+
     // statements
     rule statements() -> Vec<Token>
         = s:((comment()
@@ -23,40 +34,81 @@ parser!(pub grammar parser() for str {
         = WHITESPACE() s:(
             assert() /
             print() /
+            log() /
             var() /
             assignment() /
-            call() /
             identifier_chain() /
-            rtn()
+            call() /
+            rtn() /
+            yield_value() /
+            defer() /
+            break_loop() /
+            continue_loop()
         ) WHITESPACE() SEMICOLON()+ WHITESPACE() { s }  / expected!("single statement")
 
     // control flow statements without semicolon
     rule control_flow() -> Token
         = WHITESPACE() c:(
+            labeled_loop() /
             if_else() /
             while_loop() /
+            do_while_loop() /
             foreach_loop() /
-            fori_loop()
+            fori_loop() /
+            match_statement()
         ) WHITESPACE() { c } / expected!("control flow")
 
-    // import external file
+    // compiler pragma such as #strict
+    rule pragma() -> Token
+        = "#" n:identifier_as_string() WHITESPACE() { Token::Pragma(n) }
+
+    // compile time constant for everything that follows
+    rule define() -> Token
+        = "define" _ n:identifier_as_string() _ "=" _ e:expression() _ SEMICOLON()+ WHITESPACE() { Token::Define(n, Box::new(e)) }
+
+    // namespace for the classes that follow
+    rule namespace() -> Token
+        = "namespace" _ n:qualified_name() _ SEMICOLON()+ WHITESPACE() { Token::Namespace(n) }
+
+    // bring the classes of a namespace into scope
+    rule import_namespace() -> Token
+        = "import" _ n:qualified_name() _ SEMICOLON()+ WHITESPACE() { Token::ImportNamespace(n) }
+
+    // import the classes of an external file, optionally under an alias
     rule import() -> Token
-        = "import" _ s:string() _ SEMICOLON()+ { Token::Import(s.to_string()) }
+        = "import" _ s:string() a:(_ "as" _ a:identifier_as_string() { a })? _ SEMICOLON()+ WHITESPACE() { Token::Import(s.to_string(), a) }
+
+    // import named classes from an external file
+    rule from_import() -> Token
+        = "from" _ s:string() _ "import" _ n:(identifier_as_string() ++ (_ "," _)) _ SEMICOLON()+ WHITESPACE() { Token::FromImport(s.to_string(), n) }
 
     // single line comment
     rule comment() -> Token
         = "//" s:$([' ' |'a'..='z' | 'A'..='Z' | '0'..='9']*) NEWLINE() { Token::Comment(s.to_owned()) }
 
+    // documentation for the class or function that follows
+    rule doc_comment() -> Token
+        = "///" " "? s:$([^'\n' | '\r']*) NEWLINE() WHITESPACE() { Token::DocComment(s.trim_end().to_owned()) }
+
+    // attribute such as @export for the class member that follows
+    rule attribute() -> Token
+        = "@" n:identifier_as_string() WHITESPACE() { Token::Attribute(n) }
+
     // class definition
     rule class() -> Token
         = "class" WHITESPACE() i:identifier() WHITESPACE() "{" WHITESPACE()
-        items:(WHITESPACE() item:(var_statement() / constructor() / function()) WHITESPACE() { item })*
+        items:(WHITESPACE() item:(doc_comment() / attribute() / comment() / var_statement() / constructor() / generator() / function()) WHITESPACE() { item })*
         WHITESPACE() "}" WHITESPACE()
     { Token::Class(i.to_string(), items) }
 
-    // class member call chain
+    // class member call chain, each item is taken from the value of the one before it
     rule identifier_chain() -> Token
-        = i:identifier_chain_item() "." chain:((e:identifier_chain_item() {e}) ** ".") { Token::DotChain(Box::new(i), chain) }
+        = i:chain_start() "." chain:((e:identifier_chain_item() {e}) ** ".") { Token::DotChain(Box::new(i), chain) }
+
+    rule chain_start() -> Token
+        = "(" _ e:expression() _ ")" { e }
+        / new_object_call()
+        / identifier_chain_item()
 
     rule identifier_chain_item() -> Token
         = item:( call() / array_index() / identifier()) { item }
@@ -71,6 +123,10 @@ parser!(pub grammar parser() for str {
         = "function" _ name:identifier() _ "()" stmts:block() WHITESPACE() { Token::Function(name.to_string(), vec![], stmts) }
         / "function" _ name:identifier() _ "(" params:param_list() ")" stmts:block() WHITESPACE() { Token::Function(name.to_string(), params, stmts) }
 
+    // generator function which is run a yield at a time
+    rule generator() -> Token
+        = "function*" _ name:identifier() _ "(" params:param_list() ")" stmts:block() WHITESPACE() { Token::Generator(name.to_string(), params, stmts) }
+
     // function call with arguments
     rule call() -> Token
         = i:identifier() "(" args:arg_list() ")" { Token::Call(Box::new(i), args) }
@@ -83,10 +139,25 @@ parser!(pub grammar parser() for str {
     rule assert() -> Token
         = "assert" _ e:expression() { Token::Assert(Box::new(e)) }
 
+    // hand a value to whoever is iterating the generator
+    rule yield_value() -> Token
+        = "yield" _ e:expression() { Token::Yield(Box::new(e)) }
+
     // print value
+    // a statement that can not leave the function, run when the function does
+    rule defer() -> Token
+        = "defer" !IDENTIFIER_CHAR() _ s:(print() / log() / assignment() / identifier_chain() / call()) { Token::Defer(Box::new(s)) }
+
     rule print() -> Token
         = "print " _ e:expression() { Token::Print(Box::new(e)) }
 
+    // log statements keep the line they are on, so the host can tell where they came from
+    rule log() -> Token
+        = l:line() "Log." level:$("info" / "warn" / "error" / "debug") "(" _ e:expression() _ ")" { Token::Log(level.to_owned(), l, Box::new(e)) }
+
+    rule line() -> usize
+        = #{|input, pos| peg::RuleResult::Matched(pos, input[..pos].matches('\n').count() + 1)}
+
     // anonymous function call
     rule anonfunc() -> Token
         = "function(" params:param_list() ")" stmts:block()
@@ -122,11 +193,29 @@ parser!(pub grammar parser() for str {
         = "while" _ e:evaluation() s:block()
         { Token::WhileLoop(Box::new(e), s) }
 
+    // a loop named so an inner loop can break out of or continue it
+    rule labeled_loop() -> Token
+        = n:identifier_as_string() _ ":" WHITESPACE() l:(while_loop() / do_while_loop() / foreach_loop() / fori_loop())
+        { Token::LabeledLoop(n, Box::new(l)) }
+
+    // leave the innermost loop, or the one named
+    rule break_loop() -> Token
+        = "break" !IDENTIFIER_CHAR() n:(_ n:identifier_as_string() { n })? { Token::Break(n) }
+
+    // go round the innermost loop again, or the one named
+    rule continue_loop() -> Token
+        = "continue" !IDENTIFIER_CHAR() n:(_ n:identifier_as_string() { n })? { Token::Continue(n) }
+
+    // the body runs once before the condition is first checked
+    rule do_while_loop() -> Token
+        = "do" s:block() WHITESPACE() "while" _ e:evaluation() _ SEMICOLON()+
+        { Token::DoWhileLoop(s, Box::new(e)) }
+
     rule evaluation() -> Token
         = "(" e:expression() ")" { e } / e:expression() { e }
 
     rule foreach_loop() -> Token
-        = "for" _ "(" _ i:identifier() _ "in" _ e:( identifier() / list()) _ ")" s:block()
+        = "for" _ "(" _ i:identifier() _ "in" _ e:expression() _ ")" s:block()
         { Token::ForEach(Box::new(i), Box::new(e), s) }
 
     rule fori_loop() -> Token
@@ -134,41 +223,94 @@ parser!(pub grammar parser() for str {
         { Token::ForI(Box::new(v), Box::new(to), Box::new(step), s) }
 
 
+    // run the statements of the first arm whose pattern matches the value
+    rule match_statement() -> Token
+        = "match" _ e:expression() WHITESPACE() "{" arms:(WHITESPACE() a:match_arm() { a })* WHITESPACE() "}"
+        { Token::Match(Box::new(e), arms) }
+
+    rule match_arm() -> Token
+        = p:pattern() g:guard()? _ "=>" WHITESPACE() s:(block() / s:single_statement() { vec![s] }) (WHITESPACE() ",")?
+        { Token::MatchArm(Box::new(p), g, s) }
+
+    // the value of the first arm whose pattern matches, or null if none do
+    rule match_expression() -> Token
+        = "match" _ e:expression() WHITESPACE() "{" WHITESPACE() arms:(match_value_arm() ** (WHITESPACE() "," WHITESPACE())) (WHITESPACE() ",")? WHITESPACE() "}"
+        { Token::MatchValue(Box::new(e), arms) }
+
+    rule match_value_arm() -> Token
+        = p:pattern() g:guard()? _ "=>" WHITESPACE() e:expression()
+        { Token::MatchArm(Box::new(p), g, vec![e]) }
+
+    rule guard() -> Box<Token>
+        = _ "if" _ e:expression() { Box::new(e) }
+
+    // patterns test the shape of a value and bind the parts of it that are named
+    rule pattern() -> Token
+        = n:bigint() { Token::BigInt(n) }
+        / n:decimal() { Token::Decimal(n) }
+        / f:float() { Token::Float(f) }
+        / i:integer() { Token::Integer(i) }
+        / s:string() { s }
+        / n:null() !IDENTIFIER_CHAR() { n }
+        / b:boolean() !IDENTIFIER_CHAR() { b }
+        / "[" WHITESPACE() items:((WHITESPACE() p:(".." { Token::Rest } / pattern()) WHITESPACE() { p }) ** ",") "]" { Token::Array(items) }
+        / "{" WHITESPACE() kv:((WHITESPACE() k:string() WHITESPACE() ":" WHITESPACE() p:pattern() WHITESPACE() { Token::KeyValuePair(k.to_string(), Box::new(p)) }) ** ",") "}" { Token::Dictionary(kv) }
+        / n:qualified_name() _ "(" items:((_ p:pattern() _ { p }) ** ",") ")" { Token::Object(Box::new(Token::Identifier(n)), items) }
+        / identifier()
+
     rule rtn() -> Token
-        = "return" _ e:expression() { Token::Return(Box::new(e)) }
+        = "return" !IDENTIFIER_CHAR() e:(_ e:expression() { Box::new(e) })? { Token::Return(e) }
 
     rule expression() -> Token = precedence!{
-        a:@ _ "==" _ b:(@) { Token::Eq(Box::new(a), Box::new(b)) }
-        a:@ _ "!=" _ b:(@) { Token::Ne(Box::new(a), Box::new(b)) }
-        a:@ _ "<"  _ b:(@) { Token::Lt(Box::new(a), Box::new(b)) }
-        a:@ _ "<=" _ b:(@) { Token::Le(Box::new(a), Box::new(b)) }
-        a:@ _ ">"  _ b:(@) { Token::Gt(Box::new(a), Box::new(b)) }
-        a:@ _ ">=" _ b:(@) { Token::Ge(Box::new(a), Box::new(b)) }
+        a:(@) _ "&&" _ b:@ { Token::And(Box::new(a), Box::new(b)) }
         --
-        a:@ _ "+" _ b:(@) { Token::Add(Box::new(a), Box::new(b)) }
-        a:@ _ "-" _ b:(@) { Token::Sub(Box::new(a), Box::new(b)) }
+        a:(@) _ "===" _ b:@ { Token::Identical(Box::new(a), Box::new(b)) }
+        a:(@) _ "==" _ b:@ { Token::Eq(Box::new(a), Box::new(b)) }
+        a:(@) _ "!=" _ b:@ { Token::Ne(Box::new(a), Box::new(b)) }
+        a:(@) _ "<=" _ b:@ { chain_comparison(a, b, Token::Le) }
+        a:(@) _ "<"  _ b:@ { chain_comparison(a, b, Token::Lt) }
+        a:(@) _ ">=" _ b:@ { chain_comparison(a, b, Token::Ge) }
+        a:(@) _ ">"  _ b:@ { chain_comparison(a, b, Token::Gt) }
         --
-        a:@ _ "*" _ b:(@) { Token::Mul(Box::new(a), Box::new(b)) }
-        a:@ _ "/" _ b:(@) { Token::Div(Box::new(a), Box::new(b)) }
+        a:(@) _ "+" _ b:@ { Token::Add(Box::new(a), Box::new(b)) }
+        a:(@) _ "-" _ b:@ { Token::Sub(Box::new(a), Box::new(b)) }
+        --
+        a:(@) _ "*" _ b:@ { Token::Mul(Box::new(a), Box::new(b)) }
+        a:(@) _ "//" _ b:@ { Token::FloorDiv(Box::new(a), Box::new(b)) }
+        a:(@) _ "/" _ b:@ { Token::Div(Box::new(a), Box::new(b)) }
         a:@ _ "^" _ b:(@) { Token::Pow(Box::new(a), Box::new(b)) }
         --
+        a:@ "?" { Token::Try(Box::new(a)) }
+        --
         l:literal() { l }
     }
 
+    // a value, followed by the members and method calls of a chain if there are any
     rule literal() -> Token
-        = f:float() { Token::Float(f) }
+        = v:value() chain:("." c:(identifier_chain_item() ++ ".") { c })? {
+            match chain {
+                Some(chain) => Token::DotChain(Box::new(v), chain),
+                None => v
+            }
+        }
+
+    rule value() -> Token
+        = "(" _ e:expression() _ ")" { e }
+        / n:bigint() { Token::BigInt(n) }
+        / n:decimal() { Token::Decimal(n) }
+        / f:float() { Token::Float(f) }
         / i:integer() { Token::Integer(i) }
         / list()
         / dictionary()
+        / s:string() { s }
         / array_index()
         / c:anonfunc() { c }
+        / match_expression()
         / c:call() { c }
-        / c:identifier_chain() { c }
         / new_object_call()
         / n:null() { n }
         / b:boolean() { b }
         / i:identifier() { i }
-        / s:string() { s }
 
 
     rule null() -> Token
@@ -179,7 +321,7 @@ parser!(pub grammar parser() for str {
         / "false" { Token::Bool(false) }
 
     rule new_object_call() -> Token
-        = quiet!{"new" _ i:identifier() "(" args:arg_list() ")" { Token::Object(Box::new(i), args) } }
+        = quiet!{"new" _ n:qualified_name() "(" args:arg_list() ")" { Token::Object(Box::new(Token::Identifier(n)), args) } }
 
     rule arg_list() -> Vec<Token>
         = quiet!{args:((_ e:expression() _ {e}) ** ",") { args } }
@@ -191,6 +333,10 @@ parser!(pub grammar parser() for str {
     rule identifier_as_string() -> String
         = n:$(['a'..='z' | 'A'..='Z' | '_']['a'..='z' | 'A'..='Z' | '0'..='9' | '_']*) { n.to_owned() }
 
+    // identifiers separated by dots such as game.ai.Bot
+    rule qualified_name() -> String
+        = n:$(identifier_as_string() ++ ".") { n.to_owned() }
+
     rule identifier() -> Token
         = n:$(['a'..='z' | 'A'..='Z' | '_']['a'..='z' | 'A'..='Z' | '0'..='9' | '_']*) { Token::Identifier(n.to_owned()) }
         / expected!("identifier")
@@ -201,20 +347,35 @@ parser!(pub grammar parser() for str {
     rule array_index() -> Token
         =  i:identifier() s:square_index() { Token::ArrayIndex(Box::new(i), Box::new(s)) }
 
+    // strings are either """multi-line""" with a leading newline dropped, r#"raw"# which can hold quotes, r"raw" or "plain"
     rule string() -> Token
-        = "\""  n:$([^'"']*) "\""  { Token::String(n.to_owned()) }
+        = "\"\"\"" ("\r\n" / "\n")? n:$((!"\"\"\"" [_])*) "\"\"\"" { Token::String(n.to_owned()) }
+        / "r#\"" n:$((!"\"#" [_])*) "\"#" { Token::String(n.to_owned()) }
+        / "r\"" n:$([^'"']*) "\"" { Token::String(n.to_owned()) }
+        / "\""  n:$([^'"']*) "\""  { Token::String(n.to_owned()) }
 
     rule integer() -> i32
-        = n:$("-"? ['0'..='9']+) { n.parse().unwrap() }
+        = n:$("-"? ['0'..='9']+) {? n.parse().or(Err("integer that fits in 32 bits")) }
 
     rule float() -> f32
-        = n:$("-"? ['0'..='9']+ "." ['0'..='9']+) { n.parse().unwrap() }
+        = n:$("-"? ['0'..='9']+ "." ['0'..='9']+) {? n.parse().or(Err("float")) }
+
+    // exact numbers are written with a suffix, 10n for a bigint and 1.23d for a decimal
+    rule bigint() -> String
+        = n:$("-"? ['0'..='9']+) "n" !['a'..='z' | 'A'..='Z' | '0'..='9' | '_'] { n.to_owned() }
+
+    rule decimal() -> String
+        = n:$("-"? ['0'..='9']+ ("." ['0'..='9']+)?) "d" !['a'..='z' | 'A'..='Z' | '0'..='9' | '_'] { n.to_owned() }
 
     rule list() -> Token
-        = quiet!{ "[" WHITESPACE() elements:(( WHITESPACE() e:expression() _ {e}) ** ",") WHITESPACE() "]" { Token::Array(elements) } }
+        = quiet!{ "[" WHITESPACE() elements:(( WHITESPACE() e:(spread() / expression()) _ {e}) ** ",") WHITESPACE() "]" { Token::Array(elements) } }
 
     rule dictionary() -> Token
-        = "{" WHITESPACE() kv:(( WHITESPACE() k:string() WHITESPACE() ":" WHITESPACE() e:expression() WHITESPACE() {  Token::KeyValuePair(k.to_string(), Box::new(e)) } ) ** ",") WHITESPACE() "}" { Token::Dictionary(kv) }
+        = "{" WHITESPACE() kv:(( WHITESPACE() e:(spread() / k:string() WHITESPACE() ":" WHITESPACE() e:expression() {  Token::KeyValuePair(k.to_string(), Box::new(e)) }) WHITESPACE() { e } ) ** ",") WHITESPACE() "}" { Token::Dictionary(kv) }
+
+    // copy the items of an array or the entries of a dictionary into the literal
+    rule spread() -> Token
+        = "..." _ e:expression() { Token::Spread(Box::new(e)) }
 
 
 
@@ -225,6 +386,30 @@ parser!(pub grammar parser() for str {
     rule NEWLINE() = quiet!{ ['\n'|'\r'] }
     rule NEWLINES() = quiet!{ ['\n'|'\r']* }
     rule WHITESPACE() = quiet!{ [' '|'\t'|'\n'|'\r']* }
+    rule IDENTIFIER_CHAR() = quiet!{ ['a'..='z' | 'A'..='Z' | '0'..='9' | '_'] }
     rule UTF8CHAR() -> char = quiet!{ c:([^ '\x00'..='\x1F' | '\t' | '\n'|'\r']) { c } }
 
 });
+
+// turn 'a < b < c' into a chain of 'a < b' and 'b < c', which the compiler runs with b evaluated once
+fn chain_comparison(left: Token, right: Token, comparison: fn(Box<Token>, Box<Token>) -> Token) -> Token {
+    match left {
+        Token::Chain(mut comparisons) => {
+            let middle = comparisons.last().and_then(ordering_right).expect("a chain should end with an ordering");
+            comparisons.push(comparison(middle, Box::new(right)));
+            Token::Chain(comparisons)
+        }
+        left => match ordering_right(&left) {
+            Some(middle) => Token::Chain(vec![left, comparison(middle, Box::new(right))]),
+            None => comparison(Box::new(left), Box::new(right))
+        }
+    }
+}
+
+// right hand side of an ordering comparison
+fn ordering_right(token: &Token) -> Option<Box<Token>> {
+    match token {
+        Token::Lt(_, b) | Token::Le(_, b) | Token::Gt(_, b) | Token::Ge(_, b) => Some(b.clone()),
+        _ => None
+    }
+}
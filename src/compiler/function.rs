@@ -1,14 +1,115 @@
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::rc::Rc;
-use log::{debug, trace};
-use crate::compiler::compiler::CLASS_CONSTRUCTOR_FUNCTION_NAME;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use core::cell::RefCell;
+use log::warn;
+use crate::collections::HashMap;
+use crate::logging::{debug, trace};
+use crate::compiler::compiler::{CompilerOptions, CLASS_CONSTRUCTOR_FUNCTION_NAME, CLASS_SELF_VARIABLE_NAME, EXPECTATION_CLASS_NAME, EXPECT_FUNCTION_NAME, TRY_CHECK_METHOD_NAME, TRY_UNWRAP_METHOD_NAME};
+use crate::compiler::constant::Defines;
+use crate::compiler::error::CompileError;
+use crate::compiler::peephole;
 use crate::compiler::token::Token;
 use crate::compiler::variable::Variable;
-use crate::vm::instruction::Instruction;
+use crate::prelude::*;
+use crate::vm::builtin;
+use crate::vm::instruction::{max_stack_depth_from, Instruction};
+use crate::vm::program::FunctionMetadata;
 use crate::vm::value::Value;
 
-const CLASS_SELF_VARIABLE_NAME: &str = "this";
+// jump target which is resolved to an instruction position once the function is compiled
+type Label = usize;
+
+// instructions of a compiled function, its metadata and the name of each of its local slots
+pub type Compiled = (Vec<Instruction>, FunctionMetadata, Vec<String>);
+
+// a compiled function with its full name and the names of its parameters
+pub type Named = (String, Vec<String>, Compiled);
+
+// names of the fields of each class in the order they are declared, by the global of the class
+pub type ClassFields = HashMap<usize, Vec<String>>;
+
+// name of slots only used by the compiler, which debuggers leave out
+const TEMP_SLOT_NAME: &str = "$tmp";
+
+// anonymous functions are named after the function they are written in, with this and a number
+const ANONYMOUS_FUNCTION_SUFFIX: &str = "$anon";
+
+// name of the slots that record whether each defer has been reached
+const DEFER_SLOT_NAME: &str = "$defer";
+
+// pattern that matches anything without binding it
+const WILDCARD_PATTERN: &str = "_";
+
+// a loop being compiled, which break and continue jump out of or back round
+struct Loop {
+    name: Option<String>,
+    exit: Label,
+    next: Label,
+}
+
+// a block being compiled
+#[derive(Default)]
+struct Scope {
+    variables: HashMap<String, Variable>,
+    // slots holding values the compiler keeps, such as the array a for in loop walks
+    temps: Vec<usize>,
+}
+
+// Function Declaration
+//
+// a function waiting to be compiled. it is plain data, so declarations can be compiled on other
+// threads
+#[derive(Clone, Debug)]
+pub struct FunctionDecl {
+    class_name: String,
+    name: String,
+    parameters: Vec<Token>,
+    statements: Vec<Token>,
+    // namespaces searched for unqualified class names
+    pub namespaces: Vec<String>,
+    // constants declared with define in the file, None for those below the class
+    pub defines: Arc<Defines>,
+    // calls return a generator instead of running the body
+    pub generator: bool,
+    // the file the function is declared in, for log statements
+    pub script: String,
+    // fields of every class, for class patterns
+    pub fields: Arc<ClassFields>,
+}
+
+impl FunctionDecl {
+
+    pub fn new(class_name: &str, func_name: &str, parameters: Vec<Token>, statements: Vec<Token>) -> Self {
+        FunctionDecl {
+            class_name: class_name.to_string(),
+            name: func_name.to_string(),
+            parameters,
+            statements,
+            namespaces: vec![],
+            defines: Arc::default(),
+            generator: false,
+            script: String::new(),
+            fields: Arc::default(),
+        }
+    }
+
+    // the function, then the anonymous functions written in it
+    pub fn compile(self, global_lookup: HashMap<String, usize>, options: &CompilerOptions) -> Result<Vec<Named>, CompileError> {
+        let mut func = Function::new(&self.class_name, &self.name, self.parameters, self.statements);
+        func.namespaces = self.namespaces;
+        func.defines = self.defines;
+        func.generator = self.generator;
+        func.script = self.script;
+        func.fields = self.fields;
+        func.compile(global_lookup, options)
+    }
+
+    // get name
+    pub fn get_full_name(&self) -> String {
+        format!("{}.{}", self.class_name, self.name)
+    }
+
+}
 
 // Function
 pub struct Function {
@@ -17,16 +118,40 @@ pub struct Function {
     parameters: Vec<Token>,
     statements: Vec<Token>,
     instructions: Vec<Instruction>,
-    anonymous_functions: Vec<Token>,
-    variables: HashMap<String, Variable>,
-    pub globals: HashMap<String, Value>,
-    pub global_lookup: HashMap<String, usize>,
+    // anonymous functions have no 'this', as calling a function value only passes its arguments
+    anonymous: bool,
+    // anonymous functions written in the function, and those written in them
+    lifted: Vec<Named>,
+    // variables of each block being compiled, the function body first and the innermost last
+    scopes: Vec<Scope>,
+    // slots of blocks that have ended, which later declarations reuse
+    free_slots: Vec<usize>,
+    // names held by each slot, for debuggers
+    slots: Vec<String>,
+    // names declared in blocks that have ended
+    ended: Vec<String>,
+    labels: Vec<Option<usize>>,
+    strict: bool,
+    optimize: bool,
+    errors: Vec<String>,
+    global_lookup: HashMap<String, usize>,
+    namespaces: Vec<String>,
+    defines: Arc<Defines>,
+    generator: bool,
+    script: String,
+    fields: Arc<ClassFields>,
+    // loops around the statement being compiled, innermost last
+    loops: Vec<Loop>,
+    // name given to the loop about to be compiled
+    loop_name: Option<String>,
+    // statements deferred by the function, with the slot recording whether each was reached
+    defers: Vec<(usize, Token)>,
 }
 
 
 impl Function {
 
-    pub fn new(class_name: &str, func_name: &str, parameters: Vec<Token>, statements: Vec<Token>) -> Self {
+    fn new(class_name: &str, func_name: &str, parameters: Vec<Token>, statements: Vec<Token>) -> Self {
         trace!("compiling function '{}' in '{}' with parameters {:?}", func_name, class_name, parameters);
 
         // create a new function
@@ -36,86 +161,204 @@ impl Function {
             parameters,
             statements,
             instructions: vec![],
-            anonymous_functions: vec![],
-            variables: Default::default(),
-            globals: Default::default(),
+            anonymous: false,
+            lifted: vec![],
+            scopes: vec![Scope::default()],
+            free_slots: vec![],
+            slots: vec![],
+            ended: vec![],
+            labels: vec![],
+            strict: false,
+            optimize: false,
+            errors: vec![],
             global_lookup: Default::default(),
+            namespaces: vec![],
+            defines: Arc::default(),
+            generator: false,
+            script: String::new(),
+            fields: Arc::default(),
+            loops: vec![],
+            loop_name: None,
+            defers: vec![],
         }
     }
 
-    pub fn compile(mut self, globals: HashMap<String, Value>, global_lookup: HashMap<String, usize>) -> Vec<Instruction> {
+    fn compile(self, global_lookup: HashMap<String, usize>, options: &CompilerOptions) -> Result<Vec<Named>, CompileError> {
+        let full_name = self.get_full_name();
+        self.compile_function(global_lookup, options).map_err(|e| CompileError::Function(full_name, Box::new(e)))
+    }
+
+    fn compile_function(mut self, global_lookup: HashMap<String, usize>, options: &CompilerOptions) -> Result<Vec<Named>, CompileError> {
 
         // store the globals
-        self.globals = globals;
         self.global_lookup = global_lookup;
-
-        // if there are no statements then return
-        if self.statements.is_empty() {
-            return vec![Instruction::Return(false)];
-        }
+        self.strict = options.strict;
+        self.optimize = options.optimize;
+        let full_name = self.get_full_name();
+        let parameter_names = self.parameters.iter().map(|p| p.to_string()).collect();
 
         // add the 'this' parameter
-        self.parameters.insert(0, Token::Identifier(CLASS_SELF_VARIABLE_NAME.to_string()));
+        if !self.anonymous {
+            self.parameters.insert(0, Token::Identifier(CLASS_SELF_VARIABLE_NAME.to_string()));
+        }
 
         // store the parameters as variables
-        self.add_parameters(self.parameters.clone());
+        self.add_parameters(self.parameters.clone())?;
 
         // compile the statements
-        self.compile_statements(self.statements.clone().as_slice());
+        self.compile_statements(self.statements.clone().as_slice())?;
 
-        // if tha last instruction is not a return then add one
-        if matches!(self.instructions.last(), Some(Instruction::Return(_))) == false {
+        // return null from the end of the function, unless it ends with a return that nothing
+        // jumps past, such as the last return of an if without an else
+        let end = self.instructions.len();
+        if !matches!(self.instructions.last(), Some(Instruction::Return(_))) || self.labels.contains(&Some(end)) {
             self.instructions.push(Instruction::Return(false));
         }
 
-        self.instructions
+        // deferred statements follow the body, and are only run by the vm as the function leaves
+        let cleanup = match self.defers.is_empty() {
+            true => None,
+            false => Some(self.compile_cleanup()?),
+        };
+
+        // strict mode turns warnings into errors
+        if !self.errors.is_empty() {
+            return Err(CompileError::Strict(self.errors));
+        }
+
+        // point jumps at their targets
+        self.resolve_labels();
+
+        // fuse common sequences into superinstructions
+        let mut cleanup = cleanup;
+        if options.optimize {
+            let (fused, positions) = peephole::fuse(core::mem::take(&mut self.instructions));
+            self.instructions = fused;
+            cleanup = cleanup.map(|cleanup| positions[cleanup]);
+        }
+
+        // record the frame size needed to run the function
+        let entries: Vec<usize> = [0].into_iter().chain(cleanup).collect();
+        let metadata = FunctionMetadata {
+            local_slots: self.slots.len(),
+            max_stack_depth: max_stack_depth_from(&self.instructions, &entries),
+            generator: self.generator,
+            cleanup,
+        };
+
+        let mut compiled = vec![(full_name, parameter_names, (self.instructions, metadata, self.slots))];
+        compiled.append(&mut self.lifted);
+        Ok(compiled)
     }
 
-    fn add_parameters(&mut self, parameters: Vec<Token>) {
+    fn add_parameters(&mut self, parameters: Vec<Token>) -> Result<(), CompileError> {
         for param in parameters {
             let pname = param.to_string();
             trace!("storing parameter as variable '{}'", pname);
-            self.add_variable(pname, Value::Null);
+            self.add_variable(pname)?;
         }
+
+        Ok(())
     }
 
     // get name
-    pub fn get_full_name(&self) -> String {
-        return format!("{}.{}", self.class_name, self.name);
+    fn get_full_name(&self) -> String {
+        format!("{}.{}", self.class_name, self.name)
     }
 
     // compile a list of statements
-    fn compile_statements(&mut self, statements: &[Token]) {
+    fn compile_statements(&mut self, statements: &[Token]) -> Result<(), CompileError> {
         for statement in statements {
-            self.compile_statement(statement);
+            self.compile_statement(statement)?;
         }
+
+        Ok(())
     }
 
     // compile a statement
-    fn compile_statement(&mut self, statement: &Token) {
+    fn compile_statement(&mut self, statement: &Token) -> Result<(), CompileError> {
         match statement {
-            Token::Assert(exp) => self.compile_assert(exp),
-            Token::Print(exp) => self.compile_print(exp),
-            Token::Call(name, args) => self.compile_call(name, args),
-            Token::Variable(left, right) => self.compile_variable(left, right),
-            Token::Assign(left, right) => self.compile_assignment(left, right),
-            Token::IfElse(expr, then_body, else_body) => self.compile_ifelse(expr, then_body, else_body),
-            Token::WhileLoop(expr, statements) => self.compile_whileloop(expr, statements),
-            Token::ForEach(item, array, stmts) => self.compile_foreach(item, array, stmts),
-            Token::Return(expr) => self.compile_return(expr),
-            Token::ForI(start, end, step, stmts) => self.compile_forloop(start, end, step, stmts),
-            Token::DotChain(start, chain) => self.compile_chain(start, chain),
-            Token::Comment(_) => {},
-            _ => unimplemented!("statement not implemented: {:?}", statement)
+            Token::Assert(exp) => self.compile_assert(exp)?,
+            Token::Print(exp) => self.compile_print(exp)?,
+            Token::Log(level, line, exp) => self.compile_log(level, *line, exp)?,
+            Token::Call(name, args) => {
+                self.compile_call(name, args)?;
+                self.instructions.push(Instruction::Pop);
+            },
+            Token::Variable(left, right) => self.compile_variable(left, right)?,
+            Token::Assign(left, right) => self.compile_assignment(left, right)?,
+            Token::IfElse(expr, then_body, else_body) => self.compile_ifelse(expr, then_body, else_body)?,
+            Token::WhileLoop(expr, statements) => self.compile_whileloop(expr, statements)?,
+            Token::DoWhileLoop(statements, expr) => self.compile_dowhileloop(statements, expr)?,
+            Token::LabeledLoop(name, statement) => {
+                self.loop_name = Some(name.to_string());
+                self.compile_statement(statement)?;
+            },
+            Token::Break(name) => {
+                let exit = self.find_loop("break", name)?.exit;
+                self.instructions.push(Instruction::Jump(exit));
+            },
+            Token::Continue(name) => {
+                let next = self.find_loop("continue", name)?.next;
+                self.instructions.push(Instruction::Jump(next));
+            },
+            Token::ForEach(item, array, stmts) => self.compile_foreach(item, array, stmts)?,
+            Token::Return(expr) => self.compile_return(expr)?,
+            Token::Yield(expr) => self.compile_yield(expr)?,
+            Token::Defer(statement) => self.compile_defer(statement)?,
+            Token::ForI(start, end, step, stmts) => self.compile_forloop(start, end, step, stmts)?,
+            Token::Match(value, arms) => self.compile_match(value, arms, false)?,
+            Token::DotChain(start, chain) => {
+                self.compile_chain(start, chain)?;
+                self.instructions.push(Instruction::Pop);
+            },
+            Token::Comment(text) => trace!("skipping comment {}", text),
+            _ => return Err(CompileError::UnsupportedToken(format!("{:?}", statement)))
         }
+
+        Ok(())
+    }
+
+    // log a message with where it was logged from, unless the script has its own Log
+    fn compile_log(&mut self, level: &str, line: usize, exp: &Token) -> Result<(), CompileError> {
+        let module = builtin::LOG_MODULE_NAME;
+        if self.variable_declared(module) || self.resolve_global(module).is_some() {
+            let call = Token::Call(Box::new(Token::Identifier(level.to_string())), vec![exp.clone()]);
+            return self.compile_statement(&Token::DotChain(Box::new(Token::Identifier(module.to_string())), vec![call]));
+        }
+
+        self.compile_expression(exp)?;
+        self.instructions.push(Instruction::StackPush(Value::from(self.script.clone())));
+        self.instructions.push(Instruction::StackPush(Value::from(self.get_full_name())));
+        self.instructions.push(Instruction::StackPush(Value::Integer(line.try_into().unwrap_or(i32::MAX))));
+        self.instructions.push(Instruction::CallBuiltin(format!("{}.{}", module, level), 4));
+        self.instructions.push(Instruction::Pop);
+        Ok(())
     }
 
     // compile a chain of statements
-    fn compile_chain(&mut self, start: &Token, chain: &[Token]) {
+    fn compile_chain(&mut self, start: &Token, chain: &[Token]) -> Result<(), CompileError> {
+
+        // calls on a builtin module are run by the vm
+        if let (Token::Identifier(module), Some(Token::Call(name, args))) = (start, chain.first()) {
+            if !self.variable_declared(module) && self.resolve_global(module).is_none() && builtin::is_builtin_module(module) {
+                for arg in args {
+                    self.compile_expression(arg)?;
+                }
+                self.instructions.push(Instruction::CallBuiltin(format!("{}.{}", module, name), args.len()));
+                return self.compile_chain_items(&chain[1..]);
+            }
+        }
 
         // load the start of the chain
         trace!("compiling chain start {:?}", start);
-        self.compile_expression(start);
+        self.compile_expression(start)?;
+
+        self.compile_chain_items(chain)
+    }
+
+    // compile the members and method calls that follow the start of a chain
+    fn compile_chain_items(&mut self, chain: &[Token]) -> Result<(), CompileError> {
 
         // for each item in chain
         for item in chain {
@@ -125,75 +368,71 @@ impl Function {
             // push load object member instruction onto stack
             match item {
                 Token::Identifier(name) => {
-                    self.instructions.push(Instruction::StackPush(Value::String(name.to_string())));
+                    self.instructions.push(Instruction::StackPush(Value::from(name.to_string())));
+                    self.instructions.push(Instruction::GetCollectionItemByKey);
+                },
+                Token::ArrayIndex(name, index) => {
+                    self.instructions.push(Instruction::StackPush(Value::from(name.to_string())));
+                    self.instructions.push(Instruction::GetCollectionItemByKey);
+                    self.compile_expression(index)?;
                     self.instructions.push(Instruction::GetCollectionItemByKey);
                 },
-                Token::Call(name, args) => self.compile_call(name, args),
-                // Token::Call(name, args) => {
-                //
-                //     // load the object member
-                //     trace!("loading object member {:?}", name);
-                //     self.instructions.push(Instruction::StackPush(Value::String(name.to_string())));
-                //     self.instructions.push(Instruction::GetKeyValue);
-                //
-                //     // push 'this' onto stack
-                //     let variable = self.get_variable(start.to_string());
-                //     self.instructions.push(Instruction::LoadLocalVariable(variable.index));
-                //
-                //     // compile the arguments
-                //     for arg in args {
-                //         self.compile_expression(arg);
-                //     }
-                //
-                //     // call the function
-                //     trace!("calling function with {} args", args.len());
-                //     self.instructions.push(Instruction::Call(args.len() + 1));
-                //
-                // },
-                _ => unreachable!("chain item is not a variable or index")
+                Token::Call(name, args) => self.compile_method_call(name, args)?,
+                _ => return Err(CompileError::InvalidChain)
             }
 
         }
 
+        Ok(())
     }
 
     // compile an assert statement
-    fn compile_assert(&mut self, exp: &Box<Token>) {
-        self.compile_expression(exp);
+    fn compile_assert(&mut self, exp: &Token) -> Result<(), CompileError> {
+        self.compile_expression(exp)?;
         trace!("asserting");
         self.instructions.push(Instruction::Assert);
+
+        Ok(())
     }
 
     // compile a variable declaration
-    fn compile_variable(&mut self, name: &Box<Token>, value: &Box<Token>) {
+    fn compile_variable(&mut self, name: &Token, value: &Token) -> Result<(), CompileError> {
 
         // Declare variable
-        self.add_variable(name.to_string(), Value::Null);
-        let slot = self.get_variable(name.to_string()).index;
+        self.add_variable(name.to_string())?;
+        let slot = self.get_variable(name.to_string())?.index;
 
         // compile the value
-        self.compile_expression(value);
+        self.compile_expression(value)?;
 
         // store the value
         self.instructions.push(Instruction::MoveToLocalVariable(slot));
+
+        Ok(())
     }
 
     // compile assignment
-    fn compile_assignment(&mut self, left: &Box<Token>, right: &Box<Token>) {
+    fn compile_assignment(&mut self, left: &Token, right: &Token) -> Result<(), CompileError> {
 
         debug!("compiling assignment {:?} = {:?}", left, right);
 
-        match *left.clone() {
+        match left.clone() {
 
             // store value in variable
             Token::Identifier(name) => {
-                trace!("storing value in variable {}", name.to_string());
+                trace!("storing value in variable {}", name);
+
+                // declare the variable if this is the first time it is seen
+                if !self.variable_declared(&name) {
+                    self.add_undeclared_variable(name.to_string())?;
+                    self.warning(format!("assignment to undeclared variable '{}'", name));
+                }
 
                 // get the variable slot
-                let slot = self.get_variable(name.to_string()).index;
+                let slot = self.get_variable(name.to_string())?.index;
 
                 // compile the value
-                self.compile_expression(right);
+                self.compile_expression(right)?;
 
                 // store the value
                 self.instructions.push(Instruction::MoveToLocalVariable(slot));
@@ -202,242 +441,432 @@ impl Function {
             Token::DotChain(start, mut chain) => {
 
                 // remove last item from chain
-                let last_item = chain.pop().expect("chain to have at least one item");
+                let last_item = chain.pop().ok_or(CompileError::InvalidAssignment)?;
 
-                self.compile_chain(&start, chain.as_slice());
-                self.compile_expression(right);
+                self.compile_chain(&start, chain.as_slice())?;
 
                 match last_item {
                     Token::Identifier(name) => {
-                        self.instructions.push(Instruction::StackPush(Value::String(name.to_string())));
+                        self.compile_expression(right)?;
+                        self.instructions.push(Instruction::StackPush(Value::from(name.to_string())));
                         self.instructions.push(Instruction::SetCollectionItemByKey);
                     },
 
-                    // fixme
+                    // load the indexed collection and then set the item
                     Token::ArrayIndex(name, index) => {
-                        self.instructions.push(Instruction::StackPush(Value::String(name.to_string())));
+                        self.instructions.push(Instruction::StackPush(Value::from(name.to_string())));
+                        self.instructions.push(Instruction::GetCollectionItemByKey);
+                        self.compile_expression(right)?;
+                        self.compile_expression(&index)?;
                         self.instructions.push(Instruction::SetCollectionItemByKey);
                     },
-                    _ => panic!("last item in chain is not a variable or index")
+                    _ => return Err(CompileError::InvalidAssignment)
                 }
 
             },
 
             // store value in array index
             Token::ArrayIndex(name, index) => {
-                trace!("storing value in index {:?} of {}", index, name.to_string());
+                trace!("storing value in index {:?} of {}", index, name);
 
                 // load the variable
-                let slot = self.get_variable(name.to_string()).index;
+                let slot = self.get_variable(name.to_string())?.index;
                 self.instructions.push(Instruction::LoadLocalVariable(slot));
 
                 // compile the value
-                self.compile_expression(right);
+                self.compile_expression(right)?;
 
                 // compile the index
-                self.compile_expression(&index.clone());
+                self.compile_expression(&index.clone())?;
 
                 // add value to array
                 self.instructions.push(Instruction::SetCollectionItemByKey);
-
-                // update variable
-                self.instructions.push(Instruction::MoveToLocalVariable(slot));
             },
 
-            _ => panic!("name is not an identifier or index")
+            _ => return Err(CompileError::InvalidAssignment)
         }
 
+        Ok(())
     }
 
     // compile for loop
-    fn compile_forloop(&mut self, start: &Box<Token>, end: &Box<Token>, step: &Box<Token>, block: &[Token]) {
+    fn compile_forloop(&mut self, start: &Token, end: &Token, step: &Token, block: &[Token]) -> Result<(), CompileError> {
 
         trace!("compiling for loop");
 
-        // compile start
-        self.compile_statement(start);
+        let start_of_loop = self.new_label();
+        let end_of_loop = self.new_label();
+        let step_of_loop = self.new_label();
+
+        // the variable declared at the start belongs to the loop
+        self.enter_scope();
+        self.compile_statement(start)?;
 
         // Mark instruction pointer
-        let start_of_loop = self.instructions.len();
+        self.mark_label(start_of_loop);
 
         // Compile expression
-        self.compile_expression(&end);
+        self.compile_expression(end)?;
 
         // Jump to end if expression is false
-        let jump_not_true = self.instructions.len();
-        self.instructions.push(Instruction::Halt(String::from("no jump-not-true provided")));
+        self.instructions.push(Instruction::JumpIfFalse(end_of_loop));
 
-        // Compile statements inside loop block
-        self.compile_statements(block);
+        // Compile statements inside loop block, continue goes on to the step
+        self.compile_loop_body(block, end_of_loop, step_of_loop)?;
 
         // compile step
-        self.compile_statement(step);
+        self.mark_label(step_of_loop);
+        self.compile_statement(step)?;
 
         // Goto loop start
-        self.instructions.push(Instruction::JumpBackward(self.instructions.len() - start_of_loop));
+        self.instructions.push(Instruction::Jump(start_of_loop));
 
-        // Update jump not true value
-        let jump_to_pos = self.instructions.len() - jump_not_true;
-        self.instructions[jump_not_true] = Instruction::JumpIfFalse(jump_to_pos as i32);
+        self.mark_label(end_of_loop);
+        self.exit_scope();
 
+        Ok(())
     }
 
     // compile while loop
-    fn compile_whileloop(&mut self, expr: &Box<Token>, block: &[Token]) {
+    fn compile_whileloop(&mut self, expr: &Token, block: &[Token]) -> Result<(), CompileError> {
         trace!("compiling while loop");
 
+        let start_of_loop = self.new_label();
+        let end_of_loop = self.new_label();
+
         // Mark instruction pointer
-        let start_ins_ptr = self.instructions.len();
+        self.mark_label(start_of_loop);
 
         // Compile expression
-        self.compile_expression(&expr);
+        self.compile_expression(expr)?;
 
         // Jump to end if expression is false
-        let jump_not_true = self.instructions.len();
-        self.instructions.push(Instruction::Halt(String::from("no jump-not-true provided")));
+        self.instructions.push(Instruction::JumpIfFalse(end_of_loop));
 
         // Compile statements inside loop block
-        self.compile_statements(block);
+        self.compile_loop_body(block, end_of_loop, start_of_loop)?;
 
         // Goto loop start
-        self.instructions.push(Instruction::JumpBackward(self.instructions.len() - start_ins_ptr));
+        self.instructions.push(Instruction::Jump(start_of_loop));
+
+        self.mark_label(end_of_loop);
+
+        Ok(())
+    }
+
+    // compile do while loop
+    fn compile_dowhileloop(&mut self, block: &[Token], expr: &Token) -> Result<(), CompileError> {
+        trace!("compiling do while loop");
+
+        let start_of_loop = self.new_label();
+        let end_of_loop = self.new_label();
+        let condition = self.new_label();
+
+        // Mark instruction pointer
+        self.mark_label(start_of_loop);
 
-        // Update jump not true value
-        let jump_to_pos = self.instructions.len() - jump_not_true;
-        self.instructions[jump_not_true] = Instruction::JumpIfFalse(jump_to_pos as i32);
+        // Compile statements inside loop block, continue goes on to the condition
+        self.compile_loop_body(block, end_of_loop, condition)?;
 
+        // Compile expression
+        self.mark_label(condition);
+        self.compile_expression(expr)?;
+
+        // Leave the loop if expression is false, otherwise go round again
+        self.instructions.push(Instruction::JumpIfFalse(end_of_loop));
+        self.instructions.push(Instruction::Jump(start_of_loop));
+
+        self.mark_label(end_of_loop);
+
+        Ok(())
     }
 
     // compile for each loop
-    fn compile_foreach(&mut self, item: &Box<Token>, array: &Box<Token>, block: &[Token]) {
+    fn compile_foreach(&mut self, item: &Token, array: &Token, block: &[Token]) -> Result<(), CompileError> {
         trace!("compiling for each");
 
-        // // Find or create variables
-        // let array = self.get_variable_slot(array.to_string());
-        // let item = self.add_variable(item.to_string());
-        // let arraylen = self.create_temp_variable();
-        // let array_idx = self.create_temp_variable();
-        //
-        // // Get array length
-        // self.instructions.push(Instruction::LoadLocalVariable(array));
-        // self.instructions.push(Instruction::ArrayLength);
-        // self.instructions.push(Instruction::MoveToLocalVariable(arraylen));
-        //
-        // // Store index in tmp variable
-        // self.instructions.push(Instruction::StackPush(Value::Integer(0)));
-        // self.instructions.push(Instruction::MoveToLocalVariable(array_idx));
-        //
-        // // Start of loop
-        // let start_ins_ptr = self.instructions.len();
-        //
-        // // Update item value
-        // self.instructions.push(Instruction::LoadLocalVariable(array));
-        // self.instructions.push(Instruction::LoadLocalVariable(array_idx));
-        // self.instructions.push(Instruction::LoadIndexedValue);
-        // self.instructions.push(Instruction::MoveToLocalVariable(item));
-        //
-        // // Compile statements inside loop block
-        // self.compile_statements(block);
-        //
-        // // Increment index
-        // self.instructions.push(Instruction::LoadLocalVariable(array_idx));
-        // self.instructions.push(Instruction::StackPush(Value::Integer(1)));
-        // self.instructions.push(Instruction::Add);
-        // self.instructions.push(Instruction::MoveToLocalVariable(array_idx));
-        //
-        // // Jump if not equal
-        // self.instructions.push(Instruction::LoadLocalVariable(arraylen));
-        // self.instructions.push(Instruction::LoadLocalVariable(array_idx));
-        // self.instructions.push(Instruction::Equal);
-        // let jump_to_pos = start_ins_ptr as i32 - self.instructions.len() as i32;
-        // self.instructions.push(Instruction::JumpIfFalse(jump_to_pos as i32));
+        let start_of_loop = self.new_label();
+        let end_of_loop = self.new_label();
+        let next_item = self.new_label();
+        let after_loop = self.new_label();
+
+        // Create variables, which belong to the loop
+        self.enter_scope();
+        self.add_variable(item.to_string())?;
+        let item = self.get_variable(item.to_string())?.index;
+        let array_var = self.add_temp_variable();
+        let array_idx = self.add_temp_variable();
+
+        // Store array in tmp variable
+        self.compile_expression(array)?;
+        self.instructions.push(Instruction::MoveToLocalVariable(array_var));
+
+        // Store index in tmp variable
+        self.instructions.push(Instruction::StackPush(Value::Integer(0)));
+        self.instructions.push(Instruction::MoveToLocalVariable(array_idx));
+
+        // Start of loop
+        self.mark_label(start_of_loop);
+
+        // Get the next item, jumping to the end when there are none left
+        self.instructions.push(Instruction::LoadLocalVariable(array_var));
+        self.instructions.push(Instruction::LoadLocalVariable(array_idx));
+        self.instructions.push(Instruction::IterNext);
+        self.instructions.push(Instruction::JumpIfFalse(end_of_loop));
 
+        // Update item value
+        self.instructions.push(Instruction::MoveToLocalVariable(item));
+
+        // Compile statements inside loop block, break skips the missing item left by the last IterNext
+        self.compile_loop_body(block, after_loop, next_item)?;
+
+        // Increment index
+        self.mark_label(next_item);
+        self.instructions.push(Instruction::LoadLocalVariable(array_idx));
+        self.instructions.push(Instruction::StackPush(Value::Integer(1)));
+        self.instructions.push(Instruction::Add);
+        self.instructions.push(Instruction::MoveToLocalVariable(array_idx));
+
+        // Goto loop start
+        self.instructions.push(Instruction::Jump(start_of_loop));
+
+        // Discard the missing item
+        self.mark_label(end_of_loop);
+        self.instructions.push(Instruction::Pop);
+        self.mark_label(after_loop);
+        self.exit_scope();
+
+        Ok(())
     }
 
 
     // compile if statement
-    fn compile_ifelse(&mut self, expr: &Box<Token>, then_body: &[Token], else_body: &Option<Vec<Token>>) {
+    fn compile_ifelse(&mut self, expr: &Token, then_body: &[Token], else_body: &Option<Vec<Token>>) -> Result<(), CompileError> {
         trace!("compiling ifelse");
 
+        let else_label = self.new_label();
+
         // Compile If Statement
-        self.compile_expression(&expr);
+        self.compile_expression(expr)?;
 
         // Jump to Else if not True
-        let jump_to_else= self.instructions.len();
-        self.instructions.push(Instruction::Halt(String::from("no where to jump to")));
+        self.instructions.push(Instruction::JumpIfFalse(else_label));
 
         // Compile Statements for True
-        self.compile_statements(then_body);
-        let jump_to_end= self.instructions.len();
-        self.instructions.push(Instruction::Halt(String::from("can not jump tot end")));
-
-        // Update Else Jump
-        let jump_to_pos = self.instructions.len() - jump_to_else;
-        self.instructions[jump_to_else] = Instruction::JumpIfFalse(jump_to_pos as i32);
+        self.compile_block(then_body)?;
 
         match else_body {
-            None => {}
+            None => self.mark_label(else_label),
             Some(els) => {
-                let _ = self.compile_statements(els.as_slice());
+                let end_label = self.new_label();
+
+                // Skip over the else block
+                self.instructions.push(Instruction::Jump(end_label));
+
+                self.mark_label(else_label);
+                self.compile_block(els.as_slice())?;
+                self.mark_label(end_label);
             }
         }
 
-        // Update Jump to End
-        self.instructions[jump_to_end] = Instruction::JumpForward(self.instructions.len() - jump_to_end);
+        Ok(())
     }
 
-    fn compile_new_object(&mut self, class_name: String, params: &[Token]) {
-        trace!("class = {:?}, params = {:?}", class_name, params);
+    // compile a match, which tries the arms in order and runs the first whose pattern and guard
+    // hold. a match expression leaves the value of that arm, or null when none match
+    fn compile_match(&mut self, value: &Token, arms: &[Token], has_value: bool) -> Result<(), CompileError> {
+        trace!("compiling match");
+
+        let end = self.new_label();
+
+        // keep the value in a variable for the patterns to test
+        self.enter_scope();
+        let subject = self.add_temp_variable();
+        self.compile_expression(value)?;
+        self.instructions.push(Instruction::MoveToLocalVariable(subject));
+
+        for arm in arms {
+            let Token::MatchArm(pattern, guard, body) = arm else {
+                return Err(CompileError::UnsupportedToken(format!("{:?}", arm)));
+            };
+            let next_arm = self.new_label();
+
+            // the names an arm binds are only seen by its guard and body
+            self.enter_scope();
+            self.compile_pattern(pattern, subject, next_arm)?;
+            if let Some(guard) = guard {
+                self.compile_expression(guard)?;
+                self.instructions.push(Instruction::JumpIfFalse(next_arm));
+            }
 
-        // find class
-        let global_id = self.global_lookup.get(&class_name).unwrap();
+            match has_value {
+                true => self.compile_expression(body.first().ok_or_else(|| CompileError::InvalidPattern(String::from("match arm has no value")))?)?,
+                false => self.compile_statements(body)?
+            }
+            self.exit_scope();
+            self.instructions.push(Instruction::Jump(end));
+            self.mark_label(next_arm);
+        }
 
-        // load global
-        self.instructions.push(Instruction::LoadGlobal(*global_id));
+        if has_value {
+            self.instructions.push(Instruction::StackPush(Value::Null));
+        }
+        self.mark_label(end);
+        self.exit_scope();
 
-        // create object
-        self.instructions.push(Instruction::CreateObject);
+        Ok(())
+    }
+
+    // test the value in a slot against a pattern, jumping to fail if it does not match and
+    // otherwise storing the parts the pattern names in their variables
+    fn compile_pattern(&mut self, pattern: &Token, slot: usize, fail: Label) -> Result<(), CompileError> {
+        match pattern {
 
-        // store object in temp variable
-        let tmp_name = format!("tmp{}", self.instructions.len());
-        self.add_variable(tmp_name.clone(), Value::Null);
-        let obj_var = self.get_variable(tmp_name.clone()).index;
-        self.instructions.push(Instruction::CopyToLocalVariable(obj_var));
+            Token::Identifier(name) if name == WILDCARD_PATTERN => {}
 
-        // load constructor functionref
-        self.instructions.push(Instruction::StackPush(Value::String(CLASS_CONSTRUCTOR_FUNCTION_NAME.parse().unwrap())));
+            // a name matches anything and binds it, hiding any variable with the same name
+            Token::Identifier(name) => {
+                if !self.scopes.last().is_some_and(|scope| scope.variables.contains_key(name)) {
+                    self.add_variable(name.to_string())?;
+                }
+                let variable = self.get_variable(name.to_string())?.index;
+                self.instructions.push(Instruction::LoadLocalVariable(slot));
+                self.instructions.push(Instruction::MoveToLocalVariable(variable));
+            }
+
+            Token::Null | Token::Integer(_) | Token::Float(_) | Token::BigInt(_) | Token::Decimal(_) | Token::Bool(_) | Token::String(_) => {
+                self.instructions.push(Instruction::LoadLocalVariable(slot));
+                self.compile_expression(pattern)?;
+                self.instructions.push(Instruction::Equal);
+                self.instructions.push(Instruction::JumpIfFalse(fail));
+            }
+
+            // [a, b] matches arrays of two items, and [a, ..] those of at least one
+            Token::Array(items) => {
+                let at_least = matches!(items.last(), Some(Token::Rest));
+                let items = if at_least { &items[..items.len() - 1] } else { &items[..] };
+                if items.iter().any(|item| matches!(item, Token::Rest)) {
+                    return Err(CompileError::InvalidPattern(String::from("'..' can only end an array pattern")));
+                }
+
+                self.instructions.push(Instruction::LoadLocalVariable(slot));
+                self.instructions.push(Instruction::IsArray(items.len(), at_least));
+                self.instructions.push(Instruction::JumpIfFalse(fail));
+
+                for (index, item) in items.iter().enumerate() {
+                    self.compile_part_pattern(item, slot, Value::Integer(index as i32), fail)?;
+                }
+            }
+
+            // {"key": p} matches dictionaries and objects with the key, whatever else they hold
+            Token::Dictionary(pairs) => {
+                for pair in pairs {
+                    let Token::KeyValuePair(key, item) = pair else {
+                        return Err(CompileError::UnsupportedToken(format!("{:?}", pair)));
+                    };
+                    self.instructions.push(Instruction::LoadLocalVariable(slot));
+                    self.instructions.push(Instruction::HasKey(key.to_string()));
+                    self.instructions.push(Instruction::JumpIfFalse(fail));
+                    self.compile_part_pattern(item, slot, Value::from(key.to_string()), fail)?;
+                }
+            }
+
+            // Point(x, y) matches objects of the class, with its fields in the order they are declared
+            Token::Object(class_name, items) => {
+                let global = self.get_global(class_name.to_string())?;
+                let fields = self.fields.get(&global).cloned().unwrap_or_default();
+                if items.len() > fields.len() {
+                    return Err(CompileError::InvalidPattern(format!("class '{}' has {} fields but the pattern has {}", class_name, fields.len(), items.len())));
+                }
+
+                self.instructions.push(Instruction::LoadLocalVariable(slot));
+                self.instructions.push(Instruction::LoadGlobal(global));
+                self.instructions.push(Instruction::IsInstance);
+                self.instructions.push(Instruction::JumpIfFalse(fail));
+
+                for (item, field) in items.iter().zip(fields) {
+                    self.compile_part_pattern(item, slot, Value::from(field), fail)?;
+                }
+            }
+
+            _ => return Err(CompileError::InvalidPattern(format!("{:?} is not a pattern", pattern)))
+        }
+
+        Ok(())
+    }
+
+    // test the item of the value in a slot against a pattern
+    fn compile_part_pattern(&mut self, pattern: &Token, slot: usize, key: Value, fail: Label) -> Result<(), CompileError> {
+        if matches!(pattern, Token::Identifier(name) if name == WILDCARD_PATTERN) {
+            return Ok(());
+        }
+
+        let part = self.add_temp_variable();
+        self.instructions.push(Instruction::LoadLocalVariable(slot));
+        self.instructions.push(Instruction::StackPush(key));
         self.instructions.push(Instruction::GetCollectionItemByKey);
+        self.instructions.push(Instruction::MoveToLocalVariable(part));
+        self.compile_pattern(pattern, part, fail)?;
+
+        // the parts the pattern binds have been copied out, so the next part can use the slot
+        self.free_temp_variable(part);
+
+        Ok(())
+    }
+
+    fn compile_new_object(&mut self, class_name: String, params: &[Token]) -> Result<(), CompileError> {
+        trace!("class = {:?}, params = {:?}", class_name, params);
+
+        // builtin classes are created by the vm
+        if self.resolve_global(&class_name).is_none() && builtin::is_builtin_class(&class_name) {
+            for param in params {
+                self.compile_expression(param)?;
+            }
+            self.instructions.push(Instruction::CreateBuiltinObject(class_name, params.len()));
+            return Ok(());
+        }
 
-        // load object
-        self.instructions.push(Instruction::LoadLocalVariable(obj_var));
+        // find class
+        let global_id = self.get_global(class_name)?;
+
+        // load global
+        self.instructions.push(Instruction::LoadGlobal(global_id));
+
+        // create object and keep a copy under the constructor call
+        self.instructions.push(Instruction::CreateObject);
+        self.instructions.push(Instruction::Dup);
 
         // load params
         for param in params {
-            self.compile_expression(param);
+            self.compile_expression(param)?;
         }
 
-        // call constructor
-        self.instructions.push(Instruction::Call(params.len() + 1));
-
-        // load object for assignment
-        self.instructions.push(Instruction::LoadLocalVariable(obj_var));
+        // call constructor and discard its return value, leaving the object
+        self.instructions.push(Instruction::CallMethod(CLASS_CONSTRUCTOR_FUNCTION_NAME.to_string(), params.len()));
+        self.instructions.push(Instruction::Pop);
 
+        Ok(())
     }
 
 
     // compile expression
-    fn compile_expression(&mut self, token: &Token) {
+    fn compile_expression(&mut self, token: &Token) -> Result<(), CompileError> {
         match token {
 
-            // todo
+            // compiled as a function of its own, which sees the globals but not the variables
+            // around it, and pushed as a reference to that function
             Token::AnonFunction(params, statements) => {
-                let func_name = format!("func{}", self.instructions.len());
-
-                // add function to anon functions
-                self.anonymous_functions.push(Token::Function(func_name.clone(), params.clone(), statements.clone()));
-
-                // Push ref to function
-                self.instructions.push(Instruction::StackPush(Value::FunctionRef(func_name)));
+                let name = format!("{}{}{}", self.name, ANONYMOUS_FUNCTION_SUFFIX, self.lifted.len());
+                let mut func = Function::new(&self.class_name, &name, params.clone(), statements.clone());
+                func.anonymous = true;
+                func.namespaces = self.namespaces.clone();
+                func.defines = self.defines.clone();
+                func.script = self.script.clone();
+                func.fields = self.fields.clone();
+                let options = CompilerOptions { strict: self.strict, optimize: self.optimize, ..Default::default() };
+                let full_name = func.get_full_name();
+                self.lifted.extend(func.compile(self.global_lookup.clone(), &options)?);
+                self.instructions.push(Instruction::StackPush(Value::FunctionRef(Rc::new(full_name))));
             }
 
             Token::Null => {
@@ -455,6 +884,26 @@ impl Function {
                 self.instructions.push(Instruction::StackPush(Value::Float(*v)));
             }
 
+            #[cfg(feature = "bigint")]
+            Token::BigInt(v) => {
+                trace!("pushing {:?} onto stack", token);
+                let num = v.parse().map_err(|_| CompileError::UnsupportedToken(format!("{:?}", token)))?;
+                self.instructions.push(Instruction::StackPush(Value::BigInt(Rc::new(num))));
+            }
+
+            #[cfg(feature = "decimal")]
+            Token::Decimal(v) => {
+                trace!("pushing {:?} onto stack", token);
+                let num = v.parse().map_err(|_| CompileError::UnsupportedToken(format!("{:?}", token)))?;
+                self.instructions.push(Instruction::StackPush(Value::Decimal(Rc::new(num))));
+            }
+
+            #[cfg(not(feature = "bigint"))]
+            Token::BigInt(v) => return Err(CompileError::FeatureNotEnabled(String::from("bigint"), format!("{}n", v))),
+
+            #[cfg(not(feature = "decimal"))]
+            Token::Decimal(v) => return Err(CompileError::FeatureNotEnabled(String::from("decimal"), format!("{}d", v))),
+
             Token::Bool(v) => {
                 trace!("pushing {:?} onto stack", token);
                 self.instructions.push(Instruction::StackPush(Value::Bool(*v)));
@@ -462,20 +911,26 @@ impl Function {
 
             Token::String(v) => {
                 trace!("pushing {:?} onto stack", token);
-                self.instructions.push(Instruction::StackPush(Value::String(v.to_string())));
+                self.instructions.push(Instruction::StackPush(Value::from(v.to_string())));
             }
 
             Token::Identifier(id) => {
                 trace!("pushing {:?} onto stack", token);
 
-                if self.global_lookup.contains_key(id) {
-                    let idx = self.global_lookup.get(id).unwrap();
-                    self.instructions.push(Instruction::LoadGlobal(*idx));
+                if let Some(define) = self.defines.get(id) {
+                    let literal = define.clone().ok_or_else(|| CompileError::DefineUsedBeforeDeclaration(id.clone()))?;
+                    self.compile_expression(&literal)?;
+                } else if let Some(idx) = self.resolve_global(id) {
+                    self.instructions.push(Instruction::LoadGlobal(idx));
                 } else if self.variable_declared(id) {
-                    let idx = self.get_variable(id.clone()).index;
+                    let idx = self.get_variable(id.clone())?.index;
                     self.instructions.push(Instruction::LoadLocalVariable(idx));
                 } else {
-                    panic!("unidentified identifier {}", id);
+                    // undeclared variables start as null
+                    self.add_undeclared_variable(id.to_string())?;
+                    self.warning(format!("use of undeclared variable '{}'", id));
+                    let idx = self.get_variable(id.clone())?.index;
+                    self.instructions.push(Instruction::LoadLocalVariable(idx));
                 }
 
             }
@@ -487,8 +942,16 @@ impl Function {
                 self.instructions.push(Instruction::StackPush(Value::Array(ref_array)));
 
                 for element in elements {
-                    self.compile_expression(element);
-                    self.instructions.push(Instruction::ArrayAdd);
+                    match element {
+                        Token::Spread(items) => {
+                            self.compile_expression(items)?;
+                            self.instructions.push(Instruction::ArrayExtend);
+                        }
+                        _ => {
+                            self.compile_expression(element)?;
+                            self.instructions.push(Instruction::ArrayAdd);
+                        }
+                    }
                 }
 
             }
@@ -499,27 +962,35 @@ impl Function {
                 let ref_hashmap = Rc::new(RefCell::new(HashMap::default()));
                 self.instructions.push(Instruction::StackPush(Value::Dictionary(ref_hashmap)));
 
+                // entries are added in order, so later ones replace those spread in before them
                 for pair in pairs {
-                    if let Token::KeyValuePair(k, value) = pair {
-                        self.instructions.push(Instruction::StackPush(Value::String(k.to_string())));
-                        self.compile_expression(value);
-                        self.instructions.push(Instruction::DictionaryAdd);
+                    match pair {
+                        Token::KeyValuePair(k, value) => {
+                            self.instructions.push(Instruction::StackPush(Value::from(k.to_string())));
+                            self.compile_expression(value)?;
+                            self.instructions.push(Instruction::DictionaryAdd);
+                        }
+                        Token::Spread(entries) => {
+                            self.compile_expression(entries)?;
+                            self.instructions.push(Instruction::DictionaryExtend);
+                        }
+                        _ => {}
                     }
                 }
 
             }
 
-            Token::Object(class_name, params) => self.compile_new_object(class_name.to_string(), params),
+            Token::Object(class_name, params) => self.compile_new_object(class_name.to_string(), params)?,
 
             Token::ArrayIndex(id, index) => {
                 trace!("i = {:?}, e = {:?}", id, index);
 
                 // load array
-                let idx = self.get_variable(id.to_string()).index;
+                let idx = self.get_variable(id.to_string())?.index;
                 self.instructions.push(Instruction::LoadLocalVariable(idx));
 
                 // compile index
-                self.compile_expression(index);
+                self.compile_expression(index)?;
 
                 // get array value
                 self.instructions.push(Instruction::GetCollectionItemByKey);
@@ -528,166 +999,488 @@ impl Function {
 
             Token::Call(name, args) => {
                 trace!("call = {:?}, args = {:?}", name, args);
-                self.compile_call(name, args);
+                self.compile_call(name, args)?;
             }
 
             Token::Eq(t1, t2) => {
-                self.compile_expression(t1);
-                self.compile_expression(t2);
+                self.compile_expression(t1)?;
+                self.compile_expression(t2)?;
                 self.instructions.push(Instruction::Equal);
             }
 
             Token::Ne(t1, t2) => {
-                self.compile_expression(t1);
-                self.compile_expression(t2);
+                self.compile_expression(t1)?;
+                self.compile_expression(t2)?;
                 self.instructions.push(Instruction::NotEqual);
             }
 
+            Token::Identical(t1, t2) => {
+                self.compile_expression(t1)?;
+                self.compile_expression(t2)?;
+                self.instructions.push(Instruction::Identical);
+            }
+
             Token::Add(t1, t2) => {
-                self.compile_expression(t1);
-                self.compile_expression(t2);
+                self.compile_expression(t1)?;
+                self.compile_expression(t2)?;
                 self.instructions.push(Instruction::Add);
             }
 
             Token::Sub(t1, t2) => {
-                self.compile_expression(t1);
-                self.compile_expression(t2);
+                self.compile_expression(t1)?;
+                self.compile_expression(t2)?;
                 self.instructions.push(Instruction::Sub);
             }
 
             Token::Mul(t1, t2) => {
-                self.compile_expression(t1);
-                self.compile_expression(t2);
+                self.compile_expression(t1)?;
+                self.compile_expression(t2)?;
                 self.instructions.push(Instruction::Multiply);
             }
 
             Token::Div(t1, t2) => {
-                self.compile_expression(t1);
-                self.compile_expression(t2);
+                self.compile_expression(t1)?;
+                self.compile_expression(t2)?;
                 self.instructions.push(Instruction::Divide);
             }
 
+            Token::FloorDiv(t1, t2) => {
+                self.compile_expression(t1)?;
+                self.compile_expression(t2)?;
+                self.instructions.push(Instruction::FloorDivide);
+            }
+
             Token::Pow(t1, t2) => {
-                self.compile_expression(t1);
-                self.compile_expression(t2);
+                self.compile_expression(t1)?;
+                self.compile_expression(t2)?;
                 self.instructions.push(Instruction::Pow);
             }
 
             Token::Lt(a, b) => {
-                self.compile_expression(a);
-                self.compile_expression(b);
+                self.compile_expression(a)?;
+                self.compile_expression(b)?;
                 self.instructions.push(Instruction::LessThan);
             }
 
             Token::Le(a, b) => {
-                self.compile_expression(a);
-                self.compile_expression(b);
+                self.compile_expression(a)?;
+                self.compile_expression(b)?;
                 self.instructions.push(Instruction::LessThanOrEqual);
             }
 
             Token::Gt(a, b) => {
-                self.compile_expression(a);
-                self.compile_expression(b);
+                self.compile_expression(a)?;
+                self.compile_expression(b)?;
                 self.instructions.push(Instruction::GreaterThan);
             }
 
             Token::Ge(a, b) => {
-                self.compile_expression(a);
-                self.compile_expression(b);
+                self.compile_expression(a)?;
+                self.compile_expression(b)?;
                 self.instructions.push(Instruction::GreaterThanOrEqual);
             }
 
+            // the right side is only run when the left side is not false
+            Token::And(a, b) => {
+                let end = self.new_label();
+                self.compile_expression(a)?;
+                self.instructions.push(Instruction::Dup);
+                self.instructions.push(Instruction::JumpIfFalse(end));
+                self.instructions.push(Instruction::Pop);
+                self.compile_expression(b)?;
+                self.mark_label(end);
+            }
+
+            // each middle operand is run once and kept in a slot for the comparison after it, which
+            // is skipped once one comparison is false
+            Token::Chain(comparisons) => {
+                let end = self.new_label();
+                let middle = self.add_temp_variable();
+                for (i, comparison) in comparisons.iter().enumerate() {
+                    let (Token::Lt(a, b) | Token::Le(a, b) | Token::Gt(a, b) | Token::Ge(a, b)) = comparison else {
+                        unreachable!("a chain only holds orderings")
+                    };
+                    match i {
+                        0 => self.compile_expression(a)?,
+                        _ => self.instructions.push(Instruction::LoadLocalVariable(middle))
+                    }
+                    self.compile_expression(b)?;
+                    let last = i + 1 == comparisons.len();
+                    if !last {
+                        self.instructions.push(Instruction::Dup);
+                        self.instructions.push(Instruction::MoveToLocalVariable(middle));
+                    }
+                    self.instructions.push(match comparison {
+                        Token::Lt(..) => Instruction::LessThan,
+                        Token::Le(..) => Instruction::LessThanOrEqual,
+                        Token::Gt(..) => Instruction::GreaterThan,
+                        _ => Instruction::GreaterThanOrEqual
+                    });
+                    if !last {
+                        self.instructions.push(Instruction::Dup);
+                        self.instructions.push(Instruction::JumpIfFalse(end));
+                        self.instructions.push(Instruction::Pop);
+                    }
+                }
+                self.mark_label(end);
+                self.free_temp_variable(middle);
+            }
+
+            // unwrap an Ok or Some, or return an Err or None from this function
+            Token::Try(e) => {
+                let fail = self.new_label();
+                let end = self.new_label();
+                self.compile_expression(e)?;
+                self.instructions.push(Instruction::Dup);
+                self.instructions.push(Instruction::CallMethod(TRY_CHECK_METHOD_NAME.to_string(), 0));
+                self.instructions.push(Instruction::JumpIfFalse(fail));
+                self.instructions.push(Instruction::CallMethod(TRY_UNWRAP_METHOD_NAME.to_string(), 0));
+                self.instructions.push(Instruction::Jump(end));
+                self.mark_label(fail);
+                self.instructions.push(Instruction::Return(true));
+                self.mark_label(end);
+            }
+
             // handle call chain and print debug info
             Token::DotChain(start, chain) => {
-                self.compile_chain(start, chain);
+                self.compile_chain(start, chain)?;
             },
 
+            Token::MatchValue(value, arms) => self.compile_match(value, arms, true)?,
+
             // handle unreadable token and print what it is
-            _ => panic!("unhandled token: {:?}", token),
+            _ => return Err(CompileError::UnsupportedToken(format!("{:?}", token))),
 
         }
+
+        Ok(())
     }
 
     // compile a print statement
-    fn compile_print(&mut self, exp: &Box<Token>) {
-        self.compile_expression(&exp);
+    fn compile_print(&mut self, exp: &Token) -> Result<(), CompileError> {
+        self.compile_expression(exp)?;
         self.instructions.push(Instruction::Print);
+
+        Ok(())
     }
 
     // compile a function call
-    fn compile_call(&mut self, name: &Box<Token>, args: &Vec<Token>) {
-        let mut arg_len = args.len();
+    fn compile_call(&mut self, name: &Token, args: &[Token]) -> Result<(), CompileError> {
+        trace!("call to function '{:?}' with {} args", name.to_string(), args.len());
 
-        trace!("call to function '{:?}' with {} args", name.to_string(), arg_len);
+        // builtin functions are run by the vm
+        if !self.variable_declared(&name.to_string()) && builtin::is_builtin_function(&name.to_string()) {
+            for arg in args {
+                self.compile_expression(arg)?;
+            }
+            self.instructions.push(Instruction::CallBuiltin(builtin::builtin_name(&name.to_string()).to_string(), args.len()));
+            return Ok(());
+        }
 
-        // push functionref onto stack
-        if self.variable_declared(&*name.to_string()) {
-            let index = self.get_variable(name.to_string()).index;
-            self.instructions.push(Instruction::LoadLocalVariable(index))
-        } else {
-            self.instructions.push(Instruction::LoadLocalVariable(0));
-            self.instructions.push(Instruction::StackPush(Value::String(name.to_string())));
-            self.instructions.push(Instruction::GetCollectionItemByKey);
+        // expect(value) starts the checks of a script test
+        if name.to_string() == EXPECT_FUNCTION_NAME && !self.variable_declared(EXPECT_FUNCTION_NAME) && self.resolve_global(EXPECTATION_CLASS_NAME).is_some() {
+            return self.compile_new_object(EXPECTATION_CLASS_NAME.to_string(), args);
+        }
+
+        // calling a class creates an instance of it
+        if !self.variable_declared(&name.to_string()) && self.resolve_global(&name.to_string()).is_some() {
+            return self.compile_new_object(name.to_string(), args);
+        }
+
+        // calls to functions not held in variables are methods on 'this'
+        if !self.variable_declared(&name.to_string()) {
+            self.warning(format!("call to '{}' uses an implicit '{}'", name, CLASS_SELF_VARIABLE_NAME));
             self.instructions.push(Instruction::LoadLocalVariable(0));
-            arg_len += 1;
+            self.compile_method_call(name, args)?;
+            return Ok(());
         }
 
+        // push functionref onto stack
+        let index = self.get_variable(name.to_string())?.index;
+        self.instructions.push(Instruction::LoadLocalVariable(index));
+
         // compile the arguments
         for arg in args {
-            self.compile_expression(arg);
+            self.compile_expression(arg)?;
         }
 
-        self.instructions.push(Instruction::Call(arg_len));
+        self.instructions.push(Instruction::Call(args.len()));
+
+        Ok(())
     }
 
-    // compile a return statement
-    fn compile_return(&mut self, expr: &Box<Token>) {
-        self.compile_expression(expr);
-        self.instructions.push(Instruction::Return(true));
+    // compile a call to a method on the receiver at the top of the stack
+    fn compile_method_call(&mut self, name: &Token, args: &[Token]) -> Result<(), CompileError> {
+        trace!("call to method '{:?}' with {} args", name.to_string(), args.len());
+
+        // compile the arguments
+        for arg in args {
+            self.compile_expression(arg)?;
+        }
+
+        self.instructions.push(Instruction::CallMethod(name.to_string(), args.len()));
+
+        Ok(())
+    }
+
+    // compile a return statement, a return without a value returns null
+    fn compile_return(&mut self, expr: &Option<Box<Token>>) -> Result<(), CompileError> {
+        match expr {
+            Some(expr) => {
+                self.compile_expression(expr)?;
+                self.instructions.push(Instruction::Return(true));
+            }
+            None => self.instructions.push(Instruction::Return(false))
+        }
+
+        Ok(())
+    }
+
+
+    // compile a yield statement
+    // note that the statement is to run when the function leaves, from here on
+    fn compile_defer(&mut self, statement: &Token) -> Result<(), CompileError> {
+        if self.generator {
+            return Err(CompileError::InvalidDefer(String::from("a generator can not defer statements")));
+        }
+
+        // the statement runs after any block around it has ended, so only defers in the body of
+        // the function can see the same variables when it runs
+        if self.scopes.len() > 1 {
+            return Err(CompileError::InvalidDefer(String::from("defer can only be used in the body of a function, not inside a block")));
+        }
+
+        // a slot no variable has used, so it stays null until the defer is reached
+        let flag = self.slots.len();
+        self.name_slot(flag, DEFER_SLOT_NAME);
+        self.instructions.push(Instruction::StackPush(Value::Bool(true)));
+        self.instructions.push(Instruction::MoveToLocalVariable(flag));
+        self.defers.push((flag, statement.clone()));
+
+        Ok(())
+    }
+
+    // the deferred statements that were reached, last first, then the return that leaves the
+    // function. gives where they start
+    fn compile_cleanup(&mut self) -> Result<usize, CompileError> {
+        let start = self.instructions.len();
+        for (flag, statement) in core::mem::take(&mut self.defers).iter().rev() {
+            let skip = self.new_label();
+            self.instructions.push(Instruction::LoadLocalVariable(*flag));
+            self.instructions.push(Instruction::StackPush(Value::Bool(true)));
+            self.instructions.push(Instruction::Equal);
+            self.instructions.push(Instruction::JumpIfFalse(skip));
+            self.compile_statement(statement)?;
+            self.mark_label(skip);
+        }
+        self.instructions.push(Instruction::Return(false));
+
+        Ok(start)
+    }
+
+    fn compile_yield(&mut self, expr: &Token) -> Result<(), CompileError> {
+        if !self.generator {
+            return Err(CompileError::YieldOutsideGenerator);
+        }
+        self.compile_expression(expr)?;
+        self.instructions.push(Instruction::Yield);
+
+        Ok(())
     }
 
 
     //==============================================================================================
     // HELPER FUNCTIONS
 
-    fn global_declared(&self, name: &str) -> bool {
-        self.globals.contains_key(name)
+    // compile the statements of a loop, where break jumps to exit and continue jumps to next
+    fn compile_loop_body(&mut self, block: &[Token], exit: Label, next: Label) -> Result<(), CompileError> {
+        let name = self.loop_name.take();
+        self.loops.push(Loop { name, exit, next });
+        let result = self.compile_block(block);
+        self.loops.pop();
+        result
     }
 
-    fn get_global(&self, name: String) -> usize {
-        if let Some(idx) = self.global_lookup.get(&*name) {
-            *idx
+    // the innermost loop, or the one with the name, that a break or continue is in
+    fn find_loop(&self, statement: &str, name: &Option<String>) -> Result<&Loop, CompileError> {
+        match name {
+            None => self.loops.last().ok_or_else(|| CompileError::OutsideLoop(statement.to_string())),
+            Some(name) => self.loops.iter().rev().find(|l| l.name.as_ref() == Some(name)).ok_or_else(|| CompileError::UnknownLoop(name.to_string()))
+        }
+    }
+
+    // report implicit behaviour, which is an error in strict mode
+    fn warning(&mut self, message: String) {
+        if self.strict {
+            self.errors.push(message);
         } else {
-            panic!("global '{}' does not exist", name);
+            warn!("{}: {}", self.get_full_name(), message);
+        }
+    }
+
+    fn get_global(&self, name: String) -> Result<usize, CompileError> {
+        self.resolve_global(&name).ok_or(CompileError::UnknownClass(name))
+    }
+
+    // find a global by its exact name, then within the namespaces in scope
+    fn resolve_global(&self, name: &str) -> Option<usize> {
+        self.global_lookup.get(name).copied().or_else(|| {
+            self.namespaces.iter().find_map(|ns| self.global_lookup.get(&format!("{}.{}", ns, name)).copied())
+        })
+    }
+
+    // create a label to be used as a jump target
+    fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    // set the label to point at the next instruction
+    fn mark_label(&mut self, label: Label) {
+        trace!("label {} marks instruction {}", label, self.instructions.len());
+        self.labels[label] = Some(self.instructions.len());
+    }
+
+    // replace labels in jump instructions with their instruction positions
+    fn resolve_labels(&mut self) {
+        for instruction in self.instructions.iter_mut() {
+            match instruction {
+                Instruction::Jump(target) | Instruction::JumpIfFalse(target) => {
+                    *target = self.labels[*target].unwrap_or_else(|| panic!("label {} was never marked", target));
+                },
+                _ => {}
+            }
+        }
+    }
+
+    // start a block, whose variables can shadow those of the blocks around it
+    fn enter_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    // end a block, freeing the slots of its variables and temporaries for later blocks
+    fn exit_scope(&mut self) {
+        let scope = self.scopes.pop().expect("the function body should always be in scope");
+        for (name, variable) in scope.variables {
+            self.free_slot(variable.index);
+            if !self.ended.contains(&name) {
+                self.ended.push(name);
+            }
+        }
+        for slot in scope.temps {
+            self.free_slot(slot);
         }
     }
 
+    // compile statements in a block of their own
+    fn compile_block(&mut self, statements: &[Token]) -> Result<(), CompileError> {
+        self.enter_scope();
+        let result = self.compile_statements(statements);
+        self.exit_scope();
+        result
+    }
+
     fn variable_declared(&self, name: &str) -> bool {
-        self.variables.contains_key(name)
+        self.scopes.iter().any(|scope| scope.variables.contains_key(name))
     }
 
-    // get index of variable or error if it doesn't exist
-    fn get_variable(&self, name: String) -> &Variable {
-        if let Some(variable) = self.variables.get(&*name) {
-            variable
-        } else {
-            panic!("variable '{}' does not exist", name);
+    // get index of the innermost variable with the name or error if there isn't one
+    fn get_variable(&self, name: String) -> Result<&Variable, CompileError> {
+        match self.scopes.iter().rev().find_map(|scope| scope.variables.get(&*name)) {
+            Some(variable) => Ok(variable),
+            None => Err(self.undeclared(name))
         }
     }
 
-    // add variable and return its index or error if it already exists
-    fn add_variable(&mut self, name: String, value: Value) {
+    // error for a variable that is not in scope
+    fn undeclared(&self, name: String) -> CompileError {
+        match self.ended.contains(&name) {
+            true => CompileError::OutOfScope(name),
+            false => CompileError::UndeclaredVariable(name)
+        }
+    }
+
+    // take a slot for a value the compiler keeps, which is freed by free_temp_variable or at the
+    // end of the block
+    fn add_temp_variable(&mut self) -> usize {
+        let slot = self.take_slot(TEMP_SLOT_NAME);
+        self.scopes.last_mut().expect("the function body should always be in scope").temps.push(slot);
+        slot
+    }
+
+    // give back a slot from add_temp_variable once its value is no longer needed
+    fn free_temp_variable(&mut self, slot: usize) {
+        let temps = &mut self.scopes.last_mut().expect("the function body should always be in scope").temps;
+        let position = temps.iter().rposition(|temp| *temp == slot).expect("only temporaries of the innermost block can be freed");
+        temps.remove(position);
+        self.free_slot(slot);
+    }
+
+    // add variable to the innermost block or error if the block already has it
+    fn add_variable(&mut self, name: String) -> Result<(), CompileError> {
+        let scope = self.scopes.len() - 1;
+        self.check_declaration(&name, scope)?;
+        let slot = self.take_slot(&name);
+        self.declare(name, slot, scope);
+        Ok(())
+    }
+
+    // add a variable that is used before it is declared to the function body, in a slot of its
+    // own as it starts as null
+    fn add_undeclared_variable(&mut self, name: String) -> Result<(), CompileError> {
+        if self.ended.contains(&name) {
+            return Err(CompileError::OutOfScope(name));
+        }
+        self.check_declaration(&name, 0)?;
+        let slot = self.slots.len();
+        self.name_slot(slot, &name);
+        self.declare(name, slot, 0);
+        Ok(())
+    }
+
+    fn check_declaration(&self, name: &str, scope: usize) -> Result<(), CompileError> {
 
         // check if variable already exists
-        if self.variables.contains_key(name.as_str()) {
-            panic!("variable '{}' already exists", name);
+        if self.scopes[scope].variables.contains_key(name) {
+            return Err(CompileError::DuplicateVariable(name.to_string()));
+        }
+
+        // defines are constants, so they can not be declared or assigned as a variable
+        if self.defines.contains_key(name) {
+            return Err(CompileError::InvalidDefine(name.to_string(), String::from("it can not be used as a variable")));
         }
 
-        // create variable
-        let variable = Variable::new(name.clone(), self.variables.len(), value);
+        Ok(())
+    }
 
-        // add variable to list
-        self.variables.insert(name.clone(), variable);
+    fn declare(&mut self, name: String, slot: usize, scope: usize) {
+        let variable = Variable::new(name.clone(), slot);
+        trace!("declared variable '{}' in slot {}", variable.name, variable.index);
+        self.scopes[scope].variables.insert(name, variable);
+    }
+
+    // the lowest free slot, or a new one at the end of the frame
+    fn take_slot(&mut self, name: &str) -> usize {
+        let slot = self.free_slots.pop().unwrap_or(self.slots.len());
+        self.name_slot(slot, name);
+        slot
+    }
+
+    fn free_slot(&mut self, slot: usize) {
+        self.free_slots.push(slot);
+
+        // reuse the lowest slots first, so frames stay small
+        self.free_slots.sort_unstable_by(|a, b| b.cmp(a));
+    }
+
+    // a slot shared by variables of different blocks is named after them all
+    fn name_slot(&mut self, slot: usize, name: &str) {
+        match self.slots.get_mut(slot) {
+            None => self.slots.push(name.to_string()),
+            Some(names) if names == TEMP_SLOT_NAME => *names = name.to_string(),
+            Some(names) if name == TEMP_SLOT_NAME || names.split('/').any(|n| n == name) => {},
+            Some(names) => *names = format!("{}/{}", names, name)
+        }
     }
 
 }
@@ -0,0 +1,223 @@
+use crate::prelude::*;
+
+// kind of text a span covers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpanKind {
+    Keyword,
+    Identifier,
+    String,
+    Number,
+    Comment,
+    Operator,
+    Unknown,
+}
+
+// classified range of bytes in the source
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub kind: SpanKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "assert", "break", "class", "constructor", "continue", "define", "do", "else", "false", "for", "from", "function", "if", "import",
+    "in", "match", "namespace", "new", "null", "print", "return", "this", "true", "var", "while", "yield",
+];
+
+// operators are matched longest first
+const OPERATORS: &[&str] = &[
+    "===", "==", "!=", "<=", ">=", "&&", "//",
+    "<", ">", "=", "+", "-", "*", "/", "^", "!", "?", ".", ",", ";", ":", "(", ")", "[", "]", "{", "}",
+];
+
+// split source into classified spans for highlighting
+//
+// this does not parse the source, so it works on scripts that are incomplete or invalid,
+// whitespace is skipped and anything unrecognised becomes an unknown span
+pub fn lex(source: &str) -> Vec<Span> {
+    let mut spans = vec![];
+    let mut position = 0;
+
+    while position < source.len() {
+        let rest = &source[position..];
+        let c = rest.chars().next().expect("rest should not be empty");
+
+        if c.is_whitespace() {
+            position += c.len_utf8();
+            continue;
+        }
+
+        let (kind, len) = if rest.starts_with("//") && !follows_operand(&spans, source) {
+            (SpanKind::Comment, rest.find('\n').unwrap_or(rest.len()))
+        } else if rest.starts_with("\"\"\"") {
+            (SpanKind::String, delimited(rest, 3, "\"\"\""))
+        } else if rest.starts_with("r#\"") {
+            (SpanKind::String, delimited(rest, 3, "\"#"))
+        } else if rest.starts_with("r\"") {
+            (SpanKind::String, delimited(rest, 2, "\""))
+        } else if c == '"' {
+            (SpanKind::String, delimited(rest, 1, "\""))
+        } else if c == '#' || (c == '@' && word_len(&rest[1..]) > 0) {
+            (SpanKind::Keyword, 1 + word_len(&rest[1..]))
+        } else if c.is_ascii_digit() {
+            (SpanKind::Number, number_len(rest))
+        } else if c.is_alphabetic() || c == '_' {
+            let len = word_len(rest);
+            let kind = if KEYWORDS.contains(&&rest[..len]) { SpanKind::Keyword } else { SpanKind::Identifier };
+            (kind, len)
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            (SpanKind::Operator, op.len())
+        } else {
+            (SpanKind::Unknown, c.len_utf8())
+        };
+
+        spans.push(Span { kind, start: position, end: position + len });
+        position += len;
+    }
+
+    spans
+}
+
+// whether the last span ends a value, so that // is floor division rather than a comment
+fn follows_operand(spans: &[Span], source: &str) -> bool {
+    match spans.last() {
+        Some(span) => match span.kind {
+            SpanKind::Identifier | SpanKind::Number | SpanKind::String => true,
+            SpanKind::Operator => matches!(&source[span.start..span.end], ")" | "]"),
+            _ => false
+        },
+        None => false
+    }
+}
+
+// length of text up to and including the closing delimiter, or the rest if it is never closed
+fn delimited(rest: &str, open: usize, close: &str) -> usize {
+    rest[open..].find(close).map(|i| open + i + close.len()).unwrap_or(rest.len())
+}
+
+fn word_len(rest: &str) -> usize {
+    rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len())
+}
+
+// digits with an optional fraction, a trailing dot is left for the operator
+fn number_len(rest: &str) -> usize {
+    let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let whole = digits(rest);
+    let after = &rest[whole..];
+    let len = if after.starts_with('.') && after[1..].starts_with(|c: char| c.is_ascii_digit()) {
+        whole + 1 + digits(&after[1..])
+    } else {
+        whole
+    };
+
+    // the suffix of a bigint or decimal literal
+    match rest[len..].starts_with(['n', 'd']) && !rest[len + 1..].starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+        true => len + 1,
+        false => len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::lexer::{lex, SpanKind};
+
+    fn kinds(source: &str) -> Vec<(SpanKind, &str)> {
+        lex(source).into_iter().map(|s| (s.kind, &source[s.start..s.end])).collect()
+    }
+
+    #[test]
+    fn test_statement() {
+        assert_eq!(kinds("var total = 1.5 + count; // sum"), vec![
+            (SpanKind::Keyword, "var"),
+            (SpanKind::Identifier, "total"),
+            (SpanKind::Operator, "="),
+            (SpanKind::Number, "1.5"),
+            (SpanKind::Operator, "+"),
+            (SpanKind::Identifier, "count"),
+            (SpanKind::Operator, ";"),
+            (SpanKind::Comment, "// sum"),
+        ]);
+    }
+
+    #[test]
+    fn test_floor_division() {
+        assert_eq!(kinds("(a + 1) // 2; // half"), vec![
+            (SpanKind::Operator, "("),
+            (SpanKind::Identifier, "a"),
+            (SpanKind::Operator, "+"),
+            (SpanKind::Number, "1"),
+            (SpanKind::Operator, ")"),
+            (SpanKind::Operator, "//"),
+            (SpanKind::Number, "2"),
+            (SpanKind::Operator, ";"),
+            (SpanKind::Comment, "// half"),
+        ]);
+    }
+
+    #[test]
+    fn test_attributes() {
+        assert_eq!(kinds("@export\nfunction"), vec![
+            (SpanKind::Keyword, "@export"),
+            (SpanKind::Keyword, "function"),
+        ]);
+    }
+
+    #[test]
+    fn test_exact_numbers() {
+        assert_eq!(kinds("10n * 1.25d + 3dx"), vec![
+            (SpanKind::Number, "10n"),
+            (SpanKind::Operator, "*"),
+            (SpanKind::Number, "1.25d"),
+            (SpanKind::Operator, "+"),
+            (SpanKind::Number, "3"),
+            (SpanKind::Identifier, "dx"),
+        ]);
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(kinds(r##"print "a" + r"C:\x" + r#"say "hi""# + """two
+lines""";"##), vec![
+            (SpanKind::Keyword, "print"),
+            (SpanKind::String, "\"a\""),
+            (SpanKind::Operator, "+"),
+            (SpanKind::String, r#"r"C:\x""#),
+            (SpanKind::Operator, "+"),
+            (SpanKind::String, r##"r#"say "hi""#"##),
+            (SpanKind::Operator, "+"),
+            (SpanKind::String, "\"\"\"two\nlines\"\"\""),
+            (SpanKind::Operator, ";"),
+        ]);
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(kinds("a<=b&&c!=d"), vec![
+            (SpanKind::Identifier, "a"),
+            (SpanKind::Operator, "<="),
+            (SpanKind::Identifier, "b"),
+            (SpanKind::Operator, "&&"),
+            (SpanKind::Identifier, "c"),
+            (SpanKind::Operator, "!="),
+            (SpanKind::Identifier, "d"),
+        ]);
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert_eq!(kinds("#strict\nx = \"open @"), vec![
+            (SpanKind::Keyword, "#strict"),
+            (SpanKind::Identifier, "x"),
+            (SpanKind::Operator, "="),
+            (SpanKind::String, "\"open @"),
+        ]);
+        assert_eq!(kinds("1. @ é"), vec![
+            (SpanKind::Number, "1"),
+            (SpanKind::Operator, "."),
+            (SpanKind::Unknown, "@"),
+            (SpanKind::Identifier, "é"),
+        ]);
+    }
+
+}
@@ -1,15 +1,74 @@
+use crate::collections::HashMap;
 use crate::compiler::compiler::Compiler;
+use crate::compiler::function::{Compiled, FunctionDecl};
+use crate::compiler::stdlib::STD_NAMESPACE;
+use crate::compiler::token::Token;
+use crate::prelude::*;
 use crate::vm::program::Program;
 
-mod frontend;
+#[cfg(feature = "fs")]
+pub use crate::compiler::cache::DirectoryCache;
+pub use crate::compiler::cache::{CachedCompiler, CallbackCache, ProgramCache};
+pub use crate::compiler::compiler::CompilerOptions;
+pub(crate) use crate::compiler::compiler::CLASS_CONSTRUCTOR_FUNCTION_NAME;
+pub use crate::compiler::error::{CompileError, ParseError};
+pub use crate::compiler::lexer::{lex, Span, SpanKind};
+#[cfg(feature = "fs")]
+pub use crate::compiler::project::build_project;
+pub use crate::compiler::project::{Project, MANIFEST_FILE_NAME};
+#[cfg(feature = "fs")]
+pub use crate::compiler::resolver::DiskResolver;
+pub use crate::compiler::resolver::{EmbeddedResolver, MemoryResolver, ModuleResolver};
+
+mod cache;
+mod constant;
+mod error;
+pub(crate) mod frontend;
 mod function;
-mod token;
+mod lexer;
+mod peephole;
+pub(crate) mod preprocessor;
+mod project;
+mod resolver;
+mod stdlib;
+pub(crate) mod template;
+pub(crate) mod token;
+#[allow(clippy::module_inception)]
 mod compiler;
 mod variable;
 
-pub fn compile(program: &str) -> Result<Program, String> {
+pub fn compile(program: &str) -> Result<Program, CompileError> {
+
+    // Return compiled bytecode
+    Compiler::new().compile(program.to_string())
+
+}
+
+pub fn compile_with_options(program: &str, options: CompilerOptions) -> Result<Program, CompileError> {
 
     // Return compiled bytecode
-    return Compiler::new().compile(program.to_string());
+    Compiler::with_options(options).compile(program.to_string())
+
+}
+
+// name of the function an expression is compiled into
+pub(crate) const EXPRESSION_FUNCTION_NAME: &str = "$expression.eval";
+
+// compile an expression into a function that returns it, given the variables as parameters
+// and able to use the globals of a program that was compiled before. it is compiled strictly,
+// so a name that is not a variable is an error rather than null
+pub(crate) fn compile_expression(expression: &str, variables: &[String], globals: &HashMap<String, usize>) -> Result<Compiled, CompileError> {
+    let token = frontend::parser::lone_expression(expression).map_err(|e| ParseError::new(expression, e))?;
+    let (class_name, name) = EXPRESSION_FUNCTION_NAME.split_once('.').expect("name should have a class");
+    let parameters = variables.iter().map(|variable| Token::Identifier(variable.to_string())).collect();
+    let mut func = FunctionDecl::new(class_name, name, parameters, vec![Token::Return(Some(Box::new(token)))]);
+    func.namespaces = vec![STD_NAMESPACE.to_string()];
+    let mut compiled = func.compile(globals.clone(), &CompilerOptions { strict: true, no_std: true, ..Default::default() })?;
 
-}
\ No newline at end of file
+    // there is no program to add anonymous functions to
+    if compiled.len() > 1 {
+        return Err(CompileError::UnsupportedToken(String::from("anonymous function in an expression")));
+    }
+    let (_, _, function) = compiled.remove(0);
+    Ok(function)
+}
@@ -0,0 +1,125 @@
+use crate::collections::HashSet;
+use crate::logging::trace;
+
+use crate::prelude::*;
+use crate::vm::instruction::{Comparison, Instruction};
+use crate::vm::value::Value;
+
+// fuse common sequences of a function into single instructions so hot loops dispatch less,
+// also giving the new position of each old one so positions kept outside of jumps can be moved
+// with the code
+//
+// runs once jumps point at instruction positions, and only fuses a sequence when nothing
+// jumps into the middle of it
+pub fn fuse(instructions: Vec<Instruction>) -> (Vec<Instruction>, Vec<usize>) {
+
+    let targets: HashSet<usize> = instructions.iter().filter_map(Instruction::jump_target).collect();
+
+    // old position of each instruction mapped to its new position
+    let mut positions = Vec::with_capacity(instructions.len() + 1);
+    let mut fused = Vec::with_capacity(instructions.len());
+
+    let mut ip = 0;
+    while ip < instructions.len() {
+        let window = &instructions[ip..instructions.len().min(ip + 4)];
+        let length = match superinstruction(window) {
+            Some(instruction) if (ip + 1..ip + window.len()).all(|position| !targets.contains(&position)) => {
+                trace!("fusing {:?} into {:?}", window, instruction);
+                fused.push(instruction);
+                window.len()
+            }
+            _ => {
+                fused.push(instructions[ip].clone());
+                1
+            }
+        };
+        positions.extend(core::iter::repeat_n(fused.len() - 1, length));
+        ip += length;
+    }
+
+    // a jump past the last instruction stays past the last instruction
+    positions.push(fused.len());
+
+    for instruction in fused.iter_mut() {
+        if let Some(target) = instruction.jump_target_mut() {
+            *target = positions[*target];
+        }
+    }
+
+    (fused, positions)
+}
+
+// the single instruction doing the same as the start of the window, if there is one
+fn superinstruction(window: &[Instruction]) -> Option<Instruction> {
+    use Instruction::*;
+
+    match window {
+        [LoadLocalVariable(slot), StackPush(Value::Integer(step)), Add, MoveToLocalVariable(dst), ..] if slot == dst => {
+            Some(IncrementLocal(*slot, *step))
+        }
+        [LoadLocalVariable(lhs), LoadLocalVariable(rhs), Add, MoveToLocalVariable(dst), ..] => {
+            Some(AddLocals(*lhs, *rhs, *dst))
+        }
+        [LoadLocalVariable(slot), StackPush(value), comparison, JumpIfFalse(target), ..] => {
+            Comparison::of(comparison).map(|comparison| CompareLocalConstJump(comparison, *slot, value.clone(), *target))
+        }
+        [LoadLocalVariable(lhs), LoadLocalVariable(rhs), comparison, JumpIfFalse(target), ..] => {
+            Comparison::of(comparison).map(|comparison| CompareLocalsJump(comparison, *lhs, *rhs, *target))
+        }
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::peephole::fuse;
+    use crate::vm::instruction::{Comparison, Instruction};
+    use crate::vm::value::Value;
+
+    #[test]
+    fn test_fuse_loop() {
+        let (fused, _) = fuse(vec![
+            Instruction::StackPush(Value::Integer(0)),
+            Instruction::MoveToLocalVariable(1),
+            Instruction::LoadLocalVariable(1),
+            Instruction::StackPush(Value::Integer(10)),
+            Instruction::LessThan,
+            Instruction::JumpIfFalse(11),
+            Instruction::LoadLocalVariable(1),
+            Instruction::StackPush(Value::Integer(1)),
+            Instruction::Add,
+            Instruction::MoveToLocalVariable(1),
+            Instruction::Jump(2),
+            Instruction::Return(false),
+        ]);
+
+        assert!(matches!(fused.as_slice(), [
+            Instruction::StackPush(_),
+            Instruction::MoveToLocalVariable(1),
+            Instruction::CompareLocalConstJump(Comparison::LessThan, 1, Value::Integer(10), 5),
+            Instruction::IncrementLocal(1, 1),
+            Instruction::Jump(2),
+            Instruction::Return(false),
+        ]));
+    }
+
+    #[test]
+    fn test_keep_jump_into_sequence() {
+        let (fused, _) = fuse(vec![
+            Instruction::Jump(2),
+            Instruction::LoadLocalVariable(1),
+            Instruction::LoadLocalVariable(2),
+            Instruction::Add,
+            Instruction::MoveToLocalVariable(3),
+            Instruction::LoadLocalVariable(1),
+            Instruction::LoadLocalVariable(2),
+            Instruction::Add,
+            Instruction::MoveToLocalVariable(3),
+        ]);
+
+        assert_eq!(fused.len(), 6);
+        assert!(matches!(fused[0], Instruction::Jump(2)));
+        assert!(matches!(fused[5], Instruction::AddLocals(1, 2, 3)));
+    }
+
+}
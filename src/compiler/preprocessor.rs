@@ -0,0 +1,139 @@
+use crate::collections::HashSet;
+use crate::compiler::error::CompileError;
+use crate::prelude::*;
+
+// Preprocessor
+//
+// strips the lines of #if NAME, #if !NAME, #else and #endif blocks whose condition does not hold
+// for the defines. stripped lines and the directives themselves are blanked with spaces rather
+// than removed, so lines, columns and offsets in errors still match the source as it was written
+
+const IF_DIRECTIVE: &str = "if";
+const ELSE_DIRECTIVE: &str = "else";
+const ENDIF_DIRECTIVE: &str = "endif";
+
+// whether the current block keeps its lines, and whether an #else has been seen
+struct Block {
+    active: bool,
+    seen_else: bool,
+}
+
+pub fn preprocess(source: &str, defines: &HashSet<String>) -> Result<String, CompileError> {
+
+    // nothing to do for the usual script without directives
+    if !source.lines().any(|line| directive(line).is_some()) {
+        return Ok(source.to_string());
+    }
+
+    let mut output = String::with_capacity(source.len());
+    let mut blocks: Vec<Block> = vec![];
+    for (index, line) in source.split_inclusive('\n').enumerate() {
+        let invalid = |message: &str| CompileError::InvalidDirective(index + 1, message.to_string());
+        let enclosing = blocks.iter().all(|block| block.active);
+
+        let keep = match directive(line) {
+            Some((IF_DIRECTIVE, condition)) => {
+                let (negated, name) = match condition.strip_prefix('!') {
+                    Some(name) => (true, name.trim()),
+                    None => (false, condition)
+                };
+                if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    return Err(invalid("#if needs a single name to test"));
+                }
+                blocks.push(Block { active: defines.contains(name) != negated, seen_else: false });
+                false
+            }
+            Some((ELSE_DIRECTIVE, _)) => {
+                let block = blocks.last_mut().ok_or_else(|| invalid("#else without #if"))?;
+                if block.seen_else {
+                    return Err(invalid("#if already has an #else"));
+                }
+                block.active = !block.active;
+                block.seen_else = true;
+                false
+            }
+            Some((ENDIF_DIRECTIVE, _)) => {
+                blocks.pop().ok_or_else(|| invalid("#endif without #if"))?;
+                false
+            }
+            _ => enclosing
+        };
+
+        match keep {
+            true => output.push_str(line),
+            false => blank(&mut output, line)
+        }
+    }
+
+    match blocks.is_empty() {
+        true => Ok(output),
+        false => Err(CompileError::InvalidDirective(source.lines().count(), String::from("#if without #endif")))
+    }
+}
+
+// the directive and what follows it, if the line is one
+fn directive(line: &str) -> Option<(&str, &str)> {
+    let rest = line.trim().strip_prefix('#')?;
+    let (name, argument) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    match name {
+        IF_DIRECTIVE | ELSE_DIRECTIVE | ENDIF_DIRECTIVE => Some((name, argument.trim())),
+        _ => None
+    }
+}
+
+fn blank(output: &mut String, line: &str) {
+    for c in line.chars() {
+        match c {
+            '\n' | '\r' => output.push(c),
+            c => output.extend(core::iter::repeat_n(' ', c.len_utf8()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collections::HashSet;
+    use crate::compiler::error::CompileError;
+    use crate::compiler::preprocessor::preprocess;
+
+    fn defines(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_branches() {
+        let source = "a\n#if DEBUG\nb\n#else\nc\n#endif\nd\n";
+        let lines = |names: &[&str]| preprocess(source, &defines(names)).unwrap().lines().map(|line| line.trim().to_string()).collect::<Vec<String>>();
+        assert_eq!(lines(&["DEBUG"]), vec!["a", "", "b", "", "", "", "d"]);
+        assert_eq!(lines(&[]), vec!["a", "", "", "", "c", "", "d"]);
+    }
+
+    #[test]
+    fn test_nested() {
+        let source = "#if !WASM\n  #if DEBUG\n  x\n  #endif\n  y\n#endif";
+        let kept = |names: &[&str]| preprocess(source, &defines(names)).unwrap().split_whitespace().collect::<Vec<&str>>().join(" ");
+        assert_eq!(kept(&["DEBUG"]), "x y");
+        assert_eq!(kept(&[]), "y");
+        assert_eq!(kept(&["DEBUG", "WASM"]), "");
+    }
+
+    #[test]
+    fn test_offsets_are_kept() {
+        let source = "#if X\r\n\"é\"\r\n#endif\r\nz";
+        let output = preprocess(source, &defines(&[])).unwrap();
+        assert_eq!(output.len(), source.len());
+        assert_eq!(output.find('z'), source.find('z'));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let error = |source: &str| preprocess(source, &defines(&[])).unwrap_err();
+        assert_eq!(error("#if A\nx"), CompileError::InvalidDirective(2, String::from("#if without #endif")));
+        assert_eq!(error("x\n#endif"), CompileError::InvalidDirective(2, String::from("#endif without #if")));
+        assert_eq!(error("#else"), CompileError::InvalidDirective(1, String::from("#else without #if")));
+        assert_eq!(error("#if A\n#else\n#else\n#endif"), CompileError::InvalidDirective(3, String::from("#if already has an #else")));
+        assert_eq!(error("#if A && B\n#endif"), CompileError::InvalidDirective(1, String::from("#if needs a single name to test")));
+        assert_eq!(error("#if\n#endif"), CompileError::InvalidDirective(1, String::from("#if needs a single name to test")));
+    }
+
+}
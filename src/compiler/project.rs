@@ -0,0 +1,206 @@
+use alloc::sync::Arc;
+#[cfg(feature = "fs")]
+use std::fs;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use crate::collections::HashMap;
+use crate::compiler::compiler::{Compiler, CompilerOptions};
+use crate::compiler::error::CompileError;
+#[cfg(feature = "fs")]
+use crate::compiler::resolver::DiskResolver;
+use crate::compiler::resolver::ModuleResolver;
+use crate::prelude::*;
+use crate::vm::bundle::Bundle;
+use crate::vm::toml;
+use crate::vm::value::Value;
+
+// file that describes the project in its directory
+pub const MANIFEST_FILE_NAME: &str = "tinyscript.toml";
+
+const MANIFEST_KEYS: [&str; 6] = ["name", "sources", "entry_points", "defines", "capabilities", "strict"];
+
+// Project
+//
+// scripts built together into a bundle, described by a manifest such as
+//
+//     name = "greeter"
+//     sources = ["main.tny", "names.tny"]
+//     entry_points = ["Greeter.main"]
+//     defines = ["DEBUG"]
+//     capabilities = ["Hash"]
+//     strict = true
+//
+// each source is compiled on its own into a module of the bundle, in the order they are listed.
+// as with any bundle only the first gets the standard library, and two sources can not import
+// the same file as its classes would be declared twice
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Project {
+    pub name: String,
+    // paths of the scripts, relative to the project
+    pub sources: Vec<String>,
+    // functions the host is expected to call, such as 'Greeter.main'
+    pub entry_points: Vec<String>,
+    // names that #if blocks in the sources test for
+    pub defines: Vec<String>,
+    // builtins or builtin modules the sandbox must allow
+    pub capabilities: Vec<String>,
+    pub strict: bool,
+}
+
+impl Project {
+
+    // read a manifest
+    pub fn parse(manifest: &str) -> Result<Project, CompileError> {
+        let invalid = |message: String| CompileError::InvalidManifest(message);
+        let Value::Dictionary(table) = toml::parse(manifest).map_err(|e| invalid(e.to_string()))? else {
+            return Err(invalid(String::from("it is not a table")));
+        };
+        let table = table.borrow();
+
+        if let Some(key) = table.keys().find(|key| !MANIFEST_KEYS.contains(&key.as_str())) {
+            return Err(invalid(format!("unknown key '{}'", key)));
+        }
+
+        let project = Project {
+            name: string(&table, "name")?.ok_or_else(|| invalid(String::from("'name' is missing")))?,
+            sources: strings(&table, "sources")?,
+            entry_points: strings(&table, "entry_points")?,
+            defines: strings(&table, "defines")?,
+            capabilities: strings(&table, "capabilities")?,
+            strict: match table.get("strict") {
+                None => false,
+                Some(Value::Bool(strict)) => *strict,
+                Some(value) => return Err(invalid(format!("'strict' must be true or false, not {}", value.type_name())))
+            },
+        };
+        if project.sources.is_empty() {
+            return Err(invalid(String::from("there are no sources")));
+        }
+
+        Ok(project)
+    }
+
+    // compile the sources, and the files they import, found by the resolver into a bundle
+    pub fn build(&self, resolver: Arc<dyn ModuleResolver>) -> Result<Bundle, CompileError> {
+        let mut bundle = Bundle::new(&self.name);
+        let mut functions = vec![];
+
+        for (index, path) in self.sources.iter().enumerate() {
+            let source = resolver.load(path).map_err(|e| CompileError::ImportFailed(path.to_string(), e))?;
+            let options = CompilerOptions {
+                strict: self.strict,
+                resolver: resolver.clone(),
+                no_std: index > 0,
+                defines: self.defines.iter().cloned().collect(),
+                ..Default::default()
+            };
+            let program = Compiler::with_options(options).compile(source).map_err(|e| CompileError::Module(path.to_string(), Box::new(e)))?;
+            functions.extend(program.symbols.keys().cloned());
+            bundle = bundle.module(path, &program);
+        }
+
+        for name in self.entry_points.iter() {
+            if !functions.contains(name) {
+                return Err(CompileError::InvalidManifest(format!("entry point '{}' is not a function of the project", name)));
+            }
+            bundle = bundle.entry_point(name);
+        }
+        for capability in self.capabilities.iter() {
+            bundle = bundle.capability(capability);
+        }
+
+        Ok(bundle)
+    }
+
+}
+
+// build the project in a directory, or described by a manifest file, with its sources read
+// from beside the manifest
+#[cfg(feature = "fs")]
+pub fn build_project(path: impl AsRef<Path>) -> Result<Bundle, CompileError> {
+    let path = path.as_ref();
+    let manifest = match path.is_dir() {
+        true => path.join(MANIFEST_FILE_NAME),
+        false => path.to_path_buf(),
+    };
+    let text = fs::read_to_string(&manifest).map_err(|e| CompileError::InvalidManifest(format!("unable to read {}: {}", manifest.display(), e)))?;
+    let root = manifest.parent().unwrap_or(Path::new("."));
+    Project::parse(&text)?.build(Arc::new(DiskResolver::new(root)))
+}
+
+fn string(table: &HashMap<String, Value>, key: &str) -> Result<Option<String>, CompileError> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value.to_string())),
+        Some(value) => Err(CompileError::InvalidManifest(format!("'{}' must be a string, not {}", key, value.type_name())))
+    }
+}
+
+fn strings(table: &HashMap<String, Value>, key: &str) -> Result<Vec<String>, CompileError> {
+    let not_strings = || CompileError::InvalidManifest(format!("'{}' must be a list of strings", key));
+    match table.get(key) {
+        None => Ok(vec![]),
+        Some(Value::Array(items)) => items.borrow().iter().map(|item| match item {
+            Value::String(item) => Ok(item.to_string()),
+            _ => Err(not_strings())
+        }).collect(),
+        Some(_) => Err(not_strings())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+
+    use crate::compiler::error::CompileError;
+    use crate::compiler::project::Project;
+    use crate::compiler::resolver::MemoryResolver;
+    use crate::vm::config::VmConfig;
+    use crate::vm::value::Value;
+
+    const MANIFEST: &str = r#"
+        name = "greeter"
+        sources = ["main.tny", "names.tny"]
+        entry_points = ["Greeter.main"]
+        defines = ["LOUD"]
+    "#;
+
+    #[test]
+    fn test_build() {
+        let resolver = MemoryResolver::new()
+            .module("main.tny", "class Greeter { function main() {\n#if LOUD\nreturn \"HELLO\";\n#endif\nreturn \"hello\"; } }")
+            .module("names.tny", "class Names { function first() { return \"ada\"; } }");
+        let project = Project::parse(MANIFEST).unwrap();
+        assert_eq!(project.sources, vec!["main.tny", "names.tny"]);
+        assert!(!project.strict);
+
+        let bundle = project.build(Arc::new(resolver)).unwrap();
+        assert_eq!(bundle.name, "greeter");
+        assert_eq!(bundle.modules.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), vec!["main.tny", "names.tny"]);
+
+        let mut vm = bundle.instantiate(VmConfig::new()).unwrap();
+        assert_eq!(vm.exec("Greeter.main", None).unwrap(), Value::from("HELLO"));
+        assert_eq!(vm.exec("Names.first", None).unwrap(), Value::from("ada"));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let error = |manifest: &str| match Project::parse(manifest) {
+            Err(CompileError::InvalidManifest(message)) => message,
+            result => panic!("expected an invalid manifest, got {:?}", result)
+        };
+        assert_eq!(error("sources = [\"a.tny\"]"), "'name' is missing");
+        assert_eq!(error("name = \"a\""), "there are no sources");
+        assert_eq!(error("name = \"a\"\nsources = \"a.tny\""), "'sources' must be a list of strings");
+        assert_eq!(error("name = \"a\"\nsources = [\"a.tny\"]\nentry = [\"A.main\"]"), "unknown key 'entry'");
+
+        let project = Project::parse("name = \"a\"\nsources = [\"a.tny\"]\nentry_points = [\"A.missing\"]").unwrap();
+        let resolver = Arc::new(MemoryResolver::new().module("a.tny", "class A { function main() { } }"));
+        assert_eq!(project.build(resolver.clone()).err(), Some(CompileError::InvalidManifest(String::from("entry point 'A.missing' is not a function of the project"))));
+
+        let project = Project { sources: vec![String::from("b.tny")], ..project };
+        assert!(matches!(project.build(resolver), Err(CompileError::ImportFailed(path, _)) if path == "b.tny"));
+    }
+
+}
@@ -0,0 +1,122 @@
+use core::fmt::Debug;
+#[cfg(feature = "fs")]
+use std::fs;
+#[cfg(feature = "fs")]
+use std::path::PathBuf;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+
+// Module Resolver
+//
+// finds the source of a file named by an import
+pub trait ModuleResolver: Debug + Send + Sync {
+    fn load(&self, path: &str) -> Result<String, String>;
+}
+
+// reads imports from disk relative to a root directory
+#[cfg(feature = "fs")]
+#[derive(Clone, Debug)]
+pub struct DiskResolver {
+    root: PathBuf,
+}
+
+#[cfg(feature = "fs")]
+impl DiskResolver {
+
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        DiskResolver { root: root.into() }
+    }
+
+}
+
+#[cfg(feature = "fs")]
+impl Default for DiskResolver {
+    // relative to the working directory
+    fn default() -> Self {
+        DiskResolver::new(".")
+    }
+}
+
+#[cfg(feature = "fs")]
+impl ModuleResolver for DiskResolver {
+    fn load(&self, path: &str) -> Result<String, String> {
+        fs::read_to_string(self.root.join(path)).map_err(|e| e.to_string())
+    }
+}
+
+// serves imports from sources held in memory
+#[derive(Clone, Debug, Default)]
+pub struct MemoryResolver {
+    modules: HashMap<String, String>,
+}
+
+impl MemoryResolver {
+
+    pub fn new() -> Self {
+        MemoryResolver::default()
+    }
+
+    pub fn module(mut self, path: &str, source: &str) -> Self {
+        self.modules.insert(path.to_string(), source.to_string());
+        self
+    }
+
+}
+
+impl ModuleResolver for MemoryResolver {
+    fn load(&self, path: &str) -> Result<String, String> {
+        self.modules.get(path).cloned().ok_or_else(|| format!("module '{}' not found", path))
+    }
+}
+
+// serves imports from sources compiled into the binary with include_str!
+#[derive(Clone, Copy, Debug)]
+pub struct EmbeddedResolver {
+    modules: &'static [(&'static str, &'static str)],
+}
+
+impl EmbeddedResolver {
+
+    pub const fn new(modules: &'static [(&'static str, &'static str)]) -> Self {
+        EmbeddedResolver { modules }
+    }
+
+}
+
+impl ModuleResolver for EmbeddedResolver {
+    fn load(&self, path: &str) -> Result<String, String> {
+        self.modules.iter()
+            .find(|(name, _)| *name == path)
+            .map(|(_, source)| source.to_string())
+            .ok_or_else(|| format!("module '{}' is not embedded", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::resolver::{EmbeddedResolver, MemoryResolver, ModuleResolver};
+
+    #[test]
+    fn test_memory() {
+        let resolver = MemoryResolver::new().module("a.tny", "class A { }");
+        assert_eq!(resolver.load("a.tny"), Ok("class A { }".to_string()));
+        assert!(resolver.load("b.tny").is_err());
+    }
+
+    #[test]
+    fn test_embedded() {
+        const MODULES: EmbeddedResolver = EmbeddedResolver::new(&[("a.tny", "class A { }")]);
+        assert_eq!(MODULES.load("a.tny"), Ok("class A { }".to_string()));
+        assert!(MODULES.load("b.tny").is_err());
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_disk() {
+        let resolver = crate::compiler::resolver::DiskResolver::new("tests/scripts");
+        assert!(resolver.load("modules/vector.tny").unwrap().contains("class Vec2"));
+        assert!(resolver.load("modules/missing.tny").is_err());
+    }
+
+}
@@ -0,0 +1,10 @@
+// namespace of the standard library, searched after the namespaces of a file
+pub const STD_NAMESPACE: &str = "std";
+
+// sources of the standard library embedded into the crate
+pub const MODULES: &[(&str, &str)] = &[
+    ("std/arrays.tny", include_str!("stdlib/arrays.tny")),
+    ("std/strings.tny", include_str!("stdlib/strings.tny")),
+    ("std/result.tny", include_str!("stdlib/result.tny")),
+    ("std/expect.tny", include_str!("stdlib/expect.tny")),
+];
@@ -0,0 +1,107 @@
+use crate::compiler::error::CompileError;
+use crate::prelude::*;
+
+// Templates
+//
+// text with ${expression} placeholders, such as "Hello ${user.name}". the expression ends at the
+// brace that closes the placeholder, so braces and strings inside it are skipped over, and \${
+// is written out as ${ without starting a placeholder
+
+const PLACEHOLDER_START: &str = "${";
+const ESCAPED_START: &str = "\\${";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Part {
+    Text(String),
+    Placeholder(String),
+}
+
+pub fn parse_template(template: &str) -> Result<Vec<Part>, CompileError> {
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut rest = template;
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix(ESCAPED_START) {
+            text.push_str(PLACEHOLDER_START);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix(PLACEHOLDER_START) {
+            let line = template[..template.len() - rest.len()].matches('\n').count() + 1;
+            let end = placeholder_end(after).ok_or_else(|| CompileError::InvalidTemplate(line, String::from("placeholder is not closed")))?;
+            let expression = after[..end].trim();
+            if expression.is_empty() {
+                return Err(CompileError::InvalidTemplate(line, String::from("placeholder is empty")));
+            }
+
+            if !text.is_empty() {
+                parts.push(Part::Text(core::mem::take(&mut text)));
+            }
+            parts.push(Part::Placeholder(expression.to_string()));
+            rest = &after[end + 1..];
+        } else {
+            let c = rest.chars().next().expect("rest should not be empty");
+            text.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+    Ok(parts)
+}
+
+// offset of the brace that closes a placeholder, skipping nested braces and strings
+fn placeholder_end(source: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for (offset, c) in source.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '{' => depth += 1,
+                '}' if depth == 0 => return Some(offset),
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::error::CompileError;
+    use crate::compiler::template::{parse_template, Part};
+
+    fn text(s: &str) -> Part {
+        Part::Text(s.to_string())
+    }
+
+    fn placeholder(s: &str) -> Part {
+        Part::Placeholder(s.to_string())
+    }
+
+    #[test]
+    fn test_parts() {
+        assert_eq!(parse_template("Hello ${ user.name }!").unwrap(), vec![text("Hello "), placeholder("user.name"), text("!")]);
+        assert_eq!(parse_template("${a}${b}").unwrap(), vec![placeholder("a"), placeholder("b")]);
+        assert_eq!(parse_template("${format(\"{}}\", n)} é").unwrap(), vec![placeholder("format(\"{}}\", n)"), text(" é")]);
+        assert_eq!(parse_template("cost: \\${price} $5 {}").unwrap(), vec![text("cost: ${price} $5 {}")]);
+        assert!(parse_template("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(parse_template("a\nb ${c"), Err(CompileError::InvalidTemplate(2, String::from("placeholder is not closed"))));
+        assert_eq!(parse_template("${ \"}\" "), Err(CompileError::InvalidTemplate(1, String::from("placeholder is not closed"))));
+        assert_eq!(parse_template("${ }"), Err(CompileError::InvalidTemplate(1, String::from("placeholder is empty"))));
+    }
+
+}
@@ -1,12 +1,30 @@
+use core::fmt;
+use core::fmt::{Display, Formatter};
+
+use crate::prelude::*;
+
 #[derive(Debug, Clone)]
 pub enum Token {
 
     Comment(String),
+    DocComment(String),
+    Attribute(String),
     Assert(Box<Token>),
-    Import(String),
+    Import(String, Option<String>),
+    FromImport(String, Vec<String>),
+    Namespace(String),
+    ImportNamespace(String),
+    Pragma(String),
+    Define(String, Box<Token>),
     Print(Box<Token>),
+    // level, line and message
+    Log(String, usize, Box<Token>),
 
     Function(String, Vec<Token>, Vec<Token>),
+    Generator(String, Vec<Token>, Vec<Token>),
+    Yield(Box<Token>),
+    // statement run when the function returns or leaves with an error
+    Defer(Box<Token>),
     
     AnonFunction(Vec<Token>, Vec<Token>),
     Class(String, Vec<Token>),
@@ -21,6 +39,8 @@ pub enum Token {
     Null,
     Integer(i32),
     Float(f32),
+    BigInt(String),
+    Decimal(String),
     Bool(bool),
     String(String),
     Array(Vec<Token>),
@@ -28,10 +48,14 @@ pub enum Token {
 
     Dictionary(Vec<Token>),
     KeyValuePair(String, Box<Token>),
+    // ...value in a collection literal, copying in the items or entries of the value
+    Spread(Box<Token>),
 
     ArrayIndex(Box<Token>, Box<Token>),
 
     Eq(Box<Token>, Box<Token>),
+    // === compares identity rather than contents
+    Identical(Box<Token>, Box<Token>),
     Ne(Box<Token>, Box<Token>),
     Lt(Box<Token>, Box<Token>),
     Le(Box<Token>, Box<Token>),
@@ -41,24 +65,42 @@ pub enum Token {
     Sub(Box<Token>, Box<Token>),
     Mul(Box<Token>, Box<Token>),
     Div(Box<Token>, Box<Token>),
+    FloorDiv(Box<Token>, Box<Token>),
     Pow(Box<Token>, Box<Token>),
+    And(Box<Token>, Box<Token>),
+    // orderings such as a < b <= c, each comparing the right side of the one before
+    Chain(Vec<Token>),
+    Try(Box<Token>),
 
     IfElse(Box<Token>, Vec<Token>, Option<Vec<Token>>),
     WhileLoop(Box<Token>, Vec<Token>),
+    DoWhileLoop(Vec<Token>, Box<Token>),
+    // a loop with a name that break and continue can use to leave or go round it
+    LabeledLoop(String, Box<Token>),
+    Break(Option<String>),
+    Continue(Option<String>),
     ForEach(Box<Token>, Box<Token>, Vec<Token>),
     ForI(Box<Token>, Box<Token>, Box<Token>, Vec<Token>),
 
+    // match statement and match expression, each with a list of arms
+    Match(Box<Token>, Vec<Token>),
+    MatchValue(Box<Token>, Vec<Token>),
+    // pattern, guard and the statements run, or for a match expression the value
+    MatchArm(Box<Token>, Option<Box<Token>>, Vec<Token>),
+    // the '..' that ends an array pattern matching any number of further items
+    Rest,
+
     Call(Box<Token>, Vec<Token>),
-    Return(Box<Token>)
+    Return(Option<Box<Token>>)
 }
 
-impl ToString for Token {
-    fn to_string(&self) -> String {
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
-            Token::Function(name, _, _) => name.to_string(),
-            Token::Identifier(name) => name.to_string(),
-            Token::String(s) => s.to_string(),
-            _ => String::from("")
+            Token::Function(name, _, _) | Token::Generator(name, _, _) => write!(f, "{name}"),
+            Token::Identifier(name) => write!(f, "{name}"),
+            Token::String(s) => write!(f, "{s}"),
+            _ => Ok(())
         }
     }
 }
\ No newline at end of file
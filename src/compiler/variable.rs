@@ -1,17 +1,15 @@
-use crate::vm::value::Value;
+use crate::prelude::*;
 
 pub struct Variable {
     pub name: String,
     pub index: usize,
-    pub value: Value,
 }
 
 impl Variable {
-    pub fn new(name: String, index: usize, value: Value) -> Variable {
+    pub fn new(name: String, index: usize) -> Variable {
         Variable {
             name,
             index,
-            value,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,43 @@
+use core::error::Error;
+use core::fmt;
+use core::fmt::{Display, Formatter};
+
+use crate::compiler::CompileError;
+use crate::vm::error::RuntimeError;
+
+// Tinyscript Error
+#[derive(Clone, Debug, PartialEq)]
+pub enum TinyscriptError {
+    Compile(CompileError),
+    Runtime(RuntimeError),
+}
+
+impl Display for TinyscriptError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TinyscriptError::Compile(e) => write!(f, "compile error: {}", e),
+            TinyscriptError::Runtime(e) => write!(f, "runtime error: {}", e),
+        }
+    }
+}
+
+impl Error for TinyscriptError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TinyscriptError::Compile(e) => Some(e),
+            TinyscriptError::Runtime(e) => Some(e),
+        }
+    }
+}
+
+impl From<CompileError> for TinyscriptError {
+    fn from(e: CompileError) -> Self {
+        TinyscriptError::Compile(e)
+    }
+}
+
+impl From<RuntimeError> for TinyscriptError {
+    fn from(e: RuntimeError) -> Self {
+        TinyscriptError::Runtime(e)
+    }
+}
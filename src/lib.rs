@@ -1,25 +1,170 @@
-use log::LevelFilter;
-use simplelog::{ColorChoice, Config, TerminalMode, TermLogger};
-use crate::compiler::compile;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-use crate::vm::value::Value;
+extern crate alloc;
+
+use crate::collections::HashMap;
+use crate::compiler::template::Part;
+use crate::prelude::*;
 use crate::vm::VM;
 
 pub mod vm;
+mod collections;
 mod compiler;
+mod error;
+mod logging;
+mod prelude;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+#[cfg(feature = "capi")]
+pub mod capi;
+
+#[cfg(feature = "fs")]
+pub use crate::compiler::{build_project, DirectoryCache, DiskResolver};
+pub use crate::compiler::{compile, compile_with_options, lex, CachedCompiler, CallbackCache, CompileError, CompilerOptions, EmbeddedResolver, MemoryResolver, ModuleResolver, ParseError, ProgramCache, Project, Span, SpanKind, MANIFEST_FILE_NAME};
+pub use crate::error::TinyscriptError;
+pub use crate::vm::error::{LinkError, RuntimeError};
+pub use crate::vm::builder::{ValueArray, ValueMap};
+pub use crate::vm::config::{Output, StringUnits, VmConfig};
+pub use crate::vm::convert::FromValue;
+pub use crate::vm::sandbox::Sandbox;
+pub use crate::vm::value::Value;
+
+pub fn run(program: &str, main: &str, params: Option<Vec<Value>>) -> Result<Value, TinyscriptError> {
+
+    logging::init();
+
+    // Compile to bytecode
+    let bytecode = compile(program)?;
+
+    // Create new VM
+    let mut vm: VM = VM::new(bytecode);
+
+    // Execute
+    Ok(vm.exec(main, params)?)
+
+}
+
+pub fn run_with_options(program: &str, main: &str, params: Option<Vec<Value>>, options: CompilerOptions) -> Result<Value, TinyscriptError> {
 
-pub fn run(program: &str, main: &str, params: Option<Vec<Value>>) -> Result<Value, String> {
+    logging::init();
 
-    let _ = TermLogger::init(LevelFilter::Trace, Config::default(),TerminalMode::Mixed, ColorChoice::Auto);
+    // Compile to bytecode
+    let bytecode = compile_with_options(program, options)?;
+
+    // Create new VM
+    let mut vm: VM = VM::new(bytecode);
+
+    // Execute
+    Ok(vm.exec(main, params)?)
+
+}
+
+// run a program whose entry function receives its arguments by parameter name
+pub fn run_named(program: &str, main: &str, params: HashMap<String, Value>) -> Result<Value, TinyscriptError> {
+
+    logging::init();
 
     // Compile to bytecode
-    let bytecode = compile(program).expect("program error");
+    let bytecode = compile(program)?;
 
     // Create new VM
-    let vm: VM = VM::new(bytecode);
+    let mut vm: VM = VM::new(bytecode);
 
     // Execute
-    vm.exec(main, params).map_err(|e| e.to_string())
+    Ok(vm.exec_named(main, params)?)
+
+}
+
+// evaluate a single expression from a user, such as a spreadsheet formula, with the bindings as
+// its variables and the limits of Sandbox::expression
+pub fn evaluate_expression(expression: &str, bindings: HashMap<String, Value>) -> Result<Value, TinyscriptError> {
+    evaluate_expression_with_sandbox(expression, bindings, Sandbox::expression())
+}
 
+// only the expression is compiled, without the standard library, so there are no statements to
+// run and no functions to call but the builtins the sandbox allows. the strings it makes count
+// against the memory of the sandbox, so a formula can not make one too long for the host
+pub fn evaluate_expression_with_sandbox(expression: &str, bindings: HashMap<String, Value>, sandbox: Sandbox) -> Result<Value, TinyscriptError> {
+    let (names, values) = sorted_bindings(bindings);
+    let program = expression_program(expression, &names)?;
+    Ok(VM::with_sandbox(program, sandbox).exec(compiler::EXPRESSION_FUNCTION_NAME, Some(values))?)
 }
 
+// fill in the ${expression} placeholders of a template, such as "Hello ${user.name}", using the
+// entries of a dictionary as variables. each placeholder is evaluated like evaluate_expression
+pub fn render_template(template: &str, context: Value) -> Result<String, TinyscriptError> {
+    render_template_with_sandbox(template, context, Sandbox::expression())
+}
+
+// every placeholder is compiled before any is run, and each runs within the limits of the sandbox
+pub fn render_template_with_sandbox(template: &str, context: Value, sandbox: Sandbox) -> Result<String, TinyscriptError> {
+    let Value::Dictionary(context) = context else {
+        return Err(RuntimeError::TypeError(format!("template context must be a dictionary, not {}", context.type_name())).into());
+    };
+    let (names, values) = sorted_bindings(context.borrow().clone());
+
+    let parts = compiler::template::parse_template(template)?;
+    let mut programs = parts.iter()
+        .filter_map(|part| match part {
+            Part::Placeholder(expression) => Some(expression_program(expression, &names)),
+            Part::Text(_) => None
+        })
+        .collect::<Result<Vec<_>, CompileError>>()?
+        .into_iter();
+
+    let mut output = String::with_capacity(template.len());
+    for part in parts {
+        match part {
+            Part::Text(text) => output.push_str(&text),
+            Part::Placeholder(_) => {
+                let program = programs.next().expect("placeholder should have been compiled");
+                let value = VM::with_sandbox(program, sandbox.clone()).exec(compiler::EXPRESSION_FUNCTION_NAME, Some(values.clone()))?;
+                output.push_str(&value.to_string());
+            }
+        }
+    }
+    Ok(output)
+}
+
+// names and values of the bindings in name order, as the parameters of an expression
+fn sorted_bindings(bindings: HashMap<String, Value>) -> (Vec<String>, Vec<Value>) {
+    let mut bindings: Vec<(String, Value)> = bindings.into_iter().collect();
+    bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
+    bindings.into_iter().unzip()
+}
+
+// a program of just the function an expression is compiled into
+fn expression_program(expression: &str, names: &[String]) -> Result<vm::program::Program, CompileError> {
+    let (instructions, metadata, _) = compiler::compile_expression(expression, names, &HashMap::new())?;
+    let mut program = vm::program::Program { instructions, ..Default::default() };
+    program.symbols.insert(compiler::EXPRESSION_FUNCTION_NAME.to_string(), 0);
+    program.metadata.insert(compiler::EXPRESSION_FUNCTION_NAME.to_string(), metadata);
+    Ok(program)
+}
+
+// compile a program into a vm that can be run more than once and receive events
+pub fn load(program: &str, options: CompilerOptions) -> Result<VM, TinyscriptError> {
+
+    // Compile to bytecode
+    let bytecode = compile_with_options(program, options)?;
+
+    // Create new VM
+    Ok(VM::new(bytecode))
+
+}
+
+// compile a program into a vm whose calls are limited by the sandbox
+pub fn load_with_sandbox(program: &str, options: CompilerOptions, sandbox: Sandbox) -> Result<VM, TinyscriptError> {
+    load_with_config(program, options, VmConfig::new().sandbox(sandbox))
+}
+
+// compile a program into a vm created with the config
+pub fn load_with_config(program: &str, options: CompilerOptions, config: VmConfig) -> Result<VM, TinyscriptError> {
+
+    // Compile to bytecode
+    let bytecode = compile_with_options(program, options)?;
+
+    // Create new VM
+    Ok(VM::with_config(bytecode, config))
+
+}
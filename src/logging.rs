@@ -0,0 +1,56 @@
+#[cfg(feature = "terminal")]
+use log::LevelFilter;
+
+// debug and trace logging is left out of the build unless the trace feature is enabled, so
+// the interpreter loop pays nothing for it in normal builds
+
+// level of the terminal logger installed by the run functions
+#[cfg(feature = "terminal")]
+const LEVEL: LevelFilter = if cfg!(feature = "trace") { LevelFilter::Trace } else { LevelFilter::Info };
+
+// install the terminal logger unless the host has installed its own
+#[cfg(feature = "terminal")]
+pub fn init() {
+    use simplelog::{ColorChoice, Config, TerminalMode, TermLogger};
+    let _ = TermLogger::init(LEVEL, Config::default(), TerminalMode::Mixed, ColorChoice::Auto);
+}
+
+// without a terminal the host installs a logger, such as one writing to the browser console
+#[cfg(not(feature = "terminal"))]
+pub fn init() {}
+
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        if cfg!(feature = "trace") {
+            ::log::debug!($($arg)+)
+        }
+    };
+}
+
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        if cfg!(feature = "trace") {
+            ::log::trace!($($arg)+)
+        }
+    };
+}
+
+pub(crate) use debug;
+pub(crate) use trace;
+
+#[cfg(test)]
+mod tests {
+
+    #[test]
+    fn test_arguments_not_formatted() {
+        let mut formatted = false;
+        trace!("{}", {
+            formatted = true;
+            "value"
+        });
+        if !cfg!(feature = "trace") {
+            assert!(!formatted);
+        }
+    }
+
+}
@@ -0,0 +1,371 @@
+use crate::collections::HashSet;
+use crate::compiler::frontend::parser;
+use crate::compiler::preprocessor::preprocess;
+use crate::compiler::token::Token;
+use crate::compiler::{compile_with_options, CompileError, CompilerOptions};
+use crate::prelude::*;
+
+// position in a document, both zero based as in the language server protocol
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Range {
+    pub fn contains(&self, position: Position) -> bool {
+        self.start <= position && position <= self.end
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub range: Range,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Class,
+    Constructor,
+    Function,
+    Field,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocumentSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub range: Range,
+    pub documentation: Option<String>,
+    pub children: Vec<DocumentSymbol>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompletionItem {
+    pub label: String,
+    pub kind: SymbolKind,
+    pub documentation: Option<String>,
+}
+
+//==================================================================================================
+// DIAGNOSTICS
+
+// textDocument/diagnostic: parse and compile errors, plus strict mode findings as warnings
+pub fn diagnostics(source: &str) -> Vec<Diagnostic> {
+    let symbols = document_symbols(source);
+
+    let error = match compile_with_options(source, CompilerOptions::default()) {
+        Err(e) => e,
+        Ok(_) => match compile_with_options(source, CompilerOptions { strict: true, ..Default::default() }) {
+            Ok(_) => return vec![],
+            Err(e) => return to_diagnostics(&symbols, e, Severity::Warning),
+        }
+    };
+
+    to_diagnostics(&symbols, error, Severity::Error)
+}
+
+fn to_diagnostics(symbols: &[DocumentSymbol], error: CompileError, severity: Severity) -> Vec<Diagnostic> {
+    match error {
+
+        // parse errors know exactly where they happened
+        CompileError::Parse(e) => {
+            let start = Position { line: e.line - 1, character: e.column - 1 };
+            let end = Position { line: start.line, character: e.line_text.chars().count().max(start.character + 1) };
+            vec![Diagnostic { range: Range { start, end }, severity, message: format!("expected {}", e.expected.join(", ")) }]
+        }
+
+        // other errors cover the function they were found in
+        CompileError::Function(name, e) => {
+            let range = find_symbol(symbols, &name).map(|s| s.range).unwrap_or_default();
+            let messages = match *e {
+                CompileError::Strict(messages) => messages,
+                e => vec![e.to_string()]
+            };
+            messages.into_iter().map(|message| Diagnostic { range, severity, message }).collect()
+        }
+
+        e => vec![Diagnostic { range: Range::default(), severity, message: e.to_string() }]
+    }
+}
+
+// find a member by 'Class.member', the namespace of the class is not part of its symbol
+fn find_symbol<'a>(symbols: &'a [DocumentSymbol], name: &str) -> Option<&'a DocumentSymbol> {
+    let (class_name, member) = name.rsplit_once('.')?;
+    let class_name = class_name.rsplit('.').next().unwrap_or(class_name);
+    let class = symbols.iter().find(|s| s.name == class_name)?;
+    class.children.iter().find(|s| s.name == member)
+}
+
+//==================================================================================================
+// SYMBOLS
+
+// textDocument/documentSymbol: classes with their fields, constructor and functions
+pub fn document_symbols(source: &str) -> Vec<DocumentSymbol> {
+    let source = &preprocess(source, &HashSet::new()).unwrap_or_else(|_| source.to_string());
+    let Ok(script) = parser::script(source) else { return vec![] };
+    let locator = Locator::new(source);
+
+    let mut symbols = vec![];
+    let mut doc = None;
+    let mut search_from = 0;
+    for token in script.iter() {
+        match token {
+            Token::DocComment(text) => append_doc(&mut doc, text),
+            Token::Class(class_name, items) => {
+                let start = locator.find_keyword(search_from, "class", class_name).unwrap_or(search_from);
+                let end = locator.find_block_end(start);
+                search_from = end;
+
+                symbols.push(DocumentSymbol {
+                    name: class_name.to_string(),
+                    kind: SymbolKind::Class,
+                    range: locator.range(start, end),
+                    documentation: doc.take(),
+                    children: class_members(&locator, items, start),
+                });
+            }
+            _ => doc = None
+        }
+    }
+    symbols
+}
+
+fn class_members(locator: &Locator, items: &[Token], class_start: usize) -> Vec<DocumentSymbol> {
+    let mut members = vec![];
+    let mut doc = None;
+    let mut search_from = class_start;
+    for item in items {
+        let (name, kind, keyword) = match item {
+            Token::DocComment(text) => {
+                append_doc(&mut doc, text);
+                continue;
+            }
+            Token::Attribute(_) => continue,
+            Token::Variable(name, _) => (name.to_string(), SymbolKind::Field, "var"),
+            Token::Constructor(_, _) => (String::from("constructor"), SymbolKind::Constructor, ""),
+            Token::Function(name, _, _) => (name.to_string(), SymbolKind::Function, "function"),
+            _ => {
+                doc = None;
+                continue;
+            }
+        };
+
+        let start = locator.find_keyword(search_from, keyword, &name).unwrap_or(search_from);
+        let end = match kind {
+            SymbolKind::Field => locator.find_line_end(start),
+            _ => locator.find_block_end(start)
+        };
+        search_from = end;
+
+        members.push(DocumentSymbol { name, kind, range: locator.range(start, end), documentation: doc.take(), children: vec![] });
+    }
+    members
+}
+
+fn append_doc(doc: &mut Option<String>, line: &str) {
+    match doc {
+        Some(text) => {
+            text.push('\n');
+            text.push_str(line);
+        }
+        None => *doc = Some(line.to_string())
+    }
+}
+
+//==================================================================================================
+// COMPLETION
+
+// textDocument/completion: members after 'this.' or 'ClassName.', otherwise the class names
+pub fn completion(source: &str, position: Position) -> Vec<CompletionItem> {
+
+    // the line being typed is usually incomplete, so leave it out if the script does not parse
+    let mut symbols = document_symbols(source);
+    if symbols.is_empty() {
+        let without_line: Vec<&str> = source.split('\n').enumerate()
+            .map(|(i, l)| if i == position.line { "" } else { l })
+            .collect();
+        symbols = document_symbols(&without_line.join("\n"));
+    }
+
+    // find the word being completed and what is before the dot
+    let line = source.lines().nth(position.line).unwrap_or_default();
+    let before: String = line.chars().take(position.character).collect();
+    let word_start = before.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+    let receiver = word_start.strip_suffix('.').map(|r| {
+        let start = r.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+        &r[start.len()..]
+    });
+
+    let class = match receiver {
+        Some("this") => symbols.iter().find(|s| s.range.contains(position)),
+        Some(name) => symbols.iter().find(|s| s.name == name),
+        None => {
+            return symbols.iter()
+                .map(|s| CompletionItem { label: s.name.clone(), kind: s.kind, documentation: s.documentation.clone() })
+                .collect()
+        }
+    };
+
+    class.map(|c| c.children.iter()
+        .filter(|m| m.kind != SymbolKind::Constructor)
+        .map(|m| CompletionItem { label: m.name.clone(), kind: m.kind, documentation: m.documentation.clone() })
+        .collect()
+    ).unwrap_or_default()
+}
+
+//==================================================================================================
+// SOURCE POSITIONS
+
+// finds declarations in the source, as tokens do not record where they came from
+struct Locator<'a> {
+    source: &'a str,
+}
+
+impl<'a> Locator<'a> {
+
+    fn new(source: &'a str) -> Self {
+        Locator { source }
+    }
+
+    // byte offset of 'keyword name' at or after the offset
+    fn find_keyword(&self, from: usize, keyword: &str, name: &str) -> Option<usize> {
+        let mut offset = from;
+        while let Some(found) = self.source[offset..].find(name) {
+            let start = offset + found;
+            let end = start + name.len();
+            let before = self.source[..start].trim_end();
+            let boundary = !self.source[end..].starts_with(|c: char| c.is_alphanumeric() || c == '_');
+            if boundary && before.ends_with(keyword) {
+                return Some(if keyword.is_empty() { start } else { before.len() - keyword.len() });
+            }
+            offset = end;
+        }
+        None
+    }
+
+    // byte offset after the brace closing the first block at or after the offset
+    fn find_block_end(&self, from: usize) -> usize {
+        let mut depth = 0;
+        let mut in_string = false;
+        for (i, c) in self.source[from..].char_indices() {
+            match c {
+                '"' => in_string = !in_string,
+                '{' if !in_string => depth += 1,
+                '}' if !in_string => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return from + i + 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.source.len()
+    }
+
+    fn find_line_end(&self, from: usize) -> usize {
+        self.source[from..].find('\n').map(|i| from + i).unwrap_or(self.source.len())
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let before = &self.source[..offset];
+        let line = before.matches('\n').count();
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        Position { line, character: before[line_start..].chars().count() }
+    }
+
+    fn range(&self, start: usize, end: usize) -> Range {
+        Range { start: self.position(start), end: self.position(end) }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsp::{completion, diagnostics, document_symbols, Position, Severity, SymbolKind};
+
+    const SOURCE: &str = "/// A counter.\nclass Counter {\n\n    var total = 0;\n\n    /// Add one.\n    function add() {\n        this.total = this.total + 1;\n    }\n\n}\n\nclass Main {\n    function main() {\n        var c = new Counter();\n    }\n}\n";
+
+    #[test]
+    fn test_document_symbols() {
+        let symbols = document_symbols(SOURCE);
+        assert_eq!(symbols.len(), 2);
+
+        let counter = &symbols[0];
+        assert_eq!(counter.name, "Counter");
+        assert_eq!(counter.documentation.as_deref(), Some("A counter."));
+        assert_eq!(counter.range.start, Position { line: 1, character: 0 });
+        assert_eq!(counter.range.end, Position { line: 10, character: 1 });
+
+        let members: Vec<(&str, SymbolKind)> = counter.children.iter().map(|m| (m.name.as_str(), m.kind)).collect();
+        assert_eq!(members, vec![("total", SymbolKind::Field), ("add", SymbolKind::Function)]);
+        assert_eq!(counter.children[1].range.start, Position { line: 6, character: 4 });
+        assert_eq!(counter.children[1].documentation.as_deref(), Some("Add one."));
+
+        assert_eq!(symbols[1].range.start, Position { line: 12, character: 0 });
+    }
+
+    #[test]
+    fn test_document_symbols_with_directives() {
+        let symbols = document_symbols("class Test {\n#if DEBUG\n    function trace() { }\n#else\n    function run() { }\n#endif\n}");
+        let members: Vec<&str> = symbols[0].children.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(members, vec!["run"]);
+        assert_eq!(symbols[0].children[0].range.start, Position { line: 4, character: 4 });
+    }
+
+    #[test]
+    fn test_diagnostics_parse_error() {
+        let found = diagnostics("class Test {\n    function main() {\n        var x = ;\n    }\n}");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].severity, Severity::Error);
+        assert_eq!(found[0].range.start.line, 2);
+    }
+
+    #[test]
+    fn test_diagnostics_compile_error() {
+        let found = diagnostics("class Test {\n    function main() {\n        var x = new Missing();\n    }\n}");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].severity, Severity::Error);
+        assert_eq!(found[0].range.start, Position { line: 1, character: 4 });
+        assert_eq!(found[0].message, "class 'Missing' does not exist");
+    }
+
+    #[test]
+    fn test_diagnostics_strict_warnings() {
+        assert!(diagnostics(SOURCE).is_empty());
+        let found = diagnostics("class Test {\n    function main() {\n        total = 1;\n    }\n}");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_completion() {
+        let this_members = completion(SOURCE, Position { line: 7, character: 13 });
+        let labels: Vec<&str> = this_members.iter().map(|c| c.label.as_str()).collect();
+        assert_eq!(labels, vec!["total", "add"]);
+
+        let source = SOURCE.replace("var c = new Counter();", "Counter.a");
+        let class_members = completion(&source, Position { line: 14, character: 17 });
+        assert_eq!(class_members[1].documentation.as_deref(), Some("Add one."));
+
+        let classes = completion(SOURCE, Position { line: 14, character: 20 });
+        let labels: Vec<&str> = classes.iter().map(|c| c.label.as_str()).collect();
+        assert_eq!(labels, vec!["Counter", "Main"]);
+    }
+
+}
@@ -0,0 +1,7 @@
+// the parts of the std prelude that come from alloc, so that the crate builds without std
+pub use alloc::borrow::ToOwned;
+pub use alloc::boxed::Box;
+pub use alloc::format;
+pub use alloc::string::{String, ToString};
+pub use alloc::vec;
+pub use alloc::vec::Vec;
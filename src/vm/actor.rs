@@ -0,0 +1,235 @@
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
+use crate::vm::host::HostObject;
+use crate::vm::priority_queue::PriorityQueue;
+use crate::vm::value::Value;
+use crate::vm::view::ArrayView;
+use crate::vm::VM;
+
+pub const ACTOR_MESSAGE_METHOD_NAME: &str = "on_message";
+pub const MAILBOX_SEND_METHOD_NAME: &str = "send";
+
+// Actor
+//
+// an object of a script class living in a vm of its own, so it shares no values with the vm that
+// spawned it or with other actors. messages are copied into its mailbox, and its on_message method
+// is called with each of them when the host pumps it
+pub struct Actor {
+    vm: VM,
+    instance: Value,
+    mailbox: Mailbox,
+}
+
+// messages waiting for an actor. scripts hold it as a host object they can send through
+#[derive(Clone, Debug, Default)]
+pub struct Mailbox {
+    messages: Rc<RefCell<VecDeque<Value>>>,
+}
+
+impl Actor {
+
+    pub(crate) fn new(vm: VM, instance: Value) -> Actor {
+        Actor { vm, instance, mailbox: Mailbox::default() }
+    }
+
+    // copy a message into the mailbox
+    pub fn send(&self, message: &Value) -> Result<(), RuntimeError> {
+        self.mailbox.send(message)
+    }
+
+    // a value scripts can send messages to the actor through, with handle.send(message)
+    pub fn handle(&self) -> Value {
+        Value::HostObject(Rc::new(HostObject::new(self.mailbox.clone())))
+    }
+
+    pub fn pending(&self) -> usize {
+        self.mailbox.len()
+    }
+
+    // call on_message with each message waiting, including any sent meanwhile, and collect what
+    // it returns. an error leaves the messages after the one that failed in the mailbox
+    pub fn pump(&mut self) -> Result<Vec<Value>, RuntimeError> {
+        let mut results = vec![];
+        while let Some(message) = self.mailbox.take() {
+            results.push(self.vm.call_member(&self.instance, ACTOR_MESSAGE_METHOD_NAME, vec![message])?);
+        }
+        Ok(results)
+    }
+
+    // the object the actor is, which belongs to its vm
+    pub fn instance(&self) -> &Value {
+        &self.instance
+    }
+
+    pub fn vm(&mut self) -> &mut VM {
+        &mut self.vm
+    }
+
+}
+
+impl Mailbox {
+
+    pub fn send(&self, message: &Value) -> Result<(), RuntimeError> {
+        let message = copy(message)?;
+        self.messages.borrow_mut().push_back(message);
+        Ok(())
+    }
+
+    fn take(&self) -> Option<Value> {
+        self.messages.borrow_mut().pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+}
+
+// copy a value so nothing in it is shared with the original, keeping values that appear more
+// than once, or inside themselves, shared within the copy. host objects are the host's to share,
+// so they are passed as they are, while generators, coroutines and sequences can not be copied
+pub fn copy(value: &Value) -> Result<Value, RuntimeError> {
+    Copier::default().copy(value)
+}
+
+#[derive(Default)]
+struct Copier {
+    copies: HashMap<usize, Value>,
+}
+
+impl Copier {
+
+    fn copy(&mut self, value: &Value) -> Result<Value, RuntimeError> {
+        if let Some(copy) = value.identity().and_then(|identity| self.copies.get(&identity)) {
+            return Ok(copy.clone());
+        }
+        match value {
+            Value::Array(items) => {
+                let copy = Rc::new(RefCell::new(vec![]));
+                self.keep(value, Value::Array(copy.clone()));
+                let items = self.copy_all(items.borrow().iter())?;
+                copy.borrow_mut().extend(items);
+                Ok(Value::Array(copy))
+            }
+            Value::Dictionary(entries) | Value::Object(entries) => {
+                let copy = Rc::new(RefCell::new(HashMap::new()));
+                let copied = if matches!(value, Value::Object(_)) { Value::Object(copy.clone()) } else { Value::Dictionary(copy.clone()) };
+                self.keep(value, copied.clone());
+                let entries = self.copy_map(&entries.borrow())?;
+                copy.borrow_mut().extend(entries);
+                Ok(copied)
+            }
+            Value::Class(members) => {
+                let copy = Value::Class(Rc::new(self.copy_map(members)?));
+                self.keep(value, copy.clone());
+                Ok(copy)
+            }
+            Value::Deque(items) => {
+                let copy = Rc::new(RefCell::new(VecDeque::new()));
+                self.keep(value, Value::Deque(copy.clone()));
+                let items = self.copy_all(items.borrow().iter())?;
+                copy.borrow_mut().extend(items);
+                Ok(Value::Deque(copy))
+            }
+            Value::PriorityQueue(queue) => {
+                let copy = Rc::new(RefCell::new(PriorityQueue::new()));
+                self.keep(value, Value::PriorityQueue(copy.clone()));
+                let entries = queue.borrow().entries();
+                for (item, priority) in entries {
+                    let item = self.copy(&item)?;
+                    copy.borrow_mut().push(item, priority)?;
+                }
+                Ok(Value::PriorityQueue(copy))
+            }
+            // sets only hold simple values
+            Value::Set(set) => Ok(self.keep(value, Value::Set(Rc::new(RefCell::new(set.borrow().clone()))))),
+            Value::StringBuilder(buffer) => Ok(self.keep(value, Value::StringBuilder(Rc::new(RefCell::new(buffer.borrow().clone()))))),
+            Value::ArrayView(view) => {
+                let (start, end) = view.range();
+                match self.copy(&Value::Array(view.array().clone()))? {
+                    Value::Array(items) => Ok(Value::ArrayView(Rc::new(ArrayView::restore(items, start, end)))),
+                    _ => unreachable!()
+                }
+            }
+            Value::WeakRef(_) | Value::Generator(_) | Value::Coroutine(_) | Value::Sequence(_) => {
+                Err(RuntimeError::TypeError(format!("can not copy a {} to another vm", value.type_name())))
+            }
+            value => Ok(value.clone())
+        }
+    }
+
+    // remember the copy of a shared value before copying what is in it, so it can hold itself
+    fn keep(&mut self, value: &Value, copy: Value) -> Value {
+        if let Some(identity) = value.identity() {
+            self.copies.insert(identity, copy.clone());
+        }
+        copy
+    }
+
+    fn copy_all<'a>(&mut self, values: impl Iterator<Item = &'a Value>) -> Result<Vec<Value>, RuntimeError> {
+        values.map(|value| self.copy(value)).collect()
+    }
+
+    fn copy_map(&mut self, map: &HashMap<String, Value>) -> Result<HashMap<String, Value>, RuntimeError> {
+        map.iter().map(|(key, value)| Ok((key.clone(), self.copy(value)?))).collect()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    use crate::value;
+    use crate::vm::actor::{copy, Mailbox};
+    use crate::vm::coroutine::Coroutine;
+    use crate::vm::value::Value;
+
+    #[test]
+    fn test_copy_shares_nothing() {
+        let original = value!({ "tags": ["a", "b"], "count": 2 });
+        let copied = copy(&original).unwrap();
+        assert_eq!(copied, original);
+
+        let Value::Dictionary(entries) = &original else { panic!("expected a dictionary") };
+        entries.borrow_mut().insert(String::from("count"), Value::Integer(3));
+        assert_eq!(copied.get_path("count"), Value::Integer(2));
+        assert!(!copied.get_path("tags").identical(&original.get_path("tags")));
+    }
+
+    #[test]
+    fn test_copy_keeps_cycles() {
+        let items = value!([1]);
+        let Value::Array(inner) = &items else { panic!("expected an array") };
+        inner.borrow_mut().push(items.clone());
+
+        let Value::Array(copied) = copy(&items).unwrap() else { panic!("expected an array") };
+        assert!(matches!(&copied.borrow()[1], Value::Array(nested) if Rc::ptr_eq(nested, &copied)));
+        inner.borrow_mut().clear();
+        copied.borrow_mut().clear();
+    }
+
+    #[test]
+    fn test_mailbox() {
+        let mailbox = Mailbox::default();
+        mailbox.send(&Value::from("hello")).unwrap();
+        assert_eq!(mailbox.len(), 1);
+        assert_eq!(mailbox.take(), Some(Value::from("hello")));
+        assert!(mailbox.is_empty());
+
+        let coroutine = Value::Coroutine(Rc::new(RefCell::new(Coroutine::new(String::from("Test.run"), Value::Null))));
+        assert!(mailbox.send(&value!([coroutine])).is_err());
+        assert!(mailbox.is_empty());
+    }
+
+}
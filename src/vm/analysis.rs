@@ -0,0 +1,302 @@
+use core::fmt;
+use core::fmt::{Display, Formatter};
+
+use crate::collections::{HashMap, HashSet};
+use crate::compiler::CLASS_CONSTRUCTOR_FUNCTION_NAME;
+use crate::prelude::*;
+use crate::vm::instruction::Instruction;
+use crate::vm::program::Program;
+use crate::vm::value::Value;
+
+// Call Graph
+//
+// which functions of a program can call which, found without running it. methods are called by
+// name on whatever the receiver turns out to be, so a method call is taken to reach every method
+// of that name, and a function referenced as a value is taken to be called. the graph can show
+// calls that never happen but never misses one made by name or reference
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CallGraph {
+    // every function, in name order
+    pub functions: Vec<FunctionNode>,
+    // groups of functions that can call each other around, each in name order
+    pub cycles: Vec<Vec<String>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionNode {
+    pub name: String,
+    pub instructions: usize,
+    // functions it can call, in name order
+    pub calls: Vec<String>,
+    // most functions deep a call to it can go, itself included. a cycle counts once
+    pub depth: usize,
+}
+
+impl CallGraph {
+
+    pub fn function(&self, name: &str) -> Option<&FunctionNode> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    // the deepest call chain of any function, with cycles counted once
+    pub fn max_depth(&self) -> usize {
+        self.functions.iter().map(|function| function.depth).max().unwrap_or(0)
+    }
+
+    pub fn is_recursive(&self, name: &str) -> bool {
+        self.cycles.iter().any(|cycle| cycle.iter().any(|function| function == name))
+    }
+
+    // the graph for graphviz, with calls inside a cycle drawn in red
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph calls {\n");
+        for function in self.functions.iter() {
+            dot.push_str(&format!("    \"{}\" [label=\"{}\\n{} instructions\"];\n", function.name, function.name, function.instructions));
+        }
+        for function in self.functions.iter() {
+            for call in function.calls.iter() {
+                let cyclic = self.cycles.iter().any(|cycle| cycle.contains(&function.name) && cycle.contains(call));
+                dot.push_str(&format!("    \"{}\" -> \"{}\"{};\n", function.name, call, if cyclic { " [color=red]" } else { "" }));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+}
+
+// one line per function, such as 'Test.main 12 instructions, depth 2, calls Test.add', and
+// one per cycle
+impl Display for CallGraph {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for function in self.functions.iter() {
+            write!(f, "{} {} instructions, depth {}", function.name, function.instructions, function.depth)?;
+            if !function.calls.is_empty() {
+                write!(f, ", calls {}", function.calls.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        for cycle in self.cycles.iter() {
+            writeln!(f, "cycle {}", cycle.join(" -> "))?;
+        }
+        writeln!(f, "max depth {}", self.max_depth())
+    }
+}
+
+impl Program {
+
+    pub fn call_graph(&self) -> CallGraph {
+        let mut methods: HashMap<&str, Vec<&String>> = HashMap::new();
+        for value in self.globals.iter() {
+            if let Value::Class(members) = value {
+                for (member, value) in members.iter() {
+                    if let Value::FunctionRef(function) = value {
+                        methods.entry(member.as_str()).or_default().push(function);
+                    }
+                }
+            }
+        }
+
+        let mut functions: Vec<FunctionNode> = self.functions().into_iter().map(|(name, range)| {
+            let mut calls: HashSet<String> = HashSet::new();
+            // classes being constructed, whose constructor is the next one called
+            let mut constructing: Vec<Option<&String>> = vec![];
+            let mut previous: Option<&Instruction> = None;
+
+            for instruction in self.instructions[range.clone()].iter() {
+                match instruction {
+                    Instruction::CreateObject => constructing.push(match previous {
+                        Some(Instruction::LoadGlobal(index)) => match self.globals.get(*index) {
+                            Some(Value::Class(members)) => match members.get(CLASS_CONSTRUCTOR_FUNCTION_NAME) {
+                                Some(Value::FunctionRef(constructor)) => Some(constructor),
+                                _ => None
+                            },
+                            _ => None
+                        },
+                        _ => None
+                    }),
+                    Instruction::CallMethod(method, _) if method == CLASS_CONSTRUCTOR_FUNCTION_NAME && !constructing.is_empty() => {
+                        if let Some(Some(constructor)) = constructing.pop() {
+                            calls.insert(constructor.clone());
+                        }
+                    }
+                    Instruction::CallMethod(method, _) => {
+                        calls.extend(methods.get(method.as_str()).into_iter().flatten().map(|function| function.to_string()));
+                    }
+                    Instruction::StackPush(Value::FunctionRef(function)) if self.symbols.contains_key(function.as_str()) => {
+                        calls.insert(function.to_string());
+                    }
+                    _ => {}
+                }
+                previous = Some(instruction);
+            }
+
+            let mut calls: Vec<String> = calls.into_iter().collect();
+            calls.sort();
+            FunctionNode { name: name.to_string(), instructions: range.len(), calls, depth: 0 }
+        }).collect();
+        functions.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let components = Components::find(&functions);
+        let mut cycles = components.groups.iter()
+            .filter(|group| group.len() > 1 || functions[group[0]].calls.contains(&functions[group[0]].name))
+            .map(|group| {
+                let mut names: Vec<String> = group.iter().map(|index| functions[*index].name.clone()).collect();
+                names.sort();
+                names
+            })
+            .collect::<Vec<_>>();
+
+        // groups are found callees first, so the depth of every group called is already known
+        let mut depths = vec![0; components.groups.len()];
+        for (group_index, group) in components.groups.iter().enumerate() {
+            let called = group.iter()
+                .flat_map(|index| functions[*index].calls.iter())
+                .filter_map(|call| functions.binary_search_by(|f| f.name.cmp(call)).ok())
+                .map(|index| components.group_of[index])
+                .filter(|called| *called != group_index)
+                .map(|called| depths[called])
+                .max()
+                .unwrap_or(0);
+            depths[group_index] = called + 1;
+        }
+        for (index, function) in functions.iter_mut().enumerate() {
+            function.depth = depths[components.group_of[index]];
+        }
+
+        cycles.sort();
+        CallGraph { functions, cycles }
+    }
+
+}
+
+// the functions that can call each other around, found with tarjan's algorithm
+struct Components {
+    groups: Vec<Vec<usize>>,
+    group_of: Vec<usize>,
+}
+
+impl Components {
+
+    fn find(functions: &[FunctionNode]) -> Components {
+        let edges: Vec<Vec<usize>> = functions.iter()
+            .map(|function| function.calls.iter().filter_map(|call| functions.binary_search_by(|f| f.name.cmp(call)).ok()).collect())
+            .collect();
+
+        let count = functions.len();
+        let mut order = vec![usize::MAX; count];
+        let mut low = vec![0; count];
+        let mut on_stack = vec![false; count];
+        let mut stack = vec![];
+        let mut next = 0;
+        let mut components = Components { groups: vec![], group_of: vec![0; count] };
+
+        // walked without recursion so a long chain of calls can not overflow the host stack
+        for root in 0..count {
+            if order[root] != usize::MAX {
+                continue;
+            }
+            let mut walk = vec![(root, 0)];
+            order[root] = next;
+            low[root] = next;
+            next += 1;
+            stack.push(root);
+            on_stack[root] = true;
+
+            while let Some((node, edge)) = walk.pop() {
+                if let Some(&callee) = edges[node].get(edge) {
+                    walk.push((node, edge + 1));
+                    if order[callee] == usize::MAX {
+                        order[callee] = next;
+                        low[callee] = next;
+                        next += 1;
+                        stack.push(callee);
+                        on_stack[callee] = true;
+                        walk.push((callee, 0));
+                    } else if on_stack[callee] {
+                        low[node] = low[node].min(order[callee]);
+                    }
+                    continue;
+                }
+
+                if let Some((caller, _)) = walk.last() {
+                    low[*caller] = low[*caller].min(low[node]);
+                }
+                if low[node] == order[node] {
+                    let mut group = vec![];
+                    while let Some(member) = stack.pop() {
+                        on_stack[member] = false;
+                        components.group_of[member] = components.groups.len();
+                        group.push(member);
+                        if member == node {
+                            break;
+                        }
+                    }
+                    components.groups.push(group);
+                }
+            }
+        }
+
+        components
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::compile;
+
+    const SOURCE: &str = r#"
+        class Shape {
+            var side = 2;
+            function area() { return this.side * this.side; }
+        }
+        class Test {
+            function main() {
+                var shape = new Shape();
+                return this.fib(shape.area()) + this.even(3);
+            }
+            function fib(n) {
+                if (n < 2) { return n; }
+                return this.fib(n - 1) + this.fib(n - 2);
+            }
+            function even(n) { if (n == 0) { return true; } return this.odd(n - 1); }
+            function odd(n) { if (n == 0) { return false; } return this.even(n - 1); }
+            function unused() { return 0; }
+        }
+    "#;
+
+    #[test]
+    fn test_call_graph() {
+        let program = compile(SOURCE).unwrap();
+        let graph = program.call_graph();
+
+        let main = graph.function("Test.main").unwrap();
+        assert_eq!(main.calls, vec!["Shape.area", "Shape.constructor", "Test.even", "Test.fib"]);
+        assert_eq!(main.instructions, program.functions().into_iter().find(|(name, _)| *name == "Test.main").unwrap().1.len());
+        assert!(graph.function("Test.unused").unwrap().calls.is_empty());
+
+        assert!(graph.cycles.contains(&vec![String::from("Test.fib")]));
+        assert!(graph.cycles.contains(&vec![String::from("Test.even"), String::from("Test.odd")]));
+        assert!(graph.is_recursive("Test.odd") && !graph.is_recursive("Test.main"));
+
+        // main, then the even and odd cycle counted once
+        assert_eq!(graph.function("Test.even").unwrap().depth, 1);
+        assert_eq!(graph.function("Test.main").unwrap().depth, 2);
+        assert_eq!(graph.max_depth(), 2);
+    }
+
+    #[test]
+    fn test_report() {
+        let graph = compile(SOURCE).unwrap().call_graph();
+        let report = graph.to_string();
+        assert!(report.contains("cycle Test.even -> Test.odd\n"));
+        assert!(report.ends_with("max depth 2\n"));
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph calls {\n"));
+        assert!(dot.contains("    \"Test.main\" -> \"Test.fib\";\n"));
+        assert!(dot.contains("    \"Test.fib\" -> \"Test.fib\" [color=red];\n"));
+    }
+
+}
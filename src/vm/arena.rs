@@ -0,0 +1,155 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+use crate::vm::value::Value;
+
+type Array = Rc<RefCell<Vec<Value>>>;
+type Map = Rc<RefCell<HashMap<String, Value>>>;
+
+// Arena
+//
+// hands out the arrays, dictionaries and objects created while a call from the host runs and,
+// once it returns, takes back the ones nothing else holds so their memory is used again
+#[derive(Debug, Default)]
+pub struct Arena {
+    arrays: Vec<Array>,
+    maps: Vec<Map>,
+    free_arrays: Vec<Array>,
+    free_maps: Vec<Map>,
+    // how many collections may be handed out before looking for ones to take back
+    sweep_at: usize,
+}
+
+const MIN_SWEEP: usize = 64;
+
+impl Arena {
+
+    pub fn array(&mut self) -> Array {
+        self.sweep_if_full();
+        let array = self.free_arrays.pop().unwrap_or_default();
+        self.arrays.push(array.clone());
+        array
+    }
+
+    // dictionaries and objects share the same maps
+    pub fn map(&mut self) -> Map {
+        self.sweep_if_full();
+        let map = self.free_maps.pop().unwrap_or_default();
+        self.maps.push(map.clone());
+        map
+    }
+
+    // take back everything only the arena holds, leaving values that escaped to the host or globals alone
+    pub fn reset(&mut self) {
+        self.reclaim(false);
+    }
+
+    // a collection only the arena holds can not be reached by the script, so long running calls
+    // take back what they dropped whenever the number handed out doubles
+    fn sweep_if_full(&mut self) {
+        if self.arrays.len() + self.maps.len() >= self.sweep_at.max(MIN_SWEEP) {
+            self.reclaim(true);
+            self.sweep_at = (self.arrays.len() + self.maps.len()) * 2;
+        }
+    }
+
+    // collections are checked in the order they were created, so emptying an outer collection
+    // frees the ones created inside it in time for them to be checked. one with weak references
+    // is dropped instead of used again, so that the references give null rather than a new value
+    fn reclaim(&mut self, keep_tracking: bool) {
+        for array in core::mem::take(&mut self.arrays) {
+            if Rc::strong_count(&array) == 1 && Rc::weak_count(&array) == 0 {
+                array.borrow_mut().clear();
+                self.free_arrays.push(array);
+            } else if keep_tracking && Rc::strong_count(&array) > 1 {
+                self.arrays.push(array);
+            }
+        }
+        for map in core::mem::take(&mut self.maps) {
+            if Rc::strong_count(&map) == 1 && Rc::weak_count(&map) == 0 {
+                map.borrow_mut().clear();
+                self.free_maps.push(map);
+            } else if keep_tracking && Rc::strong_count(&map) > 1 {
+                self.maps.push(map);
+            }
+        }
+    }
+
+    // number of collections waiting to be used again
+    pub fn free(&self) -> usize {
+        self.free_arrays.len() + self.free_maps.len()
+    }
+
+}
+
+// a new array, from the arena when there is one
+pub fn new_array(arena: &mut Option<Arena>) -> Array {
+    arena.as_mut().map(Arena::array).unwrap_or_default()
+}
+
+// a new map, from the arena when there is one
+pub fn new_map(arena: &mut Option<Arena>) -> Map {
+    arena.as_mut().map(Arena::map).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::vm::arena::{Arena, MIN_SWEEP};
+    use crate::vm::value::Value;
+
+    #[test]
+    fn test_reset_reuses() {
+        let mut arena = Arena::default();
+        let outer = arena.array();
+        let inner = arena.array();
+        outer.borrow_mut().push(Value::Array(inner));
+        drop(outer);
+
+        arena.reset();
+        assert_eq!(arena.free(), 2);
+
+        let reused = arena.array();
+        assert!(reused.borrow().is_empty());
+        assert_eq!(arena.free(), 1);
+    }
+
+    #[test]
+    fn test_sweep_while_running() {
+        let mut arena = Arena::default();
+        let kept = arena.array();
+        for _ in 0..200 {
+            let _ = arena.map();
+        }
+        // dropped maps are reused so no more than one sweep's worth are ever allocated
+        assert!(arena.free() > 0);
+        assert_eq!(arena.arrays.len() + arena.maps.len() + arena.free(), MIN_SWEEP);
+        drop(kept);
+    }
+
+    #[test]
+    fn test_reset_drops_weakly_held() {
+        let mut arena = Arena::default();
+        let watched = Rc::downgrade(&arena.map());
+
+        arena.reset();
+        assert_eq!(arena.free(), 0);
+        assert!(watched.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_reset_keeps_escaped() {
+        let mut arena = Arena::default();
+        let kept = arena.map();
+        kept.borrow_mut().insert(String::from("a"), Value::Integer(1));
+        let _ = arena.map();
+
+        arena.reset();
+        assert_eq!(arena.free(), 1);
+        assert_eq!(kept.borrow().get("a"), Some(&Value::Integer(1)));
+    }
+
+}
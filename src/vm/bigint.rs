@@ -0,0 +1,320 @@
+use core::cmp::Ordering;
+use core::fmt;
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
+
+use crate::prelude::*;
+
+// each limb holds nine decimal digits, so printing and parsing need no conversion
+const BASE: u64 = 1_000_000_000;
+const BASE_DIGITS: usize = 9;
+
+// Big Integer
+//
+// an integer of any size, stored as a sign and limbs with the least significant first.
+// zero has no limbs and is never negative, so equal values always have the same fields
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+
+    pub fn zero() -> Self {
+        BigInt::default()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    // bytes used by the limbs, for memory limits
+    pub fn size(&self) -> usize {
+        self.limbs.len() * size_of::<u32>()
+    }
+
+    // the value as an i32, if it fits
+    pub fn to_i32(&self) -> Option<i32> {
+        let magnitude = self.limbs.iter().rev().try_fold(0i64, |total, limb| total.checked_mul(BASE as i64)?.checked_add(*limb as i64))?;
+        i32::try_from(if self.negative { -magnitude } else { magnitude }).ok()
+    }
+
+    // the nearest f64, which may lose precision
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self.limbs.iter().rev().fold(0.0, |total, limb| total * BASE as f64 + *limb as f64);
+        if self.negative { -magnitude } else { magnitude }
+    }
+
+    pub fn neg(&self) -> BigInt {
+        BigInt::new(!self.negative, self.limbs.clone())
+    }
+
+    pub fn abs(&self) -> BigInt {
+        BigInt::new(false, self.limbs.clone())
+    }
+
+    pub fn add(&self, rhs: &BigInt) -> BigInt {
+        if self.negative == rhs.negative {
+            return BigInt::new(self.negative, add_limbs(&self.limbs, &rhs.limbs));
+        }
+        match compare_limbs(&self.limbs, &rhs.limbs) {
+            Ordering::Less => BigInt::new(rhs.negative, sub_limbs(&rhs.limbs, &self.limbs)),
+            _ => BigInt::new(self.negative, sub_limbs(&self.limbs, &rhs.limbs))
+        }
+    }
+
+    pub fn sub(&self, rhs: &BigInt) -> BigInt {
+        self.add(&rhs.neg())
+    }
+
+    pub fn mul(&self, rhs: &BigInt) -> BigInt {
+        let mut limbs = vec![0u64; self.limbs.len() + rhs.limbs.len()];
+        for (i, a) in self.limbs.iter().enumerate() {
+            let mut carry = 0;
+            for (j, b) in rhs.limbs.iter().enumerate() {
+                let total = limbs[i + j] + *a as u64 * *b as u64 + carry;
+                limbs[i + j] = total % BASE;
+                carry = total / BASE;
+            }
+            limbs[i + rhs.limbs.len()] += carry;
+        }
+        BigInt::new(self.negative != rhs.negative, limbs.into_iter().map(|limb| limb as u32).collect())
+    }
+
+    // the quotient rounded towards zero and the remainder, which has the sign of self
+    pub fn div_rem(&self, rhs: &BigInt) -> Option<(BigInt, BigInt)> {
+        if rhs.is_zero() {
+            return None;
+        }
+
+        // long division, finding each limb of the quotient by a binary search
+        let mut quotient = vec![0u32; self.limbs.len()];
+        let mut remainder: Vec<u32> = vec![];
+        for (index, limb) in self.limbs.iter().enumerate().rev() {
+            remainder.insert(0, *limb);
+            trim(&mut remainder);
+
+            let (mut low, mut high) = (0, BASE as u32 - 1);
+            while low < high {
+                let mid = low + (high - low).div_ceil(2);
+                match compare_limbs(&mul_small(&rhs.limbs, mid), &remainder) {
+                    Ordering::Greater => high = mid - 1,
+                    _ => low = mid
+                }
+            }
+            quotient[index] = low;
+            remainder = sub_limbs(&remainder, &mul_small(&rhs.limbs, low));
+        }
+
+        Some((BigInt::new(self.negative != rhs.negative, quotient), BigInt::new(self.negative, remainder)))
+    }
+
+    // the quotient rounded down
+    pub fn floor_div(&self, rhs: &BigInt) -> Option<BigInt> {
+        let (quotient, remainder) = self.div_rem(rhs)?;
+        match !remainder.is_zero() && self.negative != rhs.negative {
+            true => Some(quotient.sub(&BigInt::from(1))),
+            false => Some(quotient)
+        }
+    }
+
+    pub fn pow(&self, mut exponent: u32) -> BigInt {
+        let mut result = BigInt::from(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.mul(&base);
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = base.mul(&base);
+            }
+        }
+        result
+    }
+
+    fn new(negative: bool, mut limbs: Vec<u32>) -> Self {
+        trim(&mut limbs);
+        BigInt { negative: negative && !limbs.is_empty(), limbs }
+    }
+
+}
+
+// drop the most significant limbs that are zero
+fn trim(limbs: &mut Vec<u32>) {
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+}
+
+fn compare_limbs(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_limbs(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut limbs = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let total = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        limbs.push((total % BASE) as u32);
+        carry = total / BASE;
+    }
+    limbs.push(carry as u32);
+    limbs
+}
+
+// a minus b, where a is at least b
+fn sub_limbs(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut limbs = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, limb) in a.iter().enumerate() {
+        let mut total = *limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = (total < 0) as i64;
+        if total < 0 {
+            total += BASE as i64;
+        }
+        limbs.push(total as u32);
+    }
+    trim(&mut limbs);
+    limbs
+}
+
+fn mul_small(a: &[u32], b: u32) -> Vec<u32> {
+    let mut limbs = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0;
+    for limb in a {
+        let total = *limb as u64 * b as u64 + carry;
+        limbs.push((total % BASE) as u32);
+        carry = total / BASE;
+    }
+    limbs.push(carry as u32);
+    trim(&mut limbs);
+    limbs
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> Self {
+        let mut magnitude = value.unsigned_abs();
+        let mut limbs = vec![];
+        while magnitude > 0 {
+            limbs.push((magnitude % BASE) as u32);
+            magnitude /= BASE;
+        }
+        BigInt::new(value < 0, limbs)
+    }
+}
+
+impl From<i32> for BigInt {
+    fn from(value: i32) -> Self {
+        BigInt::from(value as i64)
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = String;
+
+    // decimal digits with an optional minus sign
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text)
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("'{}' is not an integer", text));
+        }
+
+        let limbs = digits.as_bytes().rchunks(BASE_DIGITS)
+            .map(|chunk| chunk.iter().fold(0, |limb, digit| limb * 10 + (digit - b'0') as u32))
+            .collect();
+        Ok(BigInt::new(negative, limbs))
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Some((first, rest)) = self.limbs.split_last() else {
+            return write!(f, "0");
+        };
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", first)?;
+        for limb in rest.iter().rev() {
+            write!(f, "{:09}", limb)?;
+        }
+        Ok(())
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, rhs: &Self) -> Option<Ordering> {
+        Some(self.cmp(rhs))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, rhs: &Self) -> Ordering {
+        match (self.negative, rhs.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_limbs(&self.limbs, &rhs.limbs),
+            (true, true) => compare_limbs(&rhs.limbs, &self.limbs)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::bigint::BigInt;
+
+    fn big(text: &str) -> BigInt {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(big("123456789012345678901234567890").to_string(), "123456789012345678901234567890");
+        assert_eq!(big("-000000000001000000000").to_string(), "-1000000000");
+        assert_eq!(big("-0").to_string(), "0");
+        assert!("12a".parse::<BigInt>().is_err());
+        assert!("".parse::<BigInt>().is_err());
+        assert_eq!(BigInt::from(i64::MIN).to_string(), "-9223372036854775808");
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = big("99999999999999999999");
+        assert_eq!(a.add(&BigInt::from(1)).to_string(), "100000000000000000000");
+        assert_eq!(BigInt::from(5).sub(&a).to_string(), "-99999999999999999994");
+        assert_eq!(a.mul(&a).to_string(), "9999999999999999999800000000000000000001");
+        assert_eq!(a.mul(&BigInt::from(-1)), a.neg());
+        assert_eq!(BigInt::from(2).pow(100).to_string(), "1267650600228229401496703205376");
+    }
+
+    #[test]
+    fn test_division() {
+        let a = big("1267650600228229401496703205376");
+        let (quotient, remainder) = a.div_rem(&big("1000000007")).unwrap();
+        assert_eq!(quotient.mul(&big("1000000007")).add(&remainder), a);
+        assert!(remainder < big("1000000007"));
+
+        assert_eq!(BigInt::from(-7).div_rem(&BigInt::from(2)), Some((BigInt::from(-3), BigInt::from(-1))));
+        assert_eq!(BigInt::from(-7).floor_div(&BigInt::from(2)), Some(BigInt::from(-4)));
+        assert_eq!(BigInt::from(7).floor_div(&BigInt::from(-2)), Some(BigInt::from(-4)));
+        assert_eq!(BigInt::from(1).div_rem(&BigInt::zero()), None);
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(BigInt::from(i32::MIN).to_i32(), Some(i32::MIN));
+        assert_eq!(BigInt::from(i32::MAX as i64 + 1).to_i32(), None);
+        assert_eq!(big("1000000000000").to_f64(), 1e12);
+        assert!(big("-5") < big("3") && big("-5") < big("-3") && big("1000000000") > big("999999999"));
+    }
+
+}
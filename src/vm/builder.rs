@@ -0,0 +1,138 @@
+use crate::collections::HashMap;
+use crate::prelude::*;
+use crate::vm::value::Value;
+
+// builds a dictionary value one entry at a time
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValueMap {
+    entries: HashMap<String, Value>,
+}
+
+impl ValueMap {
+
+    pub fn new() -> Self {
+        ValueMap::default()
+    }
+
+    pub fn insert(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.entries.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn build(self) -> Value {
+        Value::from(self.entries)
+    }
+
+}
+
+impl From<ValueMap> for Value {
+    fn from(map: ValueMap) -> Self {
+        map.build()
+    }
+}
+
+// builds an array value one item at a time
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValueArray {
+    items: Vec<Value>,
+}
+
+impl ValueArray {
+
+    pub fn new() -> Self {
+        ValueArray::default()
+    }
+
+    pub fn push(mut self, value: impl Into<Value>) -> Self {
+        self.items.push(value.into());
+        self
+    }
+
+    pub fn build(self) -> Value {
+        Value::from(self.items)
+    }
+
+}
+
+impl From<ValueArray> for Value {
+    fn from(array: ValueArray) -> Self {
+        array.build()
+    }
+}
+
+// build a value from json like syntax, where any other expression is converted with Value::from
+//
+//     value!({ "name": "ada", "tags": ["a", "b"], "age": 36, "boss": null })
+#[macro_export]
+macro_rules! value {
+
+    // push each comma separated item onto the array
+    (@array $array:expr;) => { $array.build() };
+    (@array $array:expr; null $(, $($rest:tt)*)?) => { $crate::value!(@array $array.push($crate::value!(null)); $($($rest)*)?) };
+    (@array $array:expr; [ $($item:tt)* ] $(, $($rest:tt)*)?) => { $crate::value!(@array $array.push($crate::value!([ $($item)* ])); $($($rest)*)?) };
+    (@array $array:expr; { $($item:tt)* } $(, $($rest:tt)*)?) => { $crate::value!(@array $array.push($crate::value!({ $($item)* })); $($($rest)*)?) };
+    (@array $array:expr; $item:expr $(, $($rest:tt)*)?) => { $crate::value!(@array $array.push($crate::value!($item)); $($($rest)*)?) };
+
+    // insert each comma separated key and value into the map
+    (@map $map:expr;) => { $map.build() };
+    (@map $map:expr; $key:literal : null $(, $($rest:tt)*)?) => { $crate::value!(@map $map.insert($key, $crate::value!(null)); $($($rest)*)?) };
+    (@map $map:expr; $key:literal : [ $($item:tt)* ] $(, $($rest:tt)*)?) => { $crate::value!(@map $map.insert($key, $crate::value!([ $($item)* ])); $($($rest)*)?) };
+    (@map $map:expr; $key:literal : { $($item:tt)* } $(, $($rest:tt)*)?) => { $crate::value!(@map $map.insert($key, $crate::value!({ $($item)* })); $($($rest)*)?) };
+    (@map $map:expr; $key:literal : $value:expr $(, $($rest:tt)*)?) => { $crate::value!(@map $map.insert($key, $crate::value!($value)); $($($rest)*)?) };
+
+    // a single value
+    (null) => { $crate::vm::value::Value::Null };
+    ([]) => { $crate::vm::builder::ValueArray::new().build() };
+    ([ $($tt:tt)+ ]) => { $crate::value!(@array $crate::vm::builder::ValueArray::new(); $($tt)+) };
+    ({}) => { $crate::vm::builder::ValueMap::new().build() };
+    ({ $($tt:tt)+ }) => { $crate::value!(@map $crate::vm::builder::ValueMap::new(); $($tt)+) };
+    ($other:expr) => { $crate::vm::value::Value::from($other) };
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::collections::HashMap;
+    use crate::vm::builder::{ValueArray, ValueMap};
+    use crate::vm::value::Value;
+
+    #[test]
+    fn test_builders() {
+        let built = ValueMap::new()
+            .insert("name", "ada")
+            .insert("tags", ValueArray::new().push(1).push(2.5).push(true))
+            .insert("boss", None::<i32>)
+            .build();
+
+        assert_eq!(built.get_path("name"), Value::from("ada"));
+        assert_eq!(built.get_path("tags[1]"), Value::Float(2.5));
+        assert_eq!(built.get_path("tags[2]"), Value::Bool(true));
+        assert!(matches!(&built, Value::Dictionary(d) if d.borrow().get("boss") == Some(&Value::Null)));
+    }
+
+    #[test]
+    fn test_macro() {
+        let count = 3;
+        let built = value!({
+            "name": "ada",
+            "count": count + 1,
+            "nested": { "tags": ["a", -1, null, [], { "deep": true }] },
+            "empty": {},
+            "boss": null,
+        });
+
+        assert_eq!(built.get_path("name"), Value::from("ada"));
+        assert_eq!(built.get_path("count"), Value::Integer(4));
+        assert_eq!(built.get_path("nested.tags[1]"), Value::Integer(-1));
+        assert_eq!(built.get_path("nested.tags[2]"), Value::Null);
+        assert_eq!(built.get_path("nested.tags[3]"), Value::Array(Rc::new(RefCell::new(vec![]))));
+        assert_eq!(built.get_path("nested.tags[4].deep"), Value::Bool(true));
+        assert_eq!(built.get_path("empty"), Value::Dictionary(Rc::new(RefCell::new(HashMap::new()))));
+        assert_eq!(value!(null), Value::Null);
+        assert_eq!(value!([1, 2]), Value::from(vec![1, 2]));
+    }
+
+}
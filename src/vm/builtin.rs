@@ -0,0 +1,780 @@
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::fmt::Write;
+
+use crate::logging::trace;
+
+use crate::prelude::*;
+use crate::vm::config::StringUnits;
+use crate::vm::csv;
+use crate::vm::error::RuntimeError;
+use crate::vm::expect;
+use crate::vm::format;
+#[cfg(feature = "bigint")]
+use crate::vm::exact;
+#[cfg(feature = "hash")]
+use crate::vm::hash;
+#[cfg(feature = "toml")]
+use crate::vm::toml;
+#[cfg(feature = "uuid")]
+use crate::vm::uuid;
+#[cfg(feature = "yaml")]
+use crate::vm::yaml;
+use crate::vm::priority_queue::PriorityQueue;
+use crate::vm::set;
+use crate::vm::set::ValueSet;
+use crate::vm::value::Value;
+use crate::vm::view::ArrayView;
+use crate::vm::weak::WeakRef;
+
+pub const STRING_BUILDER_CLASS_NAME: &str = "StringBuilder";
+pub const EVENT_HANDLER_FUNCTION_NAME: &str = "on";
+pub const FORMAT_FUNCTION_NAME: &str = "format";
+pub const FORMAT_NUMBER_FUNCTION_NAME: &str = "format_number";
+pub const GET_PATH_FUNCTION_NAME: &str = "get_path";
+pub const FROM_CHAR_CODE_FUNCTION_NAME: &str = "from_char_code";
+pub const COMPARE_FUNCTION_NAME: &str = "compare";
+pub const WEAKREF_FUNCTION_NAME: &str = "weakref";
+pub const ID_FUNCTION_NAME: &str = "id";
+pub const HASH_FUNCTION_NAME: &str = "hash";
+// coroutines run in the frames of the vm, so it makes and runs them rather than call_function
+pub const COROUTINE_FUNCTION_NAME: &str = "coroutine";
+pub const CSV_MODULE_NAME: &str = "Csv";
+pub const HASH_MODULE_NAME: &str = "Hash";
+pub const UUID_MODULE_NAME: &str = "Uuid";
+pub const TOML_MODULE_NAME: &str = "Toml";
+pub const YAML_MODULE_NAME: &str = "Yaml";
+pub const LOG_MODULE_NAME: &str = "Log";
+pub const EXPECT_MODULE_NAME: &str = "Expect";
+pub const BIGINT_MODULE_NAME: &str = "BigInt";
+pub const DECIMAL_MODULE_NAME: &str = "Decimal";
+pub const SET_MODULE_NAME: &str = "Set";
+pub const DEQUE_MODULE_NAME: &str = "Deque";
+pub const PRIORITY_QUEUE_MODULE_NAME: &str = "PriorityQueue";
+// timers are kept by the vm, so their functions are called by it rather than call_function
+pub const TIMER_MODULE_NAME: &str = "Timer";
+// target of the records scripts log, so hosts can filter them from their own
+pub const LOG_TARGET: &str = "tinyscript::script";
+pub const GENERATOR_NEXT_METHOD_NAME: &str = "next";
+pub const GENERATOR_DONE_METHOD_NAME: &str = "done";
+// sorting calls back into the script, so the vm runs it rather than call_method
+pub const ARRAY_SORT_BY_METHOD_NAME: &str = "sort_by";
+pub const ARRAY_VIEW_METHOD_NAME: &str = "view";
+pub const COROUTINE_RESUME_METHOD_NAME: &str = "resume";
+pub const COROUTINE_YIELD_METHOD_NAME: &str = "yield";
+
+// check if a class is provided by the vm rather than the script
+pub fn is_builtin_class(name: &str) -> bool {
+    matches!(name, STRING_BUILDER_CLASS_NAME)
+}
+
+// camelCase names scripts written for javascript reach for, each an alias of the snake_case
+// builtin the rest of the language uses
+const ALIASES: &[(&str, &str)] = &[
+    ("fromCharCode", FROM_CHAR_CODE_FUNCTION_NAME),
+    ("startsWith", "starts_with"),
+    ("endsWith", "ends_with"),
+    ("padStart", "pad_start"),
+    ("padEnd", "pad_end"),
+    ("charCodeAt", "char_code_at"),
+    ("equalsIgnoreCase", "equals_ignore_case"),
+    ("compareIgnoreCase", "compare_ignore_case"),
+];
+
+// the name a builtin function or method is known by, following an alias
+pub fn builtin_name(name: &str) -> &str {
+    ALIASES.iter().find(|(alias, _)| *alias == name).map_or(name, |(_, builtin)| builtin)
+}
+
+// check if a function is provided by the vm rather than the script
+pub fn is_builtin_function(name: &str) -> bool {
+    matches!(builtin_name(name), EVENT_HANDLER_FUNCTION_NAME | FORMAT_FUNCTION_NAME | FORMAT_NUMBER_FUNCTION_NAME | GET_PATH_FUNCTION_NAME | FROM_CHAR_CODE_FUNCTION_NAME | COMPARE_FUNCTION_NAME | WEAKREF_FUNCTION_NAME | ID_FUNCTION_NAME | HASH_FUNCTION_NAME | COROUTINE_FUNCTION_NAME)
+}
+
+// modules of functions and whether the feature they need is enabled
+const MODULES: &[(&str, bool)] = &[
+    (CSV_MODULE_NAME, true),
+    (SET_MODULE_NAME, true),
+    (DEQUE_MODULE_NAME, true),
+    (PRIORITY_QUEUE_MODULE_NAME, true),
+    (TIMER_MODULE_NAME, true),
+    (LOG_MODULE_NAME, true),
+    (EXPECT_MODULE_NAME, true),
+    (HASH_MODULE_NAME, cfg!(feature = "hash")),
+    (UUID_MODULE_NAME, cfg!(feature = "uuid")),
+    (TOML_MODULE_NAME, cfg!(feature = "toml")),
+    (YAML_MODULE_NAME, cfg!(feature = "yaml")),
+    (BIGINT_MODULE_NAME, cfg!(feature = "bigint")),
+    (DECIMAL_MODULE_NAME, cfg!(feature = "decimal")),
+];
+
+// check if a module of functions is provided by the vm
+pub fn is_builtin_module(name: &str) -> bool {
+    MODULES.iter().any(|(module, enabled)| *enabled && *module == name)
+}
+
+// call a builtin function that does not need the state of the vm
+pub fn call_function(name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    trace!("calling builtin function '{}' with args {:?}", name, args);
+
+    // functions of a module are named module.function
+    match name.split_once('.') {
+        Some((CSV_MODULE_NAME, function)) => return csv_function(function, args),
+        Some((SET_MODULE_NAME, function)) => return set_function(function, args),
+        Some((DEQUE_MODULE_NAME, function)) => return deque_function(function, args),
+        Some((PRIORITY_QUEUE_MODULE_NAME, function)) => return priority_queue_function(function, args),
+        Some((LOG_MODULE_NAME, function)) => return log_function(function, args),
+        Some((EXPECT_MODULE_NAME, function)) => return expect::check(function, args),
+        #[cfg(feature = "hash")]
+        Some((HASH_MODULE_NAME, function)) => return hash_function(function, args),
+        #[cfg(feature = "uuid")]
+        Some((UUID_MODULE_NAME, function)) => return uuid_function(function, args),
+        #[cfg(feature = "toml")]
+        Some((TOML_MODULE_NAME, "parse")) => return parse_config(TOML_MODULE_NAME, toml::parse, args),
+        #[cfg(feature = "yaml")]
+        Some((YAML_MODULE_NAME, "parse")) => return parse_config(YAML_MODULE_NAME, yaml::parse, args),
+        #[cfg(feature = "bigint")]
+        Some((BIGINT_MODULE_NAME, function)) => return bigint_function(function, args),
+        #[cfg(feature = "decimal")]
+        Some((DECIMAL_MODULE_NAME, function)) => return decimal_function(function, args),
+        Some(_) => return Err(RuntimeError::UnknownFunction(name.to_string())),
+        None => {}
+    }
+
+    match (name, args) {
+        (FORMAT_FUNCTION_NAME, [Value::String(template), args @ ..]) => Ok(Value::from(format::format(template, args)?)),
+        (FORMAT_NUMBER_FUNCTION_NAME, [value, Value::Integer(precision), Value::String(thousands), Value::String(decimal)]) if *precision >= 0 => {
+            Ok(Value::from(format::format_number(value, *precision as usize, thousands, decimal)?))
+        }
+        (GET_PATH_FUNCTION_NAME, [value, Value::String(path)]) => Ok(value.get_path(path)),
+        // the string of the unicode code points
+        (FROM_CHAR_CODE_FUNCTION_NAME, codes) => codes.iter().map(|code| match code {
+            Value::Integer(code) => u32::try_from(*code).ok().and_then(char::from_u32).ok_or_else(|| RuntimeError::TypeError(format!("{} is not a unicode code point", code))),
+            _ => Err(RuntimeError::TypeError(format!("{} expects integer code points", name)))
+        }).collect::<Result<String, RuntimeError>>().map(Value::from),
+        (COMPARE_FUNCTION_NAME, [Value::String(a), Value::String(b)]) => Ok(ordering(a.as_str().cmp(b.as_str()))),
+        (WEAKREF_FUNCTION_NAME, [value]) => Ok(Value::WeakRef(Rc::new(WeakRef::new(value)?))),
+        // ids are text so they can be used as dictionary keys
+        (ID_FUNCTION_NAME, [value]) => value.identity().map(|identity| Value::from(format!("{:x}", identity)))
+            .ok_or_else(|| RuntimeError::TypeError(format!("{} is not a reference so has no id", value.type_name()))),
+        (HASH_FUNCTION_NAME, [value]) => set::hash(value).map(Value::Integer)
+            .ok_or_else(|| RuntimeError::TypeError(format!("can not hash {}", value.type_name()))),
+        (FORMAT_FUNCTION_NAME, _) => Err(RuntimeError::TypeError(format!("{} expects a template string", name))),
+        (FORMAT_NUMBER_FUNCTION_NAME, _) => Err(RuntimeError::TypeError(format!("{} expects a number, precision, thousands separator and decimal separator", name))),
+        (GET_PATH_FUNCTION_NAME, _) => Err(RuntimeError::TypeError(format!("{} expects a value and a path", name))),
+        (COMPARE_FUNCTION_NAME, _) => Err(RuntimeError::TypeError(format!("{} expects two strings", name))),
+        (ID_FUNCTION_NAME | HASH_FUNCTION_NAME, _) => Err(RuntimeError::TypeError(format!("{} expects one value", name))),
+        (WEAKREF_FUNCTION_NAME, _) => Err(RuntimeError::TypeError(format!("{} expects an array, dictionary or object", name))),
+        _ => Err(RuntimeError::UnknownFunction(name.to_string()))
+    }
+}
+
+// create a new instance of a builtin class
+// charge is given the bytes of text the object starts with, before it is made
+pub fn create_object(class_name: &str, args: &[Value], charge: &mut dyn FnMut(usize) -> Result<(), RuntimeError>) -> Result<Value, RuntimeError> {
+    trace!("creating builtin {} with args {:?}", class_name, args);
+    match class_name {
+        STRING_BUILDER_CLASS_NAME => {
+            let buffer = joined(args);
+            charge(buffer.len())?;
+            Ok(Value::StringBuilder(Rc::new(RefCell::new(buffer))))
+        }
+        _ => Err(RuntimeError::UnknownFunction(class_name.to_string()))
+    }
+}
+
+// Csv functions
+fn csv_function(name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    match (name, args) {
+        ("parse", [Value::String(text)]) => csv::parse(text),
+        ("stringify", [rows]) => Ok(Value::from(csv::stringify(rows, None)?)),
+        ("stringify", [rows, columns]) => Ok(Value::from(csv::stringify(rows, Some(columns))?)),
+        ("parse" | "stringify", _) => Err(RuntimeError::TypeError(format!("{}.{} was given the wrong arguments", CSV_MODULE_NAME, name))),
+        _ => Err(RuntimeError::UnknownFunction(format!("{}.{}", CSV_MODULE_NAME, name)))
+    }
+}
+
+// Log functions, which pass a message to the host's logger with where it came from. the
+// compiler adds the script, function and line to log statements
+fn log_function(name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    let level = match name {
+        "error" => log::Level::Error,
+        "warn" => log::Level::Warn,
+        "info" => log::Level::Info,
+        "debug" => log::Level::Debug,
+        _ => return Err(RuntimeError::UnknownFunction(format!("{}.{}", LOG_MODULE_NAME, name)))
+    };
+    match args {
+        [message, Value::String(script), Value::String(function), Value::Integer(line)] => {
+            log::log!(target: LOG_TARGET, level, script = script.as_str(), function = function.as_str(), line = *line; "{}", message);
+        }
+        [message] => log::log!(target: LOG_TARGET, level, "{}", message),
+        _ => return Err(RuntimeError::TypeError(format!("{}.{} expects a message", LOG_MODULE_NAME, name)))
+    }
+    Ok(Value::Null)
+}
+
+// Hash functions, which return hex digests of a string
+#[cfg(feature = "hash")]
+fn hash_function(name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    let function = match name {
+        "sha256" => hash::sha256,
+        "md5" => hash::md5,
+        "crc32" => hash::crc32,
+        _ => return Err(RuntimeError::UnknownFunction(format!("{}.{}", HASH_MODULE_NAME, name)))
+    };
+    match args {
+        [Value::String(s)] => Ok(Value::from(function(s.as_bytes()))),
+        _ => Err(RuntimeError::TypeError(format!("{}.{} expects a string", HASH_MODULE_NAME, name)))
+    }
+}
+
+// Uuid functions, parse gives null for a string that is not a uuid
+#[cfg(feature = "uuid")]
+fn uuid_function(name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    match (name, args) {
+        ("v4", []) => Ok(Value::from(uuid::v4())),
+        ("parse", [Value::String(s)]) => Ok(uuid::parse(s).map(Value::from).unwrap_or(Value::Null)),
+        ("v4" | "parse", _) => Err(RuntimeError::TypeError(format!("{}.{} was given the wrong arguments", UUID_MODULE_NAME, name))),
+        _ => Err(RuntimeError::UnknownFunction(format!("{}.{}", UUID_MODULE_NAME, name)))
+    }
+}
+
+// BigInt functions, which convert to and from integers of any size
+#[cfg(feature = "bigint")]
+fn bigint_function(name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    match (name, args) {
+        ("from", [value]) => exact::bigint(value),
+        ("to_integer", [Value::BigInt(num)]) => num.to_i32().map(Value::Integer).ok_or(RuntimeError::IntegerOverflow),
+        ("to_float", [Value::BigInt(num)]) => Ok(Value::Float(num.to_f64() as f32)),
+        ("from" | "to_integer" | "to_float", _) => Err(RuntimeError::TypeError(format!("{}.{} was given the wrong arguments", BIGINT_MODULE_NAME, name))),
+        _ => Err(RuntimeError::UnknownFunction(format!("{}.{}", BIGINT_MODULE_NAME, name)))
+    }
+}
+
+// Decimal functions, round gives a decimal with exactly the places asked for
+#[cfg(feature = "decimal")]
+fn decimal_function(name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    match (name, args) {
+        ("from", [value]) => exact::decimal(value),
+        ("round", [Value::Decimal(num), Value::Integer(places)]) if *places >= 0 => Ok(Value::Decimal(Rc::new(num.round(*places as u32)))),
+        ("to_float", [Value::Decimal(num)]) => Ok(Value::Float(num.to_f64() as f32)),
+        ("from" | "round" | "to_float", _) => Err(RuntimeError::TypeError(format!("{}.{} was given the wrong arguments", DECIMAL_MODULE_NAME, name))),
+        _ => Err(RuntimeError::UnknownFunction(format!("{}.{}", DECIMAL_MODULE_NAME, name)))
+    }
+}
+
+// Toml and Yaml parse text into dictionaries and arrays
+#[cfg(any(feature = "toml", feature = "yaml"))]
+fn parse_config(module: &str, parse: fn(&str) -> Result<Value, RuntimeError>, args: &[Value]) -> Result<Value, RuntimeError> {
+    match args {
+        [Value::String(text)] => parse(text),
+        _ => Err(RuntimeError::TypeError(format!("{}.parse expects a string", module)))
+    }
+}
+
+// call a method on a value that is not a script object
+pub fn call_method(receiver: &Value, name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    trace!("calling builtin method '{}' with args {:?}", name, args);
+    match receiver {
+        Value::StringBuilder(buffer) => string_builder_method(buffer, name, args, &mut |_| Ok(())),
+        Value::String(text) => string_method(text, name, args, StringUnits::default(), &|_| Ok(())),
+        Value::Array(items) if name == ARRAY_VIEW_METHOD_NAME => match args {
+            [Value::Integer(start), Value::Integer(end)] => Ok(Value::ArrayView(Rc::new(ArrayView::new(items.clone(), *start, *end)?))),
+            _ => Err(RuntimeError::TypeError(format!("array {} expects a start and an end", name)))
+        },
+        Value::Array(items) => array_method(&items.borrow(), name, args),
+        Value::ArrayView(view) => view_method(view, name, args),
+        Value::Set(set) => set_method(set, name, args),
+        Value::Deque(items) => deque_method(items, name, args),
+        Value::PriorityQueue(queue) => priority_queue_method(queue, name, args),
+        Value::WeakRef(weak) if name == "get" && args.is_empty() => Ok(weak.get()),
+        Value::Generator(generator) if name == GENERATOR_DONE_METHOD_NAME => Ok(Value::Bool(generator.borrow().is_done())),
+        Value::Coroutine(coroutine) if name == "status" && args.is_empty() => Ok(Value::from(coroutine.borrow().status())),
+        // sequences are stepped like generators
+        Value::Sequence(sequence) if name == GENERATOR_NEXT_METHOD_NAME => Ok(sequence.borrow_mut().next().unwrap_or(Value::Null)),
+        Value::Sequence(sequence) if name == GENERATOR_DONE_METHOD_NAME => Ok(Value::Bool(sequence.borrow().is_done())),
+        _ => Err(RuntimeError::UnknownMethod(receiver.to_string(), name.to_string()))
+    }
+}
+
+// StringBuilder methods
+// charge is given the bytes of text about to be appended, so the sandbox can refuse them
+pub(crate) fn string_builder_method(buffer: &Rc<RefCell<String>>, name: &str, args: &[Value], charge: &mut dyn FnMut(usize) -> Result<(), RuntimeError>) -> Result<Value, RuntimeError> {
+    let value = match name {
+        "append" => {
+            let text = joined(args);
+            charge(text.len())?;
+            buffer.borrow_mut().push_str(&text);
+            Value::StringBuilder(buffer.clone())
+        }
+        "build" => Value::from(buffer.borrow().clone()),
+        "length" => Value::Integer(buffer.borrow().chars().count() as i32),
+        "clear" => {
+            buffer.borrow_mut().clear();
+            Value::StringBuilder(buffer.clone())
+        }
+        _ => return Err(RuntimeError::UnknownMethod(STRING_BUILDER_CLASS_NAME.to_string(), name.to_string()))
+    };
+    Ok(value)
+}
+
+// String methods, where lengths and widths count the units the vm was configured with and
+// code points are always characters
+// check_length is given the length of a string before a method that can make a long one makes it
+pub(crate) fn string_method(text: &str, name: &str, args: &[Value], units: StringUnits, check_length: &dyn Fn(usize) -> Result<(), RuntimeError>) -> Result<Value, RuntimeError> {
+    let name = builtin_name(name);
+    let value = match (name, args) {
+        ("length", []) => Value::Integer(units.count(text) as i32),
+        ("to_lower", []) => Value::from(text.to_lowercase()),
+        ("to_upper", []) => Value::from(text.to_uppercase()),
+        ("fold_case", []) => Value::from(fold_case(text)),
+        ("equals_ignore_case", [Value::String(other)]) => Value::Bool(fold_case(text) == fold_case(other)),
+        ("compare_ignore_case", [Value::String(other)]) => ordering(fold_case(text).cmp(&fold_case(other))),
+        ("codepoints", []) => Value::from(text.chars().map(|c| Value::Integer(c as i32)).collect::<Vec<Value>>()),
+        #[cfg(feature = "graphemes")]
+        ("graphemes", []) => Value::from(StringUnits::Graphemes.split(text).into_iter().map(Value::from).collect::<Vec<Value>>()),
+        ("starts_with", [Value::String(prefix)]) => Value::Bool(text.starts_with(prefix.as_str())),
+        ("ends_with", [Value::String(suffix)]) => Value::Bool(text.ends_with(suffix.as_str())),
+        ("pad_start" | "pad_end", [Value::Integer(width), rest @ ..]) if rest.len() <= 1 => {
+            let padding = match rest {
+                [] => " ",
+                [Value::String(padding)] => padding.as_str(),
+                _ => return Err(RuntimeError::TypeError(format!("{} expects a width and a padding string", name)))
+            };
+            let missing = usize::try_from(*width).unwrap_or(0).saturating_sub(units.count(text));
+            check_length(text.len().saturating_add(missing))?;
+            let fill: String = padding.chars().cycle().take(missing).collect();
+            match name {
+                "pad_start" => Value::from(fill + text),
+                _ => Value::from(String::from(text) + &fill)
+            }
+        }
+        ("repeat", [Value::Integer(times)]) => {
+            let times = usize::try_from(*times).map_err(|_| RuntimeError::TypeError(format!("can not repeat a string {} times", times)))?;
+            let length = text.len().checked_mul(times).ok_or_else(|| RuntimeError::TypeError(format!("repeating a string {} times is too long", times)))?;
+            check_length(length)?;
+            Value::from(text.repeat(times))
+        }
+        // the unicode code point at a position, or null past the end
+        ("char_code_at", [Value::Integer(index)]) => usize::try_from(*index).ok()
+            .and_then(|index| text.chars().nth(index))
+            .map(|c| Value::Integer(c as i32))
+            .unwrap_or(Value::Null),
+        #[cfg(feature = "graphemes")]
+        ("graphemes", _) => return Err(RuntimeError::TypeError(String::from("invalid arguments for string graphemes"))),
+        ("length" | "to_lower" | "to_upper" | "fold_case" | "equals_ignore_case" | "compare_ignore_case" | "codepoints" | "starts_with" | "ends_with" | "pad_start" | "pad_end" | "repeat" | "char_code_at", _) => {
+            return Err(RuntimeError::TypeError(format!("invalid arguments for string {}", name)))
+        }
+        _ => return Err(RuntimeError::UnknownMethod(String::from("string"), name.to_string()))
+    };
+    Ok(value)
+}
+
+// text with case differences removed, for comparing rather than showing. each character is
+// upper cased and then lower cased, which gives unicode full case folding for all but a few
+// characters, so 'ß' and 'SS' fold to 'ss' and final and medial sigma are the same. the same on
+// every platform and with no locale, so the turkish dotless i is not special
+fn fold_case(text: &str) -> String {
+    text.chars().flat_map(char::to_uppercase).flat_map(char::to_lowercase).collect()
+}
+
+// -1, 0 or 1 for sorting. strings are ordered by code point, which is not the order of any
+// language but is the same everywhere
+fn ordering(ordering: core::cmp::Ordering) -> Value {
+    Value::Integer(ordering as i32)
+}
+
+// Set functions create sets, empty or from the items of an array
+fn set_function(name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    let set = match (name, args) {
+        ("new", []) => ValueSet::new(),
+        ("new", [Value::Array(items)]) => ValueSet::from_values(items.borrow().iter().cloned())?,
+        ("new", _) => return Err(RuntimeError::TypeError(format!("{}.new expects nothing or an array", SET_MODULE_NAME))),
+        _ => return Err(RuntimeError::UnknownFunction(format!("{}.{}", SET_MODULE_NAME, name)))
+    };
+    Ok(Value::Set(Rc::new(RefCell::new(set))))
+}
+
+// Set methods, where union and intersect give new sets and leave both sets as they were
+fn set_method(set: &Rc<RefCell<ValueSet>>, name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    let value = match (name, args) {
+        ("add", [value]) => Value::Bool(set.borrow_mut().add(value.clone())?),
+        ("remove", [value]) => Value::Bool(set.borrow_mut().remove(value)),
+        ("contains", [value]) => Value::Bool(set.borrow().contains(value)),
+        ("length", []) => Value::Integer(set.borrow().len() as i32),
+        ("union", [Value::Set(other)]) => Value::Set(Rc::new(RefCell::new(set.borrow().union(&other.borrow())))),
+        ("intersect", [Value::Set(other)]) => Value::Set(Rc::new(RefCell::new(set.borrow().intersect(&other.borrow())))),
+        ("to_array", []) => Value::from(set.borrow().items().to_vec()),
+        ("add" | "remove" | "contains" | "length" | "union" | "intersect" | "to_array", _) => {
+            return Err(RuntimeError::TypeError(format!("invalid arguments for set {}", name)))
+        }
+        _ => return Err(RuntimeError::UnknownMethod(String::from("set"), name.to_string()))
+    };
+    Ok(value)
+}
+
+// Deque functions create deques, empty or holding the items of an array
+fn deque_function(name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    let items = match (name, args) {
+        ("new", []) => VecDeque::new(),
+        ("new", [Value::Array(items)]) => items.borrow().iter().cloned().collect(),
+        ("new", _) => return Err(RuntimeError::TypeError(format!("{}.new expects nothing or an array", DEQUE_MODULE_NAME))),
+        _ => return Err(RuntimeError::UnknownFunction(format!("{}.{}", DEQUE_MODULE_NAME, name)))
+    };
+    Ok(Value::Deque(Rc::new(RefCell::new(items))))
+}
+
+// Deque methods, which add and take at either end without moving the other items, where taking
+// from or looking at an empty deque gives null
+fn deque_method(items: &Rc<RefCell<VecDeque<Value>>>, name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    let value = match (name, args) {
+        ("push_front", [value]) => {
+            items.borrow_mut().push_front(value.clone());
+            Value::Null
+        }
+        ("push_back", [value]) => {
+            items.borrow_mut().push_back(value.clone());
+            Value::Null
+        }
+        ("pop_front", []) => items.borrow_mut().pop_front().unwrap_or(Value::Null),
+        ("pop_back", []) => items.borrow_mut().pop_back().unwrap_or(Value::Null),
+        ("peek_front", []) => items.borrow().front().cloned().unwrap_or(Value::Null),
+        ("peek_back", []) => items.borrow().back().cloned().unwrap_or(Value::Null),
+        ("length", []) => Value::Integer(items.borrow().len() as i32),
+        ("is_empty", []) => Value::Bool(items.borrow().is_empty()),
+        ("clear", []) => {
+            items.borrow_mut().clear();
+            Value::Null
+        }
+        ("to_array", []) => Value::from(items.borrow().iter().cloned().collect::<Vec<Value>>()),
+        ("push_front" | "push_back" | "pop_front" | "pop_back" | "peek_front" | "peek_back" | "length" | "is_empty" | "clear" | "to_array", _) => {
+            return Err(RuntimeError::TypeError(format!("invalid arguments for deque {}", name)))
+        }
+        _ => return Err(RuntimeError::UnknownMethod(String::from("deque"), name.to_string()))
+    };
+    Ok(value)
+}
+
+// PriorityQueue functions create empty queues
+fn priority_queue_function(name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    match (name, args) {
+        ("new", []) => Ok(Value::PriorityQueue(Rc::new(RefCell::new(PriorityQueue::new())))),
+        ("new", _) => Err(RuntimeError::TypeError(format!("{}.new expects nothing", PRIORITY_QUEUE_MODULE_NAME))),
+        _ => Err(RuntimeError::UnknownFunction(format!("{}.{}", PRIORITY_QUEUE_MODULE_NAME, name)))
+    }
+}
+
+// PriorityQueue methods, where taking from or looking at an empty queue gives null
+fn priority_queue_method(queue: &Rc<RefCell<PriorityQueue>>, name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    let value = match (name, args) {
+        ("push", [item, priority]) => {
+            queue.borrow_mut().push(item.clone(), priority.clone())?;
+            Value::Null
+        }
+        ("pop", []) => queue.borrow_mut().pop().unwrap_or(Value::Null),
+        ("peek", []) => queue.borrow().peek().cloned().unwrap_or(Value::Null),
+        ("peek_priority", []) => queue.borrow().peek_priority().cloned().unwrap_or(Value::Null),
+        ("length", []) => Value::Integer(queue.borrow().len() as i32),
+        ("is_empty", []) => Value::Bool(queue.borrow().is_empty()),
+        ("clear", []) => {
+            queue.borrow_mut().clear();
+            Value::Null
+        }
+        ("to_array", []) => Value::from(queue.borrow().entries().into_iter().map(|(item, _)| item).collect::<Vec<Value>>()),
+        ("push" | "pop" | "peek" | "peek_priority" | "length" | "is_empty" | "clear" | "to_array", _) => {
+            return Err(RuntimeError::TypeError(format!("invalid arguments for priority queue {}", name)))
+        }
+        _ => return Err(RuntimeError::UnknownMethod(String::from("priority queue"), name.to_string()))
+    };
+    Ok(value)
+}
+
+// Array methods for numbers, which are much faster than the same loops written in a script
+fn array_method(items: &[Value], name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    match (name, args) {
+        ("sum", []) => sum(items),
+        // the mean as a float, or null when the array is empty
+        ("avg", []) if items.is_empty() => Ok(Value::Null),
+        ("avg", []) => sum(items)? / Value::Integer(items.len() as i32),
+        // the smallest or largest item, or null when the array is empty
+        ("min" | "max", []) => {
+            let mut best: Option<&Value> = None;
+            for item in items {
+                let better = match best {
+                    None => true,
+                    Some(best) if name == "min" => item.compare(best)?.is_lt(),
+                    Some(best) => item.compare(best)?.is_gt()
+                };
+                if better {
+                    best = Some(item);
+                }
+            }
+            Ok(best.cloned().unwrap_or(Value::Null))
+        }
+        ("sum" | "avg" | "min" | "max", _) => Err(RuntimeError::TypeError(format!("array {} takes no arguments", name))),
+        _ => Err(RuntimeError::UnknownMethod(String::from("array"), name.to_string()))
+    }
+}
+
+// ArrayView methods, which read the array the view is of rather than a copy of it
+fn view_method(view: &ArrayView, name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    match (name, args) {
+        (ARRAY_VIEW_METHOD_NAME, [Value::Integer(start), Value::Integer(end)]) => Ok(Value::ArrayView(Rc::new(view.view(*start, *end)?))),
+        (ARRAY_VIEW_METHOD_NAME, _) => Err(RuntimeError::TypeError(format!("array view {} expects a start and an end", name))),
+        ("length", []) => Ok(Value::Integer(view.len() as i32)),
+        ("to_array", []) => Ok(Value::from(view.with_items(<[Value]>::to_vec))),
+        ("length" | "to_array", _) => Err(RuntimeError::TypeError(format!("array view {} takes no arguments", name))),
+        _ => view.with_items(|items| array_method(items, name, args))
+    }
+}
+
+// total of the items, which must all be numbers so strings are not joined by accident
+fn sum(items: &[Value]) -> Result<Value, RuntimeError> {
+    items.iter().try_fold(Value::Integer(0), |total, item| match item.is_number() {
+        true => total + item.clone(),
+        false => Err(RuntimeError::TypeError(format!("can not sum {}", item.type_name())))
+    })
+}
+
+// write values onto the end of a string without creating intermediate strings
+// the values as they would print, one after another
+fn joined(values: &[Value]) -> String {
+    let mut buffer = String::new();
+    for value in values {
+        match value {
+            Value::String(s) => buffer.push_str(s),
+            _ => write!(buffer, "{}", value).expect("writing to a string should not fail")
+        }
+    }
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use log::{Log, Metadata, Record};
+
+    use crate::compiler::{compile_with_options, CompilerOptions};
+    use crate::vm::builtin::{call_function, call_method, create_object, COMPARE_FUNCTION_NAME, FORMAT_FUNCTION_NAME, FORMAT_NUMBER_FUNCTION_NAME, FROM_CHAR_CODE_FUNCTION_NAME, LOG_TARGET, STRING_BUILDER_CLASS_NAME, WEAKREF_FUNCTION_NAME, ID_FUNCTION_NAME, HASH_FUNCTION_NAME};
+    use crate::vm::error::RuntimeError;
+    use crate::vm::value::Value;
+    use crate::vm::VM;
+
+    // keeps the records scripts log as level, message and fields
+    struct Capture(Mutex<Vec<String>>);
+
+    impl Log for Capture {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.target() == LOG_TARGET
+        }
+
+        fn log(&self, record: &Record) {
+            if self.enabled(record.metadata()) {
+                let field = |key: &str| record.key_values().get(key.into()).map(|v| v.to_string()).unwrap_or_default();
+                let line = format!("{} {} {}:{}:{}", record.level(), record.args(), field("script"), field("function"), field("line"));
+                self.0.lock().unwrap().push(line);
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE: Capture = Capture(Mutex::new(vec![]));
+
+    #[test]
+    fn test_string_builder() {
+        let sb = create_object(STRING_BUILDER_CLASS_NAME, &[Value::from("a")], &mut |_| Ok(())).unwrap();
+        call_method(&sb, "append", &[Value::Integer(1), Value::Bool(true)]).unwrap();
+        call_method(&sb, "append", &[Value::Float(2.5)]).unwrap();
+        assert_eq!(call_method(&sb, "build", &[]).unwrap(), Value::from("a1true2.5"));
+        assert_eq!(call_method(&sb, "length", &[]).unwrap(), Value::Integer(9));
+    }
+
+    #[test]
+    fn test_string_builder_clear() {
+        let sb = create_object(STRING_BUILDER_CLASS_NAME, &[Value::from("abc")], &mut |_| Ok(())).unwrap();
+        call_method(&sb, "clear", &[]).unwrap();
+        assert_eq!(call_method(&sb, "build", &[]).unwrap(), Value::from(""));
+    }
+
+    #[test]
+    fn test_format_functions() {
+        let formatted = call_function(FORMAT_FUNCTION_NAME, &[Value::from("{:>6,}"), Value::Integer(1234)]).unwrap();
+        assert_eq!(formatted, Value::from(" 1,234"));
+        let number = call_function(FORMAT_NUMBER_FUNCTION_NAME, &[Value::Float(0.5), Value::Integer(1), Value::from("."), Value::from(",")]).unwrap();
+        assert_eq!(number, Value::from("0,5"));
+        assert!(call_function(FORMAT_FUNCTION_NAME, &[Value::Integer(1)]).is_err());
+    }
+
+    #[test]
+    fn test_string_methods() {
+        let text = Value::from("héllo");
+        let call = |name: &str, args: &[Value]| call_method(&text, name, args);
+        assert_eq!(call("starts_with", &[Value::from("hé")]), Ok(Value::Bool(true)));
+        assert_eq!(call("ends_with", &[Value::from("x")]), Ok(Value::Bool(false)));
+        assert_eq!(call("pad_start", &[Value::Integer(8), Value::from("ab")]), Ok(Value::from("abahéllo")));
+        assert_eq!(call("pad_end", &[Value::Integer(7)]), Ok(Value::from("héllo  ")));
+        assert_eq!(call("pad_end", &[Value::Integer(2), Value::from("-")]), Ok(Value::from("héllo")));
+        assert_eq!(call("repeat", &[Value::Integer(2)]), Ok(Value::from("héllohéllo")));
+        assert_eq!(call("char_code_at", &[Value::Integer(1)]), Ok(Value::Integer(233)));
+        assert_eq!(call("char_code_at", &[Value::Integer(5)]), Ok(Value::Null));
+        assert_eq!(call("length", &[]), Ok(Value::Integer(5)));
+        assert_eq!(call("codepoints", &[]), Ok(Value::from(vec![Value::Integer(104), Value::Integer(233), Value::Integer(108), Value::Integer(108), Value::Integer(111)])));
+
+        assert!(call("repeat", &[Value::Integer(-1)]).is_err());
+        assert!(call("starts_with", &[Value::Integer(1)]).is_err());
+        assert_eq!(call("trim", &[]), Err(RuntimeError::UnknownMethod(String::from("string"), String::from("trim"))));
+
+        assert_eq!(call_function(FROM_CHAR_CODE_FUNCTION_NAME, &[Value::Integer(104), Value::Integer(233)]), Ok(Value::from("hé")));
+        assert!(call_function(FROM_CHAR_CODE_FUNCTION_NAME, &[Value::Integer(0xD800)]).is_err());
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let call = |text: &str, name: &str, other: &str| call_method(&Value::from(text), name, &[Value::from(other)]).unwrap();
+        assert_eq!(call("Straße", "equals_ignore_case", "STRASSE"), Value::Bool(true));
+        assert_eq!(call("ὈΔΥΣΣΕΎΣ", "equals_ignore_case", "ὀδυσσεύς"), Value::Bool(true));
+        assert_eq!(call("apple", "equals_ignore_case", "apples"), Value::Bool(false));
+        assert_eq!(call("apple", "compare_ignore_case", "Banana"), Value::Integer(-1));
+        assert_eq!(call("ÉCOLE", "compare_ignore_case", "école"), Value::Integer(0));
+        assert_eq!(call_method(&Value::from("Straße"), "fold_case", &[]), Ok(Value::from("strasse")));
+
+        let compare = |a: &str, b: &str| call_function(COMPARE_FUNCTION_NAME, &[Value::from(a), Value::from(b)]).unwrap();
+        assert_eq!(compare("apple", "Banana"), Value::Integer(1));
+        assert_eq!(compare("a", "a"), Value::Integer(0));
+        assert_eq!(compare("z", "é"), Value::Integer(-1));
+        assert!(call_function(COMPARE_FUNCTION_NAME, &[Value::Integer(1), Value::Integer(2)]).is_err());
+    }
+
+    #[test]
+    fn test_array_methods() {
+        let numbers = Value::from(vec![Value::Integer(4), Value::Float(1.5), Value::Integer(-2)]);
+        assert_eq!(call_method(&numbers, "sum", &[]), Ok(Value::Float(3.5)));
+        assert_eq!(call_method(&numbers, "min", &[]), Ok(Value::Integer(-2)));
+        assert_eq!(call_method(&numbers, "max", &[]), Ok(Value::Integer(4)));
+        assert_eq!(call_method(&Value::from(vec![Value::Integer(1), Value::Integer(2)]), "avg", &[]), Ok(Value::Float(1.5)));
+
+        let empty = Value::from(Vec::<Value>::new());
+        assert_eq!(call_method(&empty, "sum", &[]), Ok(Value::Integer(0)));
+        assert_eq!(call_method(&empty, "avg", &[]), Ok(Value::Null));
+        assert_eq!(call_method(&empty, "max", &[]), Ok(Value::Null));
+
+        let words = Value::from(vec![Value::from("b"), Value::from("a")]);
+        assert_eq!(call_method(&words, "min", &[]), Ok(Value::from("a")));
+        assert!(call_method(&words, "sum", &[]).is_err());
+        assert!(call_method(&numbers, "sum", &[Value::Integer(1)]).is_err());
+    }
+
+    #[test]
+    fn test_sets() {
+        let set = call_function("Set.new", &[Value::from(vec![Value::Integer(1), Value::Integer(2), Value::Integer(1)])]).unwrap();
+        assert_eq!(call_method(&set, "length", &[]), Ok(Value::Integer(2)));
+        assert_eq!(call_method(&set, "add", &[Value::Integer(3)]), Ok(Value::Bool(true)));
+        assert_eq!(call_method(&set, "add", &[Value::Integer(3)]), Ok(Value::Bool(false)));
+        assert_eq!(call_method(&set, "contains", &[Value::Integer(2)]), Ok(Value::Bool(true)));
+
+        let other = [call_function("Set.new", &[Value::from(vec![Value::Integer(3), Value::Integer(4)])]).unwrap()];
+        let both = call_method(&set, "intersect", &other).unwrap();
+        assert_eq!(call_method(&both, "to_array", &[]), Ok(Value::from(vec![Value::Integer(3)])));
+        let union = call_method(&set, "union", &other).unwrap();
+        assert_eq!(call_method(&union, "to_array", &[]), Ok(Value::from(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3), Value::Integer(4)])));
+
+        assert!(call_method(&set, "add", &[Value::Float(1.5)]).is_err());
+        assert!(call_method(&set, "union", &[Value::Integer(1)]).is_err());
+        assert!(call_function("Set.new", &[Value::Integer(1)]).is_err());
+    }
+
+    #[test]
+    fn test_deques() {
+        let deque = call_function("Deque.new", &[Value::from(vec![Value::Integer(2)])]).unwrap();
+        assert_eq!(call_method(&deque, "push_back", &[Value::Integer(3)]), Ok(Value::Null));
+        assert_eq!(call_method(&deque, "push_front", &[Value::Integer(1)]), Ok(Value::Null));
+        assert_eq!(call_method(&deque, "to_array", &[]), Ok(Value::from(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)])));
+        assert_eq!(call_method(&deque, "peek_back", &[]), Ok(Value::Integer(3)));
+        assert_eq!(call_method(&deque, "pop_front", &[]), Ok(Value::Integer(1)));
+        assert_eq!(call_method(&deque, "pop_back", &[]), Ok(Value::Integer(3)));
+        assert_eq!(call_method(&deque, "pop_back", &[]), Ok(Value::Integer(2)));
+        assert_eq!(call_method(&deque, "pop_front", &[]), Ok(Value::Null));
+        assert_eq!(call_method(&deque, "is_empty", &[]), Ok(Value::Bool(true)));
+
+        assert!(call_method(&deque, "push_back", &[]).is_err());
+        assert!(call_function("Deque.new", &[Value::Integer(1)]).is_err());
+    }
+
+    #[test]
+    fn test_priority_queues() {
+        let queue = call_function("PriorityQueue.new", &[]).unwrap();
+        assert_eq!(call_method(&queue, "push", &[Value::from("later"), Value::Integer(5)]), Ok(Value::Null));
+        assert_eq!(call_method(&queue, "push", &[Value::from("sooner"), Value::Float(0.5)]), Ok(Value::Null));
+        assert_eq!(call_method(&queue, "peek_priority", &[]), Ok(Value::Float(0.5)));
+        assert_eq!(call_method(&queue, "to_array", &[]), Ok(Value::from(vec![Value::from("sooner"), Value::from("later")])));
+        assert_eq!(call_method(&queue, "pop", &[]), Ok(Value::from("sooner")));
+        assert_eq!(call_method(&queue, "length", &[]), Ok(Value::Integer(1)));
+
+        assert!(call_method(&queue, "push", &[Value::from("never")]).is_err());
+        assert!(call_method(&queue, "push", &[Value::Null, Value::Null]).is_err());
+    }
+
+    #[test]
+    fn test_weakref() {
+        let object = create_object(STRING_BUILDER_CLASS_NAME, &[], &mut |_| Ok(())).unwrap();
+        assert!(call_function(WEAKREF_FUNCTION_NAME, &[object]).is_err());
+
+        let target = [Value::from(vec![Value::Integer(1)])];
+        let weak = call_function(WEAKREF_FUNCTION_NAME, &target).unwrap();
+        assert_eq!(call_method(&weak, "get", &[]), Ok(target[0].clone()));
+        drop(target);
+        assert_eq!(call_method(&weak, "get", &[]), Ok(Value::Null));
+    }
+
+    #[test]
+    fn test_id_and_hash() {
+        let items = [Value::from(vec![Value::Integer(1)])];
+        let copy = [Value::from(vec![Value::Integer(1)])];
+        assert_eq!(call_function(ID_FUNCTION_NAME, &items), call_function(ID_FUNCTION_NAME, &items));
+        assert_ne!(call_function(ID_FUNCTION_NAME, &items), call_function(ID_FUNCTION_NAME, &copy));
+        assert!(call_function(ID_FUNCTION_NAME, &[Value::Integer(1)]).is_err());
+
+        assert_eq!(call_function(HASH_FUNCTION_NAME, &[Value::from("a")]), call_function(HASH_FUNCTION_NAME, &[Value::from("a")]));
+        assert!(call_function(HASH_FUNCTION_NAME, &[Value::from(vec![Value::Null])]).is_err());
+    }
+
+    #[test]
+    fn test_views() {
+        let numbers = Value::from((1..=6).map(Value::Integer).collect::<Vec<_>>());
+        let view = call_method(&numbers, "view", &[Value::Integer(1), Value::Integer(5)]).unwrap();
+        assert_eq!(call_method(&view, "length", &[]), Ok(Value::Integer(4)));
+        assert_eq!(call_method(&view, "sum", &[]), Ok(Value::Integer(14)));
+
+        let inner = call_method(&view, "view", &[Value::Integer(2), Value::Integer(4)]).unwrap();
+        assert_eq!(call_method(&inner, "to_array", &[]), Ok(Value::from(vec![Value::Integer(4), Value::Integer(5)])));
+
+        assert_eq!(call_method(&numbers, "view", &[Value::Integer(0), Value::Integer(7)]), Err(RuntimeError::IndexOutOfBounds(7)));
+        assert!(call_method(&view, "view", &[Value::Integer(0)]).is_err());
+    }
+
+    #[test]
+    fn test_unknown_method() {
+        let error = call_method(&Value::Integer(1), "append", &[]).unwrap_err();
+        assert_eq!(error, RuntimeError::UnknownMethod(String::from("1"), String::from("append")));
+    }
+
+    #[test]
+    fn test_log() {
+        log::set_logger(&CAPTURE).expect("logger should not be set");
+        log::set_max_level(log::LevelFilter::Debug);
+
+        let script = "class Test {\n    function main() {\n        Log.info(\"started\");\n\n        Log.warn(\"count is \" + 3);\n        return Log.error(\"no location\");\n    }\n}";
+        let program = compile_with_options(script, CompilerOptions::default()).unwrap();
+        assert_eq!(VM::new(program).exec("Test.main", None), Ok(Value::Null));
+        assert_eq!(*CAPTURE.0.lock().unwrap(), vec![
+            String::from("INFO started main:Test.main:3"),
+            String::from("WARN count is 3 main:Test.main:5"),
+            String::from("ERROR no location ::"),
+        ]);
+    }
+
+}
@@ -0,0 +1,224 @@
+use crate::prelude::*;
+use crate::vm::config::VmConfig;
+use crate::vm::error::RuntimeError;
+use crate::vm::image::{Reader, Writer};
+use crate::vm::program::Program;
+use crate::vm::VM;
+
+const BUNDLE_MAGIC: &[u8; 4] = b"TSNB";
+const BUNDLE_FORMAT: u16 = 1;
+
+// the attribute that marks a function as part of what a bundle offers its host
+pub const EXPORT_ATTRIBUTE: &str = "export";
+
+// Bundle
+//
+// compiled modules and what a host needs to know about them, shipped as a single .tnb file.
+// modules are kept as bytecode and only decoded when the bundle is instantiated
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bundle {
+    pub name: String,
+    // functions the host is expected to call, such as 'Plugin.main'
+    pub entry_points: Vec<String>,
+    // builtins or builtin modules the sandbox must allow
+    pub capabilities: Vec<String>,
+    // functions marked @export in any of the modules
+    pub exports: Vec<String>,
+    // bytecode of each module by name, linked in this order
+    pub modules: Vec<(String, Vec<u8>)>,
+}
+
+impl Bundle {
+
+    pub fn new(name: &str) -> Self {
+        Bundle { name: name.to_string(), ..Default::default() }
+    }
+
+    pub fn entry_point(mut self, name: &str) -> Self {
+        self.entry_points.push(name.to_string());
+        self
+    }
+
+    pub fn capability(mut self, name: &str) -> Self {
+        self.capabilities.push(name.to_string());
+        self
+    }
+
+    // add a compiled module, every module after the first must be compiled with no_std
+    pub fn module(mut self, name: &str, program: &Program) -> Self {
+        self.exports.extend(program.members_with(EXPORT_ATTRIBUTE));
+        self.modules.push((name.to_string(), program.to_bytes()));
+        self
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes.extend_from_slice(BUNDLE_MAGIC);
+        w.bytes.extend_from_slice(&BUNDLE_FORMAT.to_le_bytes());
+
+        w.string(&self.name);
+        for list in [&self.entry_points, &self.capabilities, &self.exports] {
+            w.usize(list.len());
+            for item in list.iter() {
+                w.string(item);
+            }
+        }
+
+        w.usize(self.modules.len());
+        for (name, bytecode) in self.modules.iter() {
+            w.string(name);
+            w.usize(bytecode.len());
+            w.bytes.extend_from_slice(bytecode);
+        }
+
+        w.bytes
+    }
+
+    // read the metadata and modules of a bundle, checking each module is bytecode this crate can read
+    pub fn load(bytes: &[u8]) -> Result<Bundle, RuntimeError> {
+        let mut r = Reader::new(bytes, RuntimeError::InvalidBundle);
+        if r.take(BUNDLE_MAGIC.len()).ok() != Some(BUNDLE_MAGIC.as_slice()) {
+            return Err(RuntimeError::InvalidBundle(String::from("not a tinyscript bundle")));
+        }
+        let format = u16::from_le_bytes(r.take(2)?.try_into().expect("slice should be 2 bytes"));
+        if format != BUNDLE_FORMAT {
+            return Err(RuntimeError::InvalidBundle(format!("format {} is not supported", format)));
+        }
+
+        let name = r.string()?;
+        let mut lists = vec![];
+        for _ in 0..3 {
+            lists.push((0..r.usize()?).map(|_| r.string()).collect::<Result<Vec<String>, RuntimeError>>()?);
+        }
+        let [entry_points, capabilities, exports] = <[Vec<String>; 3]>::try_from(lists).expect("there should be 3 lists");
+
+        let mut modules = vec![];
+        for _ in 0..r.usize()? {
+            let module = r.string()?;
+            let len = r.usize()?;
+            let bytecode = r.take(len)?.to_vec();
+            let version = Program::bytecode_version(&bytecode)?;
+            if !version.is_compatible() {
+                return Err(RuntimeError::InvalidBundle(format!("module '{}' has bytecode version {}", module, version)));
+            }
+            modules.push((module, bytecode));
+        }
+        r.finish()?;
+
+        Ok(Bundle { name, entry_points, capabilities, exports, modules })
+    }
+
+    // link the modules into a vm, failing if its sandbox does not allow what the bundle needs
+    pub fn instantiate(&self, config: VmConfig) -> Result<VM, RuntimeError> {
+        if let Some(capability) = self.capabilities.iter().find(|c| config.sandbox.check_builtin(c).is_err()) {
+            return Err(RuntimeError::BuiltinNotAllowed(capability.to_string()));
+        }
+
+        Ok(VM::with_config(self.link()?, config))
+    }
+
+    // link the modules into one program, checking the functions the bundle offers are in it
+    pub fn link(&self) -> Result<Program, RuntimeError> {
+        let mut program = Program::new();
+        for (name, bytecode) in self.modules.iter() {
+            program.link(Program::load_any(bytecode)?).map_err(|e| RuntimeError::InvalidBundle(format!("module '{}': {}", name, e)))?;
+        }
+
+        if let Some(name) = self.entry_points.iter().chain(self.exports.iter()).find(|name| !program.symbols.contains_key(*name)) {
+            return Err(RuntimeError::UnknownFunction(name.to_string()));
+        }
+
+        Ok(program)
+    }
+
+    // the bundle as one linked module, without what its entry points and exports can not reach
+    pub fn stripped(&self) -> Result<Bundle, RuntimeError> {
+        let mut program = self.link()?;
+        let roots: Vec<&str> = self.entry_points.iter().chain(self.exports.iter()).map(String::as_str).collect();
+        program.strip_unused(&roots)?;
+        Ok(Bundle { modules: vec![(self.name.clone(), program.to_bytes())], ..self.clone() })
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::{compile_with_options, CompilerOptions};
+    use crate::vm::bundle::Bundle;
+    use crate::vm::config::VmConfig;
+    use crate::vm::error::RuntimeError;
+    use crate::vm::sandbox::Sandbox;
+    use crate::vm::value::Value;
+
+    const PLUGIN: &str = r#"
+        class Plugin {
+            function main() {
+                return "ready";
+            }
+
+            @export
+            function size(text) {
+                return Hash.sha256(text);
+            }
+        }
+    "#;
+
+    const GREETER: &str = r#"
+        class Greeter {
+            /// say hello
+            @export
+            @pure
+            function greet(name) {
+                return "hello " + name;
+            }
+        }
+    "#;
+
+    fn bundle() -> Bundle {
+        let no_std = || CompilerOptions { no_std: true, ..Default::default() };
+        Bundle::new("greeter")
+            .entry_point("Plugin.main")
+            .capability("Hash")
+            .module("plugin", &compile_with_options(PLUGIN, CompilerOptions::default()).unwrap())
+            .module("greeter", &compile_with_options(GREETER, no_std()).unwrap())
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bundle = bundle();
+        assert_eq!(bundle.exports, vec!["Plugin.size", "Greeter.greet"]);
+
+        let loaded = Bundle::load(&bundle.to_bytes()).unwrap();
+        assert_eq!(loaded, bundle);
+
+        let mut vm = loaded.instantiate(VmConfig::new()).unwrap();
+        assert_eq!(vm.exec("Plugin.main", None).unwrap(), Value::from("ready"));
+        assert_eq!(vm.exec("Greeter.greet", Some(vec![Value::from("bundle")])).unwrap(), Value::from("hello bundle"));
+        assert_eq!(vm.attributes("Greeter.greet"), ["export", "pure"]);
+        assert_eq!(vm.doc("Greeter.greet"), Some("say hello"));
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let sandbox = Sandbox { allowed_builtins: Some(vec![]), ..Sandbox::default() };
+        let error = bundle().instantiate(VmConfig::new().sandbox(sandbox)).err();
+        assert_eq!(error, Some(RuntimeError::BuiltinNotAllowed(String::from("Hash"))));
+        assert!(bundle().instantiate(VmConfig::new().sandbox(Sandbox::strict())).is_ok());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(Bundle::load(b"TSBC").err(), Some(RuntimeError::InvalidBundle(String::from("not a tinyscript bundle"))));
+
+        let mut bytes = bundle().to_bytes();
+        bytes.pop();
+        assert!(matches!(Bundle::load(&bytes), Err(RuntimeError::InvalidBundle(_))));
+
+        let missing = bundle().entry_point("Plugin.stop");
+        assert_eq!(missing.instantiate(VmConfig::new()).err(), Some(RuntimeError::UnknownFunction(String::from("Plugin.stop"))));
+
+        let twice = bundle().module("again", &compile_with_options(GREETER, CompilerOptions { no_std: true, ..Default::default() }).unwrap());
+        assert!(matches!(twice.instantiate(VmConfig::new()), Err(RuntimeError::InvalidBundle(message)) if message.starts_with("module 'again'")));
+    }
+
+}
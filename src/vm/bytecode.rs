@@ -0,0 +1,601 @@
+use core::fmt;
+use core::fmt::{Display, Formatter};
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
+use crate::vm::hash::sha256;
+use crate::vm::image::{Decoder, Encoder, ImageValue, Reader, Writer};
+use crate::vm::instruction::{Comparison, Instruction};
+use crate::vm::program::{BuildMetadata, FunctionMetadata, Program};
+
+const BYTECODE_MAGIC: &[u8; 4] = b"TSBC";
+
+// Bytecode Version
+//
+// the minor version goes up when instructions or sections are added, which older bytecode
+// never uses, so any older minor version of the same major version can be read as it is.
+// the major version goes up when the meaning of existing bytecode changes, and older major
+// versions are read by migrating them in Program::load_any
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BytecodeVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+pub const BYTECODE_VERSION: BytecodeVersion = BytecodeVersion { major: 1, minor: 8 };
+
+impl BytecodeVersion {
+
+    // whether bytecode of this version can be read without a migration
+    pub fn is_compatible(&self) -> bool {
+        self.major == BYTECODE_VERSION.major && *self <= BYTECODE_VERSION
+    }
+
+}
+
+impl Display for BytecodeVersion {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl Program {
+
+    // encode the program so it can be shipped without its source
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::default();
+        let globals = encoder.encode_all(&self.globals).expect(ENCODABLE);
+        let instructions: Vec<EncodedInstruction> = self.instructions.iter().map(|instruction| encode_instruction(&mut encoder, instruction)).collect();
+
+        let mut w = Writer::default();
+        w.bytes.extend_from_slice(BYTECODE_MAGIC);
+        w.bytes.extend_from_slice(&BYTECODE_VERSION.major.to_le_bytes());
+        w.bytes.extend_from_slice(&BYTECODE_VERSION.minor.to_le_bytes());
+
+        w.bool(self.strict);
+        w.bool(self.checked);
+        w.heap(&encoder.heap);
+        w.values(&globals);
+
+        // changed in 1.5 to write operands as varints
+        w.usize(instructions.len());
+        for instruction in instructions.iter() {
+            write_instruction(&mut w, instruction);
+        }
+
+        write_map(&mut w, &self.symbols, |w, position| w.usize(*position));
+        write_map(&mut w, &self.metadata, |w, metadata| {
+            w.usize(metadata.local_slots);
+            w.usize(metadata.max_stack_depth);
+            w.bool(metadata.generator);
+        });
+        write_map(&mut w, &self.global_symbols, |w, index| w.usize(*index));
+        write_map(&mut w, &self.docs, |w, doc| w.string(doc));
+        write_map(&mut w, &self.params, write_strings);
+
+        // added in 1.1
+        write_map(&mut w, &self.attributes, write_strings);
+
+        // added in 1.2
+        write_map(&mut w, &self.locals, write_strings);
+
+        // added in 1.6
+        w.string(&self.build.compiler_version);
+        match self.build.compiled_at {
+            Some(time) => { w.bool(true); w.varint(time); },
+            None => w.bool(false),
+        }
+        write_map(&mut w, &self.build.sources, |w, hash| w.string(hash));
+        w.tag(self.build.optimization_level);
+
+        // added in 1.7
+        let cleanups: HashMap<String, usize> = self.metadata.iter().filter_map(|(name, metadata)| Some((name.clone(), metadata.cleanup?))).collect();
+        write_map(&mut w, &cleanups, |w, cleanup| w.usize(*cleanup));
+
+        w.bytes
+    }
+
+    // decode bytecode of exactly the version this crate writes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Program, RuntimeError> {
+        let version = Program::bytecode_version(bytes)?;
+        if version != BYTECODE_VERSION {
+            return Err(RuntimeError::UnsupportedBytecode(version.to_string(), BYTECODE_VERSION.to_string()));
+        }
+        Program::load_any(bytes)
+    }
+
+    // decode bytecode written by this or an earlier version of the crate, checking that it is
+    // safe to run since it did not come from the compiler
+    pub fn load_any(bytes: &[u8]) -> Result<Program, RuntimeError> {
+        let version = Program::bytecode_version(bytes)?;
+        let program = match version {
+            version if version.is_compatible() => read_program(bytes, version)?,
+            // a migration for each older major version goes here once there is one
+            version => {
+                let supported = format!("{}.0 to {}", BYTECODE_VERSION.major, BYTECODE_VERSION);
+                return Err(RuntimeError::UnsupportedBytecode(version.to_string(), supported));
+            }
+        };
+        program.verify()?;
+        Ok(program)
+    }
+
+    // the version bytecode was written with, without decoding the rest
+    pub fn bytecode_version(bytes: &[u8]) -> Result<BytecodeVersion, RuntimeError> {
+        let mut r = Reader::new(bytes, RuntimeError::InvalidBytecode);
+        if r.take(BYTECODE_MAGIC.len()).ok() != Some(BYTECODE_MAGIC.as_slice()) {
+            return Err(RuntimeError::InvalidBytecode(String::from("not tinyscript bytecode")));
+        }
+        let major = u16::from_le_bytes(r.take(2)?.try_into().expect("slice should be 2 bytes"));
+        let minor = u16::from_le_bytes(r.take(2)?.try_into().expect("slice should be 2 bytes"));
+        Ok(BytecodeVersion { major, minor })
+    }
+
+}
+
+fn read_program(bytes: &[u8], version: BytecodeVersion) -> Result<Program, RuntimeError> {
+    let mut r = Reader::new(bytes, RuntimeError::InvalidBytecode);
+    r.take(BYTECODE_MAGIC.len() + 4)?;
+
+    let strict = r.bool()?;
+    let checked = r.bool()?;
+    let heap = r.heap()?;
+    let globals = r.values()?;
+
+    // operands have been varints since 1.5
+    let operands = match version.minor {
+        0..=4 => Operands::Fixed,
+        _ => Operands::Varint
+    };
+    let mut instructions = vec![];
+    for _ in 0..r.usize()? {
+        instructions.push(read_instruction(&mut r, operands)?);
+    }
+
+    let symbols = read_map(&mut r, |r| r.usize())?;
+    let mut metadata = read_map(&mut r, |r| Ok(FunctionMetadata { local_slots: r.usize()?, max_stack_depth: r.usize()?, generator: r.bool()?, ..Default::default() }))?;
+    let global_symbols = read_map(&mut r, |r| r.usize())?;
+    let docs = read_map(&mut r, |r| r.string())?;
+    let params = read_map(&mut r, read_strings)?;
+    let attributes = match version.minor {
+        0 => HashMap::new(),
+        _ => read_map(&mut r, read_strings)?
+    };
+    let locals = match version.minor {
+        0 | 1 => HashMap::new(),
+        _ => read_map(&mut r, read_strings)?
+    };
+    let build = match version.minor {
+        0..=5 => BuildMetadata::default(),
+        _ => BuildMetadata {
+            compiler_version: r.string()?,
+            compiled_at: if r.bool()? { Some(r.varint()?) } else { None },
+            sources: read_map(&mut r, |r| r.string())?,
+            optimization_level: r.tag()?,
+        }
+    };
+    if version.minor >= 7 {
+        for (name, cleanup) in read_map(&mut r, |r| r.usize())? {
+            let function = metadata.get_mut(&name).ok_or_else(|| RuntimeError::InvalidBytecode(format!("deferred statements of unknown function {}", name)))?;
+            function.cleanup = Some(cleanup);
+        }
+    }
+    r.finish()?;
+
+    // constants are decoded after everything is read so they can share the heap
+    let invalid = |e: RuntimeError| match e {
+        RuntimeError::InvalidImage(message) => RuntimeError::InvalidBytecode(message),
+        e => e
+    };
+    let mut decoder = Decoder::new(&heap).map_err(invalid)?;
+    let globals = decoder.decode_all(&globals).map_err(invalid)?;
+    let instructions = instructions.into_iter().map(|instruction| decode_instruction(&mut decoder, instruction)).collect::<Result<Vec<Instruction>, RuntimeError>>().map_err(invalid)?;
+
+    Ok(Program { instructions, symbols, metadata, globals, global_symbols, docs, params, attributes, locals, strict, checked, build })
+}
+
+// instructions on their own, so functions compiled on other threads can be sent back
+pub(crate) fn encode_instructions(instructions: &[Instruction]) -> Vec<u8> {
+    let mut encoder = Encoder::default();
+    let encoded: Vec<EncodedInstruction> = instructions.iter().map(|instruction| encode_instruction(&mut encoder, instruction)).collect();
+
+    let mut w = Writer::default();
+    w.heap(&encoder.heap);
+    w.usize(encoded.len());
+    for instruction in encoded.iter() {
+        write_instruction(&mut w, instruction);
+    }
+    w.bytes
+}
+
+#[cfg(feature = "parallel")]
+pub(crate) fn decode_instructions(bytes: &[u8]) -> Result<Vec<Instruction>, RuntimeError> {
+    let mut r = Reader::new(bytes, RuntimeError::InvalidBytecode);
+    let heap = r.heap()?;
+    let mut encoded = vec![];
+    for _ in 0..r.usize()? {
+        encoded.push(read_instruction(&mut r, Operands::Varint)?);
+    }
+    r.finish()?;
+
+    let mut decoder = Decoder::new(&heap)?;
+    encoded.into_iter().map(|instruction| decode_instruction(&mut decoder, instruction)).collect()
+}
+
+// sorted by key so the same program always gives the same bytes
+// sha-256 of the code of a program, so a vm image is only restored into the program it was taken
+// from. globals are left out as they change while the program runs
+pub(crate) fn fingerprint(instructions: &[Instruction], symbols: &HashMap<String, usize>, metadata: &HashMap<String, FunctionMetadata>, global_symbols: &HashMap<String, usize>) -> String {
+    let mut w = Writer { bytes: encode_instructions(instructions) };
+    write_map(&mut w, symbols, |w, position| w.usize(*position));
+    write_map(&mut w, metadata, |w, metadata| {
+        w.usize(metadata.local_slots);
+        w.usize(metadata.max_stack_depth);
+        w.bool(metadata.generator);
+        w.usize(metadata.cleanup.map_or(0, |cleanup| cleanup + 1));
+    });
+    write_map(&mut w, global_symbols, |w, index| w.usize(*index));
+    sha256(&w.bytes)
+}
+
+fn write_map<T>(w: &mut Writer, map: &HashMap<String, T>, write: impl Fn(&mut Writer, &T)) {
+    let mut keys: Vec<&String> = map.keys().collect();
+    keys.sort();
+    w.usize(keys.len());
+    for key in keys {
+        w.string(key);
+        write(w, &map[key]);
+    }
+}
+
+fn write_strings(w: &mut Writer, strings: &Vec<String>) {
+    w.usize(strings.len());
+    for string in strings {
+        w.string(string);
+    }
+}
+
+fn read_strings(r: &mut Reader) -> Result<Vec<String>, RuntimeError> {
+    (0..r.usize()?).map(|_| r.string()).collect()
+}
+
+fn read_map<T>(r: &mut Reader, read: impl Fn(&mut Reader) -> Result<T, RuntimeError>) -> Result<HashMap<String, T>, RuntimeError> {
+    (0..r.usize()?).map(|_| Ok((r.string()?, read(r)?))).collect()
+}
+
+//==================================================================================================
+// INSTRUCTIONS
+
+// the compiler only puts literals and classes in a program, never host objects or sequences
+const ENCODABLE: &str = "a program should only hold values that can be encoded";
+
+// an instruction whose constant has been encoded, so constants can refer to the shared heap
+enum EncodedInstruction {
+    Plain(Instruction),
+    StackPush(ImageValue),
+    CompareLocalConstJump(Comparison, usize, ImageValue, usize),
+}
+
+fn encode_instruction(encoder: &mut Encoder, instruction: &Instruction) -> EncodedInstruction {
+    match instruction {
+        Instruction::StackPush(value) => EncodedInstruction::StackPush(encoder.encode(value).expect(ENCODABLE)),
+        Instruction::CompareLocalConstJump(comparison, slot, value, target) => EncodedInstruction::CompareLocalConstJump(*comparison, *slot, encoder.encode(value).expect(ENCODABLE), *target),
+        instruction => EncodedInstruction::Plain(instruction.clone())
+    }
+}
+
+fn decode_instruction(decoder: &mut Decoder, instruction: EncodedInstruction) -> Result<Instruction, RuntimeError> {
+    Ok(match instruction {
+        EncodedInstruction::Plain(instruction) => instruction,
+        EncodedInstruction::StackPush(value) => Instruction::StackPush(decoder.decode(&value)?),
+        EncodedInstruction::CompareLocalConstJump(comparison, slot, value, target) => Instruction::CompareLocalConstJump(comparison, slot, decoder.decode(&value)?, target),
+    })
+}
+
+// how the numbers in instructions are written
+#[derive(Clone, Copy)]
+enum Operands {
+    // 8 bytes each, as bytecode before 1.5 was written
+    Fixed,
+    // as few bytes as the number needs
+    Varint,
+}
+
+impl Operands {
+
+    fn usize(self, r: &mut Reader) -> Result<usize, RuntimeError> {
+        match self {
+            Operands::Fixed => r.usize(),
+            Operands::Varint => usize::try_from(r.varint()?).map_err(|_| r.error(String::from("operand is too large")))
+        }
+    }
+
+    fn i32(self, r: &mut Reader) -> Result<i32, RuntimeError> {
+        match self {
+            Operands::Fixed => Ok(i32::from_le_bytes(r.take(4)?.try_into().expect("slice should be 4 bytes"))),
+            Operands::Varint => {
+                let value = r.varint()?;
+                let value = u32::try_from(value).map_err(|_| r.error(String::from("operand is too large")))?;
+                Ok((value >> 1) as i32 ^ -((value & 1) as i32))
+            }
+        }
+    }
+
+    fn string(self, r: &mut Reader) -> Result<String, RuntimeError> {
+        let len = self.usize(r)?;
+        r.string_of(len)
+    }
+
+}
+
+// names in instructions are short, so their length is a varint as well
+fn write_name(w: &mut Writer, name: &str) {
+    w.varint(name.len() as u64);
+    w.bytes.extend_from_slice(name.as_bytes());
+}
+
+// small negative steps take as few bytes as small positive ones
+fn zigzag(value: i32) -> u64 {
+    ((value << 1) ^ (value >> 31)) as u32 as u64
+}
+
+// tags are never reused, new instructions take the next free tag and bump the minor version
+fn write_instruction(w: &mut Writer, instruction: &EncodedInstruction) {
+    let instruction = match instruction {
+        EncodedInstruction::StackPush(value) => { w.tag(2); w.value(value); return; }
+        EncodedInstruction::CompareLocalConstJump(comparison, slot, value, target) => {
+            w.tag(40);
+            write_comparison(w, *comparison);
+            w.varint(*slot as u64);
+            w.value(value);
+            w.varint(*target as u64);
+            return;
+        }
+        EncodedInstruction::Plain(instruction) => instruction
+    };
+    match instruction {
+        Instruction::Assert => w.tag(0),
+        Instruction::Print => w.tag(1),
+        Instruction::StackPush(_) | Instruction::CompareLocalConstJump(..) => unreachable!("constants are encoded first"),
+        Instruction::Pop => w.tag(3),
+        Instruction::Dup => w.tag(4),
+        Instruction::Swap => w.tag(5),
+        Instruction::MoveToLocalVariable(slot) => { w.tag(6); w.varint(*slot as u64); }
+        Instruction::CopyToLocalVariable(slot) => { w.tag(7); w.varint(*slot as u64); }
+        Instruction::LoadLocalVariable(slot) => { w.tag(8); w.varint(*slot as u64); }
+        Instruction::StoreGlobal(index) => { w.tag(9); w.varint(*index as u64); }
+        Instruction::LoadGlobal(index) => { w.tag(10); w.varint(*index as u64); }
+        Instruction::CreateObject => w.tag(11),
+        Instruction::CreateBuiltinObject(name, args) => { w.tag(12); write_name(w, name); w.varint(*args as u64); }
+        Instruction::DictionaryAdd => w.tag(13),
+        Instruction::ArrayLength => w.tag(14),
+        Instruction::ArrayAdd => w.tag(15),
+        Instruction::IterNext => w.tag(16),
+        Instruction::Yield => w.tag(17),
+        Instruction::GetCollectionItemByKey => w.tag(18),
+        Instruction::SetCollectionItemByKey => w.tag(19),
+        Instruction::Call(args) => { w.tag(20); w.varint(*args as u64); }
+        Instruction::CallMethod(name, args) => { w.tag(21); write_name(w, name); w.varint(*args as u64); }
+        Instruction::CallBuiltin(name, args) => { w.tag(22); write_name(w, name); w.varint(*args as u64); }
+        Instruction::Jump(target) => { w.tag(23); w.varint(*target as u64); }
+        Instruction::JumpIfFalse(target) => { w.tag(24); w.varint(*target as u64); }
+        Instruction::Return(value) => { w.tag(25); w.bool(*value); }
+        Instruction::Equal => w.tag(26),
+        Instruction::NotEqual => w.tag(27),
+        Instruction::Add => w.tag(28),
+        Instruction::Sub => w.tag(29),
+        Instruction::Multiply => w.tag(30),
+        Instruction::Divide => w.tag(31),
+        Instruction::FloorDivide => w.tag(32),
+        Instruction::Pow => w.tag(33),
+        Instruction::LessThan => w.tag(34),
+        Instruction::LessThanOrEqual => w.tag(35),
+        Instruction::GreaterThan => w.tag(36),
+        Instruction::GreaterThanOrEqual => w.tag(37),
+        Instruction::AddLocals(lhs, rhs, dst) => { w.tag(38); w.varint(*lhs as u64); w.varint(*rhs as u64); w.varint(*dst as u64); }
+        Instruction::IncrementLocal(slot, step) => { w.tag(39); w.varint(*slot as u64); w.varint(zigzag(*step)); }
+        Instruction::CompareLocalsJump(comparison, lhs, rhs, target) => {
+            w.tag(41);
+            write_comparison(w, *comparison);
+            w.varint(*lhs as u64);
+            w.varint(*rhs as u64);
+            w.varint(*target as u64);
+        }
+        Instruction::Halt(message) => { w.tag(42); write_name(w, message); }
+        Instruction::IsArray(len, at_least) => { w.tag(43); w.varint(*len as u64); w.bool(*at_least); }
+        Instruction::HasKey(key) => { w.tag(44); write_name(w, key); }
+        Instruction::IsInstance => w.tag(45),
+        Instruction::ArrayExtend => w.tag(46),
+        Instruction::DictionaryExtend => w.tag(47),
+        Instruction::Identical => w.tag(48),
+    }
+}
+
+fn read_instruction(r: &mut Reader, operands: Operands) -> Result<EncodedInstruction, RuntimeError> {
+    let instruction = match r.tag()? {
+        0 => Instruction::Assert,
+        1 => Instruction::Print,
+        2 => return Ok(EncodedInstruction::StackPush(r.value()?)),
+        3 => Instruction::Pop,
+        4 => Instruction::Dup,
+        5 => Instruction::Swap,
+        6 => Instruction::MoveToLocalVariable(operands.usize(r)?),
+        7 => Instruction::CopyToLocalVariable(operands.usize(r)?),
+        8 => Instruction::LoadLocalVariable(operands.usize(r)?),
+        9 => Instruction::StoreGlobal(operands.usize(r)?),
+        10 => Instruction::LoadGlobal(operands.usize(r)?),
+        11 => Instruction::CreateObject,
+        12 => Instruction::CreateBuiltinObject(operands.string(r)?, operands.usize(r)?),
+        13 => Instruction::DictionaryAdd,
+        14 => Instruction::ArrayLength,
+        15 => Instruction::ArrayAdd,
+        16 => Instruction::IterNext,
+        17 => Instruction::Yield,
+        18 => Instruction::GetCollectionItemByKey,
+        19 => Instruction::SetCollectionItemByKey,
+        20 => Instruction::Call(operands.usize(r)?),
+        21 => Instruction::CallMethod(operands.string(r)?, operands.usize(r)?),
+        22 => Instruction::CallBuiltin(operands.string(r)?, operands.usize(r)?),
+        23 => Instruction::Jump(operands.usize(r)?),
+        24 => Instruction::JumpIfFalse(operands.usize(r)?),
+        25 => Instruction::Return(r.bool()?),
+        26 => Instruction::Equal,
+        27 => Instruction::NotEqual,
+        28 => Instruction::Add,
+        29 => Instruction::Sub,
+        30 => Instruction::Multiply,
+        31 => Instruction::Divide,
+        32 => Instruction::FloorDivide,
+        33 => Instruction::Pow,
+        34 => Instruction::LessThan,
+        35 => Instruction::LessThanOrEqual,
+        36 => Instruction::GreaterThan,
+        37 => Instruction::GreaterThanOrEqual,
+        38 => Instruction::AddLocals(operands.usize(r)?, operands.usize(r)?, operands.usize(r)?),
+        39 => Instruction::IncrementLocal(operands.usize(r)?, operands.i32(r)?),
+        40 => {
+            let comparison = read_comparison(r)?;
+            return Ok(EncodedInstruction::CompareLocalConstJump(comparison, operands.usize(r)?, r.value()?, operands.usize(r)?));
+        }
+        41 => Instruction::CompareLocalsJump(read_comparison(r)?, operands.usize(r)?, operands.usize(r)?, operands.usize(r)?),
+        42 => Instruction::Halt(operands.string(r)?),
+        // added in 1.3
+        43 => Instruction::IsArray(operands.usize(r)?, r.bool()?),
+        44 => Instruction::HasKey(operands.string(r)?),
+        45 => Instruction::IsInstance,
+        // added in 1.4
+        46 => Instruction::ArrayExtend,
+        47 => Instruction::DictionaryExtend,
+        // added in 1.8
+        48 => Instruction::Identical,
+        tag => return Err(r.error(format!("unknown instruction tag {}", tag)))
+    };
+    Ok(EncodedInstruction::Plain(instruction))
+}
+
+fn write_comparison(w: &mut Writer, comparison: Comparison) {
+    w.tag(match comparison {
+        Comparison::Equal => 0,
+        Comparison::NotEqual => 1,
+        Comparison::LessThan => 2,
+        Comparison::LessThanOrEqual => 3,
+        Comparison::GreaterThan => 4,
+        Comparison::GreaterThanOrEqual => 5,
+    });
+}
+
+fn read_comparison(r: &mut Reader) -> Result<Comparison, RuntimeError> {
+    Ok(match r.tag()? {
+        0 => Comparison::Equal,
+        1 => Comparison::NotEqual,
+        2 => Comparison::LessThan,
+        3 => Comparison::LessThanOrEqual,
+        4 => Comparison::GreaterThan,
+        5 => Comparison::GreaterThanOrEqual,
+        tag => return Err(r.error(format!("unknown comparison tag {}", tag)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::{compile_with_options, CompilerOptions};
+    use crate::vm::bytecode::{read_instruction, write_instruction, zigzag, BytecodeVersion, EncodedInstruction, Operands, BYTECODE_VERSION};
+    use crate::vm::image::{Reader, Writer};
+    use crate::vm::instruction::Instruction;
+    use crate::vm::error::RuntimeError;
+    use crate::vm::program::Program;
+    use crate::vm::value::Value;
+    use crate::vm::VM;
+
+    const SOURCE: &str = r#"
+        class Counter {
+            var start = 3;
+        }
+        class Test {
+            @export
+            function main() {
+                var counter = new Counter();
+                defer counter.start = 0;
+                var total = 0;
+                for (var i = counter.start; i < 10; i = i + 1) {
+                    total = total + i;
+                }
+                var shape = match [counter, {"a": 1}] {
+                    [Counter(start), {"a": a}] => start + a,
+                    _ => 0
+                };
+                return [total, "done", {"a": 1.5}, shape];
+            }
+        }
+    "#;
+
+    fn with_version(mut bytes: Vec<u8>, version: BytecodeVersion) -> Vec<u8> {
+        bytes[4..6].copy_from_slice(&version.major.to_le_bytes());
+        bytes[6..8].copy_from_slice(&version.minor.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let program = compile_with_options(SOURCE, CompilerOptions::default()).unwrap();
+        let expected = VM::new(compile_with_options(SOURCE, CompilerOptions::default()).unwrap()).exec("Test.main", None).unwrap();
+
+        let bytes = program.to_bytes();
+        assert_eq!(Program::bytecode_version(&bytes), Ok(BYTECODE_VERSION));
+        let loaded = Program::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.to_bytes(), bytes);
+        assert_eq!(loaded.members_with("export"), vec!["Test.main"]);
+        assert_eq!(loaded.metadata["Test.main"].cleanup, program.metadata["Test.main"].cleanup);
+        assert!(loaded.metadata["Test.main"].cleanup.is_some());
+        assert_eq!(VM::new(loaded).exec("Test.main", None).unwrap(), expected);
+        assert!(matches!(expected, Value::Array(_)));
+    }
+
+    #[test]
+    fn test_versions() {
+        let bytes = compile_with_options(SOURCE, CompilerOptions::default()).unwrap().to_bytes();
+
+        // bytecode from a newer crate may use instructions this one does not know
+        let newer = with_version(bytes.clone(), BytecodeVersion { major: 1, minor: BYTECODE_VERSION.minor + 1 });
+        let error = Program::load_any(&newer).err().unwrap();
+        assert_eq!(error, RuntimeError::UnsupportedBytecode(format!("1.{}", BYTECODE_VERSION.minor + 1), format!("1.0 to {}", BYTECODE_VERSION)));
+        assert_eq!(error.to_string(), format!("bytecode version 1.{} can not be read, only 1.0 to {} is supported", BYTECODE_VERSION.minor + 1, BYTECODE_VERSION));
+
+        let other = with_version(bytes, BytecodeVersion { major: 0, minor: 9 });
+        assert!(matches!(Program::load_any(&other), Err(RuntimeError::UnsupportedBytecode(version, _)) if version == "0.9"));
+    }
+
+    #[test]
+    fn test_operands() {
+        let instructions = [
+            (Instruction::LoadLocalVariable(3), 2),
+            (Instruction::Jump(300), 3),
+            (Instruction::IncrementLocal(1, -1), 3),
+            (Instruction::IncrementLocal(1, i32::MIN), 7),
+            (Instruction::CallMethod(String::from("area"), 0), 7),
+        ];
+        for (instruction, len) in instructions {
+            let mut w = Writer::default();
+            write_instruction(&mut w, &EncodedInstruction::Plain(instruction.clone()));
+            assert_eq!(w.bytes.len(), len);
+
+            let mut r = Reader::new(&w.bytes, RuntimeError::InvalidBytecode);
+            assert!(matches!(read_instruction(&mut r, Operands::Varint), Ok(EncodedInstruction::Plain(read)) if read == instruction));
+            r.finish().unwrap();
+        }
+        assert_eq!(zigzag(0), 0);
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(Program::load_any(b"TSVM\x02").err(), Some(RuntimeError::InvalidBytecode(String::from("not tinyscript bytecode"))));
+
+        let mut bytes = compile_with_options(SOURCE, CompilerOptions::default()).unwrap().to_bytes();
+        bytes.pop();
+        assert!(matches!(Program::load_any(&bytes), Err(RuntimeError::InvalidBytecode(_))));
+    }
+
+}
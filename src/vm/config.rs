@@ -0,0 +1,185 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::fmt;
+use core::fmt::{Debug, Formatter};
+
+use crate::prelude::*;
+use crate::vm::sandbox::Sandbox;
+use crate::vm::value::Value;
+
+// Vm Config
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VmConfig {
+    pub sandbox: Sandbox,
+    pub profile: bool,
+    pub arena: bool,
+    pub output: Output,
+    pub strings: StringUnits,
+}
+
+impl VmConfig {
+
+    pub fn new() -> Self {
+        VmConfig::default()
+    }
+
+    // limit the calls made from the host
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    // record the time spent in each function
+    pub fn profile(mut self, enabled: bool) -> Self {
+        self.profile = enabled;
+        self
+    }
+
+    // reuse the collections created by each call from the host once it returns
+    pub fn arena(mut self, enabled: bool) -> Self {
+        self.arena = enabled;
+        self
+    }
+
+    // where print statements write to
+    pub fn output(mut self, output: Output) -> Self {
+        self.output = output;
+        self
+    }
+
+    // what the length and positions of strings count
+    pub fn strings(mut self, units: StringUnits) -> Self {
+        self.strings = units;
+        self
+    }
+
+}
+
+// String Units
+//
+// strings are unicode text, and their length, indexes and padding count characters, which are
+// unicode scalar values, unless graphemes are asked for. bytes are never counted, so a position
+// is always the start of a character
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum StringUnits {
+    #[default]
+    Chars,
+    // what a reader sees as one character, such as a letter with an accent made of two chars
+    #[cfg(feature = "graphemes")]
+    Graphemes,
+}
+
+impl StringUnits {
+
+    // the text split into units
+    pub fn split(self, text: &str) -> Vec<&str> {
+        match self {
+            StringUnits::Chars => text.char_indices().map(|(start, c)| &text[start..start + c.len_utf8()]).collect(),
+            #[cfg(feature = "graphemes")]
+            StringUnits::Graphemes => crate::vm::grapheme::graphemes(text)
+        }
+    }
+
+    pub fn count(self, text: &str) -> usize {
+        match self {
+            StringUnits::Chars => text.chars().count(),
+            #[cfg(feature = "graphemes")]
+            StringUnits::Graphemes => crate::vm::grapheme::graphemes(text).len()
+        }
+    }
+
+    // the unit at a position, or none past the end
+    pub fn get(self, text: &str, index: usize) -> Option<&str> {
+        match self {
+            StringUnits::Chars => text.char_indices().nth(index).map(|(start, c)| &text[start..start + c.len_utf8()]),
+            #[cfg(feature = "graphemes")]
+            StringUnits::Graphemes => crate::vm::grapheme::graphemes(text).get(index).copied()
+        }
+    }
+
+}
+
+// Output
+//
+// print statements go to stdout unless the host captures them, such as to test what a script prints,
+// or hands each line to a callback, such as to show it on a web page where there is no stdout.
+// without std there is no stdout and print statements are captured unless given a callback
+#[derive(Clone)]
+pub enum Output {
+    #[cfg(feature = "std")]
+    Stdout,
+    Capture(Rc<RefCell<String>>),
+    Callback(Rc<dyn Fn(&str)>),
+}
+
+impl Default for Output {
+    #[cfg(feature = "std")]
+    fn default() -> Self {
+        Output::Stdout
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn default() -> Self {
+        Output::capture()
+    }
+}
+
+impl Output {
+
+    // an output that keeps everything printed, shared with any clones so the host can read it back
+    pub fn capture() -> Self {
+        Output::Capture(Rc::default())
+    }
+
+    pub fn callback(callback: impl Fn(&str) + 'static) -> Self {
+        Output::Callback(Rc::new(callback))
+    }
+
+    // everything printed so far, always empty for stdout and callbacks
+    pub fn captured(&self) -> String {
+        match self {
+            #[cfg(feature = "std")]
+            Output::Stdout => String::new(),
+            Output::Callback(_) => String::new(),
+            Output::Capture(text) => text.borrow().clone()
+        }
+    }
+
+    pub fn print(&self, value: &Value) {
+        let line = format!("{:?}", value.to_string());
+        match self {
+            #[cfg(feature = "std")]
+            Output::Stdout => println!("{}", line),
+            Output::Capture(text) => {
+                let mut text = text.borrow_mut();
+                text.push_str(&line);
+                text.push('\n');
+            }
+            Output::Callback(callback) => callback(&line)
+        }
+    }
+
+}
+
+impl Debug for Output {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            Output::Stdout => write!(f, "Stdout"),
+            Output::Capture(text) => f.debug_tuple("Capture").field(text).finish(),
+            Output::Callback(_) => write!(f, "Callback")
+        }
+    }
+}
+
+impl PartialEq for Output {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            #[cfg(feature = "std")]
+            (Output::Stdout, Output::Stdout) => true,
+            (Output::Capture(a), Output::Capture(b)) => a == b,
+            (Output::Callback(a), Output::Callback(b)) => Rc::ptr_eq(a, b),
+            _ => false
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use alloc::rc::Rc;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
+use crate::vm::value::Value;
+
+// a rust type that a value returned by a script can be converted into
+pub trait FromValue: Sized {
+
+    // the name of the type for errors
+    fn type_name() -> String;
+
+    fn from_value(value: Value) -> Result<Self, RuntimeError>;
+
+}
+
+fn mismatch<T: FromValue>(value: &Value) -> RuntimeError {
+    RuntimeError::ReturnTypeMismatch(T::type_name(), value.type_name().to_string())
+}
+
+impl FromValue for Value {
+    fn type_name() -> String {
+        String::from("value")
+    }
+
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        Ok(value)
+    }
+}
+
+impl FromValue for () {
+    fn type_name() -> String {
+        String::from("null")
+    }
+
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Null => Ok(()),
+            v => Err(mismatch::<Self>(&v))
+        }
+    }
+}
+
+impl FromValue for i32 {
+    fn type_name() -> String {
+        String::from("integer")
+    }
+
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Integer(v) => Ok(v),
+            v => Err(mismatch::<Self>(&v))
+        }
+    }
+}
+
+impl FromValue for i64 {
+    fn type_name() -> String {
+        String::from("integer")
+    }
+
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        i32::from_value(value).map(i64::from)
+    }
+}
+
+// integers are accepted where a float is expected
+impl FromValue for f32 {
+    fn type_name() -> String {
+        String::from("float")
+    }
+
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Float(v) => Ok(v),
+            Value::Integer(v) => Ok(v as f32),
+            v => Err(mismatch::<Self>(&v))
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn type_name() -> String {
+        String::from("float")
+    }
+
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        f32::from_value(value).map(f64::from)
+    }
+}
+
+impl FromValue for bool {
+    fn type_name() -> String {
+        String::from("bool")
+    }
+
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Bool(v) => Ok(v),
+            v => Err(mismatch::<Self>(&v))
+        }
+    }
+}
+
+impl FromValue for String {
+    fn type_name() -> String {
+        String::from("string")
+    }
+
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::String(v) => Ok(Rc::unwrap_or_clone(v)),
+            v => Err(mismatch::<Self>(&v))
+        }
+    }
+}
+
+// report a mismatch inside a collection or option as a mismatch of the whole type
+fn outer<T: FromValue>(error: RuntimeError) -> RuntimeError {
+    match error {
+        RuntimeError::ReturnTypeMismatch(_, found) => RuntimeError::ReturnTypeMismatch(T::type_name(), found),
+        e => e
+    }
+}
+
+// null becomes none
+impl<T: FromValue> FromValue for Option<T> {
+    fn type_name() -> String {
+        format!("{} or null", T::type_name())
+    }
+
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Null => Ok(None),
+            v => T::from_value(v).map(Some).map_err(outer::<Self>)
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn type_name() -> String {
+        format!("array of {}", T::type_name())
+    }
+
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Array(items) => items.borrow().iter().cloned().map(T::from_value).collect::<Result<_, _>>().map_err(outer::<Self>),
+            v => Err(mismatch::<Self>(&v))
+        }
+    }
+}
+
+// dictionaries and objects become maps of their entries
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    fn type_name() -> String {
+        format!("dictionary of {}", T::type_name())
+    }
+
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Dictionary(map) | Value::Object(map) => map.borrow().iter()
+                .map(|(key, value)| T::from_value(value.clone()).map(|v| (key.clone(), v)))
+                .collect::<Result<_, _>>()
+                .map_err(outer::<Self>),
+            v => Err(mismatch::<Self>(&v))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collections::HashMap;
+    use crate::value;
+    use crate::vm::convert::FromValue;
+    use crate::vm::error::RuntimeError;
+    use crate::vm::value::Value;
+
+    #[test]
+    fn test_scalars() {
+        assert_eq!(i64::from_value(Value::Integer(3)), Ok(3));
+        assert_eq!(f64::from_value(Value::Integer(3)), Ok(3.0));
+        assert_eq!(bool::from_value(Value::Bool(true)), Ok(true));
+        assert_eq!(String::from_value(value!("a")), Ok(String::from("a")));
+        assert_eq!(<()>::from_value(Value::Null), Ok(()));
+        assert_eq!(i32::from_value(value!("a")), Err(RuntimeError::ReturnTypeMismatch(String::from("integer"), String::from("string"))));
+    }
+
+    #[test]
+    fn test_collections() {
+        assert_eq!(Vec::<i32>::from_value(value!([1, 2])), Ok(vec![1, 2]));
+        assert_eq!(Option::<i32>::from_value(Value::Null), Ok(None));
+        assert_eq!(HashMap::<String, bool>::from_value(value!({ "a": true })), Ok(HashMap::from([(String::from("a"), true)])));
+        assert_eq!(Vec::<i32>::from_value(value!([1, "2"])), Err(RuntimeError::ReturnTypeMismatch(String::from("array of integer"), String::from("string"))));
+        assert_eq!(Option::<i32>::from_value(Value::Bool(true)), Err(RuntimeError::ReturnTypeMismatch(String::from("integer or null"), String::from("bool"))));
+    }
+
+}
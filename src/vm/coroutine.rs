@@ -0,0 +1,134 @@
+use core::fmt;
+use core::fmt::{Debug, Formatter};
+
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
+use crate::vm::frame::Frame;
+use crate::vm::value::Value;
+
+// Coroutine
+//
+// a function that runs until it yields and is resumed later, so a script can interleave several
+// activities in one vm. unlike a generator it can yield from functions it calls, so while it is
+// suspended it holds every frame from the function it started with up to the one that yielded
+#[derive(Clone)]
+pub struct Coroutine {
+    function: String,
+    // the function is called as a method of what made the coroutine. an object that keeps its own
+    // coroutine in a field, as in this.task = coroutine(this.run), holds it in a cycle of Rc that
+    // is never freed, so set the field to null once the coroutine is no longer wanted
+    this: Value,
+    state: State,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum State {
+    // made but never resumed
+    Created,
+    // the frames of the calls it is in, the first being the function it started with, and where to continue
+    Suspended(Vec<Frame>, usize),
+    Running,
+    Dead,
+}
+
+// what to do to carry on a coroutine
+pub enum Resume {
+    // call the function with what it was made with
+    Start(String, Value),
+    // put the frames back and continue from the position
+    Continue(Vec<Frame>, usize),
+}
+
+impl Coroutine {
+
+    pub fn new(function: String, this: Value) -> Self {
+        Coroutine { function, this, state: State::Created }
+    }
+
+    // rebuild a coroutine from a snapshot
+    pub fn restore(function: String, this: Value, state: State) -> Self {
+        Coroutine { function, this, state }
+    }
+
+    // mark the coroutine as running, giving what it needs to carry on
+    pub fn resume(&mut self) -> Result<Resume, RuntimeError> {
+        match core::mem::replace(&mut self.state, State::Running) {
+            State::Created => Ok(Resume::Start(self.function.clone(), self.this.clone())),
+            State::Suspended(frames, ip) => Ok(Resume::Continue(frames, ip)),
+            state => {
+                let status = status(&state);
+                self.state = state;
+                Err(RuntimeError::TypeError(format!("can not resume a {} coroutine", status)))
+            }
+        }
+    }
+
+    // keep the frames until it is resumed again
+    pub fn suspend(&mut self, frames: Vec<Frame>, ip: usize) {
+        self.state = State::Suspended(frames, ip);
+    }
+
+    pub fn finish(&mut self) {
+        self.state = State::Dead;
+    }
+
+    // suspended, running or dead
+    pub fn status(&self) -> &'static str {
+        status(&self.state)
+    }
+
+    pub fn get_function(&self) -> &str {
+        &self.function
+    }
+
+    pub fn get_this(&self) -> &Value {
+        &self.this
+    }
+
+    pub fn get_state(&self) -> &State {
+        &self.state
+    }
+
+}
+
+// the object is left out, as it usually holds the coroutine and would be printed forever
+impl Debug for Coroutine {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Coroutine({}, {})", self.function, self.status())
+    }
+}
+
+fn status(state: &State) -> &'static str {
+    match state {
+        State::Created | State::Suspended(..) => "suspended",
+        State::Running => "running",
+        State::Dead => "dead",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::coroutine::{Coroutine, Resume, State};
+    use crate::vm::frame::Frame;
+    use crate::vm::value::Value;
+
+    #[test]
+    fn test_status() {
+        let mut coroutine = Coroutine::new(String::from("Test.run"), Value::Null);
+        assert_eq!(coroutine.status(), "suspended");
+        assert!(matches!(coroutine.resume(), Ok(Resume::Start(function, Value::Null)) if function == "Test.run"));
+        assert_eq!(coroutine.status(), "running");
+        assert!(coroutine.resume().is_err());
+
+        coroutine.suspend(vec![Frame::new(String::from("Test.run[1]"), Some(4), None)], 9);
+        assert_eq!(coroutine.status(), "suspended");
+        assert!(matches!(coroutine.resume(), Ok(Resume::Continue(frames, 9)) if frames.len() == 1));
+
+        coroutine.finish();
+        assert_eq!(coroutine.get_state(), &State::Dead);
+        assert!(coroutine.resume().is_err());
+        assert_eq!(coroutine.status(), "dead");
+        assert_eq!(format!("{:?}", coroutine), "Coroutine(Test.run, dead)");
+    }
+
+}
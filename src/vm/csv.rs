@@ -0,0 +1,199 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
+use crate::vm::value::Value;
+
+// split text into records of fields, where quoted fields may hold commas, quotes doubled as "" and new lines
+fn records(text: &str) -> Result<Vec<Vec<String>>, RuntimeError> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(core::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                record.push(core::mem::take(&mut field));
+                records.push(core::mem::take(&mut record));
+            }
+            c => field.push(c)
+        }
+    }
+
+    if quoted {
+        return Err(invalid(&format!("quoted field on line {} is not closed", records.len() + 1)));
+    }
+
+    // the last line may not end with a new line
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+// parse csv text into an array with a dictionary for each row, keyed by the header on the first line
+pub fn parse(text: &str) -> Result<Value, RuntimeError> {
+    let mut records = records(text)?.into_iter();
+    let header = match records.next() {
+        Some(header) => header,
+        None => return Ok(Value::Array(Rc::new(RefCell::new(vec![]))))
+    };
+
+    let mut rows = vec![];
+    for (line, record) in records.enumerate() {
+        if record.len() != header.len() {
+            return Err(invalid(&format!("row {} has {} fields but the header has {}", line + 1, record.len(), header.len())));
+        }
+        let row: HashMap<String, Value> = header.iter().cloned().zip(record.into_iter().map(Value::from)).collect();
+        rows.push(Value::Dictionary(Rc::new(RefCell::new(row))));
+    }
+
+    Ok(Value::Array(Rc::new(RefCell::new(rows))))
+}
+
+// write an array of rows as csv text
+//
+// rows that are dictionaries are written under a header of the columns given, or of their keys in order,
+// while rows that are arrays are written as they are
+pub fn stringify(rows: &Value, columns: Option<&Value>) -> Result<String, RuntimeError> {
+    let rows = match rows {
+        Value::Array(rows) => rows.borrow(),
+        _ => return Err(RuntimeError::TypeError(format!("can not write {} as csv", rows.type_name())))
+    };
+
+    let columns: Option<Vec<String>> = match columns {
+        Some(Value::Array(columns)) => Some(columns.borrow().iter().map(|c| c.to_string()).collect()),
+        Some(other) => return Err(RuntimeError::TypeError(format!("csv columns must be an array, not {}", other.type_name()))),
+        None => {
+            let mut keys: Vec<String> = rows.iter()
+                .filter_map(|row| match row {
+                    Value::Dictionary(d) => Some(d.borrow().keys().cloned().collect::<Vec<String>>()),
+                    _ => None
+                })
+                .flatten()
+                .collect();
+            keys.sort();
+            keys.dedup();
+            if keys.is_empty() { None } else { Some(keys) }
+        }
+    };
+
+    let mut output = String::new();
+    if let Some(columns) = &columns {
+        write_record(&mut output, columns.iter().cloned());
+    }
+
+    for row in rows.iter() {
+        match (row, &columns) {
+            (Value::Array(fields), _) => write_record(&mut output, fields.borrow().iter().map(field)),
+            (Value::Dictionary(d), Some(columns)) => {
+                let d = d.borrow();
+                write_record(&mut output, columns.iter().map(|c| d.get(c).map(field).unwrap_or_default()));
+            }
+            _ => return Err(RuntimeError::TypeError(format!("can not write {} as a csv row", row.type_name())))
+        }
+    }
+
+    Ok(output)
+}
+
+// null is written as an empty field
+fn field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        v => v.to_string()
+    }
+}
+
+fn write_record(output: &mut String, fields: impl Iterator<Item = String>) {
+    for (i, field) in fields.enumerate() {
+        if i > 0 {
+            output.push(',');
+        }
+        if field.contains([',', '"', '\n', '\r']) {
+            output.push('"');
+            output.push_str(&field.replace('"', "\"\""));
+            output.push('"');
+        } else {
+            output.push_str(&field);
+        }
+    }
+    output.push('\n');
+}
+
+fn invalid(message: &str) -> RuntimeError {
+    RuntimeError::TypeError(format!("invalid csv: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::vm::csv::{parse, stringify};
+    use crate::vm::value::Value;
+
+    fn string(s: &str) -> Value {
+        Value::from(s)
+    }
+
+    #[test]
+    fn test_parse() {
+        let rows = parse("name,notes\r\nada,\"likes \"\"maths\"\", engines\"\nalan,\"two\nlines\"").unwrap();
+        let rows = match rows {
+            Value::Array(rows) => rows.borrow().clone(),
+            _ => panic!("expected an array")
+        };
+        assert_eq!(rows.len(), 2);
+        match &rows[0] {
+            Value::Dictionary(d) => {
+                assert_eq!(d.borrow().get("name"), Some(&string("ada")));
+                assert_eq!(d.borrow().get("notes"), Some(&string("likes \"maths\", engines")));
+            }
+            _ => panic!("expected a dictionary")
+        }
+        match &rows[1] {
+            Value::Dictionary(d) => assert_eq!(d.borrow().get("notes"), Some(&string("two\nlines"))),
+            _ => panic!("expected a dictionary")
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse("a,b\n1\n").is_err());
+        assert!(parse("a\n\"open\n").is_err());
+        assert_eq!(parse("").unwrap(), Value::Array(Rc::new(RefCell::new(vec![]))));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = "a,b\n1,\"x, y\"\n2,\"say \"\"hi\"\"\"\n";
+        assert_eq!(stringify(&parse(text).unwrap(), None).unwrap(), text);
+
+        let columns = Value::Array(Rc::new(RefCell::new(vec![string("b"), string("a")])));
+        assert_eq!(stringify(&parse("a,b\n1,2\n").unwrap(), Some(&columns)).unwrap(), "b,a\n2,1\n");
+    }
+
+    #[test]
+    fn test_stringify_arrays() {
+        let row = Value::Array(Rc::new(RefCell::new(vec![Value::Integer(1), Value::Null, Value::Float(2.5)])));
+        let rows = Value::Array(Rc::new(RefCell::new(vec![row])));
+        assert_eq!(stringify(&rows, None).unwrap(), "1,,2.5\n");
+        assert!(stringify(&Value::Integer(1), None).is_err());
+    }
+
+}
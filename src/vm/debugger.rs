@@ -0,0 +1,344 @@
+use crate::compiler::{compile_expression, EXPRESSION_FUNCTION_NAME};
+use crate::error::TinyscriptError;
+use crate::prelude::*;
+use crate::vm::config::VmConfig;
+use crate::vm::error::RuntimeError;
+use crate::vm::image::{Decoder, Encoder};
+use crate::vm::instruction::Instruction;
+use crate::vm::program::Program;
+use crate::vm::sandbox::Sandbox;
+use crate::vm::value::Value;
+use crate::vm::VM;
+
+// instructions an evaluated expression may run, so a watch that never finishes can not hang the debugger
+const EVAL_MAX_INSTRUCTIONS: usize = 1_000_000;
+
+// Debugger
+//
+// told each time the vm pauses, before the instruction at a breakpoint or the next one after a
+// step runs, and decides how the vm carries on. the vm only looks for somewhere to pause while
+// a debugger is attached
+pub trait Debugger {
+    fn paused(&mut self, session: &mut DebugSession) -> Resume;
+}
+
+// how the vm carries on after a pause
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resume {
+    // run until the next breakpoint
+    Continue,
+    // pause before the next instruction, in this function or one it calls
+    Step,
+    // pause before the next instruction of this function, running any calls without pausing
+    Next,
+    // pause once this function has returned
+    Finish,
+}
+
+// a call that is running
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackFrame {
+    pub function: String,
+    // the instruction the call is at, which is unknown for calls below one made by the host
+    pub position: Option<usize>,
+}
+
+// the paused vm, as a debugger sees it. frames are numbered from the call made by the host
+pub struct DebugSession<'a> {
+    vm: &'a mut VM,
+}
+
+impl DebugSession<'_> {
+
+    // position of the instruction about to run
+    pub fn position(&self) -> usize {
+        self.vm.ip
+    }
+
+    pub fn instruction(&self) -> &Instruction {
+        &self.vm.instructions[self.vm.ip]
+    }
+
+    // the calls that are running, innermost last
+    pub fn frames(&self) -> Vec<StackFrame> {
+        let frames = &self.vm.frames;
+        frames.iter().enumerate().map(|(index, frame)| {
+            let position = match frames.get(index + 1) {
+                Some(called) => called.get_return_position().map(|position| position - 1),
+                None => Some(self.vm.ip)
+            };
+            StackFrame { function: frame.get_function_name().to_string(), position }
+        }).collect()
+    }
+
+    // named local variables of a frame, in the order they were declared
+    pub fn locals(&self, frame: usize) -> Vec<(String, Value)> {
+        let Some(running) = self.vm.frames.get(frame) else { return vec![] };
+        let names = self.vm.locals.get(running.get_function_name()).map(Vec::as_slice).unwrap_or_default();
+        names.iter().zip(running.get_variables())
+            .filter(|(name, _)| !name.starts_with('$'))
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect()
+    }
+
+    // values on the stack of a frame, the top last
+    pub fn stack(&self, frame: usize) -> &[Value] {
+        self.vm.frames.get(frame).map(|running| running.get_stack()).unwrap_or_default()
+    }
+
+    pub fn eval(&self, frame: usize, expression: &str) -> Result<Value, TinyscriptError> {
+        self.vm.eval_in_frame(frame, expression)
+    }
+
+    pub fn break_at(&mut self, function: &str) -> Result<(), RuntimeError> {
+        self.vm.break_at(function)
+    }
+
+    pub fn clear_breakpoint(&mut self, function: &str) -> bool {
+        self.vm.clear_breakpoint(function)
+    }
+
+}
+
+impl VM {
+
+    pub fn attach_debugger(&mut self, debugger: impl Debugger + 'static) {
+        self.debugger = Some(Box::new(debugger));
+    }
+
+    pub fn detach_debugger(&mut self) -> Option<Box<dyn Debugger>> {
+        self.pause_depth = None;
+        self.debugger.take()
+    }
+
+    // pause when a function is called
+    pub fn break_at(&mut self, function: &str) -> Result<(), RuntimeError> {
+        let position = *self.functions.get(function).ok_or_else(|| RuntimeError::UnknownFunction(function.to_string()))?;
+        self.breakpoints.insert(position);
+        Ok(())
+    }
+
+    // remove a breakpoint, returning false if the function did not have one
+    pub fn clear_breakpoint(&mut self, function: &str) -> bool {
+        self.functions.get(function).is_some_and(|position| self.breakpoints.remove(position))
+    }
+
+    // evaluate an expression with the variables of a running frame, such as 'this.count * 2'.
+    //
+    // it runs in a vm of its own on a copy of the globals and variables, so nothing it does,
+    // such as calling a method that changes a field, is seen by this vm
+    pub fn eval_in_frame(&self, frame: usize, expression: &str) -> Result<Value, TinyscriptError> {
+        let running = self.frames.get(frame).ok_or(RuntimeError::UnknownFrame(frame))?;
+
+        // 'this' is always the first slot, and is given to the expression as it is
+        let names = self.locals.get(running.get_function_name()).map(Vec::as_slice).unwrap_or_default();
+        // a name shadowed in an inner block is in more than one slot, and the innermost is last
+        let (names, values): (Vec<String>, Vec<Value>) = names.iter().zip(running.get_variables()).enumerate().skip(1)
+            .filter(|(slot, (name, _))| !name.starts_with('$') && !names[slot + 1..].contains(name))
+            .map(|(_, local)| local)
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .unzip();
+        let this = running.get_variables().first().cloned().unwrap_or_default();
+
+        let (mut instructions, metadata, _) = compile_expression(expression, &names, &self.global_symbols)?;
+        let start = self.instructions.len();
+        instructions.iter_mut().for_each(|instruction| instruction.relocate(start));
+
+        // copying through an image keeps values that are shared, shared
+        let mut encoder = Encoder::default();
+        let globals = encoder.encode_all(&self.globals)?;
+        let args = encoder.encode_all(&[vec![this], values].concat())?;
+        let mut decoder = Decoder::new(&encoder.heap)?;
+        let (globals, args) = (decoder.decode_all(&globals)?, decoder.decode_all(&args)?);
+
+        let mut program = Program {
+            instructions: self.instructions.iter().cloned().chain(instructions).collect(),
+            symbols: self.functions.clone(),
+            metadata: self.metadata.clone(),
+            globals,
+            global_symbols: self.global_symbols.clone(),
+            strict: self.strict,
+            checked: self.checked,
+            ..Program::new()
+        };
+        program.symbols.insert(EXPRESSION_FUNCTION_NAME.to_string(), start);
+        program.metadata.insert(EXPRESSION_FUNCTION_NAME.to_string(), metadata);
+
+        let sandbox = Sandbox { max_instructions: Some(self.sandbox.max_instructions.unwrap_or(EVAL_MAX_INSTRUCTIONS).min(EVAL_MAX_INSTRUCTIONS)), ..self.sandbox.clone() };
+        let mut vm = VM::with_config(program, VmConfig::new().sandbox(sandbox).output(self.output.clone()));
+        Ok(vm.call_function(EXPRESSION_FUNCTION_NAME, start, args)?)
+    }
+
+    // pause before the next instruction that runs, such as the first of the next call
+    pub fn pause_next(&mut self) {
+        self.pause_depth = Some(usize::MAX);
+    }
+
+    // whether the attached debugger wants the instruction about to run to pause
+    pub(crate) fn should_pause(&self) -> bool {
+        self.breakpoints.contains(&self.ip) || self.pause_depth.is_some_and(|depth| self.frames.len() <= depth)
+    }
+
+    // hand the vm to the debugger until it says how to carry on
+    pub(crate) fn pause(&mut self) {
+        let Some(mut debugger) = self.debugger.take() else { return };
+        let resume = debugger.paused(&mut DebugSession { vm: self });
+        self.pause_depth = match resume {
+            Resume::Continue => None,
+            Resume::Step => Some(usize::MAX),
+            Resume::Next => Some(self.frames.len()),
+            Resume::Finish => Some(self.frames.len().saturating_sub(1)),
+        };
+        self.debugger = Some(debugger);
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::compiler::{compile, CompileError};
+    use crate::error::TinyscriptError;
+    use crate::vm::debugger::{DebugSession, Debugger, Resume, StackFrame};
+    use crate::vm::error::RuntimeError;
+    use crate::vm::value::Value;
+    use crate::vm::VM;
+
+    const SCRIPT: &str = r#"
+        class Test {
+            function main() {
+                var total = 0;
+                for (i in [1, 2, 3]) {
+                    total = total + this.double(i);
+                }
+                return total;
+            }
+
+            function double(n) {
+                var result = n * 2;
+                return result;
+            }
+        }
+    "#;
+
+    // the frames and innermost locals at a pause
+    type Pause = (Vec<StackFrame>, Vec<(String, Value)>);
+
+    // answers each pause with the next of its replies, keeping what it saw
+    struct Scripted {
+        replies: Vec<Resume>,
+        seen: Rc<RefCell<Vec<Pause>>>,
+    }
+
+    impl Debugger for Scripted {
+        fn paused(&mut self, session: &mut DebugSession) -> Resume {
+            let innermost = session.frames().len() - 1;
+            self.seen.borrow_mut().push((session.frames(), session.locals(innermost)));
+            match self.replies.is_empty() {
+                true => Resume::Continue,
+                false => self.replies.remove(0)
+            }
+        }
+    }
+
+    fn debug(replies: Vec<Resume>, setup: impl FnOnce(&mut VM)) -> Vec<Pause> {
+        let seen = Rc::new(RefCell::new(vec![]));
+        let mut vm = VM::new(compile(SCRIPT).unwrap());
+        vm.attach_debugger(Scripted { replies, seen: seen.clone() });
+        setup(&mut vm);
+        assert_eq!(vm.exec("Test.main", None).unwrap(), Value::Integer(12));
+        seen.take()
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let seen = debug(vec![], |vm| vm.break_at("Test.double").unwrap());
+        assert_eq!(seen.len(), 3);
+
+        let (frames, locals) = &seen[1];
+        assert_eq!(frames.iter().map(|frame| frame.function.as_str()).collect::<Vec<&str>>(), ["Test.main", "Test.double"]);
+        assert_eq!(locals[1], (String::from("n"), Value::Integer(2)));
+        assert_eq!(locals[2], (String::from("result"), Value::Null));
+    }
+
+    #[test]
+    fn test_stepping() {
+        // next stays in the function while step goes into the call
+        let next = debug(vec![Resume::Next; 50], |vm| vm.break_at("Test.double").unwrap());
+        assert!(next.iter().all(|(frames, _)| frames.len() == 2 || frames.len() == 1));
+        let in_main: Vec<&Vec<(String, Value)>> = next.iter().filter(|(frames, _)| frames.len() == 1).map(|(_, locals)| locals).collect();
+        assert!(in_main.iter().any(|locals| locals.iter().any(|(name, value)| name == "total" && *value == Value::Integer(2))));
+
+        let step = debug(vec![Resume::Step; 5], |vm| vm.pause_next());
+        assert_eq!(step.len(), 6);
+        assert!(step.iter().all(|(frames, _)| frames.len() == 1));
+
+        // finish returns to main
+        let finish = debug(vec![Resume::Finish, Resume::Continue], |vm| vm.break_at("Test.double").unwrap());
+        assert_eq!(finish[1].0.len(), 1);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut vm = VM::new(compile(SCRIPT).unwrap());
+        assert_eq!(vm.break_at("Test.missing"), Err(RuntimeError::UnknownFunction(String::from("Test.missing"))));
+        vm.break_at("Test.double").unwrap();
+        assert!(vm.clear_breakpoint("Test.double"));
+        assert!(!vm.clear_breakpoint("Test.double"));
+    }
+
+    // evaluates each expression at every pause
+    struct Watch {
+        expressions: Vec<&'static str>,
+        results: Rc<RefCell<Vec<Result<Value, TinyscriptError>>>>,
+    }
+
+    impl Debugger for Watch {
+        fn paused(&mut self, session: &mut DebugSession) -> Resume {
+            let innermost = session.frames().len() - 1;
+            for expression in self.expressions.iter() {
+                self.results.borrow_mut().push(session.eval(innermost, expression));
+            }
+            Resume::Continue
+        }
+    }
+
+    #[test]
+    fn test_eval() {
+        let script = r#"
+            class Counter {
+                var count = 0;
+                function bump() { this.count = this.count + 1; return this.count; }
+            }
+            class Test {
+                function main() {
+                    var counter = new Counter();
+                    counter.bump();
+                    return Test.check(counter, 5);
+                }
+                function check(counter, n) {
+                    return counter.count;
+                }
+            }
+        "#;
+        let results = Rc::new(RefCell::new(vec![]));
+        let mut vm = VM::new(compile(script).unwrap());
+        vm.attach_debugger(Watch { expressions: vec!["n * 2 + counter.count", "counter.bump()", "[n, \"x\"]", "Arrays.length([n, 7, 8])", "missing", "1; }"], results: results.clone() });
+        vm.break_at("Test.check").unwrap();
+
+        // the bump from the watch is not seen by the script
+        assert_eq!(vm.exec("Test.main", None).unwrap(), Value::Integer(1));
+
+        let results = results.take();
+        assert_eq!(results[0], Ok(Value::Integer(11)));
+        assert_eq!(results[1], Ok(Value::Integer(2)));
+        assert_eq!(results[2].as_ref().map(|value| format!("{:?}", value)), Ok(String::from("Array(RefCell { value: [Integer(5), String(\"x\")] })")));
+        assert_eq!(results[3], Ok(Value::Integer(3)));
+        assert!(matches!(&results[4], Err(TinyscriptError::Compile(CompileError::Function(_, _)))));
+        assert!(matches!(&results[5], Err(TinyscriptError::Compile(CompileError::Parse(_)))));
+        assert_eq!(vm.eval_in_frame(0, "1"), Err(TinyscriptError::Runtime(RuntimeError::UnknownFrame(0))));
+    }
+
+}
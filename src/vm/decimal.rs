@@ -0,0 +1,243 @@
+use core::cmp::Ordering;
+use core::fmt;
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
+
+use crate::prelude::*;
+use crate::vm::bigint::BigInt;
+
+// places kept when a division does not end, beyond those of its operands
+pub const DIVISION_PLACES: u32 = 16;
+
+// Decimal
+//
+// an exact decimal number, the mantissa divided by ten to the power of the scale. the scale
+// is kept as written, so 1.50 stays 1.50 for amounts of money
+#[derive(Clone, Debug, Default)]
+pub struct Decimal {
+    mantissa: BigInt,
+    scale: u32,
+}
+
+impl Decimal {
+
+    pub fn new(mantissa: BigInt, scale: u32) -> Self {
+        Decimal { mantissa, scale }
+    }
+
+    // bytes used by the mantissa, for memory limits
+    pub fn size(&self) -> usize {
+        self.mantissa.size()
+    }
+
+    // the whole part, rounded towards zero
+    pub fn trunc(&self) -> BigInt {
+        let (quotient, _) = self.mantissa.div_rem(&ten_to(self.scale)).expect("power of ten should not be zero");
+        quotient
+    }
+
+    // the nearest f64, which may lose precision
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    pub fn neg(&self) -> Decimal {
+        Decimal::new(self.mantissa.neg(), self.scale)
+    }
+
+    pub fn add(&self, rhs: &Decimal) -> Decimal {
+        let scale = self.scale.max(rhs.scale);
+        Decimal::new(self.rescale(scale).add(&rhs.rescale(scale)), scale)
+    }
+
+    pub fn sub(&self, rhs: &Decimal) -> Decimal {
+        self.add(&rhs.neg())
+    }
+
+    pub fn mul(&self, rhs: &Decimal) -> Decimal {
+        Decimal::new(self.mantissa.mul(&rhs.mantissa), self.scale + rhs.scale)
+    }
+
+    // rounded half away from zero after DIVISION_PLACES more places than either operand, with
+    // the zeros that were not needed removed
+    pub fn div(&self, rhs: &Decimal) -> Option<Decimal> {
+        let kept = self.scale.max(rhs.scale);
+        let scale = kept + DIVISION_PLACES;
+
+        // self * 10^scale / rhs, with both mantissas brought to the same scale first
+        let quotient = round_div(&self.rescale(kept).mul(&ten_to(scale)), &rhs.rescale(kept))?;
+        Some(Decimal::new(quotient, scale).trimmed(kept))
+    }
+
+    // rounded half away from zero to the given places, adding zeros when there are fewer
+    pub fn round(&self, places: u32) -> Decimal {
+        match places.checked_sub(self.scale) {
+            Some(_) => Decimal::new(self.rescale(places), places),
+            None => {
+                let quotient = round_div(&self.mantissa, &ten_to(self.scale - places)).expect("power of ten should not be zero");
+                Decimal::new(quotient, places)
+            }
+        }
+    }
+
+    // the quotient rounded down to a whole number
+    pub fn floor_div(&self, rhs: &Decimal) -> Option<Decimal> {
+        let scale = self.scale.max(rhs.scale);
+        Some(Decimal::new(self.rescale(scale).floor_div(&rhs.rescale(scale))?, 0))
+    }
+
+    pub fn pow(&self, exponent: u32) -> Decimal {
+        Decimal::new(self.mantissa.pow(exponent), self.scale * exponent)
+    }
+
+    // the mantissa at a scale at least as large as this one
+    fn rescale(&self, scale: u32) -> BigInt {
+        self.mantissa.mul(&ten_to(scale - self.scale))
+    }
+
+    // remove zeros from the end of the fraction, keeping at least the given places
+    fn trimmed(mut self, places: u32) -> Decimal {
+        let ten = BigInt::from(10);
+        while self.scale > places {
+            let (quotient, remainder) = self.mantissa.div_rem(&ten).expect("ten should not be zero");
+            if !remainder.is_zero() {
+                break;
+            }
+            self.mantissa = quotient;
+            self.scale -= 1;
+        }
+        self
+    }
+
+}
+
+// divide and round half away from zero
+fn round_div(numerator: &BigInt, denominator: &BigInt) -> Option<BigInt> {
+    let (quotient, remainder) = numerator.div_rem(denominator)?;
+    let rounded = match remainder.abs().add(&remainder.abs()) >= denominator.abs() {
+        true if numerator.is_negative() != denominator.is_negative() => quotient.sub(&BigInt::from(1)),
+        true => quotient.add(&BigInt::from(1)),
+        false => quotient
+    };
+    Some(rounded)
+}
+
+fn ten_to(power: u32) -> BigInt {
+    BigInt::from(10).pow(power)
+}
+
+impl From<BigInt> for Decimal {
+    fn from(value: BigInt) -> Self {
+        Decimal::new(value, 0)
+    }
+}
+
+impl From<i32> for Decimal {
+    fn from(value: i32) -> Self {
+        Decimal::new(BigInt::from(value), 0)
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = String;
+
+    // digits with an optional minus sign and fraction
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+        if text.ends_with('.') || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("'{}' is not a decimal", text));
+        }
+        let mantissa = format!("{}{}", whole, fraction).parse().map_err(|_| format!("'{}' is not a decimal", text))?;
+        Ok(Decimal::new(mantissa, fraction.len() as u32))
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let digits = self.mantissa.to_string();
+        let (sign, digits) = match digits.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", digits.as_str())
+        };
+        if self.scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+        let digits = format!("{:0>width$}", digits, width = self.scale as usize + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - self.scale as usize);
+        write!(f, "{}{}.{}", sign, whole, fraction)
+    }
+}
+
+// decimals are equal by value, so 1.5 equals 1.50
+impl PartialEq for Decimal {
+    fn eq(&self, rhs: &Self) -> bool {
+        self.cmp(rhs) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, rhs: &Self) -> Option<Ordering> {
+        Some(self.cmp(rhs))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, rhs: &Self) -> Ordering {
+        let scale = self.scale.max(rhs.scale);
+        self.rescale(scale).cmp(&rhs.rescale(scale))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::bigint::BigInt;
+    use crate::vm::decimal::Decimal;
+
+    fn dec(text: &str) -> Decimal {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(dec("1.50").to_string(), "1.50");
+        assert_eq!(dec("-0.05").to_string(), "-0.05");
+        assert_eq!(dec("12").to_string(), "12");
+        assert!("1.".parse::<Decimal>().is_err());
+        assert!("1.2.3".parse::<Decimal>().is_err());
+        assert!("x".parse::<Decimal>().is_err());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(dec("0.1").add(&dec("0.2")).to_string(), "0.3");
+        assert_eq!(dec("1.50").sub(&dec("2")).to_string(), "-0.50");
+        assert_eq!(dec("1.25").mul(&dec("-0.2")).to_string(), "-0.250");
+        assert_eq!(dec("1.1").pow(2).to_string(), "1.21");
+        assert_eq!(dec("1.5"), dec("1.50"));
+        assert!(dec("-1.5") < dec("-1.49"));
+    }
+
+    #[test]
+    fn test_division() {
+        assert_eq!(dec("1.00").div(&dec("4")).unwrap().to_string(), "0.25");
+        assert_eq!(dec("10").div(&dec("4")).unwrap().to_string(), "2.5");
+        assert_eq!(dec("2").div(&dec("3")).unwrap().to_string(), "0.6666666666666667");
+        assert_eq!(dec("-2").div(&dec("3")).unwrap().to_string(), "-0.6666666666666667");
+        assert_eq!(dec("1").div(&dec("-3")).unwrap().to_string(), "-0.3333333333333333");
+        assert_eq!(dec("-7.5").floor_div(&dec("2")).unwrap().to_string(), "-4");
+        assert!(dec("1").div(&dec("0.0")).is_none());
+        assert_eq!(dec("-2.75").trunc(), BigInt::from(-2));
+    }
+
+    #[test]
+    fn test_round() {
+        assert_eq!(dec("2.345").round(2).to_string(), "2.35");
+        assert_eq!(dec("-2.345").round(2).to_string(), "-2.35");
+        assert_eq!(dec("2.344").round(2).to_string(), "2.34");
+        assert_eq!(dec("1.5").round(2).to_string(), "1.50");
+        assert_eq!(dec("0.5").round(0).to_string(), "1");
+    }
+
+}
@@ -0,0 +1,173 @@
+use core::fmt;
+use core::fmt::{Display, Formatter};
+use core::ops::Range;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+use crate::vm::instruction::Instruction;
+use crate::vm::program::Program;
+use crate::vm::value::Value;
+
+// Program Diff
+//
+// what changed between two builds of a program, function by function. code is compared after
+// taking away where it was laid out, so a function only shows as changed when it does
+// something different, not when a function before it grew or a global moved
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProgramDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<FunctionChange>,
+}
+
+// a function in both programs whose code or signature is different
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionChange {
+    pub name: String,
+    // instruction count before and after
+    pub instructions: (usize, usize),
+    // parameter names before and after, if they changed
+    pub params: Option<(Vec<String>, Vec<String>)>,
+}
+
+impl ProgramDiff {
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+}
+
+impl Program {
+
+    // compare the functions of two programs, each list is in name order
+    pub fn diff(old: &Program, new: &Program) -> ProgramDiff {
+        let old_functions: HashMap<&String, Range<usize>> = old.functions().into_iter().collect();
+        let new_functions: HashMap<&String, Range<usize>> = new.functions().into_iter().collect();
+        let (old_globals, new_globals) = (global_names(old), global_names(new));
+
+        let mut diff = ProgramDiff::default();
+        for (name, range) in new_functions.iter() {
+            let Some(old_range) = old_functions.get(name) else {
+                diff.added.push(name.to_string());
+                continue;
+            };
+
+            let params = (old.params.get(*name).cloned().unwrap_or_default(), new.params.get(*name).cloned().unwrap_or_default());
+            let same_code = old.metadata.get(*name) == new.metadata.get(*name)
+                && old_range.len() == range.len()
+                && old.instructions[old_range.clone()].iter().zip(new.instructions[range.clone()].iter())
+                    .all(|(a, b)| same_instruction((a, old_range.start, &old_globals), (b, range.start, &new_globals)));
+
+            if !same_code || params.0 != params.1 {
+                diff.changed.push(FunctionChange {
+                    name: name.to_string(),
+                    instructions: (old_range.len(), range.len()),
+                    params: (params.0 != params.1).then_some(params),
+                });
+            }
+        }
+        diff.removed = old_functions.keys().filter(|name| !new_functions.contains_key(*name)).map(|name| name.to_string()).collect();
+
+        diff.added.sort();
+        diff.removed.sort();
+        diff.changed.sort_by(|a, b| a.name.cmp(&b.name));
+        diff
+    }
+
+}
+
+// one line per function, such as '~ Test.main 12 -> 14 instructions'
+impl Display for ProgramDiff {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for name in self.added.iter() {
+            writeln!(f, "+ {}", name)?;
+        }
+        for name in self.removed.iter() {
+            writeln!(f, "- {}", name)?;
+        }
+        for change in self.changed.iter() {
+            write!(f, "~ {} {} -> {} instructions", change.name, change.instructions.0, change.instructions.1)?;
+            if let Some((old, new)) = &change.params {
+                write!(f, ", ({}) -> ({})", old.join(", "), new.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// the name of each global that has one
+fn global_names(program: &Program) -> HashMap<usize, &String> {
+    program.global_symbols.iter().map(|(name, index)| (*index, name)).collect()
+}
+
+// an instruction and the start and global names of the function it is in
+type Placed<'a> = (&'a Instruction, usize, &'a HashMap<usize, &'a String>);
+
+// whether two instructions do the same thing, with jumps taken relative to the start of their
+// function and globals compared by name
+fn same_instruction((a, a_start, a_globals): Placed, (b, b_start, b_globals): Placed) -> bool {
+    let same_global = |x: &usize, y: &usize| match (a_globals.get(x), b_globals.get(y)) {
+        (None, None) => x == y,
+        (x_name, y_name) => x_name == y_name
+    };
+
+    match (a, b) {
+        (Instruction::LoadGlobal(x), Instruction::LoadGlobal(y)) | (Instruction::StoreGlobal(x), Instruction::StoreGlobal(y)) => same_global(x, y),
+        (Instruction::StackPush(x), Instruction::StackPush(y)) => same_constant(x, y),
+        (Instruction::CompareLocalConstJump(.., x, _), Instruction::CompareLocalConstJump(.., y, _)) if !same_constant(x, y) => false,
+        _ => {
+            let (mut a, mut b) = (a.clone(), b.clone());
+            if let (Some(x), Some(y)) = (a.jump_target_mut(), b.jump_target_mut()) {
+                *x = x.wrapping_sub(a_start);
+                *y = y.wrapping_sub(b_start);
+            }
+            a == b
+        }
+    }
+}
+
+// 1 and 1.0 are equal values but not the same constant
+fn same_constant(a: &Value, b: &Value) -> bool {
+    a.type_name() == b.type_name() && a == b
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::{compile_with_options, CompilerOptions};
+    use crate::vm::diff::FunctionChange;
+    use crate::vm::program::Program;
+
+    fn compile(source: &str) -> Program {
+        compile_with_options(source, CompilerOptions { no_std: true, ..Default::default() }).unwrap()
+    }
+
+    #[test]
+    fn test_unchanged() {
+        let old = compile("class A { function f() { if 1 > 2 { return 1; } return 2; } }");
+
+        // moving a function down the program moves its jumps but does not change it
+        let new = compile("class B { function g() { var x = 1; return x + 1; } }\nclass A { function f() { if 1 > 2 { return 1; } return 2; } }");
+        let diff = Program::diff(&old, &new);
+        assert_eq!(diff.added, vec!["B.constructor", "B.g"]);
+        assert!(diff.removed.is_empty() && diff.changed.is_empty());
+        assert!(Program::diff(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_changes() {
+        let old = compile("class A { function f(a) { return a + 1; } function g() { return 1; } function h() { } }");
+        let new = compile("class A { function f(a, b) { return a + b; } function g() { return 1.0; } }");
+        let diff = Program::diff(&old, &new);
+
+        assert_eq!(diff.removed, vec!["A.h"]);
+        assert_eq!(diff.changed.len(), 2);
+        assert_eq!(diff.changed[0].name, "A.f");
+        assert_eq!(diff.changed[0].params, Some((vec![String::from("a")], vec![String::from("a"), String::from("b")])));
+        assert_eq!(diff.changed[1], FunctionChange { name: String::from("A.g"), instructions: (diff.changed[1].instructions.0, diff.changed[1].instructions.0), params: None });
+        assert_eq!(diff.to_string().lines().next(), Some("- A.h"));
+        assert!(diff.to_string().contains(", (a) -> (a, b)"));
+    }
+
+}
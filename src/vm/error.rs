@@ -0,0 +1,90 @@
+use core::error::Error;
+use core::fmt;
+use core::fmt::{Display, Formatter};
+
+use crate::prelude::*;
+
+// Runtime Error
+#[derive(Clone, Debug, PartialEq)]
+pub enum RuntimeError {
+    AssertionFailed,
+    ExpectationFailed(String),
+    TypeError(String),
+    KeyNotFound(String),
+    IndexOutOfBounds(i32),
+    UnknownFunction(String),
+    UnknownParameter(String, String),
+    UnknownMethod(String, String),
+    InvalidImage(String),
+    InvalidBytecode(String),
+    // version of the bytecode and the versions that can be read
+    UnsupportedBytecode(String, String),
+    InvalidBundle(String),
+    InstructionLimitExceeded(usize),
+    MemoryLimitExceeded(usize),
+    CallDepthExceeded(usize),
+    BuiltinNotAllowed(String),
+    ReturnTypeMismatch(String, String),
+    IntegerOverflow,
+    DivisionByZero,
+    InexactFloat(i32),
+    NotANumber,
+    InvalidProgram(String),
+    // a frame asked for that is not running
+    UnknownFrame(usize),
+    UnknownGlobal(String),
+    // the host holds a borrow of a global it asked to change
+    GlobalBorrowed(String),
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::AssertionFailed => write!(f, "assertion failed"),
+            RuntimeError::ExpectationFailed(message) => write!(f, "{}", message),
+            RuntimeError::TypeError(message) => write!(f, "type error: {}", message),
+            RuntimeError::KeyNotFound(key) => write!(f, "key '{}' does not exist", key),
+            RuntimeError::IndexOutOfBounds(index) => write!(f, "index {} is out of bounds", index),
+            RuntimeError::UnknownFunction(name) => write!(f, "function '{}' does not exist", name),
+            RuntimeError::UnknownParameter(function, name) => write!(f, "function '{}' has no parameter '{}'", function, name),
+            RuntimeError::UnknownMethod(receiver, name) => write!(f, "method '{}' does not exist on {}", name, receiver),
+            RuntimeError::InvalidImage(message) => write!(f, "invalid image: {}", message),
+            RuntimeError::InvalidBytecode(message) => write!(f, "invalid bytecode: {}", message),
+            RuntimeError::UnsupportedBytecode(version, supported) => write!(f, "bytecode version {} can not be read, only {} is supported", version, supported),
+            RuntimeError::InvalidBundle(message) => write!(f, "invalid bundle: {}", message),
+            RuntimeError::InstructionLimitExceeded(limit) => write!(f, "instruction limit of {} exceeded", limit),
+            RuntimeError::MemoryLimitExceeded(limit) => write!(f, "memory limit of {} bytes exceeded", limit),
+            RuntimeError::CallDepthExceeded(limit) => write!(f, "call depth limit of {} exceeded", limit),
+            RuntimeError::BuiltinNotAllowed(name) => write!(f, "builtin '{}' is not allowed", name),
+            RuntimeError::ReturnTypeMismatch(expected, found) => write!(f, "expected {} to be returned but found {}", expected, found),
+            RuntimeError::IntegerOverflow => write!(f, "integer overflow"),
+            RuntimeError::DivisionByZero => write!(f, "division by zero"),
+            RuntimeError::InexactFloat(num) => write!(f, "integer {} can not be exactly represented as a float", num),
+            RuntimeError::NotANumber => write!(f, "nan can not be compared"),
+            RuntimeError::InvalidProgram(message) => write!(f, "invalid program: {}", message),
+            RuntimeError::UnknownFrame(index) => write!(f, "frame {} is not running", index),
+            RuntimeError::UnknownGlobal(name) => write!(f, "global '{}' does not exist", name),
+            RuntimeError::GlobalBorrowed(name) => write!(f, "global '{}' is borrowed by the host", name),
+        }
+    }
+}
+
+impl Error for RuntimeError {}
+
+// Link Error
+#[derive(Clone, Debug, PartialEq)]
+pub enum LinkError {
+    DuplicateSymbol(String),
+    DuplicateGlobal(String),
+}
+
+impl Display for LinkError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            LinkError::DuplicateSymbol(name) => write!(f, "function '{}' is defined in both programs", name),
+            LinkError::DuplicateGlobal(name) => write!(f, "global '{}' is defined in both programs", name),
+        }
+    }
+}
+
+impl Error for LinkError {}
@@ -0,0 +1,215 @@
+use alloc::rc::Rc;
+use core::cmp::Ordering;
+
+use crate::prelude::*;
+use crate::vm::bigint::BigInt;
+#[cfg(feature = "decimal")]
+use crate::vm::decimal::Decimal;
+use crate::vm::error::RuntimeError;
+use crate::vm::value::Value;
+
+// Exact Numbers
+//
+// big integers and decimals work with integers, which are promoted to the exact type of the
+// other operand, and a big integer is promoted to a decimal. mixing them with floats is an
+// error as it would lose the precision they are used for
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    FloorDiv,
+}
+
+// both operands as the same exact type
+enum Operands {
+    BigInt(BigInt, BigInt),
+    #[cfg(feature = "decimal")]
+    Decimal(Decimal, Decimal),
+}
+
+fn is_exact(value: &Value) -> bool {
+    match value {
+        Value::BigInt(_) => true,
+        #[cfg(feature = "decimal")]
+        Value::Decimal(_) => true,
+        _ => false
+    }
+}
+
+// promote the operands when either is exact and the other is an integer or exact
+fn promote(lhs: &Value, rhs: &Value) -> Option<Operands> {
+    if !is_exact(lhs) && !is_exact(rhs) {
+        return None;
+    }
+
+    #[cfg(feature = "decimal")]
+    if matches!(lhs, Value::Decimal(_)) || matches!(rhs, Value::Decimal(_)) {
+        return Some(Operands::Decimal(to_decimal(lhs)?, to_decimal(rhs)?));
+    }
+
+    Some(Operands::BigInt(to_bigint(lhs)?, to_bigint(rhs)?))
+}
+
+fn to_bigint(value: &Value) -> Option<BigInt> {
+    match value {
+        Value::Integer(num) => Some(BigInt::from(*num)),
+        Value::BigInt(num) => Some(num.as_ref().clone()),
+        _ => None
+    }
+}
+
+#[cfg(feature = "decimal")]
+fn to_decimal(value: &Value) -> Option<Decimal> {
+    match value {
+        Value::Decimal(num) => Some(num.as_ref().clone()),
+        _ => to_bigint(value).map(Decimal::from)
+    }
+}
+
+// the result of an operation on an exact number, or None if neither operand is one
+pub fn apply(operation: Operation, lhs: &Value, rhs: &Value) -> Option<Result<Value, RuntimeError>> {
+
+    // adding to a string joins the text like any other number
+    if operation == Operation::Add && (is_exact(lhs) || is_exact(rhs)) && matches!((lhs, rhs), (Value::String(_), _) | (_, Value::String(_))) {
+        return Some(Ok(Value::from(format!("{}{}", lhs, rhs))));
+    }
+
+    let result = match promote(lhs, rhs)? {
+        Operands::BigInt(lhs, rhs) => match operation {
+            Operation::Add => Ok(Value::BigInt(Rc::new(lhs.add(&rhs)))),
+            Operation::Sub => Ok(Value::BigInt(Rc::new(lhs.sub(&rhs)))),
+            Operation::Mul => Ok(Value::BigInt(Rc::new(lhs.mul(&rhs)))),
+            Operation::FloorDiv => lhs.floor_div(&rhs).map(|num| Value::BigInt(Rc::new(num))).ok_or(RuntimeError::DivisionByZero),
+
+            // dividing gives a fraction, which only a decimal can hold exactly
+            #[cfg(feature = "decimal")]
+            Operation::Div => Decimal::from(lhs).div(&Decimal::from(rhs)).map(|num| Value::Decimal(Rc::new(num))).ok_or(RuntimeError::DivisionByZero),
+            #[cfg(not(feature = "decimal"))]
+            Operation::Div => Err(RuntimeError::TypeError(String::from("can not divide bigint exactly without the decimal feature, use // instead"))),
+        },
+        #[cfg(feature = "decimal")]
+        Operands::Decimal(lhs, rhs) => match operation {
+            Operation::Add => Ok(Value::Decimal(Rc::new(lhs.add(&rhs)))),
+            Operation::Sub => Ok(Value::Decimal(Rc::new(lhs.sub(&rhs)))),
+            Operation::Mul => Ok(Value::Decimal(Rc::new(lhs.mul(&rhs)))),
+            Operation::Div => lhs.div(&rhs).map(|num| Value::Decimal(Rc::new(num))).ok_or(RuntimeError::DivisionByZero),
+            Operation::FloorDiv => lhs.floor_div(&rhs).map(|num| Value::Decimal(Rc::new(num))).ok_or(RuntimeError::DivisionByZero),
+        }
+    };
+    Some(result)
+}
+
+// raise an exact number to a power that is a whole number and not negative
+pub fn pow(lhs: &Value, rhs: &Value) -> Option<Result<Value, RuntimeError>> {
+    if !is_exact(lhs) && !is_exact(rhs) {
+        return None;
+    }
+    let exponent = match to_bigint(rhs).and_then(|num| num.to_i32()) {
+        Some(exponent) if exponent >= 0 => exponent as u32,
+        _ => return Some(Err(RuntimeError::TypeError(format!("can not raise {} to the power of {}", lhs.type_name(), rhs.type_name()))))
+    };
+    match lhs {
+        Value::Integer(_) | Value::BigInt(_) => to_bigint(lhs).map(|num| Ok(Value::BigInt(Rc::new(num.pow(exponent))))),
+        #[cfg(feature = "decimal")]
+        Value::Decimal(num) => Some(Ok(Value::Decimal(Rc::new(num.pow(exponent))))),
+        _ => None
+    }
+}
+
+// the order of two numbers when either is exact and the other is an integer or exact
+pub fn compare(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    match promote(lhs, rhs)? {
+        Operands::BigInt(lhs, rhs) => Some(lhs.cmp(&rhs)),
+        #[cfg(feature = "decimal")]
+        Operands::Decimal(lhs, rhs) => Some(lhs.cmp(&rhs)),
+    }
+}
+
+// convert a value to a big integer, where a decimal loses its fraction
+pub fn bigint(value: &Value) -> Result<Value, RuntimeError> {
+    let num = match value {
+        Value::Integer(_) | Value::BigInt(_) => to_bigint(value),
+        Value::String(text) => text.trim().parse().ok(),
+        #[cfg(feature = "decimal")]
+        Value::Decimal(num) => Some(num.trunc()),
+        _ => None
+    };
+    num.map(|num| Value::BigInt(Rc::new(num)))
+        .ok_or_else(|| RuntimeError::TypeError(format!("can not convert {} to a bigint", value.type_name())))
+}
+
+// convert a value to a decimal, a float becomes the decimal it is displayed as
+#[cfg(feature = "decimal")]
+pub fn decimal(value: &Value) -> Result<Value, RuntimeError> {
+    let num = match value {
+        Value::Integer(_) | Value::BigInt(_) | Value::Decimal(_) => to_decimal(value),
+        Value::Float(num) if num.is_finite() => num.to_string().parse().ok(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None
+    };
+    num.map(|num| Value::Decimal(Rc::new(num)))
+        .ok_or_else(|| RuntimeError::TypeError(format!("can not convert {} to a decimal", value.type_name())))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use std::rc::Rc;
+
+    use crate::vm::bigint::BigInt;
+    use crate::vm::error::RuntimeError;
+    use crate::vm::exact::{apply, bigint, compare, pow, Operation};
+    use crate::vm::value::Value;
+
+    fn big(text: &str) -> Value {
+        Value::BigInt(Rc::new(text.parse::<BigInt>().unwrap()))
+    }
+
+    #[test]
+    fn test_promotion() {
+        assert_eq!(apply(Operation::Add, &big("2147483647"), &Value::Integer(1)), Some(Ok(big("2147483648"))));
+        assert_eq!(apply(Operation::Mul, &Value::Integer(-3), &big("5")), Some(Ok(big("-15"))));
+        assert_eq!(apply(Operation::FloorDiv, &big("-7"), &Value::Integer(2)), Some(Ok(big("-4"))));
+        assert_eq!(apply(Operation::FloorDiv, &big("1"), &Value::Integer(0)), Some(Err(RuntimeError::DivisionByZero)));
+        assert_eq!(apply(Operation::Add, &Value::from("n = "), &big("5")), Some(Ok(Value::from("n = 5"))));
+        assert_eq!(apply(Operation::Add, &Value::Integer(1), &Value::Integer(2)), None);
+        assert_eq!(apply(Operation::Add, &big("1"), &Value::Float(2.0)), None);
+        assert_eq!(pow(&big("10"), &Value::Integer(20)), Some(Ok(big("100000000000000000000"))));
+        assert!(matches!(pow(&big("10"), &Value::Integer(-1)), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(compare(&big("5"), &Value::Integer(5)), Some(Ordering::Equal));
+        assert_eq!(compare(&Value::Integer(6), &big("5")), Some(Ordering::Greater));
+        assert_eq!(compare(&big("5"), &Value::Float(5.0)), None);
+        assert_eq!(big("5"), Value::Integer(5));
+        assert_ne!(big("5"), Value::Float(5.0));
+    }
+
+    #[test]
+    fn test_convert() {
+        assert_eq!(bigint(&Value::from(" 42 ")), Ok(big("42")));
+        assert_eq!(bigint(&Value::Integer(7)), Ok(big("7")));
+        assert!(bigint(&Value::Float(1.5)).is_err());
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn test_decimal() {
+        use crate::vm::decimal::Decimal;
+        use crate::vm::exact::decimal;
+
+        let dec = |text: &str| Value::Decimal(Rc::new(text.parse::<Decimal>().unwrap()));
+        assert_eq!(apply(Operation::Add, &dec("0.10"), &Value::Integer(1)), Some(Ok(dec("1.10"))));
+        assert_eq!(apply(Operation::Div, &big("1"), &big("4")), Some(Ok(dec("0.25"))));
+        assert_eq!(apply(Operation::Div, &dec("1"), &Value::Integer(0)), Some(Err(RuntimeError::DivisionByZero)));
+        assert_eq!(compare(&dec("2.50"), &big("2")), Some(Ordering::Greater));
+        assert_eq!(decimal(&Value::Float(0.1)).map(|value| value.to_string()), Ok(String::from("0.1")));
+        assert_eq!(bigint(&dec("-2.75")), Ok(big("-2")));
+    }
+
+}
@@ -0,0 +1,132 @@
+use crate::prelude::*;
+use crate::vm::builtin::EXPECT_MODULE_NAME;
+use crate::vm::error::RuntimeError;
+use crate::vm::value::Value;
+
+// collections nested deeper than this are shown as ..., which also stops an array holding itself
+const MAX_DEPTH: usize = 4;
+
+// show a value the way it would be written in a script, so "1" and 1 can be told apart
+pub fn render(value: &Value) -> String {
+    render_at(value, 0)
+}
+
+fn render_at(value: &Value, depth: usize) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        Value::Array(_) | Value::ArrayView(_) | Value::Deque(_) | Value::Dictionary(_) | Value::Object(_) if depth >= MAX_DEPTH => String::from("..."),
+        Value::Array(items) => {
+            let items: Vec<String> = items.borrow().iter().map(|item| render_at(item, depth + 1)).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::ArrayView(view) => {
+            let items: Vec<String> = view.with_items(|items| items.iter().map(|item| render_at(item, depth + 1)).collect());
+            format!("[{}]", items.join(", "))
+        }
+        Value::Deque(items) => {
+            let items: Vec<String> = items.borrow().iter().map(|item| render_at(item, depth + 1)).collect();
+            format!("Deque.new([{}])", items.join(", "))
+        }
+        Value::Dictionary(entries) | Value::Object(entries) => {
+            let entries = entries.borrow();
+            let mut keys: Vec<&String> = entries.keys().collect();
+            keys.sort();
+            let entries: Vec<String> = keys.into_iter().map(|key| format!("{:?}: {}", key, render_at(&entries[key], depth + 1))).collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        // sets only hold simple values so never nest
+        Value::Set(set) => {
+            let items: Vec<String> = set.borrow().items().iter().map(render).collect();
+            format!("Set.new([{}])", items.join(", "))
+        }
+        Value::PriorityQueue(_) => String::from("priority queue"),
+        Value::WeakRef(_) => String::from("weak reference"),
+        Value::Class(_) => String::from("class"),
+        Value::Generator(_) => String::from("generator"),
+        Value::Coroutine(_) => String::from("coroutine"),
+        Value::Sequence(_) => String::from("sequence"),
+        Value::HostObject(object) => format!("host object {}", object.type_name()),
+        value => value.to_string()
+    }
+}
+
+// whether an array, set or deque holds the item, a dictionary or object has it as a key, or a string has it as text
+fn contains(collection: &Value, item: &Value) -> Result<bool, RuntimeError> {
+    match (collection, item) {
+        (Value::Array(items), item) => Ok(items.borrow().contains(item)),
+        (Value::Set(set), item) => Ok(set.borrow().contains(item)),
+        (Value::Deque(items), item) => Ok(items.borrow().contains(item)),
+        (Value::ArrayView(view), item) => Ok(view.with_items(|items| items.contains(item))),
+        (Value::Dictionary(entries) | Value::Object(entries), Value::String(key)) => Ok(entries.borrow().contains_key(key.as_str())),
+        (Value::String(text), Value::String(part)) => Ok(text.contains(part.as_str())),
+        _ => Err(RuntimeError::TypeError(format!("can not check if {} contains {}", collection.type_name(), item.type_name())))
+    }
+}
+
+// run a check on the actual value, failing with both values shown
+pub fn check(name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    let (passed, message) = match (name, args) {
+        ("to_equal", [actual, expected]) => (actual == expected, format!("expected {} to equal {}", render(actual), render(expected))),
+        ("to_not_equal", [actual, expected]) => (actual != expected, format!("expected {} to not equal {}", render(actual), render(expected))),
+        ("to_be_true", [actual]) => (*actual == Value::Bool(true), format!("expected {} to be true", render(actual))),
+        ("to_be_false", [actual]) => (*actual == Value::Bool(false), format!("expected {} to be false", render(actual))),
+        ("to_be_null", [actual]) => (*actual == Value::Null, format!("expected {} to be null", render(actual))),
+        ("to_contain", [actual, item]) => (contains(actual, item)?, format!("expected {} to contain {}", render(actual), render(item))),
+        ("to_equal" | "to_not_equal" | "to_be_true" | "to_be_false" | "to_be_null" | "to_contain", _) => {
+            return Err(RuntimeError::TypeError(format!("{}.{} was given the wrong arguments", EXPECT_MODULE_NAME, name)))
+        }
+        _ => return Err(RuntimeError::UnknownFunction(format!("{}.{}", EXPECT_MODULE_NAME, name)))
+    };
+    match passed {
+        true => Ok(Value::Null),
+        false => Err(RuntimeError::ExpectationFailed(message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::collections::HashMap;
+    use crate::vm::error::RuntimeError;
+    use crate::vm::expect::{check, render};
+    use crate::vm::value::Value;
+
+    fn array(items: Vec<Value>) -> Value {
+        Value::Array(Rc::new(RefCell::new(items)))
+    }
+
+    #[test]
+    fn test_render() {
+        let nested = array(vec![Value::Integer(1), Value::from("a \"b\""), Value::Null]);
+        assert_eq!(render(&nested), r#"[1, "a \"b\"", null]"#);
+
+        let dictionary = Value::Dictionary(Rc::new(RefCell::new(HashMap::from([
+            (String::from("b"), Value::Bool(true)),
+            (String::from("a"), nested),
+        ]))));
+        assert_eq!(render(&dictionary), r#"{"a": [1, "a \"b\"", null], "b": true}"#);
+
+        // an array holding itself stops at the depth limit
+        let looped = array(vec![]);
+        if let Value::Array(items) = &looped {
+            items.borrow_mut().push(looped.clone());
+        }
+        assert_eq!(render(&looped), "[[[[...]]]]");
+    }
+
+    #[test]
+    fn test_check() {
+        assert_eq!(check("to_equal", &[Value::Integer(2), Value::Float(2.0)]), Ok(Value::Null));
+        assert_eq!(check("to_equal", &[Value::from("1"), Value::Integer(1)]),
+                   Err(RuntimeError::ExpectationFailed(String::from(r#"expected "1" to equal 1"#))));
+        assert_eq!(check("to_be_true", &[Value::Integer(1)]),
+                   Err(RuntimeError::ExpectationFailed(String::from("expected 1 to be true"))));
+        assert_eq!(check("to_contain", &[array(vec![Value::Integer(3)]), Value::Integer(3)]), Ok(Value::Null));
+        assert_eq!(check("to_contain", &[Value::from("abc"), Value::from("d")]),
+                   Err(RuntimeError::ExpectationFailed(String::from(r#"expected "abc" to contain "d""#))));
+        assert!(matches!(check("to_contain", &[Value::Integer(1), Value::Integer(1)]), Err(RuntimeError::TypeError(_))));
+    }
+
+}
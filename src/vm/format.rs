@@ -0,0 +1,287 @@
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
+use crate::vm::value::Value;
+
+// the widest a placeholder may pad to and the most decimals it may show, so a template can not
+// make the host allocate without bound
+const MAX_WIDTH: usize = 4096;
+const MAX_PRECISION: usize = 255;
+
+// how a placeholder such as {:>8.2} lays out its value
+#[derive(Debug, Default, PartialEq)]
+struct Spec {
+    fill: Option<char>,
+    align: Option<char>,
+    width: usize,
+    grouping: Option<char>,
+    precision: Option<usize>,
+}
+
+// replace each {} in the template with the next argument, or {n} with argument n
+//
+// a placeholder may have a spec after a colon of [[fill]align][width][,|_][.precision]
+// where align is < left, > right or ^ centre, and , or _ groups the digits of numbers in thousands
+pub fn format(template: &str, args: &[Value]) -> Result<String, RuntimeError> {
+    let mut output = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    let mut next_arg = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return Err(invalid("placeholder is not closed"))
+                    }
+                }
+
+                let (index, spec) = placeholder.split_once(':').unwrap_or((placeholder.as_str(), ""));
+                let index = if index.is_empty() {
+                    next_arg += 1;
+                    next_arg - 1
+                } else {
+                    index.parse().map_err(|_| invalid(&format!("'{}' is not an argument number", index)))?
+                };
+                let value = args.get(index).ok_or_else(|| invalid(&format!("there is no argument {}", index)))?;
+                output.push_str(&render(value, &parse_spec(spec)?));
+            }
+            '}' => return Err(invalid("unmatched '}'")),
+            c => output.push(c)
+        }
+    }
+
+    Ok(output)
+}
+
+// format a number with a fixed number of decimals and the separators of a locale, such as 1.234,50
+pub fn format_number(value: &Value, precision: usize, thousands: &str, decimal: &str) -> Result<String, RuntimeError> {
+    if precision > MAX_PRECISION {
+        return Err(RuntimeError::TypeError(format!("can not format a number with more than {} decimals", MAX_PRECISION)));
+    }
+    let text = match value {
+        Value::Integer(v) => format!("{:.*}", precision, *v as f64),
+        Value::Float(v) => format!("{:.*}", precision, v),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(_) => exact_text(value, precision),
+        #[cfg(feature = "decimal")]
+        Value::Decimal(_) => exact_text(value, precision),
+        _ => return Err(RuntimeError::TypeError(format!("can not format {} as a number", value.type_name())))
+    };
+    let (whole, fraction) = text.split_once('.').unwrap_or((text.as_str(), ""));
+
+    let mut output = group(whole, thousands);
+    if !fraction.is_empty() {
+        output.push_str(decimal);
+        output.push_str(fraction);
+    }
+    Ok(output)
+}
+
+// an exact number with the given places, rounded without going through a float
+#[cfg(feature = "bigint")]
+fn exact_text(value: &Value, places: usize) -> String {
+    match value {
+        #[cfg(feature = "decimal")]
+        Value::Decimal(num) => num.round(places as u32).to_string(),
+        Value::BigInt(num) if places > 0 => format!("{}.{}", num, "0".repeat(places)),
+        v => v.to_string()
+    }
+}
+
+fn parse_spec(spec: &str) -> Result<Spec, RuntimeError> {
+    let chars: Vec<char> = spec.chars().collect();
+    let mut result = Spec::default();
+    let mut i = 0;
+
+    let is_align = |c: Option<&char>| matches!(c, Some('<' | '>' | '^'));
+    if is_align(chars.get(1)) {
+        result.fill = Some(chars[0]);
+        result.align = Some(chars[1]);
+        i = 2;
+    } else if is_align(chars.first()) {
+        result.align = Some(chars[0]);
+        i = 1;
+    }
+
+    let digits = |i: &mut usize| {
+        let start = *i;
+        while chars.get(*i).is_some_and(|c| c.is_ascii_digit()) {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect::<String>()
+    };
+
+    // a leading zero pads numbers with zeros
+    if result.align.is_none() && chars.get(i) == Some(&'0') {
+        result.fill = Some('0');
+        result.align = Some('=');
+    }
+    let width = digits(&mut i);
+    if !width.is_empty() {
+        result.width = bounded(&width, MAX_WIDTH).ok_or_else(|| invalid(&format!("width {} is more than {}", width, MAX_WIDTH)))?;
+    }
+
+    if let Some(c @ (',' | '_')) = chars.get(i) {
+        result.grouping = Some(*c);
+        i += 1;
+    }
+
+    if chars.get(i) == Some(&'.') {
+        i += 1;
+        let precision = digits(&mut i);
+        if precision.is_empty() {
+            return Err(invalid(&format!("'{}' has no precision after '.'", spec)));
+        }
+        result.precision = Some(bounded(&precision, MAX_PRECISION).ok_or_else(|| invalid(&format!("precision {} is more than {}", precision, MAX_PRECISION)))?);
+    }
+
+    if i < chars.len() {
+        return Err(invalid(&format!("'{}' is not a valid spec", spec)));
+    }
+    Ok(result)
+}
+
+// digits as a number no more than the limit, which also catches numbers too big to parse
+fn bounded(digits: &str, limit: usize) -> Option<usize> {
+    digits.parse().ok().filter(|n| *n <= limit)
+}
+
+fn render(value: &Value, spec: &Spec) -> String {
+    let text = match (value, spec.precision) {
+        (Value::Integer(v), Some(p)) => format!("{:.*}", p, *v as f64),
+        (Value::Float(v), Some(p)) => format!("{:.*}", p, v),
+        #[cfg(feature = "bigint")]
+        (Value::BigInt(_), Some(p)) => exact_text(value, p),
+        #[cfg(feature = "decimal")]
+        (Value::Decimal(_), Some(p)) => exact_text(value, p),
+        (Value::String(s), Some(p)) => s.chars().take(p).collect(),
+        (v, _) => v.to_string()
+    };
+
+    // group the whole part of numbers
+    let text = match (value.is_number(), spec.grouping) {
+        (true, Some(separator)) => {
+            let (whole, fraction) = text.split_once('.').map(|(w, f)| (w, Some(f))).unwrap_or((text.as_str(), None));
+            let mut grouped = group(whole, &separator.to_string());
+            if let Some(fraction) = fraction {
+                grouped.push('.');
+                grouped.push_str(fraction);
+            }
+            grouped
+        }
+        _ => text
+    };
+
+    let len = text.chars().count();
+    if len >= spec.width {
+        return text;
+    }
+    let padding = spec.width - len;
+    let fill = spec.fill.unwrap_or(' ').to_string();
+
+    // numbers go to the right unless told otherwise
+    let align = spec.align.unwrap_or(if value.is_number() { '>' } else { '<' });
+    match align {
+        '<' => text + &fill.repeat(padding),
+        '^' => fill.repeat(padding / 2) + &text + &fill.repeat(padding - padding / 2),
+        // zero padding goes between the sign and the digits
+        '=' => match text.strip_prefix('-') {
+            Some(digits) => format!("-{}{}", fill.repeat(padding), digits),
+            None => fill.repeat(padding) + &text
+        },
+        _ => fill.repeat(padding) + &text
+    }
+}
+
+// put a separator between each group of three digits, keeping any sign in front
+fn group(whole: &str, separator: &str) -> String {
+    let (sign, digits) = match whole.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", whole)
+    };
+    let mut output = String::from(sign);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            output.push_str(separator);
+        }
+        output.push(c);
+    }
+    output
+}
+
+fn invalid(message: &str) -> RuntimeError {
+    RuntimeError::TypeError(format!("invalid format: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::format::{format, format_number};
+    use crate::vm::value::Value;
+
+    fn f(template: &str, args: &[Value]) -> String {
+        format(template, args).unwrap()
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(f("{} + {} = {}", &[Value::Integer(1), Value::Integer(2), Value::Integer(3)]), "1 + 2 = 3");
+        assert_eq!(f("{1} {0} {1}", &[Value::from("a"), Value::from("b")]), "b a b");
+        assert_eq!(f("{{{}}}", &[Value::Bool(true)]), "{true}");
+    }
+
+    #[test]
+    fn test_alignment() {
+        assert_eq!(f("[{:>8.2}]", &[Value::Float(12.3456)]), "[   12.35]");
+        assert_eq!(f("[{:6}]", &[Value::Integer(42)]), "[    42]");
+        assert_eq!(f("[{:6}]", &[Value::from("ab")]), "[ab    ]");
+        assert_eq!(f("[{:*^7}]", &[Value::from("mid")]), "[**mid**]");
+        assert_eq!(f("[{:<5}]", &[Value::Integer(7)]), "[7    ]");
+        assert_eq!(f("[{:05}]", &[Value::Integer(-42)]), "[-0042]");
+        assert_eq!(f("[{:.3}]", &[Value::from("truncate")]), "[tru]");
+    }
+
+    #[test]
+    fn test_grouping() {
+        assert_eq!(f("{:,}", &[Value::Integer(1234567)]), "1,234,567");
+        assert_eq!(f("{:,}", &[Value::Integer(-999)]), "-999");
+        assert_eq!(f("{:_.2}", &[Value::Integer(-1234)]), "-1_234.00");
+        assert_eq!(f("{:>12,.1}", &[Value::Float(98765.25)]), "    98,765.2");
+    }
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(format_number(&Value::Float(1234.5), 2, ".", ",").unwrap(), "1.234,50");
+        assert_eq!(format_number(&Value::Integer(1000000), 0, " ", ",").unwrap(), "1 000 000");
+        assert!(format_number(&Value::Null, 2, ",", ".").is_err());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(format("{", &[]).is_err());
+        assert!(format("}", &[]).is_err());
+        assert!(format("{}", &[]).is_err());
+        assert!(format("{:>x}", &[Value::Integer(1)]).is_err());
+        assert!(format("{:.}", &[Value::Integer(1)]).is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        assert_eq!(f("{:.255}", &[Value::Float(1.5)]).len(), 257);
+        assert!(format("{:.70000}", &[Value::Float(1.5)]).is_err());
+        assert!(format("{:>99999999999}", &[Value::Integer(1)]).is_err());
+        assert!(format("{:>99999999999999999999999}", &[Value::Integer(1)]).is_err());
+        assert!(format_number(&Value::Float(1.5), 70000, ",", ".").is_err());
+    }
+
+}
@@ -1,4 +1,13 @@
-use log::{debug, trace};
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::fmt;
+use core::fmt::{Display, Formatter};
+use crate::logging::{debug, trace};
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
+use crate::vm::generator::Generator;
+use crate::vm::program::FunctionMetadata;
+use crate::vm::small_vec::Args;
 use crate::vm::value::Value;
 
 #[derive(Clone, PartialEq, Debug)]
@@ -7,11 +16,27 @@ pub struct Frame {
     return_position: Option<usize>,
     variables: Vec<Value>,
     data: Vec<Value>,
+    local_slots: usize,
+    max_stack_depth: usize,
+    // generator the frame is running for, it is held by the generator while suspended
+    generator: Option<Rc<RefCell<Generator>>>,
+    // where the deferred statements of the function start, from the start of the function,
+    // until they start running
+    cleanup: Option<usize>,
+    // how the frame leaves once its deferred statements have run
+    exit: Option<Exit>,
 }
 
-impl ToString for Frame {
-    fn to_string(&self) -> String {
-        format!("Frame: {}", self.name)
+// what a frame running its deferred statements does when they finish
+#[derive(Clone, PartialEq, Debug)]
+pub enum Exit {
+    Return(Value),
+    Error(RuntimeError),
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Frame: {}", self.name)
     }
 }
 
@@ -25,21 +50,136 @@ impl Frame {
         Frame {
             name,
             return_position,
-            variables: args.unwrap_or(vec![]),
+            variables: args.unwrap_or_default(),
             data: vec![],
+            // frames created directly are not limited by function metadata
+            local_slots: usize::MAX,
+            max_stack_depth: usize::MAX,
+            generator: None,
+            cleanup: None,
+            exit: None,
+        }
+    }
+
+    // reuse the frame for a new call, keeping the capacity of its stacks
+    pub fn reset(&mut self, name: String, return_position: Option<usize>, args: Args, metadata: &FunctionMetadata) {
+
+        trace!("reset frame {} with return position {:?} and {:?}", name, return_position, metadata);
+
+        self.name = name;
+        self.return_position = return_position;
+        self.local_slots = metadata.local_slots.max(args.len());
+        self.max_stack_depth = metadata.max_stack_depth;
+        self.generator = None;
+        self.cleanup = metadata.cleanup;
+        self.exit = None;
+
+        // allocate every variable slot up front
+        self.variables.clear();
+        self.variables.extend(args);
+        self.variables.resize(self.local_slots, Value::Null);
+
+        self.data.clear();
+        self.data.reserve(metadata.max_stack_depth);
+    }
+
+    // drop the values of a finished frame so they are not kept alive in the pool, keeping the
+    // capacity of its stacks for the next call
+    pub fn release(&mut self) {
+        self.variables.clear();
+        self.data.clear();
+        self.generator = None;
+        self.exit = None;
+    }
+
+    // rebuild a frame from a snapshot
+    pub fn restore(name: String, return_position: Option<usize>, variables: Vec<Value>, data: Vec<Value>, metadata: &FunctionMetadata) -> Frame {
+        Frame {
+            name,
+            return_position,
+            variables,
+            data,
+            local_slots: metadata.local_slots,
+            max_stack_depth: metadata.max_stack_depth,
+            generator: None,
+            cleanup: metadata.cleanup,
+            exit: None,
+        }
+    }
+
+    // get the limits the frame was created with
+    pub fn get_metadata(&self) -> FunctionMetadata {
+        FunctionMetadata {
+            local_slots: self.local_slots,
+            max_stack_depth: self.max_stack_depth,
+            generator: self.generator.is_some(),
+            cleanup: self.cleanup,
         }
     }
 
+    // get the generator the frame is running for
+    pub fn get_generator(&self) -> Option<&Rc<RefCell<Generator>>> {
+        self.generator.as_ref()
+    }
+
+    // run the frame for a generator
+    pub fn set_generator(&mut self, generator: Rc<RefCell<Generator>>) {
+        self.generator = Some(generator);
+    }
+
+    // stop running the frame for a generator, so a suspended generator does not hold itself
+    pub fn take_generator(&mut self) -> Option<Rc<RefCell<Generator>>> {
+        self.generator.take()
+    }
+
+    // whether the function has deferred statements that have not started running
+    pub fn has_cleanup(&self) -> bool {
+        self.cleanup.is_some()
+    }
+
+    // start running the deferred statements with an empty stack, giving where they start
+    pub fn begin_cleanup(&mut self, exit: Exit) -> usize {
+        self.data.clear();
+        self.exit = Some(exit);
+        self.cleanup.take().expect("function should have deferred statements")
+    }
+
+    // how to leave once the deferred statements have run
+    pub fn take_exit(&mut self) -> Option<Exit> {
+        self.exit.take()
+    }
+
+    // get the variable slots
+    pub fn get_variables(&self) -> &[Value] {
+        &self.variables
+    }
+
+    // get the values on the stack
+    pub fn get_stack(&self) -> &[Value] {
+        &self.data
+    }
+
     // get functio name
     pub fn get_name(&self) -> &String {
         &self.name
     }
 
+    // get the name of the function, without the depth that frames of calls are named with,
+    // such as 'Test.main[2]'
+    pub fn get_function_name(&self) -> &str {
+        self.name.split_once('[').map(|(name, _)| name).unwrap_or(&self.name)
+    }
+
     // get return position
     pub fn get_return_position(&self) -> Option<usize> {
         self.return_position
     }
 
+    // set where to continue once the frame returns, used when a generator is resumed
+    pub fn set_return_position(&mut self, return_position: usize) {
+        self.return_position = Some(return_position);
+    }
+
     // print debug info if debug is enabled
     pub fn print_debug_info(&self) {
         debug!("frame: {}", self.name);
@@ -56,6 +196,7 @@ impl Frame {
     // push a value to the stack
     pub fn push_value_to_stack(&mut self, value: Value) {
         trace!("push value {:?} to stack", value);
+        debug_assert!(self.data.len() < self.max_stack_depth, "stack of {} exceeded max depth {}", self.name, self.max_stack_depth);
         self.data.push(value);
     }
 
@@ -63,6 +204,7 @@ impl Frame {
     pub fn push_value_to_variable_slot(&mut self, slot: usize, value: Value) {
 
         trace!("push value {:?} to variable slot {}", value, slot);
+        debug_assert!(slot < self.local_slots, "variable slot {} exceeded {} slots in {}", slot, self.local_slots, self.name);
 
         let variables = &mut self.variables;
 
@@ -94,38 +236,41 @@ impl Frame {
     // return a clone of the top value on the stack
     pub fn get_top_value_on_stack(&self) -> Value {
         let value = self.data.last().expect("stack should have a value");
-        return value.clone();
+        value.clone()
     }
 
     // pop a value from the stack
     pub fn pop_value_from_stack(&mut self) -> Value {
         let value = self.data.pop().expect("stack should have a value");
         trace!("pop value {:?} from stack", value);
-        return value;
+        value
+    }
+
+    // swap the top 2 values on the stack
+    pub fn swap_top_values_on_stack(&mut self) {
+        let len = self.data.len();
+        assert!(len >= 2, "stack should have 2 values");
+        self.data.swap(len - 1, len - 2);
     }
 
     // pop 2 values from the stack
     pub fn pop_2_values_from_stack(&mut self) -> (Value, Value) {
         let rhs = self.pop_value_from_stack();
         let lhs = self.pop_value_from_stack();
-        return (lhs, rhs);
+        (lhs, rhs)
     }
 
-    // pop values from the stack
-    pub fn pop_values_from_stack(&mut self, count: usize) -> Vec<Value> {
+    // pop values from the stack, in the order they were pushed
+    pub fn pop_values_from_stack(&mut self, count: usize) -> Args {
         trace!("pop {} values from stack", count);
-        let mut values = vec![];
-        for _ in 0..count {
-            values.push(self.pop_value_from_stack());
-        }
-        return values;
+        let start = self.data.len().checked_sub(count).expect("stack should have enough values");
+        self.data.drain(start..).collect()
     }
 
     // get the value from the variable slot
     pub fn get_variable_or_panic(&self, slot: usize) -> &Value {
         trace!("get value from variable slot {}", slot);
-        let value = self.variables.get(slot).expect("variable slot should exist");
-        return value;
+        self.variables.get(slot).expect("variable slot should exist")
     }
 
 }
@@ -134,6 +279,8 @@ impl Frame {
 mod tests {
 
     use crate::vm::frame::Frame;
+    use crate::vm::program::FunctionMetadata;
+    use crate::vm::small_vec::Args;
     use crate::vm::value::Value;
 
     #[test]
@@ -148,6 +295,37 @@ mod tests {
         assert_eq!(frame.get_return_position(), Some(7));
     }
 
+    #[test]
+    fn test_reset() {
+        let mut frame = Frame::new("test".to_string(), Some(7), Some(vec![Value::Integer(1)]));
+        frame.push_value_to_stack(Value::Float(1.0));
+        frame.reset("test2".to_string(), Some(9), Args::from(vec![Value::Integer(2), Value::Integer(3)]), &FunctionMetadata { local_slots: 3, max_stack_depth: 16, ..Default::default() });
+        assert_eq!(frame.get_name(), "test2");
+        assert_eq!(frame.get_return_position(), Some(9));
+        assert_eq!(frame.variables, vec![Value::Integer(2), Value::Integer(3), Value::Null]);
+        assert!(frame.data.is_empty());
+        assert!(frame.data.capacity() >= 16);
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn test_stack_depth_exceeded() {
+        let mut frame = Frame::new("test".to_string(), None, None);
+        frame.reset("test".to_string(), None, Args::new(), &FunctionMetadata { local_slots: 0, max_stack_depth: 1, ..Default::default() });
+        frame.push_value_to_stack(Value::Integer(1));
+        frame.push_value_to_stack(Value::Integer(2));
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn test_local_slots_exceeded() {
+        let mut frame = Frame::new("test".to_string(), None, None);
+        frame.reset("test".to_string(), None, Args::new(), &FunctionMetadata { local_slots: 1, max_stack_depth: 0, ..Default::default() });
+        frame.push_value_to_variable_slot(1, Value::Integer(1));
+    }
+
     #[test]
     fn test_push_value_to_stack() {
         let mut frame = Frame::new("test".to_string(), None, None);
@@ -189,6 +367,15 @@ mod tests {
         assert_eq!(frame.get_variable_or_panic(0), &Value::Float(1.0));
     }
 
+    #[test]
+    fn test_swap_top_values_on_stack() {
+        let mut frame = Frame::new("test".to_string(), None, None);
+        frame.push_value_to_stack(Value::Integer(1));
+        frame.push_value_to_stack(Value::Integer(2));
+        frame.swap_top_values_on_stack();
+        assert_eq!(frame.pop_2_values_from_stack(), (Value::Integer(2), Value::Integer(1)));
+    }
+
     #[test]
     fn test_pop_value_from_stack() {
         let mut frame = Frame::new("test".to_string(), None, None);
@@ -196,6 +383,16 @@ mod tests {
         assert_eq!(frame.pop_value_from_stack(), Value::Float(1.0));
     }
 
+    #[test]
+    fn test_pop_values_from_stack() {
+        let mut frame = Frame::new("test".to_string(), None, None);
+        frame.push_value_to_stack(Value::Integer(1));
+        frame.push_value_to_stack(Value::Integer(2));
+        frame.push_value_to_stack(Value::Integer(3));
+        assert_eq!(&*frame.pop_values_from_stack(2), &[Value::Integer(2), Value::Integer(3)]);
+        assert_eq!(frame.get_stack(), &[Value::Integer(1)]);
+    }
+
     #[test]
     fn test_get_2_values_from_stack() {
         let mut frame = Frame::new("test".to_string(), None, None);
@@ -0,0 +1,67 @@
+use crate::vm::frame::Frame;
+
+// Generator
+//
+// call of a generator function which is suspended at each yield and resumed for the next value
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Generator {
+    // frame of the suspended call, taken while it runs
+    frame: Option<Frame>,
+    // where the call continues from
+    ip: usize,
+    // whether the caller wants a flag saying if a value was produced
+    iterating: bool,
+    done: bool,
+}
+
+impl Generator {
+
+    // generator for a call that has not started
+    pub fn new(frame: Frame, ip: usize) -> Self {
+        Generator { frame: Some(frame), ip, iterating: false, done: false }
+    }
+
+    // rebuild a generator from a snapshot
+    pub fn restore(frame: Option<Frame>, ip: usize, done: bool) -> Self {
+        Generator { frame, ip, iterating: false, done }
+    }
+
+    // take the frame and position to continue the call from, or None if it has finished
+    pub fn resume(&mut self, return_position: usize, iterating: bool) -> Option<(Frame, usize)> {
+        if self.done {
+            return None;
+        }
+        let mut frame = self.frame.take()?;
+        frame.set_return_position(return_position);
+        self.iterating = iterating;
+        Some((frame, self.ip))
+    }
+
+    // keep the frame until the next value is asked for
+    pub fn suspend(&mut self, frame: Frame, ip: usize) {
+        self.frame = Some(frame);
+        self.ip = ip;
+    }
+
+    pub fn finish(&mut self) {
+        self.frame = None;
+        self.done = true;
+    }
+
+    pub fn is_iterating(&self) -> bool {
+        self.iterating
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    pub fn get_frame(&self) -> Option<&Frame> {
+        self.frame.as_ref()
+    }
+
+    pub fn get_ip(&self) -> usize {
+        self.ip
+    }
+
+}
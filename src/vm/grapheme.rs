@@ -0,0 +1,59 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::prelude::*;
+
+// Graphemes
+//
+// splits text into what a reader sees as single characters, following the extended grapheme
+// cluster rules of unicode text segmentation: a letter and the marks on it, a flag made of two
+// regional indicators, an emoji joined to others or given a skin tone, and a hangul syllable
+// written with jamo are each one grapheme
+
+// the graphemes of the text in order
+pub fn graphemes(text: &str) -> Vec<&str> {
+    text.graphemes(true).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::grapheme::graphemes;
+
+    #[test]
+    fn test_graphemes() {
+        assert!(graphemes("").is_empty());
+        assert_eq!(graphemes("abc"), vec!["a", "b", "c"]);
+        assert_eq!(graphemes("e\u{301}t\u{e9}"), vec!["e\u{301}", "t", "\u{e9}"]);
+        assert_eq!(graphemes("a\r\nb\n"), vec!["a", "\r\n", "b", "\n"]);
+    }
+
+    #[test]
+    fn test_emoji() {
+        // a family joined with zero width joiners, a waving hand with a skin tone and a heart
+        // with a variation selector
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let wave = "\u{1F44B}\u{1F3FD}";
+        let heart = "\u{2764}\u{FE0F}";
+        assert_eq!(graphemes(&format!("{}{}{}!", family, wave, heart)), vec![family, wave, heart, "!"]);
+
+        // flags pair up regional indicators
+        let gb = "\u{1F1EC}\u{1F1E7}";
+        let fr = "\u{1F1EB}\u{1F1F7}";
+        assert_eq!(graphemes(&format!("{}{}\u{1F1EC}", gb, fr)), vec![gb, fr, "\u{1F1EC}"]);
+
+        // a joiner after a letter does not join what follows
+        assert_eq!(graphemes("a\u{200D}\u{1F467}"), vec!["a\u{200D}", "\u{1F467}"]);
+    }
+
+    #[test]
+    fn test_spacing_marks() {
+        // a devanagari consonant with a vowel sign that takes its own space
+        assert_eq!(graphemes("\u{928}\u{93F}\u{915}"), vec!["\u{928}\u{93F}", "\u{915}"]);
+    }
+
+    #[test]
+    fn test_hangul() {
+        // a syllable written with separate jamo, then a precomposed one
+        assert_eq!(graphemes("\u{1100}\u{1161}\u{11A8}\u{AC00}"), vec!["\u{1100}\u{1161}\u{11A8}", "\u{AC00}"]);
+    }
+
+}
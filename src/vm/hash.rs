@@ -0,0 +1,56 @@
+use core::fmt::Write;
+
+#[cfg(feature = "hash")]
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use crate::prelude::*;
+
+// lowercase hex of a digest
+fn hex(bytes: &[u8]) -> String {
+    let mut output = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        write!(output, "{:02x}", b).expect("writing to a string should not fail");
+    }
+    output
+}
+
+pub fn sha256(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+// md5 and crc32 are only builtins
+#[cfg(feature = "hash")]
+pub fn md5(data: &[u8]) -> String {
+    hex(&Md5::digest(data))
+}
+
+#[cfg(feature = "hash")]
+pub fn crc32(data: &[u8]) -> String {
+    hex(&crc32fast::hash(data).to_be_bytes())
+}
+
+#[cfg(all(test, feature = "hash"))]
+mod tests {
+    use crate::vm::hash::{crc32, md5, sha256};
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(sha256(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(sha256(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(sha256(&[b'a'; 100]), "2816597888e4a0d3a36b82b83316ab32680eb8f00f8cd3b904d681246d285a0e");
+    }
+
+    #[test]
+    fn test_md5() {
+        assert_eq!(md5(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(md5(b"The quick brown fox jumps over the lazy dog"), "9e107d9d372bb6826bd81d3542a419d6");
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), "00000000");
+        assert_eq!(crc32(b"123456789"), "cbf43926");
+    }
+
+}
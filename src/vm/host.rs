@@ -0,0 +1,208 @@
+use alloc::rc::Rc;
+use core::any::{type_name, Any, TypeId};
+use core::cell::{Ref, RefCell, RefMut};
+use core::fmt;
+use core::fmt::{Debug, Formatter};
+use core::marker::PhantomData;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
+use crate::vm::value::Value;
+
+type Finalizer = Box<dyn FnOnce(Box<dyn Any>)>;
+type HostMethod = Rc<dyn Fn(&HostObject, &[Value]) -> Result<Value, RuntimeError>>;
+type HostGetter = Rc<dyn Fn(&HostObject) -> Result<Value, RuntimeError>>;
+type HostSetter = Rc<dyn Fn(&HostObject, Value) -> Result<(), RuntimeError>>;
+
+// Host Object
+//
+// a value owned by the host, such as a file handle, connection or game entity. scripts can hold
+// and pass it back but can not look inside it. the finalizer is run with the value once the last
+// copy held by the host or a script is dropped
+pub struct HostObject {
+    value: RefCell<Option<Box<dyn Any>>>,
+    type_id: TypeId,
+    type_name: &'static str,
+    finalizer: Option<Finalizer>,
+}
+
+impl HostObject {
+
+    pub fn new<T: Any>(value: T) -> Self {
+        HostObject { value: RefCell::new(Some(Box::new(value))), type_id: TypeId::of::<T>(), type_name: type_name::<T>(), finalizer: None }
+    }
+
+    // run the finalizer with the value when it is dropped, such as to close a connection
+    pub fn with_finalizer<T: Any>(value: T, finalizer: impl FnOnce(T) + 'static) -> Self {
+        let finalizer: Finalizer = Box::new(move |value| {
+            if let Ok(value) = value.downcast::<T>() {
+                finalizer(*value);
+            }
+        });
+        HostObject { value: RefCell::new(Some(Box::new(value))), type_id: TypeId::of::<T>(), type_name: type_name::<T>(), finalizer: Some(finalizer) }
+    }
+
+    // name of the rust type held, for errors
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    pub fn is<T: Any>(&self) -> bool {
+        self.type_id == TypeId::of::<T>()
+    }
+
+    // the value if it is a T, or an error naming both types
+    pub fn borrow<T: Any>(&self) -> Result<Ref<'_, T>, RuntimeError> {
+        let value = self.value.try_borrow().map_err(|_| self.in_use())?;
+        Ref::filter_map(value, |value| value.as_ref().and_then(|value| value.downcast_ref::<T>())).map_err(|_| self.mismatch::<T>())
+    }
+
+    pub fn borrow_mut<T: Any>(&self) -> Result<RefMut<'_, T>, RuntimeError> {
+        let value = self.value.try_borrow_mut().map_err(|_| self.in_use())?;
+        RefMut::filter_map(value, |value| value.as_mut().and_then(|value| value.downcast_mut::<T>())).map_err(|_| self.mismatch::<T>())
+    }
+
+    fn mismatch<T: Any>(&self) -> RuntimeError {
+        RuntimeError::TypeError(format!("expected host object {} but found {}", type_name::<T>(), self.type_name))
+    }
+
+    // a method changing the object was given the same object as an argument
+    fn in_use(&self) -> RuntimeError {
+        RuntimeError::TypeError(format!("host object {} is already in use", self.type_name))
+    }
+
+}
+
+// what scripts can do with host objects holding one type
+#[derive(Clone, Default)]
+struct HostTypeTable {
+    methods: HashMap<String, HostMethod>,
+    getters: HashMap<String, HostGetter>,
+    setters: HashMap<String, HostSetter>,
+}
+
+// methods and properties of host objects, by the type they hold
+#[derive(Clone, Default)]
+pub struct HostTypes {
+    types: HashMap<TypeId, HostTypeTable>,
+}
+
+impl HostTypes {
+
+    pub fn register<T: Any>(&mut self) -> HostType<'_, T> {
+        HostType { table: self.types.entry(TypeId::of::<T>()).or_default(), marker: PhantomData }
+    }
+
+    pub fn call(&self, object: &HostObject, name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+        match self.types.get(&object.type_id).and_then(|table| table.methods.get(name)) {
+            Some(method) => method(object, args),
+            None => Err(RuntimeError::UnknownMethod(format!("host object {}", object.type_name), name.to_string()))
+        }
+    }
+
+    pub fn get(&self, object: &HostObject, name: &str) -> Result<Value, RuntimeError> {
+        match self.types.get(&object.type_id).and_then(|table| table.getters.get(name)) {
+            Some(getter) => getter(object),
+            None => Err(RuntimeError::KeyNotFound(name.to_string()))
+        }
+    }
+
+    pub fn set(&self, object: &HostObject, name: &str, value: Value) -> Result<(), RuntimeError> {
+        match self.types.get(&object.type_id).and_then(|table| table.setters.get(name)) {
+            Some(setter) => setter(object, value),
+            None => Err(RuntimeError::KeyNotFound(name.to_string()))
+        }
+    }
+
+}
+
+// adds the methods and properties of one host type, such as vm.register_type::<Sprite>().method("move", ...)
+pub struct HostType<'a, T> {
+    table: &'a mut HostTypeTable,
+    marker: PhantomData<T>,
+}
+
+impl<T: Any> HostType<'_, T> {
+
+    pub fn method(self, name: &str, method: impl Fn(&T, &[Value]) -> Result<Value, RuntimeError> + 'static) -> Self {
+        let method: HostMethod = Rc::new(move |object, args| method(&*object.borrow::<T>()?, args));
+        self.table.methods.insert(name.to_string(), method);
+        self
+    }
+
+    // a method that changes the object, which fails if the object is also one of its arguments
+    pub fn method_mut(self, name: &str, method: impl Fn(&mut T, &[Value]) -> Result<Value, RuntimeError> + 'static) -> Self {
+        let method: HostMethod = Rc::new(move |object, args| method(&mut *object.borrow_mut::<T>()?, args));
+        self.table.methods.insert(name.to_string(), method);
+        self
+    }
+
+    // a property read as object.name
+    pub fn getter(self, name: &str, getter: impl Fn(&T) -> Result<Value, RuntimeError> + 'static) -> Self {
+        let getter: HostGetter = Rc::new(move |object| getter(&*object.borrow::<T>()?));
+        self.table.getters.insert(name.to_string(), getter);
+        self
+    }
+
+    // a property written as object.name = value
+    pub fn setter(self, name: &str, setter: impl Fn(&mut T, Value) -> Result<(), RuntimeError> + 'static) -> Self {
+        let setter: HostSetter = Rc::new(move |object, value| setter(&mut *object.borrow_mut::<T>()?, value));
+        self.table.setters.insert(name.to_string(), setter);
+        self
+    }
+
+}
+
+impl Drop for HostObject {
+    fn drop(&mut self) {
+        if let (Some(finalizer), Some(value)) = (self.finalizer.take(), self.value.get_mut().take()) {
+            finalizer(value);
+        }
+    }
+}
+
+impl Debug for HostObject {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "HostObject({})", self.type_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use crate::vm::error::RuntimeError;
+    use crate::vm::host::HostObject;
+
+    struct Connection(u32);
+
+    #[test]
+    fn test_downcast() {
+        let object = HostObject::new(Connection(7));
+        assert!(object.is::<Connection>());
+        assert_eq!(object.borrow::<Connection>().map(|c| c.0), Ok(7));
+        assert!(!object.is::<String>());
+        assert!(matches!(object.borrow::<String>(), Err(RuntimeError::TypeError(message)) if message.contains("Connection")));
+
+        object.borrow_mut::<Connection>().unwrap().0 = 8;
+        let borrowed = object.borrow::<Connection>().unwrap();
+        assert_eq!(borrowed.0, 8);
+        assert!(object.borrow_mut::<Connection>().is_err());
+    }
+
+    #[test]
+    fn test_finalizer() {
+        let closed = Rc::new(Cell::new(0));
+        let counter = closed.clone();
+        let object = Rc::new(HostObject::with_finalizer(Connection(3), move |connection| counter.set(counter.get() + connection.0)));
+
+        let copy = object.clone();
+        drop(object);
+        assert_eq!(closed.get(), 0);
+        drop(copy);
+        assert_eq!(closed.get(), 3);
+    }
+
+}
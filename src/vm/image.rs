@@ -0,0 +1,886 @@
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+use crate::vm::coroutine::{Coroutine, State};
+use crate::vm::error::RuntimeError;
+use crate::vm::frame::Frame;
+use crate::vm::generator::Generator;
+use crate::vm::priority_queue::PriorityQueue;
+use crate::vm::program::FunctionMetadata;
+use crate::vm::set::ValueSet;
+use crate::vm::value::Value;
+use crate::vm::view::ArrayView;
+use crate::vm::weak::WeakRef;
+
+const IMAGE_MAGIC: &[u8; 4] = b"TSVM";
+const IMAGE_VERSION: u8 = 4;
+
+// Value in an image, shared values are stored once on the heap and referenced by index
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageValue {
+    Null,
+    Integer(i32),
+    Float(f32),
+    Bool(bool),
+    String(String),
+    FunctionRef(String),
+    Class(Vec<(String, ImageValue)>),
+    Heap(usize),
+    // exact numbers are kept as text, so images can be read whichever features are enabled
+    BigInt(String),
+    Decimal(String),
+    // the collection is saved as usual, so it is only kept on restore if something else holds it
+    WeakRef(Box<ImageValue>),
+    // the array and the range of it that is shown
+    ArrayView(Box<ImageValue>, usize, usize),
+}
+
+// Shared value in an image
+#[derive(Clone, Debug, PartialEq)]
+pub enum HeapValue {
+    Array(Vec<ImageValue>),
+    Dictionary(Vec<(String, ImageValue)>),
+    Object(Vec<(String, ImageValue)>),
+    StringBuilder(String),
+    // in the order they were added
+    Set(Vec<ImageValue>),
+    Deque(Vec<ImageValue>),
+    // items and their priorities in the order they would be taken
+    PriorityQueue(Vec<(ImageValue, ImageValue)>),
+    Generator(Option<Box<FrameImage>>, usize, bool),
+    // the function, what it is a method of, and how far it has got
+    Coroutine(String, ImageValue, CoroutineImage),
+}
+
+// State of a coroutine in an image
+#[derive(Clone, Debug, PartialEq)]
+pub enum CoroutineImage {
+    Created,
+    Suspended(Vec<FrameImage>, usize),
+    // a running coroutine has its frames on the stack of the vm
+    Running,
+    Dead,
+}
+
+// Frame in an image
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameImage {
+    pub name: String,
+    pub return_position: Option<usize>,
+    pub variables: Vec<ImageValue>,
+    pub data: Vec<ImageValue>,
+    pub metadata: FunctionMetadata,
+    pub generator: Option<ImageValue>,
+}
+
+// Timers in an image, with the time the vm was last given
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TimersImage {
+    pub now: u64,
+    pub next_id: i32,
+    pub pending: Vec<TimerImage>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimerImage {
+    pub id: i32,
+    pub due: u64,
+    pub every: Option<u64>,
+    pub this: ImageValue,
+    pub handler: String,
+}
+
+// Snapshot of the state of a vm
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VmImage {
+    // added in version 4, the fingerprint of the program the vm was running
+    pub program: String,
+    pub ip: usize,
+    pub globals: Vec<ImageValue>,
+    pub frames: Vec<FrameImage>,
+    pub event_handlers: Vec<(String, Vec<(ImageValue, String)>)>,
+    // added in version 3
+    pub timers: TimersImage,
+    pub heap: Vec<HeapValue>,
+}
+
+impl VmImage {
+
+    // encode the image as bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes.extend_from_slice(IMAGE_MAGIC);
+        w.bytes.push(IMAGE_VERSION);
+
+        w.string(&self.program);
+        w.usize(self.ip);
+        w.values(&self.globals);
+
+        w.usize(self.frames.len());
+        for frame in self.frames.iter() {
+            w.frame(frame);
+        }
+
+        w.usize(self.event_handlers.len());
+        for (event, handlers) in self.event_handlers.iter() {
+            w.string(event);
+            w.usize(handlers.len());
+            for (this, function_name) in handlers {
+                w.value(this);
+                w.string(function_name);
+            }
+        }
+
+        w.varint(self.timers.now);
+        w.usize(self.timers.next_id as usize);
+        w.usize(self.timers.pending.len());
+        for timer in self.timers.pending.iter() {
+            w.usize(timer.id as usize);
+            w.varint(timer.due);
+            match timer.every {
+                Some(every) => { w.bool(true); w.varint(every); },
+                None => w.bool(false),
+            }
+            w.value(&timer.this);
+            w.string(&timer.handler);
+        }
+
+        w.heap(&self.heap);
+
+        w.bytes
+    }
+
+    // decode an image from bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<VmImage, RuntimeError> {
+        let mut r = Reader::new(bytes, RuntimeError::InvalidImage);
+
+        if r.take(IMAGE_MAGIC.len())? != IMAGE_MAGIC {
+            return Err(RuntimeError::InvalidImage(String::from("not a vm image")));
+        }
+        let version = r.tag()?;
+        if version != IMAGE_VERSION {
+            return Err(RuntimeError::InvalidImage(format!("unsupported image version {}", version)));
+        }
+
+        let program = r.string()?;
+        let ip = r.usize()?;
+        let globals = r.values()?;
+
+        let mut frames = vec![];
+        for _ in 0..r.usize()? {
+            frames.push(r.frame()?);
+        }
+
+        let mut event_handlers = vec![];
+        for _ in 0..r.usize()? {
+            let event = r.string()?;
+            let mut handlers = vec![];
+            for _ in 0..r.usize()? {
+                handlers.push((r.value()?, r.string()?));
+            }
+            event_handlers.push((event, handlers));
+        }
+
+        let mut timers = TimersImage { now: r.varint()?, next_id: r.usize()? as i32, pending: vec![] };
+        for _ in 0..r.usize()? {
+            let (id, due) = (r.usize()? as i32, r.varint()?);
+            let every = if r.bool()? { Some(r.varint()?) } else { None };
+            timers.pending.push(TimerImage { id, due, every, this: r.value()?, handler: r.string()? });
+        }
+
+        let heap = r.heap()?;
+        r.finish()?;
+
+        Ok(VmImage { program, ip, globals, frames, event_handlers, timers, heap })
+    }
+
+}
+
+//==================================================================================================
+// ENCODING VALUES
+
+// turns values into image values, storing each shared value once so cycles terminate
+#[derive(Default)]
+pub struct Encoder {
+    seen: HashMap<*const (), usize>,
+    pub heap: Vec<HeapValue>,
+}
+
+impl Encoder {
+
+    pub fn encode_all(&mut self, values: &[Value]) -> Result<Vec<ImageValue>, RuntimeError> {
+        values.iter().map(|v| self.encode(v)).collect()
+    }
+
+    pub fn encode(&mut self, value: &Value) -> Result<ImageValue, RuntimeError> {
+        Ok(match value {
+            Value::Null => ImageValue::Null,
+            Value::Integer(v) => ImageValue::Integer(*v),
+            Value::Float(v) => ImageValue::Float(*v),
+            Value::Bool(v) => ImageValue::Bool(*v),
+            Value::String(v) => ImageValue::String(v.to_string()),
+            Value::FunctionRef(v) => ImageValue::FunctionRef(v.to_string()),
+            Value::Class(members) => ImageValue::Class(self.encode_map(members)?),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(num) => ImageValue::BigInt(num.to_string()),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(num) => ImageValue::Decimal(num.to_string()),
+            Value::Array(items) => {
+                self.encode_shared(Rc::as_ptr(items) as *const (), |e| Ok(HeapValue::Array(e.encode_all(&items.borrow())?)))?
+            }
+            Value::Dictionary(items) => {
+                self.encode_shared(Rc::as_ptr(items) as *const (), |e| Ok(HeapValue::Dictionary(e.encode_map(&items.borrow())?)))?
+            }
+            Value::Object(members) => {
+                self.encode_shared(Rc::as_ptr(members) as *const (), |e| Ok(HeapValue::Object(e.encode_map(&members.borrow())?)))?
+            }
+            Value::StringBuilder(buffer) => {
+                self.encode_shared(Rc::as_ptr(buffer) as *const (), |_| Ok(HeapValue::StringBuilder(buffer.borrow().clone())))?
+            }
+            Value::Deque(items) => {
+                self.encode_shared(Rc::as_ptr(items) as *const (), |e| {
+                    let items: Vec<Value> = items.borrow().iter().cloned().collect();
+                    Ok(HeapValue::Deque(e.encode_all(&items)?))
+                })?
+            }
+            Value::PriorityQueue(queue) => {
+                self.encode_shared(Rc::as_ptr(queue) as *const (), |e| {
+                    let entries = queue.borrow().entries();
+                    Ok(HeapValue::PriorityQueue(entries.iter().map(|(item, priority)| Ok((e.encode(item)?, e.encode(priority)?))).collect::<Result<_, RuntimeError>>()?))
+                })?
+            }
+            Value::Set(set) => {
+                self.encode_shared(Rc::as_ptr(set) as *const (), |e| Ok(HeapValue::Set(e.encode_all(set.borrow().items())?)))?
+            }
+            Value::WeakRef(weak) => ImageValue::WeakRef(Box::new(self.encode(&weak.get())?)),
+            Value::ArrayView(view) => {
+                let (start, end) = view.range();
+                ImageValue::ArrayView(Box::new(self.encode(&Value::Array(view.array().clone()))?), start, end)
+            }
+            // the state of host iterators and objects is outside the vm, so they can not be saved
+            Value::Sequence(_) | Value::HostObject(_) => {
+                return Err(RuntimeError::TypeError(format!("can not save a {} in an image", value.type_name())));
+            }
+            Value::Generator(generator) => {
+                self.encode_shared(Rc::as_ptr(generator) as *const (), |e| {
+                    let generator = generator.borrow();
+                    let frame = generator.get_frame().map(|frame| encode_frame(e, frame).map(Box::new)).transpose()?;
+                    Ok(HeapValue::Generator(frame, generator.get_ip(), generator.is_done()))
+                })?
+            }
+            Value::Coroutine(coroutine) => {
+                self.encode_shared(Rc::as_ptr(coroutine) as *const (), |e| {
+                    let coroutine = coroutine.borrow();
+                    let state = match coroutine.get_state() {
+                        State::Created => CoroutineImage::Created,
+                        State::Suspended(frames, ip) => CoroutineImage::Suspended(frames.iter().map(|frame| encode_frame(e, frame)).collect::<Result<_, _>>()?, *ip),
+                        State::Running => CoroutineImage::Running,
+                        State::Dead => CoroutineImage::Dead,
+                    };
+                    Ok(HeapValue::Coroutine(coroutine.get_function().to_string(), e.encode(coroutine.get_this())?, state))
+                })?
+            }
+        })
+    }
+
+    // reserve the heap slot before encoding the contents so that a value containing itself refers back to the slot
+    fn encode_shared(&mut self, ptr: *const (), contents: impl FnOnce(&mut Encoder) -> Result<HeapValue, RuntimeError>) -> Result<ImageValue, RuntimeError> {
+        if let Some(index) = self.seen.get(&ptr) {
+            return Ok(ImageValue::Heap(*index));
+        }
+        let index = self.heap.len();
+        self.seen.insert(ptr, index);
+        self.heap.push(HeapValue::StringBuilder(String::new()));
+        self.heap[index] = contents(self)?;
+        Ok(ImageValue::Heap(index))
+    }
+
+    // sort by key so the same state always gives the same image
+    fn encode_map(&mut self, map: &HashMap<String, Value>) -> Result<Vec<(String, ImageValue)>, RuntimeError> {
+        let mut keys: Vec<&String> = map.keys().collect();
+        keys.sort();
+        keys.into_iter().map(|k| Ok((k.clone(), self.encode(&map[k])?))).collect()
+    }
+
+}
+
+//==================================================================================================
+// DECODING VALUES
+
+// turns image values back into values, sharing each heap value between everything that refers to it
+pub struct Decoder {
+    heap: Vec<Value>,
+}
+
+impl Decoder {
+
+    // create every shared value before filling them in so references can point at any of them
+    pub fn new(heap: &[HeapValue]) -> Result<Decoder, RuntimeError> {
+        let mut decoder = Decoder {
+            heap: heap.iter().map(|value| match value {
+                HeapValue::Array(_) => Value::Array(Rc::new(RefCell::new(vec![]))),
+                HeapValue::Dictionary(_) => Value::Dictionary(Rc::new(RefCell::new(HashMap::new()))),
+                HeapValue::Object(_) => Value::Object(Rc::new(RefCell::new(HashMap::new()))),
+                HeapValue::StringBuilder(buffer) => Value::StringBuilder(Rc::new(RefCell::new(buffer.clone()))),
+                HeapValue::Generator(..) => Value::Generator(Rc::new(RefCell::new(Generator::default()))),
+                HeapValue::Coroutine(..) => Value::Coroutine(Rc::new(RefCell::new(Coroutine::new(String::new(), Value::Null)))),
+                HeapValue::Set(_) => Value::Set(Rc::new(RefCell::new(ValueSet::new()))),
+                HeapValue::Deque(_) => Value::Deque(Rc::new(RefCell::new(VecDeque::new()))),
+                HeapValue::PriorityQueue(_) => Value::PriorityQueue(Rc::new(RefCell::new(PriorityQueue::new()))),
+            }).collect()
+        };
+
+        for (index, value) in heap.iter().enumerate() {
+            match (value, decoder.heap[index].clone()) {
+                (HeapValue::Array(items), Value::Array(target)) => {
+                    let items = decoder.decode_all(items)?;
+                    target.borrow_mut().extend(items);
+                }
+                (HeapValue::Dictionary(entries), Value::Dictionary(target)) |
+                (HeapValue::Object(entries), Value::Object(target)) => {
+                    let entries = decoder.decode_map(entries)?;
+                    target.borrow_mut().extend(entries);
+                }
+                (HeapValue::Deque(items), Value::Deque(target)) => {
+                    let items = decoder.decode_all(items)?;
+                    target.borrow_mut().extend(items);
+                }
+                (HeapValue::PriorityQueue(entries), Value::PriorityQueue(target)) => {
+                    for (item, priority) in entries {
+                        let (item, priority) = (decoder.decode(item)?, decoder.decode(priority)?);
+                        target.borrow_mut().push(item, priority)?;
+                    }
+                }
+                (HeapValue::Set(items), Value::Set(target)) => {
+                    let items = decoder.decode_all(items)?;
+                    *target.borrow_mut() = ValueSet::from_values(items)?;
+                }
+                (HeapValue::Generator(frame, ip, done), Value::Generator(target)) => {
+                    let frame = frame.as_ref().map(|frame| decode_frame(&mut decoder, frame)).transpose()?;
+                    *target.borrow_mut() = Generator::restore(frame, *ip, *done);
+                }
+                (HeapValue::Coroutine(function, this, state), Value::Coroutine(target)) => {
+                    let this = decoder.decode(this)?;
+                    let state = match state {
+                        CoroutineImage::Created => State::Created,
+                        CoroutineImage::Suspended(frames, ip) => State::Suspended(frames.iter().map(|frame| decode_frame(&mut decoder, frame)).collect::<Result<_, _>>()?, *ip),
+                        CoroutineImage::Running => State::Running,
+                        CoroutineImage::Dead => State::Dead,
+                    };
+                    *target.borrow_mut() = Coroutine::restore(function.clone(), this, state);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(decoder)
+    }
+
+    pub fn decode_all(&mut self, values: &[ImageValue]) -> Result<Vec<Value>, RuntimeError> {
+        values.iter().map(|v| self.decode(v)).collect()
+    }
+
+    pub fn decode(&mut self, value: &ImageValue) -> Result<Value, RuntimeError> {
+        Ok(match value {
+            ImageValue::Null => Value::Null,
+            ImageValue::Integer(v) => Value::Integer(*v),
+            ImageValue::Float(v) => Value::Float(*v),
+            ImageValue::Bool(v) => Value::Bool(*v),
+            ImageValue::String(v) => Value::from(v.clone()),
+            ImageValue::FunctionRef(v) => Value::FunctionRef(Rc::new(v.clone())),
+            ImageValue::Class(members) => Value::Class(Rc::new(self.decode_map(members)?)),
+            ImageValue::Heap(index) => self.heap.get(*index).cloned()
+                .ok_or_else(|| RuntimeError::InvalidImage(format!("heap value {} does not exist", index)))?,
+            ImageValue::ArrayView(array, start, end) => match self.decode(array)? {
+                Value::Array(items) => Value::ArrayView(Rc::new(ArrayView::restore(items, *start, *end))),
+                value => return Err(RuntimeError::InvalidImage(format!("can not view {}", value.type_name())))
+            },
+            ImageValue::WeakRef(target) => match self.decode(target)? {
+                Value::Null => Value::WeakRef(Rc::new(WeakRef::default())),
+                target => Value::WeakRef(Rc::new(WeakRef::new(&target)?)),
+            },
+            #[cfg(feature = "bigint")]
+            ImageValue::BigInt(v) => Value::BigInt(Rc::new(v.parse().map_err(RuntimeError::InvalidImage)?)),
+            #[cfg(feature = "decimal")]
+            ImageValue::Decimal(v) => Value::Decimal(Rc::new(v.parse().map_err(RuntimeError::InvalidImage)?)),
+            #[cfg(not(feature = "bigint"))]
+            ImageValue::BigInt(_) => return Err(RuntimeError::InvalidImage(String::from("a bigint needs the bigint feature"))),
+            #[cfg(not(feature = "decimal"))]
+            ImageValue::Decimal(_) => return Err(RuntimeError::InvalidImage(String::from("a decimal needs the decimal feature"))),
+        })
+    }
+
+    fn decode_map(&mut self, entries: &[(String, ImageValue)]) -> Result<HashMap<String, Value>, RuntimeError> {
+        entries.iter().map(|(k, v)| Ok((k.clone(), self.decode(v)?))).collect()
+    }
+
+}
+
+// snapshot a frame
+pub fn encode_frame(encoder: &mut Encoder, frame: &Frame) -> Result<FrameImage, RuntimeError> {
+    Ok(FrameImage {
+        name: frame.get_name().clone(),
+        return_position: frame.get_return_position(),
+        variables: encoder.encode_all(frame.get_variables())?,
+        data: encoder.encode_all(frame.get_stack())?,
+        metadata: frame.get_metadata(),
+        generator: frame.get_generator().map(|generator| encoder.encode(&Value::Generator(generator.clone()))).transpose()?,
+    })
+}
+
+// rebuild a frame
+pub fn decode_frame(decoder: &mut Decoder, frame: &FrameImage) -> Result<Frame, RuntimeError> {
+    let variables = decoder.decode_all(&frame.variables)?;
+    let data = decoder.decode_all(&frame.data)?;
+    let mut restored = Frame::restore(frame.name.clone(), frame.return_position, variables, data, &frame.metadata);
+    match frame.generator.as_ref().map(|generator| decoder.decode(generator)).transpose()? {
+        Some(Value::Generator(generator)) => restored.set_generator(generator),
+        Some(other) => return Err(RuntimeError::InvalidImage(format!("frame generator is a {}", other.type_name()))),
+        None => {}
+    }
+    Ok(restored)
+}
+
+//==================================================================================================
+// BYTES
+
+// writes images and bytecode
+#[derive(Default)]
+pub(crate) struct Writer {
+    pub(crate) bytes: Vec<u8>,
+}
+
+impl Writer {
+
+    pub(crate) fn tag(&mut self, tag: u8) {
+        self.bytes.push(tag);
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.bytes.push(value as u8);
+    }
+
+    pub(crate) fn usize(&mut self, value: usize) {
+        self.bytes.extend_from_slice(&(value as u64).to_le_bytes());
+    }
+
+    // seven bits a byte, lowest first, with the top bit set on all but the last byte
+    pub(crate) fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    pub(crate) fn string(&mut self, value: &str) {
+        self.usize(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    pub(crate) fn values(&mut self, values: &[ImageValue]) {
+        self.usize(values.len());
+        for value in values {
+            self.value(value);
+        }
+    }
+
+    pub(crate) fn heap(&mut self, heap: &[HeapValue]) {
+        self.usize(heap.len());
+        for value in heap {
+            match value {
+                HeapValue::Array(items) => { self.tag(0); self.values(items); },
+                HeapValue::Dictionary(entries) => { self.tag(1); self.entries(entries); },
+                HeapValue::Object(entries) => { self.tag(2); self.entries(entries); },
+                HeapValue::StringBuilder(buffer) => { self.tag(3); self.string(buffer); },
+                HeapValue::Generator(frame, ip, done) => {
+                    self.tag(4);
+                    match frame {
+                        Some(frame) => { self.bool(true); self.frame(frame); },
+                        None => self.bool(false),
+                    }
+                    self.usize(*ip);
+                    self.bool(*done);
+                },
+                HeapValue::Set(items) => { self.tag(5); self.values(items); },
+                HeapValue::Deque(items) => { self.tag(6); self.values(items); },
+                HeapValue::PriorityQueue(entries) => {
+                    self.tag(7);
+                    self.usize(entries.len());
+                    for (item, priority) in entries {
+                        self.value(item);
+                        self.value(priority);
+                    }
+                },
+                HeapValue::Coroutine(function, this, state) => {
+                    self.tag(8);
+                    self.string(function);
+                    self.value(this);
+                    match state {
+                        CoroutineImage::Created => self.tag(0),
+                        CoroutineImage::Suspended(frames, ip) => {
+                            self.tag(1);
+                            self.usize(frames.len());
+                            for frame in frames {
+                                self.frame(frame);
+                            }
+                            self.usize(*ip);
+                        },
+                        CoroutineImage::Running => self.tag(2),
+                        CoroutineImage::Dead => self.tag(3),
+                    }
+                },
+            }
+        }
+    }
+
+    fn frame(&mut self, frame: &FrameImage) {
+        self.string(&frame.name);
+        match frame.return_position {
+            Some(position) => { self.bool(true); self.usize(position); },
+            None => self.bool(false),
+        }
+        self.values(&frame.variables);
+        self.values(&frame.data);
+        self.usize(frame.metadata.local_slots);
+        self.usize(frame.metadata.max_stack_depth);
+        self.bool(frame.metadata.generator);
+        match &frame.generator {
+            Some(generator) => { self.bool(true); self.value(generator); },
+            None => self.bool(false),
+        }
+    }
+
+    pub(crate) fn entries(&mut self, entries: &[(String, ImageValue)]) {
+        self.usize(entries.len());
+        for (key, value) in entries {
+            self.string(key);
+            self.value(value);
+        }
+    }
+
+    pub(crate) fn value(&mut self, value: &ImageValue) {
+        match value {
+            ImageValue::Null => self.tag(0),
+            ImageValue::Integer(v) => { self.tag(1); self.bytes.extend_from_slice(&v.to_le_bytes()); },
+            ImageValue::Float(v) => { self.tag(2); self.bytes.extend_from_slice(&v.to_le_bytes()); },
+            ImageValue::Bool(v) => { self.tag(3); self.bool(*v); },
+            ImageValue::String(v) => { self.tag(4); self.string(v); },
+            ImageValue::FunctionRef(v) => { self.tag(5); self.string(v); },
+            ImageValue::Class(members) => { self.tag(6); self.entries(members); },
+            ImageValue::Heap(index) => { self.tag(7); self.usize(*index); },
+            ImageValue::BigInt(v) => { self.tag(8); self.string(v); },
+            ImageValue::Decimal(v) => { self.tag(9); self.string(v); },
+            ImageValue::WeakRef(target) => { self.tag(10); self.value(target); },
+            ImageValue::ArrayView(array, start, end) => { self.tag(11); self.value(array); self.usize(*start); self.usize(*end); },
+        }
+    }
+
+}
+
+// reads images and bytecode, failing with the error made by invalid
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    invalid: fn(String) -> RuntimeError,
+}
+
+impl<'a> Reader<'a> {
+
+    pub(crate) fn new(bytes: &'a [u8], invalid: fn(String) -> RuntimeError) -> Self {
+        Reader { bytes, position: 0, invalid }
+    }
+
+    pub(crate) fn error(&self, message: String) -> RuntimeError {
+        (self.invalid)(message)
+    }
+
+    // everything has been read
+    pub(crate) fn finish(&self) -> Result<(), RuntimeError> {
+        match self.position == self.bytes.len() {
+            true => Ok(()),
+            false => Err(self.error(String::from("trailing bytes")))
+        }
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], RuntimeError> {
+        let end = self.position.checked_add(len).filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| self.error(String::from("unexpected end")))?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn tag(&mut self) -> Result<u8, RuntimeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, RuntimeError> {
+        Ok(self.tag()? != 0)
+    }
+
+    pub(crate) fn usize(&mut self) -> Result<usize, RuntimeError> {
+        let bytes = self.take(8)?.try_into().expect("slice should be 8 bytes");
+        Ok(u64::from_le_bytes(bytes) as usize)
+    }
+
+    pub(crate) fn varint(&mut self) -> Result<u64, RuntimeError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.tag()?;
+            if shift == 63 && byte > 1 {
+                return Err(self.error(String::from("varint is too large")));
+            }
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(self.error(String::from("varint is too large")))
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, RuntimeError> {
+        let len = self.usize()?;
+        self.string_of(len)
+    }
+
+    // a string whose length has already been read
+    pub(crate) fn string_of(&mut self, len: usize) -> Result<String, RuntimeError> {
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| self.error(String::from("string is not utf-8")))
+    }
+
+    pub(crate) fn values(&mut self) -> Result<Vec<ImageValue>, RuntimeError> {
+        (0..self.usize()?).map(|_| self.value()).collect()
+    }
+
+    pub(crate) fn heap(&mut self) -> Result<Vec<HeapValue>, RuntimeError> {
+        (0..self.usize()?).map(|_| Ok(match self.tag()? {
+            0 => HeapValue::Array(self.values()?),
+            1 => HeapValue::Dictionary(self.entries()?),
+            2 => HeapValue::Object(self.entries()?),
+            3 => HeapValue::StringBuilder(self.string()?),
+            4 => {
+                let frame = if self.bool()? { Some(Box::new(self.frame()?)) } else { None };
+                HeapValue::Generator(frame, self.usize()?, self.bool()?)
+            }
+            5 => HeapValue::Set(self.values()?),
+            6 => HeapValue::Deque(self.values()?),
+            7 => HeapValue::PriorityQueue((0..self.usize()?).map(|_| Ok((self.value()?, self.value()?))).collect::<Result<_, RuntimeError>>()?),
+            8 => {
+                let (function, this) = (self.string()?, self.value()?);
+                let state = match self.tag()? {
+                    0 => CoroutineImage::Created,
+                    1 => {
+                        let frames = (0..self.usize()?).map(|_| self.frame()).collect::<Result<_, RuntimeError>>()?;
+                        CoroutineImage::Suspended(frames, self.usize()?)
+                    }
+                    2 => CoroutineImage::Running,
+                    3 => CoroutineImage::Dead,
+                    tag => return Err(self.error(format!("unknown coroutine state {}", tag)))
+                };
+                HeapValue::Coroutine(function, this, state)
+            }
+            tag => return Err(self.error(format!("unknown heap tag {}", tag)))
+        })).collect()
+    }
+
+    fn frame(&mut self) -> Result<FrameImage, RuntimeError> {
+        let name = self.string()?;
+        let return_position = if self.bool()? { Some(self.usize()?) } else { None };
+        let variables = self.values()?;
+        let data = self.values()?;
+        let metadata = FunctionMetadata { local_slots: self.usize()?, max_stack_depth: self.usize()?, generator: self.bool()?, ..Default::default() };
+        let generator = if self.bool()? { Some(self.value()?) } else { None };
+        Ok(FrameImage { name, return_position, variables, data, metadata, generator })
+    }
+
+    pub(crate) fn entries(&mut self) -> Result<Vec<(String, ImageValue)>, RuntimeError> {
+        (0..self.usize()?).map(|_| Ok((self.string()?, self.value()?))).collect()
+    }
+
+    pub(crate) fn value(&mut self) -> Result<ImageValue, RuntimeError> {
+        Ok(match self.tag()? {
+            0 => ImageValue::Null,
+            1 => ImageValue::Integer(i32::from_le_bytes(self.take(4)?.try_into().expect("slice should be 4 bytes"))),
+            2 => ImageValue::Float(f32::from_le_bytes(self.take(4)?.try_into().expect("slice should be 4 bytes"))),
+            3 => ImageValue::Bool(self.bool()?),
+            4 => ImageValue::String(self.string()?),
+            5 => ImageValue::FunctionRef(self.string()?),
+            6 => ImageValue::Class(self.entries()?),
+            7 => ImageValue::Heap(self.usize()?),
+            8 => ImageValue::BigInt(self.string()?),
+            9 => ImageValue::Decimal(self.string()?),
+            10 => ImageValue::WeakRef(Box::new(self.value()?)),
+            11 => ImageValue::ArrayView(Box::new(self.value()?), self.usize()?, self.usize()?),
+            tag => return Err(self.error(format!("unknown value tag {}", tag)))
+        })
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::collections::HashMap;
+    use crate::vm::frame::Frame;
+    use crate::vm::generator::Generator;
+    use crate::vm::error::RuntimeError;
+    use crate::vm::image::{Decoder, Encoder, HeapValue, ImageValue, Reader, VmImage, Writer};
+    use crate::vm::priority_queue::PriorityQueue;
+    use crate::vm::set::ValueSet;
+    use crate::vm::value::Value;
+    use crate::vm::view::ArrayView;
+    use crate::vm::weak::WeakRef;
+
+    #[test]
+    fn test_varint() {
+        for value in [0, 1, 127, 128, 300, u64::from(u32::MAX), u64::MAX] {
+            let mut w = Writer::default();
+            w.varint(value);
+            let mut r = Reader::new(&w.bytes, RuntimeError::InvalidImage);
+            assert_eq!(r.varint(), Ok(value));
+            r.finish().unwrap();
+        }
+
+        let mut r = Reader::new(&[0xff; 10], RuntimeError::InvalidImage);
+        assert_eq!(r.varint(), Err(RuntimeError::InvalidImage(String::from("varint is too large"))));
+    }
+
+    #[test]
+    fn test_shared_values_are_stored_once() {
+        let array = Value::Array(Rc::new(RefCell::new(vec![Value::Integer(1)])));
+        let mut encoder = Encoder::default();
+        let encoded = encoder.encode_all(&[array.clone(), array]).unwrap();
+        assert_eq!(encoded, vec![ImageValue::Heap(0), ImageValue::Heap(0)]);
+        assert_eq!(encoder.heap, vec![HeapValue::Array(vec![ImageValue::Integer(1)])]);
+    }
+
+    #[test]
+    fn test_cycle() {
+        let object = Rc::new(RefCell::new(HashMap::new()));
+        object.borrow_mut().insert(String::from("self"), Value::Object(object.clone()));
+
+        let mut encoder = Encoder::default();
+        let encoded = encoder.encode(&Value::Object(object.clone())).unwrap();
+        object.borrow_mut().clear();
+
+        let mut decoder = Decoder::new(&encoder.heap).unwrap();
+        let decoded = decoder.decode(&encoded).unwrap();
+        if let Value::Object(members) = &decoded {
+            let inner = members.borrow().get("self").cloned();
+            assert!(matches!(inner, Some(Value::Object(inner)) if Rc::ptr_eq(&inner, members)));
+        } else {
+            panic!("expected object");
+        }
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut encoder = Encoder::default();
+        let globals = encoder.encode_all(&[
+            Value::Integer(-3),
+            Value::Float(1.5),
+            Value::from("hello"),
+            Value::Dictionary(Rc::new(RefCell::new(HashMap::from([(String::from("a"), Value::Bool(true))])))),
+        ]).unwrap();
+        let image = VmImage { ip: 7, globals, heap: encoder.heap, ..Default::default() };
+        assert_eq!(VmImage::from_bytes(&image.to_bytes()).unwrap(), image);
+    }
+
+    #[test]
+    fn test_generator_round_trip() {
+        let frame = Frame::new(String::from("Test.counter"), None, Some(vec![Value::Integer(3)]));
+        let generator = Value::Generator(Rc::new(RefCell::new(Generator::new(frame, 12))));
+
+        let mut encoder = Encoder::default();
+        let globals = vec![encoder.encode(&generator).unwrap()];
+        let image = VmImage { globals, heap: encoder.heap, ..Default::default() };
+        let image = VmImage::from_bytes(&image.to_bytes()).unwrap();
+
+        let mut decoder = Decoder::new(&image.heap).unwrap();
+        let decoded = decoder.decode(&image.globals[0]).unwrap();
+        if let Value::Generator(decoded) = decoded {
+            let decoded = decoded.borrow();
+            assert_eq!(decoded.get_ip(), 12);
+            assert!(!decoded.is_done());
+            assert_eq!(decoded.get_frame().unwrap().get_variables(), &[Value::Integer(3)]);
+        } else {
+            panic!("expected generator");
+        }
+    }
+
+    #[test]
+    fn test_set_round_trip() {
+        let set = ValueSet::from_values([Value::from("b"), Value::Integer(1), Value::Null]).unwrap();
+        let mut encoder = Encoder::default();
+        let globals = vec![encoder.encode(&Value::Set(Rc::new(RefCell::new(set.clone())))).unwrap()];
+        let image = VmImage::from_bytes(&VmImage { globals, heap: encoder.heap, ..Default::default() }.to_bytes()).unwrap();
+
+        let mut decoder = Decoder::new(&image.heap).unwrap();
+        let Value::Set(decoded) = decoder.decode(&image.globals[0]).unwrap() else { panic!("expected set") };
+        assert_eq!(decoded.borrow().items(), set.items());
+    }
+
+    #[test]
+    fn test_priority_queue_round_trip() {
+        let mut queue = PriorityQueue::new();
+        queue.push(Value::from("b"), Value::Integer(2)).unwrap();
+        queue.push(Value::from("a"), Value::Float(1.5)).unwrap();
+        let mut encoder = Encoder::default();
+        let globals = vec![encoder.encode(&Value::PriorityQueue(Rc::new(RefCell::new(queue.clone())))).unwrap()];
+        let image = VmImage::from_bytes(&VmImage { globals, heap: encoder.heap, ..Default::default() }.to_bytes()).unwrap();
+
+        let mut decoder = Decoder::new(&image.heap).unwrap();
+        let Value::PriorityQueue(decoded) = decoder.decode(&image.globals[0]).unwrap() else { panic!("expected priority queue") };
+        assert_eq!(decoded.borrow().entries(), queue.entries());
+    }
+
+    #[test]
+    fn test_weakref_round_trip() {
+        let target = Value::from(vec![Value::Integer(1)]);
+        let mut encoder = Encoder::default();
+        let weak = Value::WeakRef(Rc::new(WeakRef::new(&target).unwrap()));
+        let globals = vec![encoder.encode(&target).unwrap(), encoder.encode(&weak).unwrap()];
+        let image = VmImage::from_bytes(&VmImage { globals, heap: encoder.heap, ..Default::default() }.to_bytes()).unwrap();
+
+        // the weak reference points at the restored array while it is held, and at nothing after
+        let mut decoder = Decoder::new(&image.heap).unwrap();
+        let (target, weak) = (decoder.decode(&image.globals[0]).unwrap(), decoder.decode(&image.globals[1]).unwrap());
+        drop(decoder);
+        let (Value::Array(items), Value::WeakRef(weak)) = (&target, weak) else { panic!("expected array and weak reference") };
+        let Value::Array(held) = weak.get() else { panic!("expected the array to be held") };
+        assert!(Rc::ptr_eq(items, &held));
+        drop(held);
+        drop(target);
+        assert_eq!(weak.get(), Value::Null);
+    }
+
+    #[test]
+    fn test_view_round_trip() {
+        let items = Rc::new(RefCell::new(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)]));
+        let mut encoder = Encoder::default();
+        let globals = vec![encoder.encode(&Value::Array(items.clone())).unwrap(), encoder.encode(&Value::ArrayView(Rc::new(ArrayView::new(items, 1, 3).unwrap()))).unwrap()];
+        let image = VmImage::from_bytes(&VmImage { globals, heap: encoder.heap, ..Default::default() }.to_bytes()).unwrap();
+
+        // the view still reads the array it was made from
+        let mut decoder = Decoder::new(&image.heap).unwrap();
+        let (Value::Array(items), Value::ArrayView(view)) = (decoder.decode(&image.globals[0]).unwrap(), decoder.decode(&image.globals[1]).unwrap()) else { panic!("expected array and view") };
+        items.borrow_mut()[2] = Value::Integer(4);
+        assert_eq!(view.with_items(<[Value]>::to_vec), vec![Value::Integer(2), Value::Integer(4)]);
+    }
+
+    #[test]
+    fn test_invalid_bytes() {
+        assert!(VmImage::from_bytes(b"nope").is_err());
+        let mut bytes = VmImage::default().to_bytes();
+        bytes.pop();
+        assert!(VmImage::from_bytes(&bytes).is_err());
+    }
+
+}
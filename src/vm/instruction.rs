@@ -1,7 +1,9 @@
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
 use crate::vm::value::Value;
 
 // Instruction
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Instruction {
 
     // Built-in Functions
@@ -10,6 +12,9 @@ pub enum Instruction {
 
     // Stack
     StackPush(Value),
+    Pop,
+    Dup,
+    Swap,
 
     // Variables
     MoveToLocalVariable(usize),
@@ -22,13 +27,28 @@ pub enum Instruction {
 
     // Objects
     CreateObject,
+    CreateBuiltinObject(String, usize),
 
     // Dictionaries
     DictionaryAdd,
+    DictionaryExtend,
 
     // Arrays
     ArrayLength,
     ArrayAdd,
+    ArrayExtend,
+
+    // Iteration
+    IterNext,
+    Yield,
+
+    // Patterns, each pops the value to test and pushes whether it has the shape
+    // an array of exactly, or with the flag at least, this many items
+    IsArray(usize, bool),
+    // a dictionary or object with the key
+    HasKey(String),
+    // an object made by the class on top of the value
+    IsInstance,
 
     // Key Value
     GetCollectionItemByKey,
@@ -36,18 +56,21 @@ pub enum Instruction {
 
     // Instructions
     Call(usize),
-    JumpForward(usize),
-    JumpBackward(usize),
-    JumpIfFalse(i32),
+    CallMethod(String, usize),
+    CallBuiltin(String, usize),
+    Jump(usize),
+    JumpIfFalse(usize),
     Return(bool),
 
     // Operators
     Equal,
     NotEqual,
+    Identical,
     Add,
     Sub,
     Multiply,
     Divide,
+    FloorDivide,
     Pow,
 
     // Comparison
@@ -56,7 +79,272 @@ pub enum Instruction {
     GreaterThan,
     GreaterThanOrEqual,
 
+    // Superinstructions, fused from common sequences by the peephole pass
+    AddLocals(usize, usize, usize),
+    IncrementLocal(usize, i32),
+    CompareLocalConstJump(Comparison, usize, Value, usize),
+    CompareLocalsJump(Comparison, usize, usize, usize),
+
     // Halt Program
     Halt(String)
 
-}
\ No newline at end of file
+}
+
+// comparison made by a fused compare and jump
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+}
+
+impl Comparison {
+
+    // the comparison made by an operator instruction
+    pub fn of(instruction: &Instruction) -> Option<Comparison> {
+        match instruction {
+            Instruction::Equal => Some(Comparison::Equal),
+            Instruction::NotEqual => Some(Comparison::NotEqual),
+            Instruction::LessThan => Some(Comparison::LessThan),
+            Instruction::LessThanOrEqual => Some(Comparison::LessThanOrEqual),
+            Instruction::GreaterThan => Some(Comparison::GreaterThan),
+            Instruction::GreaterThanOrEqual => Some(Comparison::GreaterThanOrEqual),
+            _ => None
+        }
+    }
+
+    // compare two values the same way the operator instruction would
+    pub fn test(&self, lhs: &Value, rhs: &Value) -> Result<bool, RuntimeError> {
+        Ok(match self {
+            Comparison::Equal => lhs == rhs,
+            Comparison::NotEqual => lhs != rhs,
+            Comparison::LessThan => lhs.compare(rhs)?.is_lt(),
+            Comparison::LessThanOrEqual => lhs.compare(rhs)?.is_le(),
+            Comparison::GreaterThan => lhs.compare(rhs)?.is_gt(),
+            Comparison::GreaterThanOrEqual => lhs.compare(rhs)?.is_ge(),
+        })
+    }
+
+}
+
+impl Instruction {
+
+    // move jump targets when the instruction is placed after other code
+    pub fn relocate(&mut self, offset: usize) {
+        if let Some(target) = self.jump_target_mut() {
+            *target += offset;
+        }
+    }
+
+    // the position a jump instruction may continue at
+    pub fn jump_target(&self) -> Option<usize> {
+        match self {
+            Instruction::Jump(target) | Instruction::JumpIfFalse(target) |
+            Instruction::CompareLocalConstJump(.., target) | Instruction::CompareLocalsJump(.., target) => Some(*target),
+            _ => None
+        }
+    }
+
+    pub fn jump_target_mut(&mut self) -> Option<&mut usize> {
+        match self {
+            Instruction::Jump(target) | Instruction::JumpIfFalse(target) |
+            Instruction::CompareLocalConstJump(.., target) | Instruction::CompareLocalsJump(.., target) => Some(target),
+            _ => None
+        }
+    }
+
+    // number of values popped from and pushed onto the stack
+    pub fn stack_effect(&self) -> (usize, usize) {
+        match self {
+            Instruction::Assert | Instruction::Print => (1, 0),
+            Instruction::StackPush(_) => (0, 1),
+            Instruction::Pop => (1, 0),
+            Instruction::Dup => (1, 2),
+            Instruction::Swap => (2, 2),
+            Instruction::MoveToLocalVariable(_) => (1, 0),
+            Instruction::CopyToLocalVariable(_) => (1, 1),
+            Instruction::LoadLocalVariable(_) => (0, 1),
+            Instruction::StoreGlobal(_) => (1, 0),
+            Instruction::LoadGlobal(_) => (0, 1),
+            Instruction::CreateObject => (1, 1),
+            Instruction::CreateBuiltinObject(_, arg_len) => (*arg_len, 1),
+            Instruction::DictionaryAdd => (3, 1),
+            Instruction::ArrayLength => (1, 1),
+            Instruction::ArrayAdd => (2, 1),
+            Instruction::ArrayExtend | Instruction::DictionaryExtend => (2, 1),
+            Instruction::IterNext => (2, 2),
+            Instruction::Yield => (1, 0),
+            Instruction::IsArray(..) | Instruction::HasKey(_) => (1, 1),
+            Instruction::IsInstance => (2, 1),
+            Instruction::GetCollectionItemByKey => (2, 1),
+            Instruction::SetCollectionItemByKey => (3, 0),
+            Instruction::Call(arg_len) => (arg_len + 1, 1),
+            Instruction::CallMethod(_, arg_len) => (arg_len + 1, 1),
+            Instruction::CallBuiltin(_, arg_len) => (*arg_len, 1),
+            Instruction::Jump(_) => (0, 0),
+            Instruction::JumpIfFalse(_) => (1, 0),
+            Instruction::Return(has_return_value) => (*has_return_value as usize, 0),
+            Instruction::Equal | Instruction::NotEqual | Instruction::Identical |
+            Instruction::Add | Instruction::Sub | Instruction::Multiply | Instruction::Divide | Instruction::FloorDivide | Instruction::Pow |
+            Instruction::LessThan | Instruction::LessThanOrEqual |
+            Instruction::GreaterThan | Instruction::GreaterThanOrEqual => (2, 1),
+            Instruction::AddLocals(..) | Instruction::IncrementLocal(..) |
+            Instruction::CompareLocalConstJump(..) | Instruction::CompareLocalsJump(..) => (0, 0),
+            Instruction::Halt(_) => (0, 0),
+        }
+    }
+
+}
+
+// highest number of values a function will hold on its stack
+pub fn max_stack_depth(instructions: &[Instruction]) -> usize {
+    max_stack_depth_from(instructions, &[0])
+}
+
+// highest number of values a function will hold on its stack, running from any of the entries
+pub fn max_stack_depth_from(instructions: &[Instruction], entries: &[usize]) -> usize {
+    let depths = stack_depths_from(instructions, 0, entries).expect("compiled instructions should keep the stack balanced");
+    instructions.iter().zip(depths)
+        .filter_map(|(instruction, depth)| depth.map(|depth| {
+            let (pops, pushes) = instruction.stack_effect();
+            depth.max(depth - pops + pushes)
+        }))
+        .max()
+        .unwrap_or(0)
+}
+
+// the number of values on the stack before each instruction of a function starting at start,
+// or None for instructions that can not be reached
+//
+// jumps are followed from the first instruction, and fail if they leave the function, pop
+// more values than the stack holds or reach an instruction with two different depths
+pub fn stack_depths(instructions: &[Instruction], start: usize) -> Result<Vec<Option<usize>>, String> {
+    stack_depths_from(instructions, start, &[0])
+}
+
+// the same as stack_depths, for a function that can also be entered with an empty stack at
+// each of the entries, such as where its deferred statements start
+pub fn stack_depths_from(instructions: &[Instruction], start: usize, entries: &[usize]) -> Result<Vec<Option<usize>>, String> {
+    let mut depths = vec![None; instructions.len()];
+    let mut pending: Vec<(usize, usize)> = entries.iter().map(|entry| (*entry, 0)).collect();
+
+    while let Some((index, depth)) = pending.pop() {
+        let position = start + index;
+        let instruction = instructions.get(index).ok_or_else(|| format!("instruction {} is outside of the function", position))?;
+
+        match depths[index] {
+            Some(known) if known == depth => continue,
+            Some(known) => return Err(format!("instruction {} is reached with {} and {} values on the stack", position, known, depth)),
+            None => depths[index] = Some(depth)
+        }
+
+        let (pops, pushes) = instruction.stack_effect();
+        if pops > depth {
+            return Err(format!("instruction {} pops {} values from a stack of {}", position, pops, depth));
+        }
+        let after = depth - pops + pushes;
+
+        let target = instruction.jump_target().map(|target| target.checked_sub(start).ok_or_else(|| format!("instruction {} jumps outside of the function", position)));
+        match instruction {
+            Instruction::Return(_) | Instruction::Halt(_) => {}
+            Instruction::Jump(_) => pending.push((target.expect("jump should have a target")?, after)),
+            _ => {
+                pending.push((index + 1, after));
+                if let Some(target) = target {
+                    pending.push((target?, after));
+                }
+            }
+        }
+    }
+
+    Ok(depths)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::instruction::{max_stack_depth, stack_depths, Instruction};
+    use crate::vm::value::Value;
+
+    #[test]
+    fn test_max_stack_depth() {
+        let instructions = vec![
+            Instruction::StackPush(Value::Integer(1)),
+            Instruction::StackPush(Value::Integer(2)),
+            Instruction::StackPush(Value::Integer(3)),
+            Instruction::Multiply,
+            Instruction::Add,
+            Instruction::MoveToLocalVariable(1),
+            Instruction::LoadLocalVariable(1),
+            Instruction::Return(true),
+        ];
+        assert_eq!(max_stack_depth(&instructions), 3);
+    }
+
+    #[test]
+    fn test_relocate() {
+        let mut jump = Instruction::Jump(3);
+        jump.relocate(10);
+        assert!(matches!(jump, Instruction::Jump(13)));
+
+        let mut jump_if_false = Instruction::JumpIfFalse(0);
+        jump_if_false.relocate(4);
+        assert!(matches!(jump_if_false, Instruction::JumpIfFalse(4)));
+
+        let mut load = Instruction::LoadLocalVariable(1);
+        load.relocate(4);
+        assert!(matches!(load, Instruction::LoadLocalVariable(1)));
+    }
+
+    #[test]
+    fn test_max_stack_depth_dup() {
+        let instructions = vec![
+            Instruction::StackPush(Value::Integer(1)),
+            Instruction::Dup,
+            Instruction::Dup,
+            Instruction::Swap,
+            Instruction::Add,
+            Instruction::Add,
+            Instruction::Return(true),
+        ];
+        assert_eq!(max_stack_depth(&instructions), 3);
+    }
+
+    #[test]
+    fn test_max_stack_depth_return_in_expression() {
+        // a return inside an expression leaves values on the stack for the path that jumps over it
+        let instructions = vec![
+            Instruction::StackPush(Value::Integer(1)),
+            Instruction::StackPush(Value::Bool(true)),
+            Instruction::Dup,
+            Instruction::JumpIfFalse(5),
+            Instruction::Jump(6),
+            Instruction::Return(true),
+            Instruction::StackPush(Value::Integer(3)),
+            Instruction::StackPush(Value::Integer(4)),
+            Instruction::Add,
+            Instruction::Add,
+            Instruction::Add,
+            Instruction::Return(true),
+        ];
+        assert_eq!(max_stack_depth(&instructions), 4);
+    }
+
+    #[test]
+    fn test_stack_depths_invalid() {
+        assert!(stack_depths(&[Instruction::Pop], 0).is_err());
+        assert!(stack_depths(&[Instruction::Jump(3)], 0).is_err());
+        assert!(stack_depths(&[Instruction::Jump(0)], 1).is_err());
+        assert!(stack_depths(&[Instruction::StackPush(Value::Null)], 0).is_err());
+        let uneven = [Instruction::StackPush(Value::Bool(true)), Instruction::JumpIfFalse(3), Instruction::StackPush(Value::Null), Instruction::Return(false)];
+        assert!(stack_depths(&uneven, 0).is_err());
+    }
+
+    #[test]
+    fn test_max_stack_depth_empty() {
+        assert_eq!(max_stack_depth(&[Instruction::Return(false)]), 0);
+    }
+
+}
@@ -0,0 +1,268 @@
+use core::fmt;
+use core::fmt::{Display, Formatter};
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+use crate::vm::instruction::{stack_depths, Instruction};
+use crate::vm::program::Program;
+use crate::vm::sandbox::Sandbox;
+use crate::vm::value::Value;
+
+// Lint
+//
+// checks a compiled program for what a host accepting scripts from others would want to look
+// at before running them: builtins its sandbox would refuse, loops nested too deeply and very
+// large literals. the code is looked at as it is, so a builtin named in a branch that never
+// runs is still found, and calls on host objects can not be seen
+#[derive(Clone, Debug, PartialEq)]
+pub struct LintRules {
+    // builtins outside what this sandbox allows are reported
+    pub sandbox: Sandbox,
+    pub max_loop_depth: usize,
+    // characters in a string constant
+    pub max_string_length: usize,
+    // items written out in an array or dictionary literal
+    pub max_literal_items: usize,
+}
+
+impl Default for LintRules {
+    fn default() -> Self {
+        LintRules {
+            sandbox: Sandbox::strict(),
+            max_loop_depth: 3,
+            max_string_length: 4096,
+            max_literal_items: 1000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LintKind {
+    // the builtin or builtin class called
+    GatedBuiltin(String),
+    // how many loops deep
+    LoopNesting(usize),
+    // characters in the string
+    LargeString(usize),
+    // items in the array or dictionary
+    LargeLiteral(usize),
+}
+
+impl LintKind {
+
+    // stable name of the rule broken, for tools that read the report
+    pub fn rule(&self) -> &'static str {
+        match self {
+            LintKind::GatedBuiltin(_) => "gated_builtin",
+            LintKind::LoopNesting(_) => "loop_nesting",
+            LintKind::LargeString(_) => "large_string",
+            LintKind::LargeLiteral(_) => "large_literal",
+        }
+    }
+
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LintFinding {
+    pub function: String,
+    // instruction within the function
+    pub position: usize,
+    pub kind: LintKind,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LintReport {
+    // in function name order, then position
+    pub findings: Vec<LintFinding>,
+}
+
+impl LintReport {
+
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    // a dictionary for each finding, with its function, position, rule and detail
+    pub fn to_value(&self) -> Value {
+        Value::from(self.findings.iter().map(|finding| {
+            let detail = match &finding.kind {
+                LintKind::GatedBuiltin(name) => Value::from(name.as_str()),
+                LintKind::LoopNesting(n) | LintKind::LargeString(n) | LintKind::LargeLiteral(n) => Value::Integer(*n as i32),
+            };
+            let entries: HashMap<String, Value> = [
+                ("function", Value::from(finding.function.as_str())),
+                ("position", Value::Integer(finding.position as i32)),
+                ("rule", Value::from(finding.kind.rule())),
+                ("detail", detail),
+            ].into_iter().map(|(key, value)| (key.to_string(), value)).collect();
+            Value::from(entries)
+        }).collect::<Vec<Value>>())
+    }
+
+}
+
+// one tab separated line per finding, such as 'Test.main	12	gated_builtin	Hash.md5'
+impl Display for LintReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for finding in self.findings.iter() {
+            let detail = match &finding.kind {
+                LintKind::GatedBuiltin(name) => name.to_string(),
+                LintKind::LoopNesting(n) | LintKind::LargeString(n) | LintKind::LargeLiteral(n) => n.to_string(),
+            };
+            writeln!(f, "{}\t{}\t{}\t{}", finding.function, finding.position, finding.kind.rule(), detail)?;
+        }
+        Ok(())
+    }
+}
+
+impl Program {
+
+    pub fn lint(&self, rules: &LintRules) -> LintReport {
+        let mut findings = vec![];
+        for (name, range) in self.functions() {
+            let code = &self.instructions[range.clone()];
+            let mut found = |position: usize, kind: LintKind| findings.push(LintFinding { function: name.to_string(), position, kind });
+
+            for (position, instruction) in code.iter().enumerate() {
+                match instruction {
+                    Instruction::CallBuiltin(builtin, _) | Instruction::CreateBuiltinObject(builtin, _) if rules.sandbox.check_builtin(builtin).is_err() => {
+                        found(position, LintKind::GatedBuiltin(builtin.to_string()));
+                    }
+                    Instruction::StackPush(Value::String(string)) | Instruction::CompareLocalConstJump(_, _, Value::String(string), _) if string.chars().count() > rules.max_string_length => {
+                        found(position, LintKind::LargeString(string.chars().count()));
+                    }
+                    _ => {}
+                }
+            }
+
+            for (position, depth) in loop_depths(code, range.start) {
+                if depth > rules.max_loop_depth {
+                    found(position, LintKind::LoopNesting(depth));
+                }
+            }
+
+            for (position, items) in literal_sizes(code, range.start) {
+                if items > rules.max_literal_items {
+                    found(position, LintKind::LargeLiteral(items));
+                }
+            }
+        }
+
+        findings.sort_by(|a, b| (&a.function, a.position).cmp(&(&b.function, b.position)));
+        LintReport { findings }
+    }
+
+}
+
+// where each loop starts and how many loops deep it is. a loop is the code a jump goes back
+// over, and jumps back to the same place, such as a continue, are the same loop
+fn loop_depths(code: &[Instruction], start: usize) -> Vec<(usize, usize)> {
+    let mut loops: HashMap<usize, usize> = HashMap::new();
+    for (position, instruction) in code.iter().enumerate() {
+        if let Some(target) = instruction.jump_target().and_then(|target| target.checked_sub(start)).filter(|target| *target <= position) {
+            let end = loops.entry(target).or_insert(position);
+            *end = (*end).max(position);
+        }
+    }
+
+    let mut depths: Vec<(usize, usize)> = loops.iter()
+        .map(|(start, end)| (*start, loops.iter().filter(|(s, e)| *s <= start && end <= *e).count()))
+        .collect();
+    depths.sort();
+    depths
+}
+
+// where each array or dictionary literal starts and how many items it is written with.
+// a literal pushes an empty collection and adds to it while it is still on the stack
+fn literal_sizes(code: &[Instruction], start: usize) -> Vec<(usize, usize)> {
+    let Ok(depths) = stack_depths(code, start) else { return vec![] };
+
+    // literals being built by where they are on the stack, with where they start
+    let mut open: HashMap<usize, usize> = HashMap::new();
+    let mut sizes: HashMap<usize, usize> = HashMap::new();
+    for (position, (instruction, depth)) in code.iter().zip(depths).enumerate() {
+        let Some(depth) = depth else { continue };
+        let (pops, _) = instruction.stack_effect();
+        let bottom = depth - pops;
+
+        match instruction {
+            Instruction::ArrayAdd | Instruction::DictionaryAdd => {
+                if let Some(literal) = open.get(&bottom) {
+                    *sizes.entry(*literal).or_default() += 1;
+                }
+                open.retain(|index, _| *index <= bottom);
+            }
+            _ => open.retain(|index, _| *index < bottom),
+        }
+
+        if let Instruction::StackPush(Value::Array(_) | Value::Dictionary(_)) = instruction {
+            open.insert(depth, position);
+        }
+    }
+
+    let mut sizes: Vec<(usize, usize)> = sizes.into_iter().collect();
+    sizes.sort();
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::compile;
+    use crate::vm::lint::{LintKind, LintRules};
+    use crate::vm::sandbox::Sandbox;
+    use crate::vm::value::Value;
+
+    const SOURCE: &str = r#"
+        class Test {
+            function main() {
+                on("tick", this.tick);
+                var names = ["a", "b", ["c", "d", "e"], {"x": 1, "y": [1, 2, 3, 4]}];
+                var total = 0;
+                for (var i = 0; i < 2; i = i + 1) {
+                    for (var j = 0; j < 2; j = j + 1) {
+                        while (total < 100) {
+                            if (total == 50) { total = total + 2; continue; }
+                            total = total + 1;
+                        }
+                    }
+                }
+                return format("abcdefghij {}", total);
+            }
+            function tick() { }
+        }
+    "#;
+
+    #[test]
+    fn test_lint() {
+        let program = compile(SOURCE).unwrap();
+        let rules = LintRules { sandbox: Sandbox { allowed_builtins: Some(vec![]), ..Sandbox::default() }, max_loop_depth: 2, max_string_length: 8, max_literal_items: 3 };
+        let report = program.lint(&rules);
+
+        // the standard library is linted with the script
+        let kinds: Vec<&LintKind> = report.findings.iter().filter(|finding| finding.function.starts_with("Test.")).map(|finding| &finding.kind).collect();
+        assert_eq!(kinds, vec![
+            &LintKind::GatedBuiltin(String::from("on")),
+            &LintKind::LargeLiteral(4),
+            &LintKind::LargeLiteral(4),
+            &LintKind::LoopNesting(3),
+            &LintKind::LargeString(13),
+            &LintKind::GatedBuiltin(String::from("format")),
+        ]);
+        assert!(report.findings.iter().any(|finding| finding.function.starts_with("std.")));
+
+        assert!(program.lint(&LintRules { sandbox: Sandbox::trusted(), ..LintRules::default() }).is_clean());
+    }
+
+    #[test]
+    fn test_report() {
+        let program = compile("class Test { function main() { return on(\"tick\", null); } }").unwrap();
+        let report = program.lint(&LintRules::default());
+        assert_eq!(report.to_string(), format!("Test.main\t{}\tgated_builtin\ton\n", report.findings[0].position));
+
+        let Value::Array(findings) = report.to_value() else { panic!("the report should be an array") };
+        let finding = findings.borrow()[0].clone();
+        assert_eq!(finding.get_path("rule"), Value::from("gated_builtin"));
+        assert_eq!(finding.get_path("detail"), Value::from("on"));
+    }
+
+}
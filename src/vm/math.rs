@@ -0,0 +1,145 @@
+// Float Math
+//
+// the float functions used by arithmetic, which come from the platform's libm with std. without
+// std they are worked out here, accurate to the f32 values scripts use
+
+#[cfg(feature = "std")]
+pub fn floor(x: f32) -> f32 {
+    x.floor()
+}
+
+#[cfg(feature = "std")]
+pub fn powi(x: f32, n: i32) -> f32 {
+    x.powi(n)
+}
+
+#[cfg(feature = "std")]
+pub fn powf(x: f32, y: f32) -> f32 {
+    x.powf(y)
+}
+
+// floats this large have no fraction
+#[cfg(not(feature = "std"))]
+const WHOLE: f32 = 8388608.0;
+
+#[cfg(not(feature = "std"))]
+pub fn floor(x: f32) -> f32 {
+    if x.is_nan() || x.abs() >= WHOLE {
+        return x;
+    }
+    let whole = x as i32 as f32;
+    match whole == x {
+        true => x,
+        false if whole > x => whole - 1.0,
+        false => whole
+    }
+}
+
+#[cfg(not(feature = "std"))]
+pub fn powi(x: f32, n: i32) -> f32 {
+    let mut base = x as f64;
+    let mut exponent = n.unsigned_abs();
+    let mut result = 1.0;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result *= base;
+        }
+        base *= base;
+        exponent >>= 1;
+    }
+    match n < 0 {
+        true => (1.0 / result) as f32,
+        false => result as f32
+    }
+}
+
+#[cfg(not(feature = "std"))]
+pub fn powf(x: f32, y: f32) -> f32 {
+    if y.is_nan() || x.is_nan() {
+        return f32::NAN;
+    }
+    if y.abs() < WHOLE && floor(y) == y {
+        return powi(x, y as i32);
+    }
+    match x {
+        x if x < 0.0 => f32::NAN,
+        0.0 if y > 0.0 => 0.0,
+        0.0 => f32::INFINITY,
+        x => exp(y as f64 * ln(x as f64)) as f32
+    }
+}
+
+// ln(m * 2^e) is e * ln(2) + ln(m), with ln(m) from the series for 2 * atanh((m - 1) / (m + 1))
+#[cfg(not(feature = "std"))]
+fn ln(x: f64) -> f64 {
+    if x.is_infinite() {
+        return x;
+    }
+    let bits = x.to_bits();
+    let mut exponent = ((bits >> 52) & 0x7ff) as i64 - 1023;
+    let mut mantissa = f64::from_bits((bits & 0x000f_ffff_ffff_ffff) | (1023 << 52));
+    if mantissa > core::f64::consts::SQRT_2 {
+        mantissa /= 2.0;
+        exponent += 1;
+    }
+    let s = (mantissa - 1.0) / (mantissa + 1.0);
+    let (mut term, mut sum) = (s, 0.0);
+    for n in (1..24).step_by(2) {
+        sum += term / n as f64;
+        term *= s * s;
+    }
+    exponent as f64 * core::f64::consts::LN_2 + 2.0 * sum
+}
+
+// e^z is 2^k * e^r, with r small enough for the taylor series
+#[cfg(not(feature = "std"))]
+fn exp(z: f64) -> f64 {
+    // past where an f32 overflows or becomes zero
+    if z > 100.0 {
+        return f64::INFINITY;
+    }
+    if z < -110.0 {
+        return 0.0;
+    }
+    let nearest = z / core::f64::consts::LN_2 + 0.5;
+    let k = nearest as i64 - (nearest < 0.0 && nearest != nearest as i64 as f64) as i64;
+    let r = z - k as f64 * core::f64::consts::LN_2;
+    let (mut term, mut sum) = (1.0, 1.0);
+    for n in 1..20 {
+        term *= r / n as f64;
+        sum += term;
+    }
+    sum * f64::from_bits(((k + 1023) as u64) << 52)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::math::{floor, powf, powi};
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() <= b.abs() * 1e-6
+    }
+
+    #[test]
+    fn test_floor() {
+        for x in [0.0, 1.0, 1.5, -1.5, -2.0, 0.25, -0.25, 1e9, -1e9, 8388607.5] {
+            assert_eq!(floor(x), x.floor());
+        }
+        assert!(floor(f32::NAN).is_nan());
+        assert_eq!(floor(f32::NEG_INFINITY), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_pow() {
+        for (x, n) in [(2.0, 10), (2.0, -3), (-3.0, 3), (1.5, 0), (10.0, -2)] {
+            assert!(close(powi(x, n), x.powi(n)));
+        }
+        for (x, y) in [(2.0, 0.5), (9.0, 1.5), (10.0, -0.25), (0.5, 3.3), (1e-3, 2.7), (2.0, 3.0), (-2.0, 3.0)] {
+            assert!(close(powf(x, y), x.powf(y)), "{} ^ {}", x, y);
+        }
+        assert!(powf(-2.0, 0.5).is_nan());
+        assert_eq!(powf(0.0, -1.5), f32::INFINITY);
+        assert_eq!(powf(10.0, 50.5), f32::INFINITY);
+    }
+
+}
@@ -1,60 +1,616 @@
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::rc::Rc;
-
-use log::{debug, error, info, trace};
-
-use crate::vm::program::Program;
+use alloc::rc::Rc;
+use core::any::Any;
+use core::cell::{OnceCell, RefCell};
+use log::{error, info};
+
+use crate::collections::{HashMap, HashSet};
+use crate::compiler::CLASS_CONSTRUCTOR_FUNCTION_NAME;
+use crate::prelude::*;
+use crate::logging::{debug, trace};
+use crate::vm::actor::{Actor, Mailbox, MAILBOX_SEND_METHOD_NAME};
+use crate::vm::debugger::Debugger;
+use crate::vm::arena::{new_array, new_map, Arena};
+use crate::vm::error::RuntimeError;
+use crate::vm::bytecode::fingerprint;
+use crate::vm::image::{decode_frame, encode_frame, CoroutineImage, Decoder, Encoder, FrameImage, HeapValue, TimerImage, TimersImage, VmImage};
+use crate::vm::config::{Output, StringUnits, VmConfig};
+use crate::vm::convert::FromValue;
+#[cfg(feature = "std")]
+use crate::vm::profiler::Profiler;
+use crate::vm::sandbox::{frame_size, value_size, Sandbox};
+use crate::vm::program::{FunctionMetadata, Program};
 use crate::vm::value::Value;
-use crate::vm::frame::Frame;
-use crate::vm::instruction::Instruction;
+use crate::vm::frame::{Exit, Frame};
+use crate::vm::small_vec::Args;
+use crate::vm::generator::Generator;
+use crate::vm::coroutine::{Coroutine, Resume};
+use crate::vm::host::{HostType, HostTypes};
+use crate::vm::instruction::{stack_depths_from, Instruction};
+use crate::vm::timer::{Timer, Timers};
 
 pub mod value;
-pub(crate) mod program;
-pub(crate) mod instruction;
+pub mod error;
+pub mod image;
+pub mod bytecode;
+pub mod bundle;
+pub mod sandbox;
+pub mod config;
+#[cfg(feature = "std")]
+pub mod profiler;
+pub mod program;
+mod strip;
+pub mod diff;
+pub mod analysis;
+pub mod lint;
+pub mod debugger;
+pub mod builder;
+pub mod convert;
+pub mod instruction;
+pub(crate) mod builtin;
+pub mod arena;
+#[cfg(feature = "bigint")]
+pub mod bigint;
+#[cfg(feature = "decimal")]
+pub mod decimal;
+#[cfg(feature = "bigint")]
+mod exact;
+mod csv;
+mod expect;
+mod format;
+mod math;
+mod hash;
+pub(crate) mod toml;
+#[cfg(feature = "uuid")]
+mod uuid;
+#[cfg(feature = "graphemes")]
+mod grapheme;
+#[cfg(feature = "yaml")]
+mod yaml;
 mod frame;
+mod small_vec;
+pub(crate) mod generator;
+pub(crate) mod coroutine;
+pub mod sequence;
+pub mod set;
+pub mod priority_queue;
+pub mod weak;
+pub mod view;
+pub mod timer;
+pub mod host;
+pub mod actor;
 
 
 // Virtual Machine
 pub struct VM {
     instructions: Vec<Instruction>,
     functions: HashMap<String, usize>,
+    metadata: HashMap<String, FunctionMetadata>,
     frames: Vec<Frame>,
+    frame_pool: Vec<Frame>,
     globals: Vec<Value>,
+    global_symbols: HashMap<String, usize>,
+    docs: HashMap<String, String>,
+    attributes: HashMap<String, Vec<String>>,
+    params: HashMap<String, Vec<String>>,
+    locals: HashMap<String, Vec<String>>,
+    strict: bool,
+    checked: bool,
+    // fingerprint of the program recorded in images, worked out the first time it is needed
+    fingerprint: OnceCell<String>,
+    event_handlers: HashMap<String, Vec<(Value, String)>>,
+    timers: Timers,
+    // coroutines that are running, innermost last, with the index of the frame each started in
+    coroutines: Vec<(Rc<RefCell<Coroutine>>, usize)>,
+    sandbox: Sandbox,
+    #[cfg(feature = "std")]
+    profiler: Option<Profiler>,
+    arena: Option<Arena>,
+    output: Output,
+    strings: StringUnits,
+    host_types: HostTypes,
+    executed: usize,
+    memory: usize,
     ip: usize,
+    debugger: Option<Box<dyn Debugger>>,
+    // positions of the functions to pause at
+    breakpoints: HashSet<usize>,
+    // pause once no more frames than this are running
+    pause_depth: Option<usize>,
 }
 
 impl VM {
 
     pub fn new(program: Program) -> Self {
+        VM::with_sandbox(program, Sandbox::default())
+    }
+
+    // create a vm whose calls are limited by the sandbox
+    pub fn with_sandbox(program: Program, sandbox: Sandbox) -> Self {
+        VM::with_config(program, VmConfig::new().sandbox(sandbox))
+    }
+
+    pub fn with_config(program: Program, config: VmConfig) -> Self {
         VM {
             instructions: program.instructions,
             functions: program.symbols,
+            metadata: program.metadata,
             globals: program.globals,
+            global_symbols: program.global_symbols,
+            docs: program.docs,
+            attributes: program.attributes,
+            params: program.params,
+            locals: program.locals,
+            strict: program.strict,
+            checked: program.checked,
+            fingerprint: OnceCell::new(),
+            event_handlers: HashMap::new(),
+            timers: Timers::default(),
+            coroutines: vec![],
+            sandbox: config.sandbox,
+            #[cfg(feature = "std")]
+            profiler: config.profile.then(Profiler::default),
+            arena: config.arena.then(Arena::default),
+            output: config.output,
+            strings: config.strings,
+            host_types: HostTypes::default(),
+            executed: 0,
+            memory: 0,
             frames: vec![],
-            ip: 0
+            frame_pool: vec![],
+            ip: 0,
+            debugger: None,
+            breakpoints: HashSet::new(),
+            pause_depth: None,
         }
     }
 
-    pub fn exec(mut self, entry: &str, parameters: Option<Vec<Value>>) -> Result<Value, String> {
+    pub fn exec(&mut self, entry: &str, parameters: Option<Vec<Value>>) -> Result<Value, RuntimeError> {
 
         info!("Executing program");
         debug!("program started with {} instructions", self.instructions.len());
 
-        if self.functions.contains_key(entry) {
-            self.ip = *self.functions.get(entry).expect("no entry found");
-        }
-
         trace!("{:?}", self.instructions);
 
         // do not run if no instructions
-        if self.instructions.len() == 0 {
+        if self.instructions.is_empty() {
             error!("no instructions to run");
             return Ok(Value::Null);
         }
 
+        let (entry_name, position, this) = self.entry_call(entry)?;
+        let mut args = parameters.unwrap_or_default();
+        args.insert(0, this);
+
+        self.call_function(&entry_name, position, args)
+    }
+
+    // run many calls one after another and return their results in order
+    //
+    // each entry is only looked up once and frames are reused between calls, so this is
+    // cheaper than calling exec for each one
+    pub fn call_batch(&mut self, calls: &[(&str, Vec<Value>)]) -> Vec<Result<Value, RuntimeError>> {
+        debug!("running a batch of {} calls", calls.len());
+
+        if self.instructions.is_empty() {
+            return calls.iter().map(|_| Ok(Value::Null)).collect();
+        }
+
+        let mut entries: HashMap<&str, Result<(String, usize, Value), RuntimeError>> = HashMap::new();
+        let mut results = Vec::with_capacity(calls.len());
+        for (entry, parameters) in calls {
+            let (entry_name, position, this) = match entries.entry(*entry).or_insert_with(|| self.entry_call(entry)) {
+                Ok(call) => call.clone(),
+                Err(e) => {
+                    results.push(Err(e.clone()));
+                    continue;
+                }
+            };
+            let mut args = Vec::with_capacity(parameters.len() + 1);
+            args.push(this);
+            args.extend(parameters.iter().cloned());
+            results.push(self.call_function(&entry_name, position, args));
+        }
+        results
+    }
+
+    // run an entry function and convert what it returns, failing when it is not the expected type
+    pub fn call_expecting<T: FromValue>(&mut self, entry: &str, parameters: Option<Vec<Value>>) -> Result<T, RuntimeError> {
+        T::from_value(self.exec(entry, parameters)?)
+    }
+
+    // run an entry function with arguments matched to its parameters by name, where missing ones are null
+    pub fn exec_named(&mut self, entry: &str, mut parameters: HashMap<String, Value>) -> Result<Value, RuntimeError> {
+        let (entry_name, _) = self.find_entry(entry)?;
+        let names = self.params.get(&entry_name).cloned().unwrap_or_default();
+
+        let args: Vec<Value> = names.iter().map(|name| parameters.remove(name).unwrap_or(Value::Null)).collect();
+        if let Some(name) = parameters.into_keys().min() {
+            return Err(RuntimeError::UnknownParameter(entry_name, name));
+        }
+
+        self.exec(entry, Some(args))
+    }
+
+    // the name, position and 'this' of an entry function, where 'this' is its class
+    fn entry_call(&self, entry: &str) -> Result<(String, usize, Value), RuntimeError> {
+        let (entry_name, position) = self.find_entry(entry)?;
+        let class_name = entry_name.rsplit_once('.').map(|(class, _)| class).unwrap_or_default();
+        let this = self.global_symbols.get(class_name).map(|idx| self.globals[*idx].clone()).unwrap_or(Value::Null);
+        Ok((entry_name, position, this))
+    }
+
+    // the name and position of an entry function
+    fn find_entry(&self, entry: &str) -> Result<(String, usize), RuntimeError> {
+        let position = *self.functions.get(entry).ok_or_else(|| RuntimeError::UnknownFunction(entry.to_string()))?;
+        Ok((entry.to_string(), position))
+    }
+
+    // call every handler registered for an event and collect their return values
+    pub fn dispatch_event(&mut self, name: &str, payload: Value) -> Result<Vec<Value>, RuntimeError> {
+        let handlers = self.event_handlers.get(name).cloned().unwrap_or_default();
+        debug!("dispatching event '{}' to {} handlers", name, handlers.len());
+
+        let mut results = Vec::with_capacity(handlers.len());
+        for (this, function_name) in handlers {
+            let position = *self.functions.get(function_name.as_str()).ok_or_else(|| RuntimeError::UnknownFunction(function_name.clone()))?;
+            results.push(self.call_function(&function_name, position, vec![this, payload.clone()])?);
+        }
+
+        Ok(results)
+    }
+
+    // call the functions of the timers due by now, which is the time on the clock of the host,
+    // including those that come due while it runs. gives when the next timer will be due, so the
+    // host knows when to call again
+    pub fn run_until_idle(&mut self, now: u64) -> Result<Option<u64>, RuntimeError> {
+        while let Some((this, function_name)) = self.timers.take_due(now) {
+            debug!("running timer {} at {}", function_name, self.timers.now());
+            let position = *self.functions.get(function_name.as_str()).ok_or_else(|| RuntimeError::UnknownFunction(function_name.clone()))?;
+            self.call_function(&function_name, position, vec![this])?;
+        }
+        self.timers.advance(now);
+        Ok(self.timers.next_due())
+    }
+
+    // make an object of a class in a vm of its own that runs the same program with copies of the
+    // globals, which the host and scripts send messages to. the arguments are copied for its constructor
+    pub fn spawn_actor(&mut self, class_name: &str, args: Vec<Value>) -> Result<Actor, RuntimeError> {
+        let program = Program {
+            instructions: self.instructions.clone(),
+            symbols: self.functions.clone(),
+            metadata: self.metadata.clone(),
+            globals: self.globals.iter().map(actor::copy).collect::<Result<_, _>>()?,
+            global_symbols: self.global_symbols.clone(),
+            docs: self.docs.clone(),
+            params: self.params.clone(),
+            attributes: self.attributes.clone(),
+            locals: self.locals.clone(),
+            strict: self.strict,
+            checked: self.checked,
+            ..Program::new()
+        };
+        let config = VmConfig::new().sandbox(self.sandbox.clone()).output(self.output.clone()).strings(self.strings);
+        let mut vm = VM::with_config(program, config);
+
+        // scripts in either vm can send to the handles of actors
+        vm.host_types = self.host_types.clone();
+        for host_types in [&mut self.host_types, &mut vm.host_types] {
+            host_types.register::<Mailbox>().method(MAILBOX_SEND_METHOD_NAME, |mailbox, args| match args {
+                [message] => mailbox.send(message).map(|_| Value::Null),
+                _ => Err(RuntimeError::TypeError(format!("{} expects a message", MAILBOX_SEND_METHOD_NAME)))
+            });
+        }
+
+        let args = args.iter().map(actor::copy).collect::<Result<_, _>>()?;
+        let instance = vm.instantiate(class_name, args)?;
+        Ok(Actor::new(vm, instance))
+    }
+
+    // make an object of a class from the host, running its constructor
+    fn instantiate(&mut self, class_name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        let class = match self.global(class_name) {
+            Some(Value::Class(class)) => class.clone(),
+            Some(other) => return Err(RuntimeError::TypeError(format!("can not create object from {}", other.type_name()))),
+            None => return Err(RuntimeError::UnknownGlobal(class_name.to_string()))
+        };
+        let object = Value::Object(Rc::new(RefCell::new(class.as_ref().clone())));
+        self.call_member(&object, CLASS_CONSTRUCTOR_FUNCTION_NAME, args)?;
+        Ok(object)
+    }
+
+    // call a method of an object from the host, such as the message handler of an actor
+    pub(crate) fn call_member(&mut self, object: &Value, name: &str, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        let member = match object {
+            Value::Object(members) => members.borrow().get(name).cloned(),
+            _ => None
+        };
+        let Some(Value::FunctionRef(function_name)) = member else {
+            return Err(RuntimeError::UnknownMethod(object.type_name().to_string(), name.to_string()));
+        };
+        let position = *self.functions.get(function_name.as_str()).ok_or_else(|| RuntimeError::UnknownFunction(function_name.to_string()))?;
+        let mut call_args = Vec::with_capacity(args.len() + 1);
+        call_args.push(object.clone());
+        call_args.extend(args);
+        self.call_function(&function_name, position, call_args)
+    }
+
+    // add methods that scripts can call on host objects holding a T
+    pub fn register_type<T: Any>(&mut self) -> HostType<'_, T> {
+        self.host_types.register::<T>()
+    }
+
+    // capture the globals, frames, event handlers and timers so they can be restored later, which
+    // fails when a host object or sequence can be reached as the host holds their state
+    pub fn snapshot(&self) -> Result<VmImage, RuntimeError> {
+        let mut encoder = Encoder::default();
+
+        let globals = encoder.encode_all(&self.globals)?;
+        let frames = self.frames.iter().map(|frame| encode_frame(&mut encoder, frame)).collect::<Result<_, _>>()?;
+
+        let mut events: Vec<&String> = self.event_handlers.keys().collect();
+        events.sort();
+        let mut event_handlers = vec![];
+        for event in events {
+            let handlers = self.event_handlers[event].iter()
+                .map(|(this, function_name)| Ok((encoder.encode(this)?, function_name.clone())))
+                .collect::<Result<_, RuntimeError>>()?;
+            event_handlers.push((event.clone(), handlers));
+        }
+
+        let timers = TimersImage {
+            now: self.timers.now(),
+            next_id: self.timers.next_id(),
+            pending: self.timers.pending().iter().map(|timer| Ok(TimerImage {
+                id: timer.id,
+                due: timer.due,
+                every: timer.every,
+                this: encoder.encode(&timer.this)?,
+                handler: timer.handler.clone(),
+            })).collect::<Result<_, RuntimeError>>()?,
+        };
+
+        Ok(VmImage { program: self.fingerprint().clone(), ip: self.ip, globals, frames, event_handlers, timers, heap: encoder.heap })
+    }
+
+    // replace the state of the vm with a snapshot taken from a vm running the same program
+    pub fn restore(&mut self, image: VmImage) -> Result<(), RuntimeError> {
+        if image.program != *self.fingerprint() {
+            return Err(RuntimeError::InvalidImage(String::from("image was taken from another program")));
+        }
+        self.check_image(&image)?;
+
+        let mut decoder = Decoder::new(&image.heap)?;
+
+        let globals = decoder.decode_all(&image.globals)?;
+        let frames = image.frames.iter().map(|frame| decode_frame(&mut decoder, frame)).collect::<Result<Vec<Frame>, RuntimeError>>()?;
+
+        let mut event_handlers: HashMap<String, Vec<(Value, String)>> = HashMap::new();
+        for (event, handlers) in image.event_handlers {
+            for (this, function_name) in handlers {
+                event_handlers.entry(event.clone()).or_default().push((decoder.decode(&this)?, function_name));
+            }
+        }
+
+        let pending = image.timers.pending.iter().map(|timer| Ok(Timer {
+            id: timer.id,
+            due: timer.due,
+            every: timer.every,
+            this: decoder.decode(&timer.this)?,
+            handler: timer.handler.clone(),
+        })).collect::<Result<Vec<Timer>, RuntimeError>>()?;
+
+        self.ip = image.ip;
+        self.timers = Timers::restore(pending, image.timers.next_id, image.timers.now);
+        self.globals = globals;
+        self.frames = frames;
+        self.event_handlers = event_handlers;
+        Ok(())
+    }
+
+    fn fingerprint(&self) -> &String {
+        self.fingerprint.get_or_init(|| fingerprint(&self.instructions, &self.functions, &self.metadata, &self.global_symbols))
+    }
+
+    // check that the frames of an image, and those held by its generators and coroutines, can
+    // carry on in this program without the vm panicking
+    fn check_image(&self, image: &VmImage) -> Result<(), RuntimeError> {
+        if image.globals.len() != self.globals.len() {
+            return Err(RuntimeError::InvalidImage(format!("image has {} globals, the program has {}", image.globals.len(), self.globals.len())));
+        }
+
+        // the frame on top of the vm carries on from where it was, those below it are given
+        // what they called, or a value and whether there are more from a generator
+        self.check_frames(&image.frames, image.ip, 0, 1..=2)?;
+
+        for value in &image.heap {
+            match value {
+                HeapValue::Generator(Some(frame), ip, _) => self.check_frames(core::slice::from_ref(frame.as_ref()), *ip, 0, 1..=1)?,
+                // a suspended coroutine is given what yield returns, and the frames below its top
+                // what they called
+                HeapValue::Coroutine(_, _, CoroutineImage::Suspended(frames, ip)) => self.check_frames(frames, *ip, 1, 1..=1)?,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    // check frames that carry on at ip, innermost last, where the innermost is given `pushed`
+    // values when it carries on and those below it any of `returned`
+    fn check_frames(&self, frames: &[FrameImage], ip: usize, pushed: usize, returned: core::ops::RangeInclusive<usize>) -> Result<(), RuntimeError> {
+        for (index, frame) in frames.iter().enumerate() {
+            let invalid = |reason: String| RuntimeError::InvalidImage(format!("frame {}: {}", frame.name, reason));
+
+            if let Some(position) = frame.return_position.filter(|position| *position >= self.instructions.len()) {
+                return Err(invalid(format!("returns to {} past the end of the program", position)));
+            }
+            let (position, given) = match frames.get(index + 1) {
+                Some(above) => (above.return_position.ok_or_else(|| invalid(String::from("the frame above it has nowhere to return to")))?, returned.clone()),
+                None => (ip, pushed..=pushed),
+            };
+
+            let function = frame.name.split_once('[').map(|(name, _)| name).unwrap_or(&frame.name);
+            let (Some(start), Some(metadata)) = (self.functions.get(function), self.metadata.get(function)) else {
+                return Err(invalid(format!("{} is not a function of the program", function)));
+            };
+            if frame.variables.len() < metadata.local_slots || frame.metadata.local_slots < metadata.local_slots || frame.metadata.max_stack_depth != metadata.max_stack_depth {
+                return Err(invalid(format!("has {} variables and room for {} values, its function needs {} and {}", frame.variables.len(), frame.metadata.max_stack_depth, metadata.local_slots, metadata.max_stack_depth)));
+            }
+
+            let end = self.functions.values().copied().filter(|other| *other > *start).min().unwrap_or(self.instructions.len());
+            if position < *start || position >= end {
+                return Err(invalid(format!("carries on at {} outside of its function", position)));
+            }
+            let entries = match metadata.cleanup {
+                Some(cleanup) => vec![0, cleanup],
+                None => vec![0],
+            };
+            let depths = stack_depths_from(&self.instructions[*start..end], *start, &entries).map_err(invalid)?;
+            let Some(depth) = depths[position - start] else {
+                return Err(invalid(format!("carries on at {} which can not be reached", position)));
+            };
+            if frame.data.len() > metadata.max_stack_depth || !given.contains(&depth.wrapping_sub(frame.data.len())) {
+                return Err(invalid(format!("has {} values on the stack where {} carries on with {}", frame.data.len(), position, depth)));
+            }
+        }
+        Ok(())
+    }
+
+    // get the doc comment of a class, or a member as 'Class.member'
+    pub fn doc(&self, name: &str) -> Option<&str> {
+        self.docs.get(name).map(|d| d.as_str())
+    }
+
+    // get the attributes written before a member as 'Class.member'
+    pub fn attributes(&self, name: &str) -> &[String] {
+        self.attributes.get(name).map(|a| a.as_slice()).unwrap_or_default()
+    }
+
+    // the globals of the program, such as its classes, in name order
+    pub fn globals(&self) -> Vec<(&str, &Value)> {
+        let mut globals: Vec<(&str, &Value)> = self.global_symbols.iter().map(|(name, index)| (name.as_str(), &self.globals[*index])).collect();
+        globals.sort_by_key(|(name, _)| *name);
+        globals
+    }
+
+    pub fn global(&self, name: &str) -> Option<&Value> {
+        self.global_symbols.get(name).map(|index| &self.globals[*index])
+    }
+
+    // replace a global between calls, such as with a dictionary of settings the script reads
+    pub fn set_global(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
+        let index = *self.global_symbols.get(name).ok_or_else(|| RuntimeError::UnknownGlobal(name.to_string()))?;
+        self.globals[index] = value;
+        Ok(())
+    }
+
+    // set a member of a global class, object or dictionary between calls. classes are shared
+    // with whatever loaded them, so a changed class is a copy that only later loads see
+    pub fn set_global_member(&mut self, name: &str, member: &str, value: Value) -> Result<(), RuntimeError> {
+        let index = *self.global_symbols.get(name).ok_or_else(|| RuntimeError::UnknownGlobal(name.to_string()))?;
+        match &mut self.globals[index] {
+            Value::Class(members) => {
+                Rc::make_mut(members).insert(member.to_string(), value);
+            }
+            Value::Object(members) | Value::Dictionary(members) => {
+                let mut members = members.try_borrow_mut().map_err(|_| RuntimeError::GlobalBorrowed(name.to_string()))?;
+                members.insert(member.to_string(), value);
+            }
+            other => return Err(RuntimeError::TypeError(format!("can not set a member of {}", other.type_name())))
+        }
+        Ok(())
+    }
+
+    // get the arena that collections are taken from, if it is enabled
+    pub fn arena(&self) -> Option<&Arena> {
+        self.arena.as_ref()
+    }
+
+    // get the profile of the calls made so far, if profiling is enabled
+    #[cfg(feature = "std")]
+    pub fn profile(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    // the profiler times calls with the std clock, so there is nothing to record without it
+    #[cfg(feature = "std")]
+    fn profile_enter(&mut self, name: &str) {
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.enter(name);
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn profile_enter(&mut self, _name: &str) {}
+
+    #[cfg(feature = "std")]
+    fn profile_exit(&mut self) {
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.exit();
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn profile_exit(&mut self) {}
+
+    // run a function from the host until it returns
+    fn call_function(&mut self, name: &str, position: usize, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        let args = Args::from(args);
+
+        // a generator called from the host is returned without running
+        let metadata = self.metadata.get(name).cloned().unwrap_or_default();
+        if metadata.generator {
+            return Ok(VM::new_generator(&mut self.frame_pool, name.to_string(), position, args, &metadata));
+        }
+
         // push new frame
-        self.frames.push(Frame::new(String::from("main"), None, parameters));
+        let main_frame = VM::new_frame(&mut self.frame_pool, name.to_string(), None, args, &metadata);
+        let depth = self.frames.len();
+
+        // limits apply to each call from the host
+        if depth == 0 {
+            self.executed = 0;
+            self.memory = 0;
+        }
+        self.memory += frame_size(&main_frame.get_metadata());
+        self.profile_enter(name);
+        self.frames.push(main_frame);
+        self.ip = position;
+
+        let mut result = self.run();
+
+        // an error leaves each frame in turn, running the deferred statements of those that
+        // have them. an error in a deferred statement replaces the one it was leaving with
+        while let Err(error) = result {
+            while self.frames.len() > depth && !self.frames.last().expect("frame should be on the stack").has_cleanup() {
+                self.discard_frame();
+            }
+            if self.frames.len() == depth {
+                result = Err(error);
+                break;
+            }
+            let frame = self.frames.last_mut().expect("frame should be on the stack");
+            self.ip = self.functions[frame.get_function_name()] + frame.begin_cleanup(Exit::Error(error));
+            result = self.run();
+        }
+
+        // keep the frames left behind by an error for reuse
+        while self.frames.len() > depth {
+            self.discard_frame();
+        }
+
+        // collections that did not escape the call are reused by the next one
+        if depth == 0 {
+            if let Some(arena) = self.arena.as_mut() {
+                arena.reset();
+            }
+        }
+
+        result
+    }
+
+    // run instructions until the frame entered from the host returns
+    fn run(&mut self) -> Result<Value, RuntimeError> {
 
         // set current frame
         let mut frame = self.frames.last_mut().expect("frame should be on the stack");
@@ -62,7 +618,18 @@ impl VM {
         // run instructions
         loop {
 
-            let instruction = self.instructions.get(self.ip as usize).expect(&*format!("instruction #{} should exist", self.ip));
+            // a debugger pauses before the instruction runs, and may look at any frame meanwhile
+            if self.debugger.is_some() {
+                if self.should_pause() {
+                    self.pause();
+                }
+                frame = self.frames.last_mut().expect("frame should be on the stack");
+            }
+
+            let instruction = self.instructions.get(self.ip).unwrap_or_else(|| panic!("instruction #{} should exist", self.ip));
+
+            self.executed += 1;
+            self.sandbox.check_instructions(self.executed)?;
 
             debug!("");
             debug!("== loop [frame {}; ip:{} ({:?})]", frame.get_name(), self.ip, instruction);
@@ -76,8 +643,9 @@ impl VM {
                     trace!("asserting '{}' is true", output);
 
                     match output {
-                        Value::Bool(val) => assert!(val),
-                        _ => panic!("unable to assert {}", output)
+                        Value::Bool(true) => {},
+                        Value::Bool(false) => return Err(RuntimeError::AssertionFailed),
+                        _ => return Err(RuntimeError::TypeError(format!("can not assert {}", output.type_name())))
                     }
 
                     self.ip += 1;
@@ -85,7 +653,7 @@ impl VM {
 
                 Instruction::Print => {
                     let output = frame.pop_value_from_stack();
-                    println!("{:?}", output.to_string());
+                    self.output.print(&output);
                     self.ip += 1;
                 }
 
@@ -93,26 +661,34 @@ impl VM {
 
                 Instruction::Call(arg_len) => {
 
-                    // cut args from stack and then reverse order
-                    let mut args = frame.pop_values_from_stack(*arg_len as usize);
-                    args.reverse();
+                    // cut args from stack
+                    let args = frame.pop_values_from_stack(*arg_len);
 
                     // pop functionref from stack
                     let name = frame.pop_value_from_stack().to_string();
-                    let function_position = *self.functions.get(name.as_str()).expect("function should exist");
+                    let function_position = *self.functions.get(name.as_str()).ok_or_else(|| RuntimeError::UnknownFunction(name.clone()))?;
+
+                    let metadata = self.metadata.get(name.as_str()).expect("function metadata should exist");
+
+                    // generators start running when the first value is asked for
+                    if metadata.generator {
+                        let generator = VM::new_generator(&mut self.frame_pool, name, function_position, args, metadata);
+                        frame.push_value_to_stack(generator);
+                        self.ip += 1;
+                        continue;
+                    }
 
                     // frame name with fp
                     let function_name = format!("{}[{}]", name, self.frames.len());
 
-                    let a = if args.is_empty() {
-                        None
-                    } else {
-                        Some(args)
-                    };
-
                     // push new frame onto frames
                     let next_ip = self.ip + 1;
-                    self.frames.push(Frame::new(function_name, Some(next_ip), a));
+                    let new_frame = VM::new_frame(&mut self.frame_pool, function_name, Some(next_ip), args, metadata);
+                    self.sandbox.check_call_depth(self.frames.len() + 1)?;
+                    self.memory += frame_size(&new_frame.get_metadata());
+                    self.sandbox.check_memory(self.memory)?;
+                    self.profile_enter(&name);
+                    self.frames.push(new_frame);
 
                     // set current frame
                     frame = self.frames.last_mut().expect("frame should be on the stack");
@@ -122,6 +698,151 @@ impl VM {
 
                 }
 
+                Instruction::CallMethod(name, arg_len) => {
+
+                    // cut args from stack
+                    let mut args = frame.pop_values_from_stack(*arg_len);
+
+                    // pop receiver from stack
+                    let receiver = frame.pop_value_from_stack();
+                    trace!("calling method '{}' on {:?}", name, receiver);
+
+                    // find the script function on classes and objects
+                    let function_ref = match &receiver {
+                        Value::Object(members) => members.borrow().get(name).cloned(),
+                        Value::Class(members) => members.get(name).cloned(),
+                        _ => None
+                    };
+
+                    match function_ref {
+                        Some(Value::FunctionRef(function_name)) => {
+                            let function_position = *self.functions.get(function_name.as_str()).ok_or_else(|| RuntimeError::UnknownFunction(function_name.to_string()))?;
+                            let metadata = self.metadata.get(function_name.as_str()).expect("function metadata should exist");
+
+                            // receiver becomes 'this' in the new frame
+                            args.insert(0, receiver);
+
+                            // generators start running when the first value is asked for
+                            if metadata.generator {
+                                let generator = VM::new_generator(&mut self.frame_pool, function_name.to_string(), function_position, args, metadata);
+                                frame.push_value_to_stack(generator);
+                                self.ip += 1;
+                                continue;
+                            }
+
+                            let next_ip = self.ip + 1;
+                            let new_frame = VM::new_frame(&mut self.frame_pool, format!("{}[{}]", function_name, self.frames.len()), Some(next_ip), args, metadata);
+                            self.sandbox.check_call_depth(self.frames.len() + 1)?;
+                            self.memory += frame_size(&new_frame.get_metadata());
+                            self.sandbox.check_memory(self.memory)?;
+                            self.profile_enter(&function_name);
+                            self.frames.push(new_frame);
+                            frame = self.frames.last_mut().expect("frame should be on the stack");
+
+                            trace!("ip jumping from {} to {}", self.ip, function_position);
+                            self.ip = function_position;
+                        }
+                        Some(other) => return Err(RuntimeError::TypeError(format!("member '{}' is a {} not a function", name, other.type_name()))),
+                        // the next value of a generator, or null once it has finished
+                        None if matches!(receiver, Value::Generator(_)) && name == builtin::GENERATOR_NEXT_METHOD_NAME => {
+                            let Value::Generator(generator) = receiver else { unreachable!() };
+                            let resumed = self.resume_generator(&generator, false)?;
+                            frame = self.frames.last_mut().expect("frame should be on the stack");
+                            if !resumed {
+                                frame.push_value_to_stack(Value::Null);
+                                self.ip += 1;
+                            }
+                        }
+                        // resuming and yielding move frames between the coroutine and the stack
+                        None if matches!(receiver, Value::Coroutine(_)) && matches!(name.as_str(), builtin::COROUTINE_RESUME_METHOD_NAME | builtin::COROUTINE_YIELD_METHOD_NAME) => {
+                            let Value::Coroutine(coroutine) = receiver else { unreachable!() };
+                            let value = match &args[..] {
+                                [] => Value::Null,
+                                [value] => value.clone(),
+                                _ => return Err(RuntimeError::TypeError(format!("coroutine {} expects at most one value", name)))
+                            };
+                            if name == builtin::COROUTINE_RESUME_METHOD_NAME {
+                                self.resume_coroutine(&coroutine, value)?;
+                            } else {
+                                self.yield_coroutine(&coroutine, value)?;
+                            }
+                            frame = self.frames.last_mut().expect("frame should be on the stack");
+                        }
+                        None if matches!(receiver, Value::Array(_)) && name == builtin::ARRAY_SORT_BY_METHOD_NAME => {
+                            let Value::Array(items) = receiver else { unreachable!() };
+                            let sorted = self.sort_by(&items, &args)?;
+                            frame = self.frames.last_mut().expect("frame should be on the stack");
+                            frame.push_value_to_stack(sorted);
+                            self.ip += 1;
+                        }
+                        None => {
+                            let result = match &receiver {
+                                Value::HostObject(object) => self.host_types.call(object, name, &args)?,
+                                Value::String(text) => {
+                                    let (sandbox, used) = (&self.sandbox, self.memory);
+                                    builtin::string_method(text, name, &args, self.strings, &|length| sandbox.check_memory(used.saturating_add(length)))?
+                                }
+                                Value::StringBuilder(buffer) => {
+                                    let (sandbox, used) = (&self.sandbox, &mut self.memory);
+                                    builtin::string_builder_method(buffer, name, &args, &mut |bytes| sandbox.charge(used, bytes))?
+                                }
+                                _ => builtin::call_method(&receiver, name, &args)?
+                            };
+                            self.sandbox.charge_string(&mut self.memory, &result)?;
+                            frame.push_value_to_stack(result);
+                            self.ip += 1;
+                        }
+                    }
+
+                }
+
+                Instruction::CallBuiltin(name, arg_len) => {
+
+                    // cut args from stack
+                    let args = frame.pop_values_from_stack(*arg_len);
+
+                    self.sandbox.check_builtin(name)?;
+
+                    match name.as_str() {
+
+                        // register the handler with the 'this' of the caller
+                        builtin::EVENT_HANDLER_FUNCTION_NAME => {
+                            let (event, handler) = match &args[..] {
+                                [Value::String(event), Value::FunctionRef(handler)] => (event.clone(), handler.clone()),
+                                _ => return Err(RuntimeError::TypeError(format!("{} expects an event name and a function", name)))
+                            };
+                            trace!("registering handler {} for event '{}'", handler, event);
+                            let this = frame.get_variable_or_panic(0).clone();
+                            self.event_handlers.entry(event.to_string()).or_default().push((this, handler.to_string()));
+                            frame.push_value_to_stack(Value::Null);
+                        }
+
+                        // the function runs as a method of the caller, like event handlers
+                        builtin::COROUTINE_FUNCTION_NAME => {
+                            let [Value::FunctionRef(function)] = &args[..] else {
+                                return Err(RuntimeError::TypeError(format!("{} expects a function", name)));
+                            };
+                            let this = frame.get_variable_or_panic(0).clone();
+                            frame.push_value_to_stack(Value::Coroutine(Rc::new(RefCell::new(Coroutine::new(function.to_string(), this)))));
+                        }
+
+                        _ => match name.split_once('.') {
+                            // timers call back with the 'this' of the caller, like event handlers
+                            Some((builtin::TIMER_MODULE_NAME, function)) => {
+                                let this = frame.get_variable_or_panic(0).clone();
+                                frame.push_value_to_stack(self.timers.call(function, &args, this)?);
+                            }
+                            _ => {
+                                let result = builtin::call_function(name, &args)?;
+                                self.sandbox.charge_string(&mut self.memory, &result)?;
+                                frame.push_value_to_stack(result);
+                            }
+                        }
+                    }
+
+                    self.ip += 1;
+                }
+
                 Instruction::Return(has_return_value) => {
 
                     let return_value = if *has_return_value {
@@ -130,7 +851,19 @@ impl VM {
                         Value::Null
                     };
 
-                    if frame.get_return_position() == None {
+                    // deferred statements run before the function returns, then it returns what
+                    // it was going to or carries on with the error it was leaving with
+                    if frame.has_cleanup() {
+                        self.ip = self.functions[frame.get_function_name()] + frame.begin_cleanup(Exit::Return(return_value));
+                        continue;
+                    }
+                    let return_value = match frame.take_exit() {
+                        Some(Exit::Return(value)) => value,
+                        Some(Exit::Error(error)) => return Err(error),
+                        None => return_value
+                    };
+
+                    if frame.get_return_position().is_none() {
                         trace!("returning {} from {}", return_value, frame.get_name());
                         return Ok(return_value);
                     }
@@ -139,17 +872,32 @@ impl VM {
                     trace!("ip jumping from {} to {:?}", self.ip, frame.get_return_position());
                     self.ip = frame.get_return_position().expect("return position should be set");
 
-                    // remove last frame
-                    self.frames.pop();
+                    // remove last frame and keep it for reuse
+                    let mut finished = self.frames.pop().expect("frame should be on the stack");
+                    let finished_generator = finished.take_generator();
+                    self.memory = self.memory.saturating_sub(frame_size(&finished.get_metadata()));
+                    self.profile_exit();
+                    finished.release();
+                    self.frame_pool.push(finished);
+                    self.finish_coroutines();
 
                     // set new current frame
                     frame = self.frames.last_mut().expect("frame should be on the stack");
 
-                    // push return value onto stack
-                    if *has_return_value {
-                        frame.push_value_to_stack(return_value);
+                    // a generator that returns has no more values
+                    if let Some(generator) = finished_generator {
+                        let mut generator = generator.borrow_mut();
+                        generator.finish();
+                        frame.push_value_to_stack(Value::Null);
+                        if generator.is_iterating() {
+                            frame.push_value_to_stack(Value::Bool(false));
+                        }
+                        continue;
                     }
 
+                    // push return value onto stack
+                    frame.push_value_to_stack(return_value);
+
                 }
 
                 // create object from class
@@ -157,51 +905,128 @@ impl VM {
                     let class = frame.pop_value_from_stack();
                     match class {
                         Value::Class(class) => {
-                            frame.push_value_to_stack(Value::Object(Rc::new(RefCell::new(class.clone()))));
+                            self.memory += class.values().map(value_size).sum::<usize>();
+                            self.sandbox.check_memory(self.memory)?;
+                            let object = new_map(&mut self.arena);
+                            object.borrow_mut().extend(class.iter().map(|(k, v)| (k.clone(), v.clone())));
+                            frame.push_value_to_stack(Value::Object(object));
                         },
-                        _ => unreachable!("{} is not a class", class)
+                        _ => return Err(RuntimeError::TypeError(format!("can not create object from {}", class.type_name())))
                     }
                     self.ip += 1;
                 }
 
 
+                // create object from builtin class
+                Instruction::CreateBuiltinObject(class_name, arg_len) => {
+                    self.sandbox.check_builtin(class_name)?;
+                    let args = frame.pop_values_from_stack(*arg_len);
+                    let (sandbox, used) = (&self.sandbox, &mut self.memory);
+                    frame.push_value_to_stack(builtin::create_object(class_name, &args, &mut |bytes| sandbox.charge(used, bytes))?);
+                    self.ip += 1;
+                }
+
                 //==================================================================================
                 // CONTROL FLOW
 
-                Instruction::JumpForward(delta) => {
-                    trace!("jumping forward by {}", delta);
-                    self.ip += *delta as usize;
-                }
-
-                Instruction::JumpBackward(delta) => {
-                    trace!("jumping backward by {}", delta);
-                    self.ip -= *delta as usize;
+                Instruction::Jump(target) => {
+                    trace!("jumping to {}", target);
+                    self.ip = *target;
                 }
 
-                Instruction::JumpIfFalse(delta) => {
+                Instruction::JumpIfFalse(target) => {
 
                     let b = frame.pop_value_from_stack();
-                    trace!("jumping if {} is false", b);
+                    trace!("jumping to {} if {} is false", target, b);
 
                     match b {
-                        Value::Bool(false) =>{
-                            if *delta > 0 {
-                                self.ip += *delta as usize;
-                            } else {
-                                self.ip -= *delta as usize;
-                            }
-                        },
+                        Value::Bool(false) => self.ip = *target,
                         _ => self.ip += 1
                     }
                 }
 
+                //==================================================================================
+                // SUPERINSTRUCTIONS
+
+                Instruction::AddLocals(lhs, rhs, dst) => {
+                    let lhs = frame.get_variable_or_panic(*lhs).clone();
+                    let rhs = frame.get_variable_or_panic(*rhs).clone();
+                    if self.checked {
+                        lhs.check_coercion(&rhs, false)?;
+                    }
+                    let sum = add(self.strict, lhs, rhs)?;
+                    self.sandbox.charge_string(&mut self.memory, &sum)?;
+                    frame.push_value_to_variable_slot(*dst, sum);
+                    self.ip += 1;
+                }
+
+                Instruction::IncrementLocal(slot, step) => {
+                    let value = frame.get_variable_or_panic(*slot).clone();
+                    if self.checked {
+                        value.check_coercion(&Value::Integer(*step), false)?;
+                    }
+                    let sum = add(self.strict, value, Value::Integer(*step))?;
+                    self.sandbox.charge_string(&mut self.memory, &sum)?;
+                    frame.push_value_to_variable_slot(*slot, sum);
+                    self.ip += 1;
+                }
+
+                Instruction::CompareLocalConstJump(comparison, slot, value, target) => {
+                    if self.checked {
+                        frame.get_variable_or_panic(*slot).check_comparable(value)?;
+                    }
+                    match comparison.test(frame.get_variable_or_panic(*slot), value)? {
+                        true => self.ip += 1,
+                        false => self.ip = *target
+                    }
+                }
+
+                Instruction::CompareLocalsJump(comparison, lhs, rhs, target) => {
+                    if self.checked {
+                        frame.get_variable_or_panic(*lhs).check_comparable(frame.get_variable_or_panic(*rhs))?;
+                    }
+                    match comparison.test(frame.get_variable_or_panic(*lhs), frame.get_variable_or_panic(*rhs))? {
+                        true => self.ip += 1,
+                        false => self.ip = *target
+                    }
+                }
+
 
                 //==================================================================================
                 // STACK
 
                 // Push value onto stack
                 Instruction::StackPush(variant) => {
-                    frame.push_value_to_stack(variant.clone());
+
+                    // each literal creates a new collection
+                    let value = match variant {
+                        Value::Array(_) => Value::Array(new_array(&mut self.arena)),
+                        Value::Dictionary(_) => Value::Dictionary(new_map(&mut self.arena)),
+                        v => v.clone()
+                    };
+                    frame.push_value_to_stack(value);
+                    self.ip += 1
+                }
+
+
+                // Discard value from top of stack
+                Instruction::Pop => {
+                    frame.pop_value_from_stack();
+                    self.ip += 1
+                }
+
+
+                // Copy the value on top of the stack
+                Instruction::Dup => {
+                    let value = frame.get_top_value_on_stack();
+                    frame.push_value_to_stack(value);
+                    self.ip += 1
+                }
+
+
+                // Exchange the top two values on the stack
+                Instruction::Swap => {
+                    frame.swap_top_values_on_stack();
                     self.ip += 1
                 }
 
@@ -226,9 +1051,15 @@ impl VM {
                     self.ip += 1;
                 }
 
+                // store in global
+                Instruction::StoreGlobal(index) => {
+                    self.globals[*index] = frame.pop_value_from_stack();
+                    self.ip += 1;
+                }
+
                 // load from global
                 Instruction::LoadGlobal(index) => {
-                    let value = self.globals.get(*index).expect(&*format!("global '{}'should exist", index));
+                    let value = self.globals.get(*index).expect("global should exist");
                     frame.push_value_to_stack(value.clone());
                     self.ip += 1;
                 }
@@ -245,13 +1076,52 @@ impl VM {
                     if let Value::Array(val) = array {
                         frame.push_value_to_stack(Value::Integer(val.borrow().len() as i32));
                     } else {
-                        panic!("can not get length on non-array {}", array)
+                        return Err(RuntimeError::TypeError(format!("can not get length of {}", array.type_name())));
                     }
 
                     self.ip += 1;
 
                 }
 
+                //==================================================================================
+                // PATTERNS
+
+                // whether the value is an array with the number of items
+                Instruction::IsArray(len, at_least) => {
+                    let value = frame.pop_value_from_stack();
+                    let matched = match value {
+                        Value::Array(items) if *at_least => items.borrow().len() >= *len,
+                        Value::Array(items) => items.borrow().len() == *len,
+                        _ => false
+                    };
+                    frame.push_value_to_stack(Value::Bool(matched));
+                    self.ip += 1;
+                }
+
+                // whether the value is a dictionary or object with the key
+                Instruction::HasKey(key) => {
+                    let value = frame.pop_value_from_stack();
+                    let matched = match value {
+                        Value::Dictionary(items) | Value::Object(items) => items.borrow().contains_key(key.as_str()),
+                        _ => false
+                    };
+                    frame.push_value_to_stack(Value::Bool(matched));
+                    self.ip += 1;
+                }
+
+                // whether the value is an object of the class, which objects tell by the constructor they share
+                Instruction::IsInstance => {
+                    let class = frame.pop_value_from_stack();
+                    let value = frame.pop_value_from_stack();
+                    let matched = match (&value, &class) {
+                        (Value::Object(members), Value::Class(class)) => members.borrow().get(CLASS_CONSTRUCTOR_FUNCTION_NAME) == class.get(CLASS_CONSTRUCTOR_FUNCTION_NAME),
+                        (_, Value::Class(_)) => false,
+                        _ => return Err(RuntimeError::TypeError(format!("can not match an object against {}", class.type_name())))
+                    };
+                    frame.push_value_to_stack(Value::Bool(matched));
+                    self.ip += 1;
+                }
+
                 // add value to array
                 Instruction::ArrayAdd => {
 
@@ -261,14 +1131,119 @@ impl VM {
                     let array = frame.pop_value_from_stack();
                     trace!("got array {:?}", array);
 
-                    if let Value::Array(mut v) = array {
-                        v.borrow_mut().push(value);
-                        frame.push_value_to_stack(Value::Array(v));
+                    self.memory += value_size(&value);
+                    self.sandbox.check_memory(self.memory)?;
+
+                    match array {
+                        Value::Array(v) => {
+                            v.borrow_mut().push(value);
+                            frame.push_value_to_stack(Value::Array(v));
+                        }
+                        _ => return Err(RuntimeError::TypeError(format!("can not add an item to {}", array.type_name())))
+                    }
+
+                    self.ip += 1;
+                }
+
+                // add the items of another array to the array
+                Instruction::ArrayExtend => {
+                    let items = frame.pop_value_from_stack();
+                    let array = frame.pop_value_from_stack();
+
+                    match (array, items) {
+                        (Value::Array(v), Value::Array(items)) => {
+                            // copied first, as spreading an array into itself is allowed
+                            let items = items.borrow().clone();
+                            self.memory += items.iter().map(value_size).sum::<usize>();
+                            self.sandbox.check_memory(self.memory)?;
+                            v.borrow_mut().extend(items);
+                            frame.push_value_to_stack(Value::Array(v));
+                        }
+                        (Value::Array(_), items) => return Err(RuntimeError::TypeError(format!("can not spread {} into an array", items.type_name()))),
+                        (array, _) => return Err(RuntimeError::TypeError(format!("can not add items to {}", array.type_name())))
                     }
 
                     self.ip += 1;
                 }
 
+                //==================================================================================
+                // ITERATION
+
+                // push the next item and true, or null and false when there are no more
+                Instruction::IterNext => {
+
+                    let index = frame.pop_value_from_stack();
+                    let iterable = frame.pop_value_from_stack();
+
+                    match (&iterable, index) {
+                        (Value::Array(items), Value::Integer(index)) => {
+                            let item = items.borrow().get(index as usize).cloned();
+                            frame.push_value_to_stack(item.clone().unwrap_or(Value::Null));
+                            frame.push_value_to_stack(Value::Bool(item.is_some()));
+                            self.ip += 1;
+                        }
+                        (Value::Set(set), Value::Integer(index)) => {
+                            let item = set.borrow().items().get(index as usize).cloned();
+                            frame.push_value_to_stack(item.clone().unwrap_or(Value::Null));
+                            frame.push_value_to_stack(Value::Bool(item.is_some()));
+                            self.ip += 1;
+                        }
+                        (Value::ArrayView(view), Value::Integer(index)) => {
+                            let item = view.get(index as usize);
+                            frame.push_value_to_stack(item.clone().unwrap_or(Value::Null));
+                            frame.push_value_to_stack(Value::Bool(item.is_some()));
+                            self.ip += 1;
+                        }
+                        (Value::Deque(items), Value::Integer(index)) => {
+                            let item = items.borrow().get(index as usize).cloned();
+                            frame.push_value_to_stack(item.clone().unwrap_or(Value::Null));
+                            frame.push_value_to_stack(Value::Bool(item.is_some()));
+                            self.ip += 1;
+                        }
+                        (Value::Sequence(sequence), _) => {
+                            let item = sequence.borrow_mut().next();
+                            frame.push_value_to_stack(item.clone().unwrap_or(Value::Null));
+                            frame.push_value_to_stack(Value::Bool(item.is_some()));
+                            self.ip += 1;
+                        }
+                        (Value::Generator(generator), _) => {
+                            let resumed = self.resume_generator(generator, true)?;
+                            frame = self.frames.last_mut().expect("frame should be on the stack");
+                            if !resumed {
+                                frame.push_value_to_stack(Value::Null);
+                                frame.push_value_to_stack(Value::Bool(false));
+                                self.ip += 1;
+                            }
+                        }
+                        _ => return Err(RuntimeError::TypeError(format!("can not iterate over {}", iterable.type_name())))
+                    }
+
+                }
+
+                // suspend the generator and hand the value to whoever resumed it
+                Instruction::Yield => {
+
+                    let value = frame.pop_value_from_stack();
+
+                    let mut suspended = self.frames.pop().expect("frame should be on the stack");
+                    self.memory = self.memory.saturating_sub(frame_size(&suspended.get_metadata()));
+                    self.profile_exit();
+
+                    let generator = suspended.take_generator().ok_or_else(|| RuntimeError::TypeError(String::from("yield outside of a running generator")))?;
+                    let mut generator = generator.borrow_mut();
+                    let return_position = suspended.get_return_position().expect("return position should be set");
+                    generator.suspend(suspended, self.ip + 1);
+
+                    trace!("ip jumping from {} to {}", self.ip, return_position);
+                    self.ip = return_position;
+
+                    frame = self.frames.last_mut().expect("frame should be on the stack");
+                    frame.push_value_to_stack(value);
+                    if generator.is_iterating() {
+                        frame.push_value_to_stack(Value::Bool(true));
+                    }
+                }
+
                 //==================================================================================
                 // DICTIONARY
 
@@ -282,9 +1257,35 @@ impl VM {
                     let dict = frame.pop_value_from_stack();
                     trace!("got dict {:?}", dict);
 
-                    if let Value::Dictionary(mut v) = dict {
-                        v.borrow_mut().insert(key.to_string(), value);
-                        frame.push_value_to_stack(Value::Dictionary(v));
+                    self.memory += value_size(&key) + value_size(&value);
+                    self.sandbox.check_memory(self.memory)?;
+
+                    match dict {
+                        Value::Dictionary(v) => {
+                            v.borrow_mut().insert(key.to_string(), value);
+                            frame.push_value_to_stack(Value::Dictionary(v));
+                        }
+                        _ => return Err(RuntimeError::TypeError(format!("can not add an entry to {}", dict.type_name())))
+                    }
+
+                    self.ip += 1;
+                }
+
+                // add the entries of another dictionary to the dictionary, replacing those it has
+                Instruction::DictionaryExtend => {
+                    let entries = frame.pop_value_from_stack();
+                    let dict = frame.pop_value_from_stack();
+
+                    match (dict, entries) {
+                        (Value::Dictionary(v), Value::Dictionary(entries)) => {
+                            let entries = entries.borrow().clone();
+                            self.memory += entries.iter().map(|(key, value)| key.len() + value_size(value)).sum::<usize>();
+                            self.sandbox.check_memory(self.memory)?;
+                            v.borrow_mut().extend(entries);
+                            frame.push_value_to_stack(Value::Dictionary(v));
+                        }
+                        (Value::Dictionary(_), entries) => return Err(RuntimeError::TypeError(format!("can not spread {} into a dictionary", entries.type_name()))),
+                        (dict, _) => return Err(RuntimeError::TypeError(format!("can not add entries to {}", dict.type_name())))
                     }
 
                     self.ip += 1;
@@ -309,10 +1310,10 @@ impl VM {
 
                             if let Value::Integer(index) = key {
                                 let borrowed_items = items.borrow();
-                                let array_value = borrowed_items.get(index as usize).expect(&*format!("array index {} should exist", index));
+                                let array_value = borrowed_items.get(index as usize).ok_or(RuntimeError::IndexOutOfBounds(index))?;
                                 frame.push_value_to_stack(array_value.clone());
                             } else {
-                                panic!("can not get index on non-integer {}", key)
+                                return Err(RuntimeError::TypeError(format!("can not index array with {}", key.type_name())));
                             }
                         },
 
@@ -322,14 +1323,47 @@ impl VM {
 
                             if let Value::String(index) = key {
                                 let items_borrowed = items.borrow();
-                                let v2 = items_borrowed.get(index.as_str()).expect(&*format!("key '{}' should exist in dictionary", index));
+                                let v2 = items_borrowed.get(index.as_str()).ok_or_else(|| RuntimeError::KeyNotFound(index.to_string()))?;
                                 frame.push_value_to_stack(v2.clone());
                             } else {
-                                panic!("can not get index on non-string {}", key)
+                                return Err(RuntimeError::TypeError(format!("can not index dictionary with {}", key.type_name())));
                             }
                         }
 
-                        _ => panic!("can not get index on non-collection {}", key)
+                        Value::ArrayView(view) => {
+                            if let Value::Integer(index) = key {
+                                let item = usize::try_from(index).ok().and_then(|index| view.get(index)).ok_or(RuntimeError::IndexOutOfBounds(index))?;
+                                frame.push_value_to_stack(item);
+                            } else {
+                                return Err(RuntimeError::TypeError(format!("can not index array view with {}", key.type_name())));
+                            }
+                        }
+
+                        // the character, or grapheme, at a position as a string of its own
+                        Value::String(text) => {
+                            if let Value::Integer(index) = key {
+                                let unit = usize::try_from(index).ok().and_then(|index| self.strings.get(&text, index)).ok_or(RuntimeError::IndexOutOfBounds(index))?;
+                                frame.push_value_to_stack(Value::from(unit));
+                            } else {
+                                return Err(RuntimeError::TypeError(format!("can not index string with {}", key.type_name())));
+                            }
+                        }
+
+                        Value::Object(members) => {
+                            let member = members.borrow().get(key.to_string().as_str()).cloned();
+                            frame.push_value_to_stack(member.ok_or_else(|| RuntimeError::KeyNotFound(key.to_string()))?);
+                        }
+
+                        Value::Class(members) => {
+                            let member = members.get(key.to_string().as_str()).cloned();
+                            frame.push_value_to_stack(member.ok_or_else(|| RuntimeError::KeyNotFound(key.to_string()))?);
+                        }
+
+                        Value::HostObject(object) => {
+                            frame.push_value_to_stack(self.host_types.get(&object, key.to_string().as_str())?);
+                        }
+
+                        _ => return Err(RuntimeError::TypeError(format!("can not index {}", collection.type_name())))
 
                     }
 
@@ -350,22 +1384,27 @@ impl VM {
                     match collection {
                         Value::Array(items) => {
                             if let Value::Integer(index) = key {
-                                items.borrow_mut()[index as usize] = value;
-                                frame.push_value_to_stack(Value::Array(items));
+                                let mut items = items.borrow_mut();
+                                let slot = items.get_mut(index as usize).ok_or(RuntimeError::IndexOutOfBounds(index))?;
+                                *slot = value;
                             } else {
-                                panic!("can not get index on non-integer {}", key)
+                                return Err(RuntimeError::TypeError(format!("can not index array with {}", key.type_name())));
                             }
                         },
                         Value::Dictionary(items) => {
                             if let Value::String(index) = key {
                                 trace!("setting key value {:?} {:?}", index, value);
-                                items.borrow_mut().insert(index, value);
-                                frame.push_value_to_stack(Value::Dictionary(items));
+                                items.borrow_mut().insert(index.to_string(), value);
                             } else {
-                                panic!("can not get index on non-string {}", key)
+                                return Err(RuntimeError::TypeError(format!("can not index dictionary with {}", key.type_name())));
                             }
                         }
-                        _ => panic!("can not get index on non-collection")
+                        Value::Object(members) => {
+                            members.borrow_mut().insert(key.to_string(), value);
+                        }
+                        Value::HostObject(object) => self.host_types.set(&object, key.to_string().as_str(), value)?,
+                        Value::ArrayView(_) => return Err(RuntimeError::TypeError(String::from("can not change an array through a view"))),
+                        _ => return Err(RuntimeError::TypeError(format!("can not index {}", collection.type_name())))
                     }
 
                     self.ip += 1;
@@ -376,30 +1415,57 @@ impl VM {
 
                 Instruction::Add => {
                     let (lhs, rhs) = frame.pop_2_values_from_stack();
-                    frame.push_value_to_stack(lhs + rhs);
+                    if self.checked {
+                        lhs.check_coercion(&rhs, false)?;
+                    }
+                    let sum = add(self.strict, lhs, rhs)?;
+                    self.sandbox.charge_string(&mut self.memory, &sum)?;
+                    frame.push_value_to_stack(sum);
                     self.ip += 1;
                 }
 
                 Instruction::Sub => {
                     let (lhs, rhs) = frame.pop_2_values_from_stack();
-                    frame.push_value_to_stack(lhs - rhs);
+                    if self.checked {
+                        lhs.check_coercion(&rhs, false)?;
+                    }
+                    frame.push_value_to_stack((lhs - rhs)?);
                     self.ip += 1;
                 }
 
                 Instruction::Multiply => {
                     let (lhs, rhs) = frame.pop_2_values_from_stack();
-                    frame.push_value_to_stack(lhs * rhs);
+                    if self.checked {
+                        lhs.check_coercion(&rhs, false)?;
+                    }
+                    frame.push_value_to_stack((lhs * rhs)?);
                     self.ip += 1;
                 }
 
                 Instruction::Divide => {
                     let (lhs, rhs) = frame.pop_2_values_from_stack();
-                    frame.push_value_to_stack(lhs / rhs);
+                    if self.checked {
+                        lhs.check_coercion(&rhs, true)?;
+                    }
+                    frame.push_value_to_stack((lhs / rhs)?);
+                    self.ip += 1;
+                }
+
+                Instruction::FloorDivide => {
+                    let (lhs, rhs) = frame.pop_2_values_from_stack();
+                    if self.checked {
+                        lhs.check_coercion(&rhs, false)?;
+                    }
+                    frame.push_value_to_stack(lhs.floor_div(rhs)?);
                     self.ip += 1;
                 }
 
                 Instruction::Pow => {
-                    // todo: implement
+                    let (lhs, rhs) = frame.pop_2_values_from_stack();
+                    if self.checked {
+                        lhs.check_coercion(&rhs, matches!(rhs, Value::Integer(power) if power < 0))?;
+                    }
+                    frame.push_value_to_stack(lhs.pow(rhs)?);
                     self.ip += 1;
                 }
 
@@ -408,37 +1474,61 @@ impl VM {
 
                 Instruction::Equal => {
                     let (lhs, rhs) = frame.pop_2_values_from_stack();
+                    if self.checked {
+                        lhs.check_comparable(&rhs)?;
+                    }
                     frame.push_value_to_stack(Value::Bool(lhs == rhs));
                     self.ip += 1;
                 }
 
                 Instruction::NotEqual => {
                     let (lhs, rhs) = frame.pop_2_values_from_stack();
+                    if self.checked {
+                        lhs.check_comparable(&rhs)?;
+                    }
                     frame.push_value_to_stack(Value::Bool(lhs != rhs));
                     self.ip += 1;
                 }
 
+                Instruction::Identical => {
+                    let (lhs, rhs) = frame.pop_2_values_from_stack();
+                    frame.push_value_to_stack(Value::Bool(lhs.identical(&rhs)));
+                    self.ip += 1;
+                }
+
                 Instruction::LessThan => {
                     let (lhs, rhs) = frame.pop_2_values_from_stack();
-                    frame.push_value_to_stack(Value::Bool(lhs < rhs));
+                    if self.checked {
+                        lhs.check_comparable(&rhs)?;
+                    }
+                    frame.push_value_to_stack(Value::Bool(lhs.compare(&rhs)?.is_lt()));
                     self.ip += 1;
                 }
 
                 Instruction::LessThanOrEqual => {
                     let (lhs, rhs) = frame.pop_2_values_from_stack();
-                    frame.push_value_to_stack(Value::Bool(lhs <= rhs));
+                    if self.checked {
+                        lhs.check_comparable(&rhs)?;
+                    }
+                    frame.push_value_to_stack(Value::Bool(lhs.compare(&rhs)?.is_le()));
                     self.ip += 1;
                 }
 
                 Instruction::GreaterThan => {
                     let (lhs, rhs) = frame.pop_2_values_from_stack();
-                    frame.push_value_to_stack(Value::Bool(lhs > rhs));
+                    if self.checked {
+                        lhs.check_comparable(&rhs)?;
+                    }
+                    frame.push_value_to_stack(Value::Bool(lhs.compare(&rhs)?.is_gt()));
                     self.ip += 1;
                 }
 
                 Instruction::GreaterThanOrEqual => {
                     let (lhs, rhs) = frame.pop_2_values_from_stack();
-                    frame.push_value_to_stack(Value::Bool(lhs >= rhs));
+                    if self.checked {
+                        lhs.check_comparable(&rhs)?;
+                    }
+                    frame.push_value_to_stack(Value::Bool(lhs.compare(&rhs)?.is_ge()));
                     self.ip += 1;
                 }
 
@@ -447,11 +1537,9 @@ impl VM {
 
                 Instruction::Halt(msg) => {
                     info!("{}", msg);
-                    break;
+                    return Ok(Value::Null);
                 }
 
-                _ => unreachable!("unknown instruction {:?}", instruction)
-
             }
 
             if self.ip == self.instructions.len() {
@@ -467,4 +1555,158 @@ impl VM {
 
     }
 
-}
\ No newline at end of file
+    // take a frame from the pool, or create one if the pool is empty
+    // suspended call of a generator function that has not started
+    fn new_generator(pool: &mut Vec<Frame>, name: String, position: usize, args: Args, metadata: &FunctionMetadata) -> Value {
+        let frame = VM::new_frame(pool, name, None, args, metadata);
+        Value::Generator(Rc::new(RefCell::new(Generator::new(frame, position))))
+    }
+
+    // continue a generator until it yields or returns, returning false if it had already finished
+    fn resume_generator(&mut self, generator: &Rc<RefCell<Generator>>, iterating: bool) -> Result<bool, RuntimeError> {
+        let Some((mut resumed, position)) = generator.borrow_mut().resume(self.ip + 1, iterating) else {
+            return Ok(false);
+        };
+        resumed.set_generator(generator.clone());
+
+        self.sandbox.check_call_depth(self.frames.len() + 1)?;
+        self.memory += frame_size(&resumed.get_metadata());
+        self.sandbox.check_memory(self.memory)?;
+        self.profile_enter(resumed.get_name());
+        self.frames.push(resumed);
+
+        trace!("ip jumping from {} to {}", self.ip, position);
+        self.ip = position;
+        Ok(true)
+    }
+
+    // run a coroutine above the frame that resumed it until it yields or returns. the value is
+    // passed to the function when it starts, and is what yield gives when it carries on
+    fn resume_coroutine(&mut self, coroutine: &Rc<RefCell<Coroutine>>, value: Value) -> Result<(), RuntimeError> {
+        let return_position = self.ip + 1;
+        let base = self.frames.len();
+
+        match coroutine.borrow_mut().resume()? {
+            Resume::Start(function, this) => {
+                let position = *self.functions.get(function.as_str()).ok_or_else(|| RuntimeError::UnknownFunction(function.clone()))?;
+                let metadata = self.metadata.get(function.as_str()).expect("function metadata should exist");
+                if metadata.generator {
+                    return Err(RuntimeError::TypeError(format!("coroutine can not run generator {}", function)));
+                }
+                let started = VM::new_frame(&mut self.frame_pool, format!("{}[{}]", function, base), Some(return_position), Args::from(vec![this, value]), metadata);
+                self.sandbox.check_call_depth(self.frames.len() + 1)?;
+                self.memory += frame_size(&started.get_metadata());
+                self.sandbox.check_memory(self.memory)?;
+                self.profile_enter(&function);
+                self.frames.push(started);
+                self.ip = position;
+            }
+            Resume::Continue(mut frames, position) => {
+                frames[0].set_return_position(return_position);
+                self.sandbox.check_call_depth(self.frames.len() + frames.len())?;
+                for resumed in frames {
+                    self.memory += frame_size(&resumed.get_metadata());
+                    self.profile_enter(resumed.get_name());
+                    self.frames.push(resumed);
+                }
+                self.sandbox.check_memory(self.memory)?;
+                self.frames.last_mut().expect("frame should be on the stack").push_value_to_stack(value);
+                self.ip = position;
+            }
+        }
+
+        trace!("resumed coroutine at {}", self.ip);
+        self.coroutines.push((coroutine.clone(), base));
+        Ok(())
+    }
+
+    // suspend the running coroutine with every frame it is in, handing the value to whoever resumed it
+    fn yield_coroutine(&mut self, coroutine: &Rc<RefCell<Coroutine>>, value: Value) -> Result<(), RuntimeError> {
+        let base = match self.coroutines.last() {
+            Some((running, base)) if Rc::ptr_eq(running, coroutine) => *base,
+            _ => return Err(RuntimeError::TypeError(String::from("only the running coroutine can yield")))
+        };
+
+        // a call from the vm back into the script, such as sort_by, can not be left part way
+        if self.frames[base + 1..].iter().any(|frame| frame.get_return_position().is_none()) {
+            return Err(RuntimeError::TypeError(String::from("can not yield from a function called by the vm")));
+        }
+
+        self.coroutines.pop();
+        let frames = self.frames.split_off(base);
+        for _ in frames.iter() {
+            self.profile_exit();
+        }
+        self.memory = self.memory.saturating_sub(frames.iter().map(|frame| frame_size(&frame.get_metadata())).sum());
+        let return_position = frames[0].get_return_position().expect("return position should be set");
+        coroutine.borrow_mut().suspend(frames, self.ip + 1);
+
+        trace!("ip jumping from {} to {}", self.ip, return_position);
+        self.ip = return_position;
+        self.frames.last_mut().expect("frame should be on the stack").push_value_to_stack(value);
+        Ok(())
+    }
+
+    // coroutines whose first frame has gone, by returning or by an error, have finished
+    fn finish_coroutines(&mut self) {
+        while let Some((coroutine, _)) = self.coroutines.pop_if(|(_, base)| *base >= self.frames.len()) {
+            coroutine.borrow_mut().finish();
+        }
+    }
+
+    // sort an array in place by the key a function gives for each item. the function is called
+    // once for each item, and items with equal keys keep their order
+    fn sort_by(&mut self, items: &Rc<RefCell<Vec<Value>>>, args: &[Value]) -> Result<Value, RuntimeError> {
+        let [Value::FunctionRef(key)] = args else {
+            return Err(RuntimeError::TypeError(format!("{} expects a function", builtin::ARRAY_SORT_BY_METHOD_NAME)));
+        };
+        let position = *self.functions.get(key.as_str()).ok_or_else(|| RuntimeError::UnknownFunction(key.to_string()))?;
+
+        // the function runs in frames above the caller, which carries on from where it was
+        let ip = self.ip;
+        let values = items.borrow().clone();
+        let mut keyed = Vec::with_capacity(values.len());
+        for item in values {
+            let result = self.call_function(key, position, vec![item.clone()]);
+            self.ip = ip;
+            keyed.push((result?, item));
+        }
+
+        let mut error = None;
+        keyed.sort_by(|(a, _), (b, _)| a.compare(b).unwrap_or_else(|e| {
+            error.get_or_insert(e);
+            core::cmp::Ordering::Equal
+        }));
+        if let Some(error) = error {
+            return Err(error);
+        }
+
+        *items.borrow_mut() = keyed.into_iter().map(|(_, item)| item).collect();
+        Ok(Value::Array(items.clone()))
+    }
+
+    // drop the frame on top, keeping it for reuse
+    fn discard_frame(&mut self) {
+        let mut frame = self.frames.pop().expect("frame should be on the stack");
+        frame.release();
+        self.memory = self.memory.saturating_sub(frame_size(&frame.get_metadata()));
+        self.profile_exit();
+        self.frame_pool.push(frame);
+        self.finish_coroutines();
+    }
+
+    fn new_frame(pool: &mut Vec<Frame>, name: String, return_position: Option<usize>, args: Args, metadata: &FunctionMetadata) -> Frame {
+        let mut frame = pool.pop().unwrap_or_else(|| Frame::new(String::new(), None, None));
+        frame.reset(name, return_position, args, metadata);
+        frame
+    }
+
+}
+
+// add two values, where strict mode does not allow mixing strings with other types
+fn add(strict: bool, lhs: Value, rhs: Value) -> Result<Value, RuntimeError> {
+    if strict && lhs.is_number() != rhs.is_number() && matches!((&lhs, &rhs), (Value::String(_), _) | (_, Value::String(_))) {
+        return Err(RuntimeError::TypeError(format!("strict mode does not allow adding {} and {}", lhs.type_name(), rhs.type_name())));
+    }
+    lhs + rhs
+}
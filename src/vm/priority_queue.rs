@@ -0,0 +1,127 @@
+use alloc::collections::BinaryHeap;
+use core::cmp::Ordering;
+
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
+use crate::vm::value::Value;
+
+// PriorityQueue
+//
+// items taken lowest priority first, as pathfinding and schedulers want, without sorting an array
+// every time one is added. items with the same priority come out in the order they went in, so a
+// script gives the same results every run
+#[derive(Clone, Debug, Default)]
+pub struct PriorityQueue {
+    entries: BinaryHeap<Entry>,
+    // counts the items ever added, to order those with the same priority
+    added: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    priority: Value,
+    order: usize,
+    item: Value,
+}
+
+impl PriorityQueue {
+
+    pub fn new() -> Self {
+        PriorityQueue::default()
+    }
+
+    // priorities have to be numbers that can be ordered, which leaves out NaN
+    pub fn push(&mut self, item: Value, priority: Value) -> Result<(), RuntimeError> {
+        if !matches!(priority, Value::Integer(_) | Value::Float(_)) || priority.partial_cmp(&priority).is_none() {
+            return Err(RuntimeError::TypeError(format!("can not use {} as a priority", priority.type_name())));
+        }
+        self.entries.push(Entry { priority, order: self.added, item });
+        self.added += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<Value> {
+        self.entries.pop().map(|entry| entry.item)
+    }
+
+    pub fn peek(&self) -> Option<&Value> {
+        self.entries.peek().map(|entry| &entry.item)
+    }
+
+    pub fn peek_priority(&self) -> Option<&Value> {
+        self.entries.peek().map(|entry| &entry.priority)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    // items and their priorities in the order they would be taken
+    pub fn entries(&self) -> Vec<(Value, Value)> {
+        let mut entries = self.entries.clone().into_sorted_vec();
+        entries.reverse();
+        entries.into_iter().map(|entry| (entry.item, entry.priority)).collect()
+    }
+
+}
+
+// the heap takes the greatest entry first, so the lowest priority and then the earliest added is the greatest
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.priority.partial_cmp(&self.priority).unwrap_or(Ordering::Equal).then_with(|| other.order.cmp(&self.order))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::priority_queue::PriorityQueue;
+    use crate::vm::value::Value;
+
+    #[test]
+    fn test_lowest_priority_first() {
+        let mut queue = PriorityQueue::new();
+        queue.push(Value::from("far"), Value::Integer(9)).unwrap();
+        queue.push(Value::from("near"), Value::Float(1.5)).unwrap();
+        queue.push(Value::from("next"), Value::Integer(3)).unwrap();
+        queue.push(Value::from("tied"), Value::Integer(3)).unwrap();
+
+        assert_eq!(queue.peek_priority(), Some(&Value::Float(1.5)));
+        assert_eq!(queue.entries().into_iter().map(|(item, _)| item).collect::<Vec<_>>(), vec![Value::from("near"), Value::from("next"), Value::from("tied"), Value::from("far")]);
+        assert_eq!(queue.pop(), Some(Value::from("near")));
+        assert_eq!(queue.pop(), Some(Value::from("next")));
+        assert_eq!(queue.pop(), Some(Value::from("tied")));
+        assert_eq!(queue.pop(), Some(Value::from("far")));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn test_invalid_priority() {
+        let mut queue = PriorityQueue::new();
+        assert!(queue.push(Value::Null, Value::from("high")).is_err());
+        assert!(queue.push(Value::Null, Value::Float(f32::NAN)).is_err());
+        assert!(queue.is_empty());
+    }
+
+}
@@ -0,0 +1,132 @@
+use core::fmt::Write;
+use std::time::{Duration, Instant};
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+
+// time spent in a script function
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FunctionProfile {
+    pub calls: usize,
+    // time from entering the function until it returns
+    pub inclusive: Duration,
+    // inclusive time minus the time spent in functions it called
+    pub exclusive: Duration,
+}
+
+struct ProfileFrame {
+    name: String,
+    started: Instant,
+    children: Duration,
+}
+
+// Profiler
+//
+// records the time spent in each function as frames are entered and left
+#[derive(Default)]
+pub struct Profiler {
+    stack: Vec<ProfileFrame>,
+    functions: HashMap<String, FunctionProfile>,
+    folded: HashMap<String, Duration>,
+}
+
+impl Profiler {
+
+    pub fn enter(&mut self, name: &str) {
+        self.stack.push(ProfileFrame {
+            name: name.to_string(),
+            started: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    pub fn exit(&mut self) {
+        let Some(frame) = self.stack.pop() else { return };
+        let inclusive = frame.started.elapsed();
+        let exclusive = inclusive.saturating_sub(frame.children);
+
+        // the path of the stack is recorded before the frame is removed from it
+        let mut path: Vec<&str> = self.stack.iter().map(|f| f.name.as_str()).collect();
+        path.push(&frame.name);
+        *self.folded.entry(path.join(";")).or_default() += exclusive;
+
+        // only the outermost call of a recursive function counts towards inclusive time
+        let recursive = self.stack.iter().any(|f| f.name == frame.name);
+        let profile = self.functions.entry(frame.name).or_default();
+        profile.calls += 1;
+        profile.exclusive += exclusive;
+        if !recursive {
+            profile.inclusive += inclusive;
+        }
+
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += inclusive;
+        }
+    }
+
+    // get the time spent in each function
+    pub fn functions(&self) -> &HashMap<String, FunctionProfile> {
+        &self.functions
+    }
+
+    // export exclusive time in microseconds per stack in the folded format read by inferno and flamegraph.pl
+    pub fn to_folded(&self) -> String {
+        let mut stacks: Vec<(&String, &Duration)> = self.folded.iter().collect();
+        stacks.sort();
+
+        let mut output = String::new();
+        for (stack, time) in stacks {
+            writeln!(output, "{} {}", stack, time.as_micros()).expect("writing to a string should not fail");
+        }
+        output
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::profiler::Profiler;
+
+    #[test]
+    fn test_nested_calls() {
+        let mut profiler = Profiler::default();
+        profiler.enter("Test.main");
+        profiler.enter("Test.add");
+        profiler.exit();
+        profiler.enter("Test.add");
+        profiler.exit();
+        profiler.exit();
+
+        let main = &profiler.functions()["Test.main"];
+        let add = &profiler.functions()["Test.add"];
+        assert_eq!(main.calls, 1);
+        assert_eq!(add.calls, 2);
+        assert!(main.inclusive >= add.inclusive);
+        assert_eq!(main.exclusive, main.inclusive - add.inclusive);
+
+        let folded = profiler.to_folded();
+        let stacks: Vec<&str> = folded.lines().map(|l| l.rsplit_once(' ').unwrap().0).collect();
+        assert_eq!(stacks, vec!["Test.main", "Test.main;Test.add"]);
+    }
+
+    #[test]
+    fn test_recursive_calls() {
+        let mut profiler = Profiler::default();
+        profiler.enter("Test.fib");
+        profiler.enter("Test.fib");
+        profiler.exit();
+        profiler.exit();
+
+        let fib = &profiler.functions()["Test.fib"];
+        assert_eq!(fib.calls, 2);
+        assert!(fib.inclusive >= fib.exclusive);
+    }
+
+    #[test]
+    fn test_exit_without_enter() {
+        let mut profiler = Profiler::default();
+        profiler.exit();
+        assert!(profiler.functions().is_empty());
+    }
+
+}
@@ -1,12 +1,82 @@
-use std::collections::HashMap;
-use crate::vm::instruction::Instruction;
+use core::ops::Range;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+use crate::vm::error::{LinkError, RuntimeError};
+use crate::vm::hash::sha256;
+use crate::vm::instruction::{stack_depths_from, Instruction};
 use crate::vm::value::Value;
 
+// the most local slots and stack values a function in bytecode may ask for, as a frame
+// allocates them all when it is called
+pub const MAX_LOCAL_SLOTS: usize = u16::MAX as usize;
+pub const MAX_STACK_DEPTH: usize = u16::MAX as usize;
+
+// Function Metadata
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FunctionMetadata {
+    pub local_slots: usize,
+    pub max_stack_depth: usize,
+    // calls return a generator instead of running the body
+    pub generator: bool,
+    // where the deferred statements start, counted from the start of the function
+    pub cleanup: Option<usize>,
+}
+
+// Build Metadata
+//
+// how a program was compiled, so a host can tell when bytecode is stale or came from another
+// version of the crate
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BuildMetadata {
+    // version of the crate that compiled the program, empty for bytecode older than 1.6
+    pub compiler_version: String,
+    // seconds since the unix epoch, only recorded when the compiler is given the time
+    pub compiled_at: Option<u64>,
+    // sha-256 of the script and of each file it imports, by path
+    pub sources: HashMap<String, String>,
+    // 0 when instructions are left as they were written, 1 when common sequences are fused
+    pub optimization_level: u8,
+}
+
+impl BuildMetadata {
+
+    pub(crate) fn add_source(&mut self, path: &str, source: &str) {
+        self.sources.insert(path.to_string(), sha256(source.as_bytes()));
+    }
+
+    // the file was compiled from exactly this source
+    pub fn source_matches(&self, path: &str, source: &str) -> bool {
+        self.sources.get(path).is_some_and(|hash| *hash == sha256(source.as_bytes()))
+    }
+
+    // compiled by the version of the crate that is running it
+    pub fn is_current_compiler(&self) -> bool {
+        self.compiler_version == env!("CARGO_PKG_VERSION")
+    }
+
+}
+
 // Program
+#[derive(Clone, Default)]
 pub struct Program {
     pub instructions: Vec<Instruction>,
     pub symbols: HashMap<String, usize>,
+    pub metadata: HashMap<String, FunctionMetadata>,
     pub globals: Vec<Value>,
+    pub global_symbols: HashMap<String, usize>,
+    pub docs: HashMap<String, String>,
+    // parameter names of each function, so hosts can pass arguments by name
+    pub params: HashMap<String, Vec<String>>,
+    // attributes such as @export written before each class member
+    pub attributes: HashMap<String, Vec<String>>,
+    // name of each local slot of each function, so debuggers can show variables
+    pub locals: HashMap<String, Vec<String>>,
+    pub strict: bool,
+    // arithmetic fails instead of losing precision or comparing with nan
+    pub checked: bool,
+    // how the program was compiled
+    pub build: BuildMetadata,
 }
 
 impl Program {
@@ -15,18 +85,260 @@ impl Program {
         Program {
             instructions: vec![],
             symbols: HashMap::new(),
+            metadata: HashMap::new(),
             globals: vec![],
+            global_symbols: HashMap::new(),
+            docs: HashMap::new(),
+            params: HashMap::new(),
+            attributes: HashMap::new(),
+            locals: HashMap::new(),
+            strict: false,
+            checked: false,
+            build: BuildMetadata::default(),
         }
     }
 
+    // how the program was compiled, such as the hashes of its sources
+    pub fn metadata(&self) -> &BuildMetadata {
+        &self.build
+    }
+
     // insert into globals and return index
     pub fn insert_global(&mut self, value: Value) -> usize {
         self.globals.push(value);
         self.globals.len() - 1
     }
 
+    // the members with an attribute, such as the functions marked @export, in name order
+    pub fn members_with(&self, attribute: &str) -> Vec<String> {
+        let mut names: Vec<String> = self.attributes.iter()
+            .filter(|(_, attributes)| attributes.iter().any(|a| a == attribute))
+            .map(|(name, _)| name.to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
     pub fn insert_into_symbols(&mut self, name: String, index: usize) {
         self.symbols.insert(name, index);
     }
 
-}
\ No newline at end of file
+    // append another program, moving its code after ours and its globals after our globals
+    //
+    // both programs must have been compiled separately, so a program that includes the
+    // standard library can only be linked with programs compiled with no_std
+    pub fn link(&mut self, other: Program) -> Result<(), LinkError> {
+
+        // check for collisions before anything is changed, naming the first by name so the error is always the same
+        if let Some(name) = other.symbols.keys().filter(|name| self.symbols.contains_key(*name)).min() {
+            return Err(LinkError::DuplicateSymbol(name.clone()));
+        }
+        if let Some(name) = other.global_symbols.keys().filter(|name| self.global_symbols.contains_key(*name)).min() {
+            return Err(LinkError::DuplicateGlobal(name.clone()));
+        }
+
+        let offset = self.instructions.len();
+        let global_offset = self.globals.len();
+
+        // move jump targets and global indexes
+        self.instructions.extend(other.instructions.into_iter().map(|mut ins| {
+            ins.relocate(offset);
+            if let Instruction::LoadGlobal(idx) | Instruction::StoreGlobal(idx) = &mut ins {
+                *idx += global_offset;
+            }
+            ins
+        }));
+        self.symbols.extend(other.symbols.into_iter().map(|(name, position)| (name, position + offset)));
+        self.global_symbols.extend(other.global_symbols.into_iter().map(|(name, idx)| (name, idx + global_offset)));
+
+        self.globals.extend(other.globals);
+        self.metadata.extend(other.metadata);
+        self.docs.extend(other.docs);
+        self.params.extend(other.params);
+        self.attributes.extend(other.attributes);
+        self.locals.extend(other.locals);
+        self.strict |= other.strict;
+        self.checked |= other.checked;
+
+        // both were compiled from a main script, which keeps the hash of ours
+        for (path, hash) in other.build.sources {
+            self.build.sources.entry(path).or_insert(hash);
+        }
+
+        Ok(())
+    }
+
+    // each function and the instructions it runs, up to the start of the next one. functions
+    // are in the order they are laid out, so the same program is always walked the same way.
+    // ranges never reach past the instructions, even for bytecode whose symbols do
+    pub(crate) fn functions(&self) -> Vec<(&String, Range<usize>)> {
+        let len = self.instructions.len();
+        let mut starts: Vec<usize> = self.symbols.values().copied().collect();
+        starts.sort_unstable();
+        starts.dedup();
+
+        let mut functions: Vec<(&String, usize)> = self.symbols.iter().map(|(name, start)| (name, *start)).collect();
+        functions.sort_by_key(|(name, start)| (*start, *name));
+        functions.into_iter().map(|(name, start)| {
+            let start = start.min(len);
+            let end = starts.iter().copied().find(|position| *position > start).unwrap_or(len);
+            (name, start..end.min(len))
+        }).collect()
+    }
+
+    // check that bytecode which did not come from the compiler can be run without the vm panicking
+    //
+    // every function must keep its stack balanced within its metadata, stay inside its own
+    // instructions and only use the local slots and globals that exist
+    pub fn verify(&self) -> Result<(), RuntimeError> {
+        let invalid = |name: &str, reason: String| RuntimeError::InvalidProgram(format!("{}: {}", name, reason));
+
+        for (name, index) in &self.global_symbols {
+            if *index >= self.globals.len() {
+                return Err(invalid(name, format!("global symbol is {} of {}", index, self.globals.len())));
+            }
+        }
+
+        for (name, range) in self.functions() {
+            let start = self.symbols[name];
+            let metadata = self.metadata.get(name).ok_or_else(|| invalid(name, String::from("function has no metadata")))?;
+            if start >= self.instructions.len() {
+                return Err(invalid(name, format!("function starts at {} past the end of the program", start)));
+            }
+            if metadata.local_slots == 0 {
+                return Err(invalid(name, String::from("function has no slot for 'this'")));
+            }
+            if metadata.local_slots > MAX_LOCAL_SLOTS || metadata.max_stack_depth > MAX_STACK_DEPTH {
+                return Err(invalid(name, format!("function asks for {} slots and {} stack values, more than {} and {}", metadata.local_slots, metadata.max_stack_depth, MAX_LOCAL_SLOTS, MAX_STACK_DEPTH)));
+            }
+
+            let instructions = &self.instructions[range];
+            let entries = match metadata.cleanup {
+                Some(cleanup) if cleanup >= instructions.len() => return Err(invalid(name, format!("deferred statements start at {} past the end of the function", start + cleanup))),
+                Some(cleanup) => vec![0, cleanup],
+                None => vec![0]
+            };
+            let depths = stack_depths_from(instructions, start, &entries).map_err(|reason| invalid(name, reason))?;
+
+            for ((index, instruction), depth) in instructions.iter().enumerate().zip(depths) {
+                let Some(depth) = depth else { continue };
+                let position = start + index;
+
+                let (pops, pushes) = instruction.stack_effect();
+                if depth.max(depth - pops + pushes) > metadata.max_stack_depth {
+                    return Err(invalid(name, format!("instruction {} goes deeper than {} values on the stack", position, metadata.max_stack_depth)));
+                }
+                if let Some(slot) = local_slots(instruction).into_iter().find(|slot| *slot >= metadata.local_slots) {
+                    return Err(invalid(name, format!("instruction {} uses slot {} of {}", position, slot, metadata.local_slots)));
+                }
+                if let Instruction::LoadGlobal(index) | Instruction::StoreGlobal(index) = instruction {
+                    if *index >= self.globals.len() {
+                        return Err(invalid(name, format!("instruction {} uses global {} of {}", position, index, self.globals.len())));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+}
+
+// local slots read or written by an instruction
+fn local_slots(instruction: &Instruction) -> Vec<usize> {
+    match instruction {
+        Instruction::MoveToLocalVariable(slot)
+        | Instruction::CopyToLocalVariable(slot)
+        | Instruction::LoadLocalVariable(slot)
+        | Instruction::IncrementLocal(slot, _)
+        | Instruction::CompareLocalConstJump(_, slot, _, _) => vec![*slot],
+        Instruction::CompareLocalsJump(_, lhs, rhs, _) => vec![*lhs, *rhs],
+        Instruction::AddLocals(lhs, rhs, dst) => vec![*lhs, *rhs, *dst],
+        _ => vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::{compile_with_options, CompilerOptions};
+    use crate::vm::error::{LinkError, RuntimeError};
+    use crate::vm::instruction::Instruction;
+    use crate::vm::program::{FunctionMetadata, Program};
+    use crate::vm::value::Value;
+    use crate::vm::VM;
+
+    fn compile(source: &str) -> Program {
+        compile_with_options(source, CompilerOptions { no_std: true, ..Default::default() }).unwrap()
+    }
+
+    #[test]
+    fn test_link() {
+        let mut program = compile("class A { function main() { if 1 > 2 { return 1; } return 2; } }");
+        program.link(compile("class Counter { var n = 5; } class B { function main() { var c = new Counter(); if c.n > 2 { return c.n; } return 0; } }")).unwrap();
+
+        let mut vm = VM::new(program);
+        assert_eq!(vm.exec("A.main", None).unwrap(), Value::Integer(2));
+        assert_eq!(vm.exec("B.main", None).unwrap(), Value::Integer(5));
+    }
+
+    #[test]
+    fn test_link_collision() {
+        let mut program = compile("class A { function main() { } }");
+        assert_eq!(program.link(compile("class A { function main() { } }")), Err(LinkError::DuplicateSymbol("A.constructor".to_string())));
+        assert_eq!(program.link(compile("class A { }")), Err(LinkError::DuplicateSymbol("A.constructor".to_string())));
+        assert_eq!(program.symbols.len(), 2);
+    }
+
+    // a single function program with room for this and one local
+    fn program(instructions: Vec<Instruction>, max_stack_depth: usize) -> Program {
+        let mut program = Program::new();
+        program.instructions = instructions;
+        program.insert_into_symbols(String::from("A.main"), 0);
+        program.metadata.insert(String::from("A.main"), FunctionMetadata { local_slots: 2, max_stack_depth, ..Default::default() });
+        program
+    }
+
+    #[test]
+    fn test_verify_compiled() {
+        let program = compile_with_options("class A { function main() { var t = 0; for (var i = 0; i < 10; i = i + 1) { t = t + i; } return t; } }", CompilerOptions::default()).unwrap();
+        assert_eq!(program.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_verify_invalid() {
+        let valid = vec![Instruction::StackPush(Value::Integer(1)), Instruction::MoveToLocalVariable(1), Instruction::Return(false)];
+        assert_eq!(program(valid.clone(), 1).verify(), Ok(()));
+
+        let invalid = [
+            program(valid, 0),
+            program(vec![Instruction::LoadLocalVariable(2), Instruction::Return(true)], 1),
+            program(vec![Instruction::LoadGlobal(0), Instruction::Return(true)], 1),
+            program(vec![Instruction::Add, Instruction::Return(true)], 2),
+            program(vec![Instruction::Jump(5)], 0),
+            program(vec![Instruction::StackPush(Value::Null)], 1),
+        ];
+        for program in invalid {
+            assert!(matches!(program.verify(), Err(RuntimeError::InvalidProgram(_))));
+        }
+
+        // a symbol past the end cuts the function before it short of where it says it ends
+        let mut past_end = program(vec![Instruction::Return(false)], 0);
+        past_end.insert_into_symbols(String::from("A.other"), 100);
+        past_end.metadata.insert(String::from("A.other"), FunctionMetadata { local_slots: 1, ..Default::default() });
+        assert!(matches!(past_end.verify(), Err(RuntimeError::InvalidProgram(reason)) if reason.contains("past the end")));
+
+        let mut huge = program(vec![Instruction::Return(false)], usize::MAX);
+        assert!(matches!(huge.verify(), Err(RuntimeError::InvalidProgram(_))));
+        huge.metadata.insert(String::from("A.main"), FunctionMetadata { local_slots: usize::MAX, ..Default::default() });
+        assert!(matches!(huge.verify(), Err(RuntimeError::InvalidProgram(_))));
+
+        let mut dangling = program(vec![Instruction::Return(false)], 0);
+        dangling.global_symbols.insert(String::from("A"), 0);
+        assert!(matches!(dangling.verify(), Err(RuntimeError::InvalidProgram(reason)) if reason.contains("global symbol")));
+
+        let mut missing = Program::new();
+        missing.insert_into_symbols(String::from("A.main"), 0);
+        assert!(matches!(missing.verify(), Err(RuntimeError::InvalidProgram(_))));
+    }
+
+}
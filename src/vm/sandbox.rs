@@ -0,0 +1,182 @@
+use core::mem::size_of;
+
+use crate::prelude::*;
+use crate::vm::builtin::{BIGINT_MODULE_NAME, COMPARE_FUNCTION_NAME, COROUTINE_FUNCTION_NAME, CSV_MODULE_NAME, DECIMAL_MODULE_NAME, DEQUE_MODULE_NAME, EXPECT_MODULE_NAME, FORMAT_FUNCTION_NAME, FORMAT_NUMBER_FUNCTION_NAME, FROM_CHAR_CODE_FUNCTION_NAME, GET_PATH_FUNCTION_NAME, HASH_FUNCTION_NAME, HASH_MODULE_NAME, ID_FUNCTION_NAME, LOG_MODULE_NAME, PRIORITY_QUEUE_MODULE_NAME, SET_MODULE_NAME, STRING_BUILDER_CLASS_NAME, TIMER_MODULE_NAME, TOML_MODULE_NAME, UUID_MODULE_NAME, WEAKREF_FUNCTION_NAME, YAML_MODULE_NAME};
+use crate::vm::error::RuntimeError;
+use crate::vm::program::FunctionMetadata;
+use crate::vm::value::Value;
+
+// Sandbox
+//
+// limits applied to a vm for each call made from the host, None means unlimited
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sandbox {
+    pub max_instructions: Option<usize>,
+    pub max_memory: Option<usize>,
+    pub max_call_depth: Option<usize>,
+    pub allowed_builtins: Option<Vec<String>>,
+}
+
+impl Sandbox {
+
+    // for scripts that are not trusted, such as plugins from users
+    pub fn strict() -> Self {
+        Sandbox {
+            max_instructions: Some(1_000_000),
+            max_memory: Some(16 * 1024 * 1024),
+            max_call_depth: Some(64),
+            allowed_builtins: Some(vec![
+                STRING_BUILDER_CLASS_NAME.to_string(),
+                FORMAT_FUNCTION_NAME.to_string(),
+                FORMAT_NUMBER_FUNCTION_NAME.to_string(),
+                GET_PATH_FUNCTION_NAME.to_string(),
+                FROM_CHAR_CODE_FUNCTION_NAME.to_string(),
+                COMPARE_FUNCTION_NAME.to_string(),
+                ID_FUNCTION_NAME.to_string(),
+                HASH_FUNCTION_NAME.to_string(),
+                WEAKREF_FUNCTION_NAME.to_string(),
+                COROUTINE_FUNCTION_NAME.to_string(),
+                CSV_MODULE_NAME.to_string(),
+                SET_MODULE_NAME.to_string(),
+                DEQUE_MODULE_NAME.to_string(),
+                PRIORITY_QUEUE_MODULE_NAME.to_string(),
+                TIMER_MODULE_NAME.to_string(),
+                LOG_MODULE_NAME.to_string(),
+                EXPECT_MODULE_NAME.to_string(),
+                HASH_MODULE_NAME.to_string(),
+                UUID_MODULE_NAME.to_string(),
+                TOML_MODULE_NAME.to_string(),
+                YAML_MODULE_NAME.to_string(),
+                BIGINT_MODULE_NAME.to_string(),
+                DECIMAL_MODULE_NAME.to_string(),
+            ]),
+        }
+    }
+
+    // for a single expression from a user, such as a spreadsheet formula, which only needs the
+    // builtins that work on values
+    pub fn expression() -> Self {
+        Sandbox {
+            max_instructions: Some(10_000),
+            max_memory: Some(1024 * 1024),
+            max_call_depth: Some(16),
+            allowed_builtins: Some(vec![
+                FORMAT_FUNCTION_NAME.to_string(),
+                FORMAT_NUMBER_FUNCTION_NAME.to_string(),
+                GET_PATH_FUNCTION_NAME.to_string(),
+                FROM_CHAR_CODE_FUNCTION_NAME.to_string(),
+                COMPARE_FUNCTION_NAME.to_string(),
+                ID_FUNCTION_NAME.to_string(),
+                HASH_FUNCTION_NAME.to_string(),
+                SET_MODULE_NAME.to_string(),
+                DEQUE_MODULE_NAME.to_string(),
+                PRIORITY_QUEUE_MODULE_NAME.to_string(),
+                BIGINT_MODULE_NAME.to_string(),
+                DECIMAL_MODULE_NAME.to_string(),
+            ]),
+        }
+    }
+
+    // for scripts written by the embedder
+    pub fn trusted() -> Self {
+        Sandbox::default()
+    }
+
+    pub fn check_instructions(&self, executed: usize) -> Result<(), RuntimeError> {
+        match self.max_instructions {
+            Some(limit) if executed > limit => Err(RuntimeError::InstructionLimitExceeded(limit)),
+            _ => Ok(())
+        }
+    }
+
+    pub fn check_memory(&self, used: usize) -> Result<(), RuntimeError> {
+        match self.max_memory {
+            Some(limit) if used > limit => Err(RuntimeError::MemoryLimitExceeded(limit)),
+            _ => Ok(())
+        }
+    }
+
+    // count a string made by the script against the memory limit, so joining a string to itself
+    // over and over can not outgrow it. strings are counted when they are made, even if they are
+    // soon dropped, as memory is only counted up during a call
+    pub fn charge_string(&self, used: &mut usize, value: &Value) -> Result<(), RuntimeError> {
+        match value {
+            Value::String(text) => self.charge(used, text.len()),
+            _ => Ok(())
+        }
+    }
+
+    // count bytes the script has made outside a string value, such as text appended to a builder
+    pub fn charge(&self, used: &mut usize, bytes: usize) -> Result<(), RuntimeError> {
+        *used = used.saturating_add(bytes);
+        self.check_memory(*used)
+    }
+
+    pub fn check_call_depth(&self, depth: usize) -> Result<(), RuntimeError> {
+        match self.max_call_depth {
+            Some(limit) if depth > limit => Err(RuntimeError::CallDepthExceeded(limit)),
+            _ => Ok(())
+        }
+    }
+
+    // allowing a module such as Hash allows each of its functions
+    pub fn check_builtin(&self, name: &str) -> Result<(), RuntimeError> {
+        let module = name.split_once('.').map(|(module, _)| module);
+        match &self.allowed_builtins {
+            Some(allowed) if !allowed.iter().any(|n| n == name || Some(n.as_str()) == module) => Err(RuntimeError::BuiltinNotAllowed(name.to_string())),
+            _ => Ok(())
+        }
+    }
+
+}
+
+// approximate memory held by a frame of a function
+pub fn frame_size(metadata: &FunctionMetadata) -> usize {
+    (metadata.local_slots + metadata.max_stack_depth) * size_of::<Value>()
+}
+
+// approximate memory held by a value added to a collection
+pub fn value_size(value: &Value) -> usize {
+    size_of::<Value>() + match value {
+        Value::String(s) => s.len(),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(num) => num.size(),
+        #[cfg(feature = "decimal")]
+        Value::Decimal(num) => num.size(),
+        _ => 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::builtin::{EVENT_HANDLER_FUNCTION_NAME, STRING_BUILDER_CLASS_NAME};
+    use crate::vm::error::RuntimeError;
+    use crate::vm::sandbox::Sandbox;
+
+    #[test]
+    fn test_trusted_is_unlimited() {
+        let sandbox = Sandbox::trusted();
+        assert!(sandbox.check_instructions(usize::MAX).is_ok());
+        assert!(sandbox.check_memory(usize::MAX).is_ok());
+        assert!(sandbox.check_call_depth(usize::MAX).is_ok());
+        assert!(sandbox.check_builtin(EVENT_HANDLER_FUNCTION_NAME).is_ok());
+    }
+
+    #[test]
+    fn test_strict_limits() {
+        let sandbox = Sandbox::strict();
+        assert!(sandbox.check_call_depth(64).is_ok());
+        assert_eq!(sandbox.check_call_depth(65), Err(RuntimeError::CallDepthExceeded(64)));
+        assert!(sandbox.check_builtin(STRING_BUILDER_CLASS_NAME).is_ok());
+        assert_eq!(sandbox.check_builtin(EVENT_HANDLER_FUNCTION_NAME), Err(RuntimeError::BuiltinNotAllowed(String::from("on"))));
+    }
+
+    #[test]
+    fn test_allowed_module() {
+        let sandbox = Sandbox { allowed_builtins: Some(vec![String::from("Hash")]), ..Sandbox::default() };
+        assert!(sandbox.check_builtin("Hash.sha256").is_ok());
+        assert!(sandbox.check_builtin("Hash").is_ok());
+        assert!(sandbox.check_builtin("Hashes.md5").is_err());
+    }
+
+}
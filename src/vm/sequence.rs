@@ -0,0 +1,103 @@
+use core::fmt;
+use core::fmt::{Debug, Formatter};
+
+use crate::prelude::*;
+use crate::vm::value::Value;
+
+// Sequence
+//
+// items a host hands out one at a time, such as rows streamed from a database, so that a script
+// can loop over them without the host collecting them first. each item is only asked for when
+// the script needs it, so a loop over a sequence with no end is still stopped by the sandbox
+pub struct Sequence {
+    items: Box<dyn Iterator<Item = Value>>,
+    done: bool,
+}
+
+impl Sequence {
+
+    pub fn new(items: impl Iterator<Item = Value> + 'static) -> Self {
+        Sequence { items: Box::new(items), done: false }
+    }
+
+    // items the host hands out a chunk at a time, such as pages of a large dataset. fetch is given
+    // the number of the chunk wanted, from 0, and is only called once the chunk before has been
+    // read. the items end when it gives None or an empty chunk
+    pub fn chunked(mut fetch: impl FnMut(usize) -> Option<Vec<Value>> + 'static) -> Self {
+        Sequence::new((0..).map_while(move |index| fetch(index).filter(|chunk| !chunk.is_empty())).flatten())
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+}
+
+impl Iterator for Sequence {
+    type Item = Value;
+
+    // the next item, the host is not asked again once it has run out
+    fn next(&mut self) -> Option<Value> {
+        if self.done {
+            return None;
+        }
+        let item = self.items.next();
+        self.done = item.is_none();
+        item
+    }
+}
+
+impl Debug for Sequence {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Sequence {{ done: {} }}", self.done)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use crate::vm::sequence::Sequence;
+    use crate::vm::value::Value;
+
+    #[test]
+    fn test_items_are_lazy() {
+        let asked = Rc::new(Cell::new(0));
+        let counter = asked.clone();
+        let mut sequence = Sequence::new((1..=2).map(move |i| {
+            counter.set(counter.get() + 1);
+            Value::Integer(i)
+        }));
+        assert_eq!(asked.get(), 0);
+
+        assert_eq!(sequence.next(), Some(Value::Integer(1)));
+        assert_eq!(asked.get(), 1);
+        assert_eq!(sequence.next(), Some(Value::Integer(2)));
+        assert!(!sequence.is_done());
+        assert_eq!(sequence.next(), None);
+        assert!(sequence.is_done());
+        assert_eq!(sequence.next(), None);
+    }
+
+    #[test]
+    fn test_chunks_are_lazy() {
+        let asked = Rc::new(Cell::new(0));
+        let counter = asked.clone();
+        let mut sequence = Sequence::chunked(move |index| {
+            counter.set(counter.get() + 1);
+            (index < 2).then(|| vec![Value::Integer(index as i32 * 2), Value::Integer(index as i32 * 2 + 1)])
+        });
+        assert_eq!(asked.get(), 0);
+
+        assert_eq!(sequence.next(), Some(Value::Integer(0)));
+        assert_eq!(sequence.next(), Some(Value::Integer(1)));
+        assert_eq!(asked.get(), 1);
+        assert_eq!(sequence.next(), Some(Value::Integer(2)));
+        assert_eq!(asked.get(), 2);
+        assert_eq!(sequence.by_ref().count(), 1);
+        assert_eq!(asked.get(), 3);
+        assert!(sequence.is_done());
+    }
+
+}
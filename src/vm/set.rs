@@ -0,0 +1,178 @@
+use crate::collections::HashSet;
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
+use crate::vm::value::Value;
+
+// Set
+//
+// values without repeats, kept in the order they were first added so that looping over a set or
+// turning it into an array gives the same order every time. only values that are equal exactly
+// when they look the same can be held, so floats, whose 1.0 equals the integer 1, and
+// collections, which can change after being added, are refused
+#[derive(Clone, Debug, Default)]
+pub struct ValueSet {
+    items: Vec<Value>,
+    keys: HashSet<Key>,
+}
+
+// what a value is known by in a set
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Key {
+    Null,
+    Bool(bool),
+    Integer(i32),
+    String(String),
+}
+
+impl Key {
+
+    fn of(value: &Value) -> Option<Key> {
+        match value {
+            Value::Null => Some(Key::Null),
+            Value::Bool(b) => Some(Key::Bool(*b)),
+            Value::Integer(n) => Some(Key::Integer(*n)),
+            Value::String(s) => Some(Key::String(s.to_string())),
+            _ => None
+        }
+    }
+
+    // 32 bit fnv-1a of the type and contents, which is the same on every run and platform
+    fn fnv(&self) -> u32 {
+        let (tag, bytes): (u8, Vec<u8>) = match self {
+            Key::Null => (0, vec![]),
+            Key::Bool(b) => (1, vec![*b as u8]),
+            Key::Integer(n) => (2, n.to_le_bytes().to_vec()),
+            Key::String(s) => (3, s.as_bytes().to_vec()),
+        };
+        core::iter::once(tag).chain(bytes).fold(0x811c9dc5, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193))
+    }
+
+}
+
+// hash of a value that can be held in a set, so equal values always hash the same
+pub fn hash(value: &Value) -> Option<i32> {
+    Key::of(value).map(|key| key.fnv() as i32)
+}
+
+impl ValueSet {
+
+    pub fn new() -> Self {
+        ValueSet::default()
+    }
+
+    // a set of the values, with repeats left out
+    pub fn from_values(values: impl IntoIterator<Item = Value>) -> Result<Self, RuntimeError> {
+        let mut set = ValueSet::new();
+        for value in values {
+            set.add(value)?;
+        }
+        Ok(set)
+    }
+
+    // add a value, giving whether it was not already there
+    pub fn add(&mut self, value: Value) -> Result<bool, RuntimeError> {
+        let key = Key::of(&value).ok_or_else(|| RuntimeError::TypeError(format!("can not add {} to a set", value.type_name())))?;
+        let added = self.keys.insert(key);
+        if added {
+            self.items.push(value);
+        }
+        Ok(added)
+    }
+
+    // remove a value, giving whether it was there
+    pub fn remove(&mut self, value: &Value) -> bool {
+        let removed = Key::of(value).is_some_and(|key| self.keys.remove(&key));
+        if removed {
+            self.items.retain(|item| item != value);
+        }
+        removed
+    }
+
+    // values that can not be in a set are never in it
+    pub fn contains(&self, value: &Value) -> bool {
+        Key::of(value).is_some_and(|key| self.keys.contains(&key))
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn items(&self) -> &[Value] {
+        &self.items
+    }
+
+    // the values of both, those of this set first
+    pub fn union(&self, other: &ValueSet) -> ValueSet {
+        let mut union = self.clone();
+        for item in other.items.iter() {
+            union.add(item.clone()).expect("items of a set can be added to a set");
+        }
+        union
+    }
+
+    // the values in both, in the order of this set
+    pub fn intersect(&self, other: &ValueSet) -> ValueSet {
+        let mut intersection = ValueSet::new();
+        for item in self.items.iter().filter(|item| other.contains(item)) {
+            intersection.add(item.clone()).expect("items of a set can be added to a set");
+        }
+        intersection
+    }
+
+}
+
+// sets are equal when they hold the same values, whatever order they were added in
+impl PartialEq for ValueSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.keys == other.keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::set::{hash, ValueSet};
+    use crate::vm::value::Value;
+
+    fn set(items: &[Value]) -> ValueSet {
+        ValueSet::from_values(items.iter().cloned()).unwrap()
+    }
+
+    #[test]
+    fn test_add_and_remove() {
+        let mut numbers = set(&[Value::Integer(3), Value::Integer(1), Value::Integer(3)]);
+        assert_eq!(numbers.items(), &[Value::Integer(3), Value::Integer(1)]);
+        assert_eq!(numbers.add(Value::Integer(1)), Ok(false));
+        assert_eq!(numbers.add(Value::from("1")), Ok(true));
+        assert!(numbers.contains(&Value::from("1")) && !numbers.contains(&Value::Float(1.0)));
+
+        assert!(numbers.remove(&Value::Integer(3)));
+        assert!(!numbers.remove(&Value::Integer(3)));
+        assert_eq!(numbers.items(), &[Value::Integer(1), Value::from("1")]);
+
+        assert!(numbers.add(Value::Float(1.0)).is_err());
+        assert!(numbers.add(Value::from(vec![Value::Integer(1)])).is_err());
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(&Value::from("a")), hash(&Value::from("a")));
+        assert_ne!(hash(&Value::from("1")), hash(&Value::Integer(1)));
+        assert_ne!(hash(&Value::Null), hash(&Value::Bool(false)));
+        assert_eq!(hash(&Value::Float(1.0)), None);
+    }
+
+    #[test]
+    fn test_union_and_intersect() {
+        let a = set(&[Value::Integer(1), Value::Integer(2), Value::Integer(3)]);
+        let b = set(&[Value::Integer(4), Value::Integer(3), Value::Integer(2)]);
+        assert_eq!(a.union(&b).items(), &[Value::Integer(1), Value::Integer(2), Value::Integer(3), Value::Integer(4)]);
+        assert_eq!(a.intersect(&b).items(), &[Value::Integer(2), Value::Integer(3)]);
+        assert!(a.intersect(&ValueSet::new()).is_empty());
+        assert_eq!(a.intersect(&b), set(&[Value::Integer(3), Value::Integer(2)]));
+    }
+
+}
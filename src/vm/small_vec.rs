@@ -0,0 +1,250 @@
+use core::fmt;
+use core::fmt::{Debug, Formatter};
+use core::mem;
+use core::ops::{Deref, DerefMut};
+
+use crate::prelude::*;
+use crate::vm::value::Value;
+
+// arguments of a call, most calls pass no more than 4
+pub type Args = SmallVec<Value, 4>;
+
+// SmallVec
+//
+// a vector that keeps up to N items inline and only moves them to the heap once it grows past
+// that, so the stacks and arguments of most calls never allocate
+#[derive(Clone)]
+pub struct SmallVec<T: Default, const N: usize> {
+    storage: Storage<T, N>,
+}
+
+#[derive(Clone)]
+enum Storage<T, const N: usize> {
+    Inline(usize, [T; N]),
+    Heap(Vec<T>),
+}
+
+impl<T: Default, const N: usize> SmallVec<T, N> {
+
+    pub fn new() -> Self {
+        SmallVec { storage: Storage::Inline(0, core::array::from_fn(|_| T::default())) }
+    }
+
+    pub fn capacity(&self) -> usize {
+        match &self.storage {
+            Storage::Inline(..) => N,
+            Storage::Heap(items) => items.capacity()
+        }
+    }
+
+    // check if the items have moved to the heap
+    pub fn spilled(&self) -> bool {
+        matches!(self.storage, Storage::Heap(_))
+    }
+
+    pub fn push(&mut self, item: T) {
+        match &mut self.storage {
+            Storage::Inline(len, items) if *len < N => {
+                items[*len] = item;
+                *len += 1;
+            }
+            Storage::Inline(..) => {
+                self.spill(N * 2);
+                self.push(item);
+            }
+            Storage::Heap(items) => items.push(item)
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        match &mut self.storage {
+            Storage::Inline(0, _) => None,
+            Storage::Inline(len, items) => {
+                *len -= 1;
+                Some(mem::take(&mut items[*len]))
+            }
+            Storage::Heap(items) => items.pop()
+        }
+    }
+
+    pub fn insert(&mut self, index: usize, item: T) {
+        assert!(index <= self.len(), "insert index should be within the vector");
+        self.push(item);
+        self[index..].rotate_right(1);
+    }
+
+    pub fn clear(&mut self) {
+        match &mut self.storage {
+            Storage::Inline(len, items) => {
+                items[..*len].iter_mut().for_each(|item| *item = T::default());
+                *len = 0;
+            }
+            Storage::Heap(items) => items.clear()
+        }
+    }
+
+    // make room for more items, moving to the heap if they will not fit inline
+    pub fn reserve(&mut self, additional: usize) {
+        match &mut self.storage {
+            Storage::Inline(len, _) if *len + additional <= N => {}
+            Storage::Inline(len, _) => {
+                let capacity = *len + additional;
+                self.spill(capacity);
+            }
+            Storage::Heap(items) => items.reserve(additional)
+        }
+    }
+
+    pub fn resize(&mut self, new_len: usize, item: T) where T: Clone {
+        self.truncate(new_len);
+        self.reserve(new_len - self.len());
+        while self.len() < new_len {
+            self.push(item.clone());
+        }
+    }
+
+    pub fn truncate(&mut self, new_len: usize) {
+        while self.len() > new_len {
+            self.pop();
+        }
+    }
+
+    // move the inline items to a vector with room for capacity items
+    fn spill(&mut self, capacity: usize) {
+        if let Storage::Inline(len, items) = &mut self.storage {
+            let mut heap = Vec::with_capacity(capacity.max(*len));
+            heap.extend(items[..*len].iter_mut().map(mem::take));
+            self.storage = Storage::Heap(heap);
+        }
+    }
+
+}
+
+impl<T: Default, const N: usize> Default for SmallVec<T, N> {
+    fn default() -> Self {
+        SmallVec::new()
+    }
+}
+
+impl<T: Default, const N: usize> Deref for SmallVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match &self.storage {
+            Storage::Inline(len, items) => &items[..*len],
+            Storage::Heap(items) => items
+        }
+    }
+}
+
+impl<T: Default, const N: usize> DerefMut for SmallVec<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        match &mut self.storage {
+            Storage::Inline(len, items) => &mut items[..*len],
+            Storage::Heap(items) => items
+        }
+    }
+}
+
+impl<T: Default + Debug, const N: usize> Debug for SmallVec<T, N> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Default + PartialEq, const N: usize> PartialEq for SmallVec<T, N> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: Default, const N: usize> Extend<T> for SmallVec<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        iter.into_iter().for_each(|item| self.push(item));
+    }
+}
+
+impl<T: Default, const N: usize> FromIterator<T> for SmallVec<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut items = SmallVec::new();
+        items.extend(iter);
+        items
+    }
+}
+
+// a vector that is already on the heap is kept there rather than copied
+impl<T: Default, const N: usize> From<Vec<T>> for SmallVec<T, N> {
+    fn from(items: Vec<T>) -> Self {
+        if items.len() <= N {
+            items.into_iter().collect()
+        } else {
+            SmallVec { storage: Storage::Heap(items) }
+        }
+    }
+}
+
+impl<T: Default, const N: usize> IntoIterator for SmallVec<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    fn into_iter(self) -> IntoIter<T, N> {
+        IntoIter { items: self, next: 0 }
+    }
+}
+
+// takes each item out of the vector in order
+pub struct IntoIter<T: Default, const N: usize> {
+    items: SmallVec<T, N>,
+    next: usize,
+}
+
+impl<T: Default, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let item = self.items.get_mut(self.next).map(mem::take);
+        self.next += 1;
+        item
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::small_vec::SmallVec;
+
+    #[test]
+    fn test_inline() {
+        let mut items: SmallVec<i32, 4> = SmallVec::new();
+        items.extend([1, 2, 3]);
+        items.insert(0, 0);
+        assert!(!items.spilled());
+        assert_eq!(&*items, &[0, 1, 2, 3]);
+        assert_eq!(items.pop(), Some(3));
+        items.clear();
+        assert!(items.is_empty());
+        assert_eq!(items.pop(), None);
+    }
+
+    #[test]
+    fn test_spill() {
+        let mut items: SmallVec<String, 2> = SmallVec::new();
+        items.extend(["a", "b", "c"].map(String::from));
+        assert!(items.spilled());
+        assert_eq!(items.into_iter().collect::<Vec<_>>(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_resize_and_reserve() {
+        let mut items: SmallVec<i32, 4> = SmallVec::new();
+        items.resize(3, 7);
+        assert_eq!(&*items, &[7, 7, 7]);
+        items.resize(1, 0);
+        assert_eq!(&*items, &[7]);
+        items.reserve(3);
+        assert!(!items.spilled());
+        items.reserve(16);
+        assert!(items.capacity() >= 17);
+        assert_eq!(SmallVec::<i32, 2>::from(vec![1, 2, 3]).len(), 3);
+    }
+
+}
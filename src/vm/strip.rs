@@ -0,0 +1,233 @@
+use alloc::rc::Rc;
+
+use crate::collections::{HashMap, HashSet};
+use crate::compiler::CLASS_CONSTRUCTOR_FUNCTION_NAME;
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
+use crate::vm::instruction::Instruction;
+use crate::vm::program::Program;
+use crate::vm::value::Value;
+
+// Unused Code
+//
+// functions and classes that can not be reached from the entry points are removed from a
+// linked program. methods are called by name, so a method of a class that is used is kept if
+// anything calls a method of that name or has it as a string, such as 'this.onTick' given to
+// on(). names built while the script runs, such as this["on" + name], can not be followed
+
+impl Program {
+
+    // remove what the entry points can not reach, giving the names of the functions removed
+    pub fn strip_unused(&mut self, entry_points: &[&str]) -> Result<Vec<String>, RuntimeError> {
+        let mut reach = Reachability::new(self);
+        for entry in entry_points {
+            if !self.symbols.contains_key(*entry) {
+                return Err(RuntimeError::UnknownFunction(entry.to_string()));
+            }
+            reach.function(entry);
+        }
+        reach.walk();
+        let Reachability { functions: kept, globals: kept_globals, .. } = reach;
+
+        // move the functions that are kept together, and their jumps with them
+        let mut instructions = vec![];
+        let mut moved: HashMap<usize, usize> = HashMap::new();
+        for (name, range) in self.functions() {
+            if !kept.contains(name) || moved.contains_key(&range.start) {
+                continue;
+            }
+            let start = instructions.len();
+            moved.insert(range.start, start);
+            instructions.extend(self.instructions[range.clone()].iter().cloned().map(|mut instruction| {
+                if let Some(target) = instruction.jump_target_mut() {
+                    *target = *target - range.start + start;
+                }
+                instruction
+            }));
+        }
+
+        let mut removed: Vec<String> = self.symbols.keys().filter(|name| !kept.contains(*name)).cloned().collect();
+        removed.sort();
+        for name in removed.iter() {
+            self.symbols.remove(name);
+            self.metadata.remove(name);
+            self.params.remove(name);
+            self.attributes.remove(name);
+            self.locals.remove(name);
+            self.docs.remove(name);
+        }
+        self.symbols.values_mut().for_each(|position| *position = moved[position]);
+        self.instructions = instructions;
+
+        // globals keep their place so nothing that loads them has to change
+        for (index, global) in self.globals.iter_mut().enumerate() {
+            match global {
+                _ if !kept_globals.contains(&index) => *global = Value::Null,
+                Value::Class(members) => {
+                    let members = members.iter()
+                        .filter(|(_, value)| !matches!(value, Value::FunctionRef(function) if !kept.contains(function.as_str())))
+                        .map(|(member, value)| (member.clone(), value.clone()))
+                        .collect();
+                    *global = Value::Class(Rc::new(members));
+                }
+                _ => {}
+            }
+        }
+        let removed_classes: Vec<String> = self.global_symbols.iter().filter(|(_, index)| !kept_globals.contains(*index)).map(|(name, _)| name.clone()).collect();
+        for name in removed_classes {
+            self.global_symbols.remove(&name);
+            self.docs.remove(&name);
+        }
+
+        Ok(removed)
+    }
+
+}
+
+// what has been found to be used, and the functions still to be looked through
+struct Reachability<'a> {
+    program: &'a Program,
+    functions: HashSet<String>,
+    globals: HashSet<usize>,
+    // method names that are called or used as strings
+    names: HashSet<String>,
+    queue: Vec<String>,
+}
+
+impl<'a> Reachability<'a> {
+
+    fn new(program: &'a Program) -> Self {
+        Reachability { program, functions: HashSet::new(), globals: HashSet::new(), names: HashSet::new(), queue: vec![] }
+    }
+
+    fn walk(&mut self) {
+        let ranges: HashMap<&String, _> = self.program.functions().into_iter().collect();
+        while let Some(function) = self.queue.pop() {
+            let Some(range) = ranges.get(&function) else { continue };
+            for instruction in self.program.instructions[range.clone()].iter() {
+                match instruction {
+                    Instruction::LoadGlobal(index) | Instruction::StoreGlobal(index) => self.global(*index),
+                    Instruction::CallMethod(name, _) => self.name(name),
+                    Instruction::StackPush(value) | Instruction::CompareLocalConstJump(_, _, value, _) => self.value(value),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn function(&mut self, name: &str) {
+        if !self.functions.insert(name.to_string()) {
+            return;
+        }
+        self.queue.push(name.to_string());
+
+        // the class of a function is its 'this' when the host calls it
+        if let Some(index) = name.rsplit_once('.').and_then(|(class, _)| self.program.global_symbols.get(class)) {
+            self.global(*index);
+        }
+    }
+
+    fn global(&mut self, index: usize) {
+        if !self.globals.insert(index) {
+            return;
+        }
+        match self.program.globals.get(index) {
+            Some(Value::Class(members)) => {
+                for (member, value) in members.iter() {
+                    match value {
+                        Value::FunctionRef(function) if member != CLASS_CONSTRUCTOR_FUNCTION_NAME && !self.names.contains(member) => {}
+                        value => self.value(value),
+                    }
+                }
+            }
+            Some(value) => self.value(value),
+            None => {}
+        }
+    }
+
+    // a method name, which keeps the methods with it of every class that is used
+    fn name(&mut self, name: &str) {
+        if !self.names.insert(name.to_string()) {
+            return;
+        }
+        let methods: Vec<String> = self.globals.iter()
+            .filter_map(|index| match self.program.globals.get(*index) {
+                Some(Value::Class(members)) => match members.get(name) {
+                    Some(Value::FunctionRef(function)) => Some(function.to_string()),
+                    _ => None
+                },
+                _ => None
+            })
+            .collect();
+        methods.iter().for_each(|method| self.function(method));
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::String(name) => self.name(name),
+            Value::FunctionRef(function) => self.function(function),
+            Value::Array(items) => items.borrow().iter().for_each(|item| self.value(item)),
+            Value::Dictionary(entries) | Value::Object(entries) => entries.borrow().values().for_each(|item| self.value(item)),
+            _ => {}
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::compile;
+    use crate::vm::value::Value;
+    use crate::vm::VM;
+
+    const SOURCE: &str = r#"
+        class Shape {
+            var side = 2;
+            function area() { return this.side * this.side; }
+            function perimeter() { return this.side * 4; }
+        }
+        class Unused {
+            function main() { return new Shape().perimeter(); }
+        }
+        class Test {
+            function main() {
+                on("tick", this.onTick);
+                var shape = new Shape();
+                var total = 0;
+                for (var i = 0; i < 3; i = i + 1) {
+                    total = total + shape.area();
+                }
+                return total;
+            }
+            function onTick(n) { return n + 1; }
+            function never() { return 0; }
+        }
+    "#;
+
+    #[test]
+    fn test_strip() {
+        let mut program = compile(SOURCE).unwrap();
+        let before = program.instructions.len();
+        let removed = program.strip_unused(&["Test.main"]).unwrap();
+
+        assert!(removed.contains(&String::from("Unused.main")));
+        assert!(removed.contains(&String::from("Test.never")));
+        assert!(removed.contains(&String::from("Shape.perimeter")));
+        assert!(!removed.contains(&String::from("Shape.area")) && !removed.contains(&String::from("Test.onTick")));
+        assert!(!program.global_symbols.contains_key("Unused"));
+        assert!(program.instructions.len() < before);
+        program.verify().unwrap();
+
+        let mut vm = VM::new(program);
+        assert_eq!(vm.exec("Test.main", None).unwrap(), Value::Integer(12));
+        assert_eq!(vm.dispatch_event("tick", Value::Integer(1)).unwrap(), vec![Value::Integer(2)]);
+    }
+
+    #[test]
+    fn test_unknown_entry_point() {
+        let mut program = compile(SOURCE).unwrap();
+        assert!(program.strip_unused(&["Test.missing"]).is_err());
+        assert!(program.symbols.contains_key("Unused.main"));
+    }
+
+}
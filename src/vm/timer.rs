@@ -0,0 +1,154 @@
+use crate::prelude::*;
+use crate::vm::builtin::TIMER_MODULE_NAME;
+use crate::vm::error::RuntimeError;
+use crate::vm::value::Value;
+
+// Timers
+//
+// functions a script asks to have called after a delay, once or over and over. the vm has no
+// clock of its own, so times are whatever the host passes to VM::run_until_idle, usually
+// milliseconds, and a delay counts from the last time it was given. functions due at the same
+// time are called in the order their timers were made
+#[derive(Clone, Debug, Default)]
+pub struct Timers {
+    pending: Vec<Timer>,
+    next_id: i32,
+    now: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timer {
+    pub id: i32,
+    pub due: u64,
+    // how often a timer that repeats is due
+    pub every: Option<u64>,
+    // the function is called as a method of what made the timer
+    pub this: Value,
+    pub handler: String,
+}
+
+impl Timers {
+
+    // the Timer functions of a script, called with the 'this' of the caller
+    pub fn call(&mut self, function: &str, args: &[Value], this: Value) -> Result<Value, RuntimeError> {
+        match (function, args) {
+            ("after", [Value::Integer(delay), Value::FunctionRef(handler)]) if *delay >= 0 => {
+                Ok(Value::Integer(self.schedule(*delay as u64, None, this, handler.to_string())))
+            }
+            ("every", [Value::Integer(interval), Value::FunctionRef(handler)]) if *interval > 0 => {
+                Ok(Value::Integer(self.schedule(*interval as u64, Some(*interval as u64), this, handler.to_string())))
+            }
+            ("cancel", [Value::Integer(id)]) => Ok(Value::Bool(self.cancel(*id))),
+            ("after", _) => Err(RuntimeError::TypeError(format!("{}.after expects a delay of at least 0 and a function", TIMER_MODULE_NAME))),
+            ("every", _) => Err(RuntimeError::TypeError(format!("{}.every expects an interval of at least 1 and a function", TIMER_MODULE_NAME))),
+            ("cancel", _) => Err(RuntimeError::TypeError(format!("{}.cancel expects the id of a timer", TIMER_MODULE_NAME))),
+            _ => Err(RuntimeError::UnknownFunction(format!("{}.{}", TIMER_MODULE_NAME, function)))
+        }
+    }
+
+    fn schedule(&mut self, delay: u64, every: Option<u64>, this: Value, handler: String) -> i32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.push(Timer { id, due: self.now.saturating_add(delay), every, this, handler });
+        id
+    }
+
+    // stop a timer, giving whether it had not already finished
+    pub fn cancel(&mut self, id: i32) -> bool {
+        let before = self.pending.len();
+        self.pending.retain(|timer| timer.id != id);
+        self.pending.len() < before
+    }
+
+    // the earliest timer due by the time, which moves the clock on to when it was due. a timer
+    // that repeats is put back for its next time before its function is called
+    pub fn take_due(&mut self, until: u64) -> Option<(Value, String)> {
+        let index = (0..self.pending.len())
+            .filter(|index| self.pending[*index].due <= until)
+            .min_by_key(|index| (self.pending[*index].due, self.pending[*index].id))?;
+        let timer = match self.pending[index].every {
+            Some(every) => {
+                let timer = self.pending[index].clone();
+                self.pending[index].due = timer.due.saturating_add(every);
+                timer
+            }
+            None => self.pending.remove(index)
+        };
+        self.now = self.now.max(timer.due);
+        Some((timer.this, timer.handler))
+    }
+
+    pub fn advance(&mut self, now: u64) {
+        self.now = self.now.max(now);
+    }
+
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    // when the next timer is due, if there is one
+    pub fn next_due(&self) -> Option<u64> {
+        self.pending.iter().map(|timer| timer.due).min()
+    }
+
+    pub fn pending(&self) -> &[Timer] {
+        &self.pending
+    }
+
+    pub fn next_id(&self) -> i32 {
+        self.next_id
+    }
+
+    pub fn restore(pending: Vec<Timer>, next_id: i32, now: u64) -> Timers {
+        Timers { pending, next_id, now }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::rc::Rc;
+
+    use crate::vm::timer::Timers;
+    use crate::vm::value::Value;
+
+    fn handler(name: &str) -> Value {
+        Value::FunctionRef(Rc::new(name.to_string()))
+    }
+
+    fn due(timers: &mut Timers, until: u64) -> Vec<String> {
+        core::iter::from_fn(|| timers.take_due(until).map(|(_, handler)| handler)).take(10).collect()
+    }
+
+    #[test]
+    fn test_after_and_every() {
+        let mut timers = Timers::default();
+        timers.call("every", &[Value::Integer(40), handler("tick")], Value::Null).unwrap();
+        timers.call("after", &[Value::Integer(50), handler("once")], Value::Null).unwrap();
+        assert_eq!(timers.next_due(), Some(40));
+
+        assert_eq!(due(&mut timers, 30), Vec::<String>::new());
+        assert_eq!(due(&mut timers, 100), vec!["tick", "once", "tick"]);
+        assert_eq!(timers.now(), 80);
+        assert_eq!(timers.next_due(), Some(120));
+
+        // delays count from the time last given
+        timers.advance(100);
+        timers.call("after", &[Value::Integer(0), handler("now")], Value::Null).unwrap();
+        assert_eq!(due(&mut timers, 100), vec!["now"]);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut timers = Timers::default();
+        let Ok(Value::Integer(id)) = timers.call("every", &[Value::Integer(10), handler("tick")], Value::Null) else { panic!("expected an id") };
+        assert_eq!(timers.call("cancel", &[Value::Integer(id)], Value::Null), Ok(Value::Bool(true)));
+        assert_eq!(timers.call("cancel", &[Value::Integer(id)], Value::Null), Ok(Value::Bool(false)));
+        assert_eq!(timers.next_due(), None);
+
+        assert!(timers.call("every", &[Value::Integer(0), handler("tick")], Value::Null).is_err());
+        assert!(timers.call("after", &[Value::Integer(-1), handler("tick")], Value::Null).is_err());
+        assert!(timers.call("at", &[], Value::Null).is_err());
+    }
+
+}
@@ -0,0 +1,105 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
+use crate::vm::value::Value;
+
+// parse toml text into a dictionary
+//
+// dates and times are kept as strings, and integers that do not fit in 32 bits become floats
+pub fn parse(text: &str) -> Result<Value, RuntimeError> {
+    let table = text.parse::<::toml::Table>().map_err(|e| RuntimeError::TypeError(format!("invalid toml: {}", e.to_string().trim_end())))?;
+    Ok(convert(::toml::Value::Table(table)))
+}
+
+fn convert(value: ::toml::Value) -> Value {
+    match value {
+        ::toml::Value::String(s) => Value::from(s),
+        ::toml::Value::Integer(i) => i32::try_from(i).map(Value::Integer).unwrap_or(Value::Float(i as f32)),
+        ::toml::Value::Float(f) => Value::Float(f as f32),
+        ::toml::Value::Boolean(b) => Value::Bool(b),
+        ::toml::Value::Datetime(datetime) => Value::from(datetime.to_string()),
+        ::toml::Value::Array(items) => Value::Array(Rc::new(RefCell::new(items.into_iter().map(convert).collect()))),
+        ::toml::Value::Table(table) => Value::Dictionary(Rc::new(RefCell::new(table.into_iter().map(|(key, value)| (key, convert(value))).collect()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::toml::parse;
+    use crate::vm::value::Value;
+
+    fn get(value: &Value, path: &str) -> Value {
+        path.split('.').fold(value.clone(), |value, key| match value {
+            Value::Dictionary(d) => d.borrow().get(key).cloned().unwrap_or(Value::Null),
+            Value::Array(a) => a.borrow()[key.parse::<usize>().unwrap()].clone(),
+            _ => Value::Null
+        })
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = parse(r#"
+# server settings
+title = "app"  # trailing comment
+port = 8_080
+ratio = 0.5
+debug = true
+mask = 0xff
+released = 1979-05-27
+tags = [ "a", 'b',
+    "c", ]
+
+[database]
+url = 'postgres://localhost'
+pool.size = 4
+limits = { read = 10, write = -2 }
+
+[[plugins]]
+name = "one"
+
+[[plugins]]
+name = "two"
+notes = """
+first line
+second"""
+"#).unwrap();
+
+        assert_eq!(get(&config, "title"), Value::from("app"));
+        assert_eq!(get(&config, "port"), Value::Integer(8080));
+        assert_eq!(get(&config, "ratio"), Value::Float(0.5));
+        assert_eq!(get(&config, "debug"), Value::Bool(true));
+        assert_eq!(get(&config, "mask"), Value::Integer(255));
+        assert_eq!(get(&config, "released"), Value::from("1979-05-27"));
+        assert_eq!(get(&config, "tags.2"), Value::from("c"));
+        assert_eq!(get(&config, "database.url"), Value::from("postgres://localhost"));
+        assert_eq!(get(&config, "database.pool.size"), Value::Integer(4));
+        assert_eq!(get(&config, "database.limits.write"), Value::Integer(-2));
+        assert_eq!(get(&config, "plugins.0.name"), Value::from("one"));
+        assert_eq!(get(&config, "plugins.1.notes"), Value::from("first line\nsecond"));
+    }
+
+    #[test]
+    fn test_escapes() {
+        let config = parse(r#"s = "tab\tquote\" é""#).unwrap();
+        assert_eq!(get(&config, "s"), Value::from("tab\tquote\" é"));
+    }
+
+    #[test]
+    fn test_large_numbers() {
+        let config = parse("big = 99999999999\nsmall = -2147483648").unwrap();
+        assert_eq!(get(&config, "big"), Value::Float(99999999999.0));
+        assert_eq!(get(&config, "small"), Value::Integer(i32::MIN));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(parse("a = 1\na = 2").is_err());
+        assert!(parse("a = \"open").is_err());
+        assert!(parse("a = 1 b = 2").is_err());
+        assert!(parse("a = 1\n[a]").is_err());
+        assert!(parse("= 1").is_err());
+    }
+
+}
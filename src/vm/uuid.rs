@@ -0,0 +1,41 @@
+use ::uuid::Uuid;
+
+use crate::prelude::*;
+
+// a random uuid in the lowercase 8-4-4-4-12 form
+pub fn v4() -> String {
+    Uuid::new_v4().hyphenated().to_string()
+}
+
+// the lowercase hyphenated form of a uuid, which may be in braces, a urn or missing its hyphens
+pub fn parse(s: &str) -> Option<String> {
+    Uuid::parse_str(s.trim()).ok().map(|id| id.hyphenated().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::uuid::{parse, v4};
+
+    #[test]
+    fn test_v4() {
+        let id = v4();
+        assert_eq!(id.len(), 36);
+        assert_eq!(&id[14..15], "4");
+        assert!(matches!(&id[19..20], "8" | "9" | "a" | "b"));
+        assert_eq!(parse(&id), Some(id.clone()));
+        assert_ne!(v4(), id);
+    }
+
+    #[test]
+    fn test_parse() {
+        let expected = Some(String::from("123e4567-e89b-12d3-a456-426614174000"));
+        assert_eq!(parse("123E4567-E89B-12D3-A456-426614174000"), expected);
+        assert_eq!(parse("{123e4567-e89b-12d3-a456-426614174000}"), expected);
+        assert_eq!(parse("urn:uuid:123e4567-e89b-12d3-a456-426614174000"), expected);
+        assert_eq!(parse("123e4567e89b12d3a456426614174000"), expected);
+        assert_eq!(parse("123e4567-e89b-12d3-a456-42661417400g"), None);
+        assert_eq!(parse("123e4567-e89b12d3-a456-4266-14174000"), None);
+        assert_eq!(parse("not a uuid"), None);
+    }
+
+}
@@ -1,26 +1,69 @@
-use std::cell::RefCell;
-use std::cmp::Ordering;
-use std::collections::HashMap;
-use std::fmt;
-use std::fmt::{Display, Formatter};
-use std::ops::{Add, Div, Mul, Not, Sub};
-use std::rc::Rc;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use core::any::{type_name, Any};
+use core::cell::{Ref, RefCell};
+use core::cmp::Ordering;
+use core::fmt;
+use core::fmt::{Display, Formatter};
+use core::ops::{Add, Div, Mul, Not, Sub};
+use core::str::Chars;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+#[cfg(feature = "bigint")]
+use crate::vm::bigint::BigInt;
+use crate::vm::coroutine::Coroutine;
+#[cfg(feature = "decimal")]
+use crate::vm::decimal::Decimal;
+use crate::vm::error::RuntimeError;
+#[cfg(feature = "bigint")]
+use crate::vm::exact;
+#[cfg(feature = "bigint")]
+use crate::vm::exact::Operation;
+use crate::vm::generator::Generator;
+use crate::vm::math;
+use crate::vm::host::HostObject;
+use crate::vm::priority_queue::PriorityQueue;
+use crate::vm::sequence::Sequence;
+use crate::vm::set::ValueSet;
+use crate::vm::view::ArrayView;
+use crate::vm::weak::WeakRef;
 
 // Value
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, Debug, Default)]
 pub enum Value {
 
     // Values
+    #[default]
     Null,
     Integer(i32),
     Float(f32),
     Bool(bool),
-    String(String),
+    // strings never change once made, so copies of a value share the text
+    String(Rc<String>),
     Array(Rc<RefCell<Vec<Value>>>),
     Dictionary(Rc<RefCell<HashMap<String, Value>>>),
-    Class(HashMap<String, Value>),
+    Set(Rc<RefCell<ValueSet>>),
+    Deque(Rc<RefCell<VecDeque<Value>>>),
+    PriorityQueue(Rc<RefCell<PriorityQueue>>),
+    WeakRef(Rc<WeakRef>),
+    ArrayView(Rc<ArrayView>),
+    // classes never change once compiled, so loading one only shares its members
+    Class(Rc<HashMap<String, Value>>),
     Object(Rc<RefCell<HashMap<String, Value>>>),
-    FunctionRef(String),
+    FunctionRef(Rc<String>),
+    StringBuilder(Rc<RefCell<String>>),
+    Generator(Rc<RefCell<Generator>>),
+    Coroutine(Rc<RefCell<Coroutine>>),
+    // items handed out by the host as the script asks for them
+    Sequence(Rc<RefCell<Sequence>>),
+    // a handle to something of the host that scripts can only pass around
+    HostObject(Rc<HostObject>),
+    // numbers are shared as they never change, and are too big to keep in every value
+    #[cfg(feature = "bigint")]
+    BigInt(Rc<BigInt>),
+    #[cfg(feature = "decimal")]
+    Decimal(Rc<Decimal>),
 }
 
 // function for finding Value by parameter. if its a number then return integer, if its a string then return string, etc.
@@ -33,12 +76,106 @@ impl Value {
                 Ok(num) => Value::Float(num),
                 Err(_) => match param.parse::<bool>() {
                     Ok(b) => Value::Bool(b),
-                    Err(_) => Value::String(param.to_string())
+                    Err(_) => Value::from(param)
                 }
             }
         }
     }
 
+    // a handle to a host value, such as a file or connection, for scripts to pass back to the host
+    pub fn host<T: Any>(value: T) -> Value {
+        Value::HostObject(Rc::new(HostObject::new(value)))
+    }
+
+    // the host value held by a handle if it is a T
+    pub fn downcast_host<T: Any>(&self) -> Result<Ref<'_, T>, RuntimeError> {
+        match self {
+            Value::HostObject(object) => object.borrow::<T>(),
+            value => Err(RuntimeError::TypeError(format!("expected host object {} but found {}", type_name::<T>(), value.type_name())))
+        }
+    }
+
+    // a sequence a script can loop over, asking the iterator for each item as it is needed
+    pub fn sequence(items: impl Iterator<Item = Value> + 'static) -> Value {
+        Value::Sequence(Rc::new(RefCell::new(Sequence::new(items))))
+    }
+
+    // a sequence of items the host fetches a chunk at a time, see Sequence::chunked
+    pub fn chunked(fetch: impl FnMut(usize) -> Option<Vec<Value>> + 'static) -> Value {
+        Value::Sequence(Rc::new(RefCell::new(Sequence::chunked(fetch))))
+    }
+
+    // check if the value is a number of any kind
+    pub fn is_number(&self) -> bool {
+        match self {
+            Value::Integer(_) | Value::Float(_) => true,
+            #[cfg(feature = "bigint")]
+            Value::BigInt(_) => true,
+            #[cfg(feature = "decimal")]
+            Value::Decimal(_) => true,
+            _ => false
+        }
+    }
+
+    // where a value that is shared by reference lives, which stays the same for as long as it does
+    pub fn identity(&self) -> Option<usize> {
+        let ptr = match self {
+            Value::Array(v) => Rc::as_ptr(v) as *const (),
+            Value::Dictionary(v) | Value::Object(v) => Rc::as_ptr(v) as *const (),
+            Value::Set(v) => Rc::as_ptr(v) as *const (),
+            Value::Deque(v) => Rc::as_ptr(v) as *const (),
+            Value::PriorityQueue(v) => Rc::as_ptr(v) as *const (),
+            Value::Class(v) => Rc::as_ptr(v) as *const (),
+            Value::StringBuilder(v) => Rc::as_ptr(v) as *const (),
+            Value::Generator(v) => Rc::as_ptr(v) as *const (),
+            Value::Coroutine(v) => Rc::as_ptr(v) as *const (),
+            Value::Sequence(v) => Rc::as_ptr(v) as *const (),
+            Value::HostObject(v) => Rc::as_ptr(v) as *const (),
+            _ => return None
+        };
+        Some(ptr as usize)
+    }
+
+    // whether two values are the same collection or object, rather than just equal. other values
+    // are identical when they are equal and of the same type, so 1 is not identical to 1.0
+    pub fn identical(&self, rhs: &Value) -> bool {
+        match (self.identity(), rhs.identity()) {
+            (Some(lhs), Some(rhs)) => lhs == rhs,
+            (None, None) => core::mem::discriminant(self) == core::mem::discriminant(rhs) && self == rhs,
+            _ => false
+        }
+    }
+
+    // name of the value type for error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Bool(_) => "bool",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Dictionary(_) => "dictionary",
+            Value::Set(_) => "set",
+            Value::Deque(_) => "deque",
+            Value::PriorityQueue(_) => "priority queue",
+            Value::WeakRef(_) => "weak reference",
+            Value::ArrayView(_) => "array view",
+            Value::Class(_) => "class",
+            Value::Object(_) => "object",
+            Value::FunctionRef(_) => "function",
+            Value::StringBuilder(_) => "StringBuilder",
+            Value::Generator(_) => "generator",
+            Value::Coroutine(_) => "coroutine",
+            Value::Sequence(_) => "sequence",
+            Value::HostObject(_) => "host object",
+            #[cfg(feature = "bigint")]
+            Value::BigInt(_) => "bigint",
+            #[cfg(feature = "decimal")]
+            Value::Decimal(_) => "decimal",
+        }
+    }
+
 }
 
 impl Display for Value {
@@ -50,35 +187,193 @@ impl Display for Value {
             Value::Bool(b) => write!(f, "{b}"),
             Value::String(string) => write!(f, "{string}"),
             Value::Array(_val) => write!(f, "Array"),
+            Value::Set(_) => write!(f, "Set"),
+            Value::Deque(_) => write!(f, "Deque"),
+            Value::PriorityQueue(_) => write!(f, "PriorityQueue"),
+            Value::ArrayView(_) => write!(f, "ArrayView"),
             Value::FunctionRef(name) => write!(f, "{name}"),
+            Value::StringBuilder(buffer) => write!(f, "{}", buffer.borrow()),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(num) => write!(f, "{num}"),
+            #[cfg(feature = "decimal")]
+            Value::Decimal(num) => write!(f, "{num}"),
             _ => write!(f, "todo for {:?}", self),
         }
     }
 }
 
+impl Value {
+
+    // compare two values, failing when they can not be ordered
+    pub fn compare(&self, rhs: &Value) -> Result<Ordering, RuntimeError> {
+        self.partial_cmp(rhs).ok_or_else(|| RuntimeError::TypeError(format!("can not compare {} and {}", self.type_name(), rhs.type_name())))
+    }
+
+    // walk a path such as a.b[2].c through dictionaries, objects and arrays, giving null when any part is missing
+    //
+    // keys may be quoted as ["a.b"] and negative indexes count back from the end of an array
+    pub fn get_path(&self, path: &str) -> Value {
+        let mut value = self.clone();
+        for segment in path_segments(path) {
+            value = match (segment, &value) {
+                (PathSegment::Key(key), Value::Dictionary(d) | Value::Object(d)) => d.borrow().get(&key).cloned(),
+                (PathSegment::Key(key), Value::Class(c)) => c.get(&key).cloned(),
+                (PathSegment::Index(index), Value::Array(a)) => {
+                    let a = a.borrow();
+                    let index = if index < 0 { a.len() as i64 + index } else { index };
+                    usize::try_from(index).ok().and_then(|i| a.get(i)).cloned()
+                }
+                _ => None
+            }.unwrap_or(Value::Null);
+
+            if value == Value::Null {
+                break;
+            }
+        }
+        value
+    }
+
+}
+
+enum PathSegment {
+    Key(String),
+    Index(i64),
+    Invalid,
+}
+
+// split a path into keys and indexes, where anything malformed becomes an invalid segment
+fn path_segments(path: &str) -> Vec<PathSegment> {
+    let mut segments = vec![];
+    let mut chars = path.chars();
+    let mut key = String::new();
+    let mut after_dot = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '.' | '[' => {
+                if !key.is_empty() {
+                    segments.push(PathSegment::Key(core::mem::take(&mut key)));
+                } else if after_dot || (c == '.' && segments.is_empty()) {
+                    segments.push(PathSegment::Invalid);
+                }
+                after_dot = c == '.';
+                if c == '[' {
+                    segments.push(bracket_segment(&mut chars));
+                }
+            }
+            ']' => segments.push(PathSegment::Invalid),
+            c => key.push(c)
+        }
+    }
+    if !key.is_empty() {
+        segments.push(PathSegment::Key(key));
+    } else if after_dot {
+        segments.push(PathSegment::Invalid);
+    }
+    segments
+}
+
+// the inside of [2] or ["key"] up to the closing bracket
+fn bracket_segment(chars: &mut Chars) -> PathSegment {
+    let inside: String = chars.by_ref().take_while(|c| *c != ']').collect();
+    let inside = inside.trim();
+    let quoted = inside.strip_prefix('"').and_then(|s| s.strip_suffix('"'))
+        .or_else(|| inside.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')));
+    match (quoted, inside.parse::<i64>()) {
+        (Some(key), _) => PathSegment::Key(key.to_string()),
+        (None, Ok(index)) => PathSegment::Index(index),
+        _ => PathSegment::Invalid
+    }
+}
+
+// Coercion
+//
+// - integers stay integers, but mixing an integer with a float gives a float
+// - an integer equals and orders against a float by value, both widened to f64 so no
+//   integer is rounded
+// - strings order by their characters, and adding a string to a number or bool joins the
+//   text of both in the order they were given
+// - big integers and decimals are promoted from integers but never mix with floats, see exact
+// - anything else has to be the same type, and only equals itself
+impl Value {
+
+    // the exact value of a number
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(num) => Some(*num as f64),
+            Value::Float(num) => Some(*num as f64),
+            _ => None
+        }
+    }
+
+}
+
+// Value Equality
+impl PartialEq for Value {
+    fn eq(&self, rhs: &Self) -> bool {
+        #[cfg(feature = "bigint")]
+        if let Some(ordering) = exact::compare(self, rhs) {
+            return ordering == Ordering::Equal;
+        }
+        match (self, rhs) {
+            (Value::Null, Value::Null) => true,
+            (Value::Integer(v1), Value::Integer(v2)) => v1 == v2,
+            (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) => self.as_f64() == rhs.as_f64(),
+            (Value::Bool(v1), Value::Bool(v2)) => v1 == v2,
+            (Value::String(v1), Value::String(v2)) => v1 == v2,
+            (Value::Array(v1), Value::Array(v2)) => v1 == v2,
+            (Value::Dictionary(v1), Value::Dictionary(v2)) => v1 == v2,
+            (Value::Set(v1), Value::Set(v2)) => v1 == v2,
+            (Value::Deque(v1), Value::Deque(v2)) => v1 == v2,
+            (Value::PriorityQueue(v1), Value::PriorityQueue(v2)) => Rc::ptr_eq(v1, v2),
+            (Value::WeakRef(v1), Value::WeakRef(v2)) => v1 == v2,
+            (Value::ArrayView(v1), Value::ArrayView(v2)) => v1 == v2,
+            (Value::Class(v1), Value::Class(v2)) => v1 == v2,
+            (Value::Object(v1), Value::Object(v2)) => v1 == v2,
+            (Value::FunctionRef(v1), Value::FunctionRef(v2)) => v1 == v2,
+            (Value::StringBuilder(v1), Value::StringBuilder(v2)) => v1 == v2,
+            (Value::Generator(v1), Value::Generator(v2)) => v1 == v2,
+            (Value::Coroutine(v1), Value::Coroutine(v2)) => Rc::ptr_eq(v1, v2),
+            (Value::Sequence(v1), Value::Sequence(v2)) => Rc::ptr_eq(v1, v2),
+            (Value::HostObject(v1), Value::HostObject(v2)) => Rc::ptr_eq(v1, v2),
+            _ => false
+        }
+    }
+}
+
 // Value Comparison
 impl PartialOrd for Value {
     fn partial_cmp(&self, rhs: &Self) -> Option<Ordering> {
+        #[cfg(feature = "bigint")]
+        if let Some(ordering) = exact::compare(self, rhs) {
+            return Some(ordering);
+        }
         match (self, rhs) {
-            (Value::Integer(v1), Value::Integer(v2)) => v1.partial_cmp(&v2),
-            (Value::Float(v1), Value::Float(v2)) => v1.partial_cmp(&v2),
-            _ => unreachable!("can not subtract values")
+            (Value::Integer(v1), Value::Integer(v2)) => v1.partial_cmp(v2),
+            (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) => self.as_f64()?.partial_cmp(&rhs.as_f64()?),
+            (Value::String(v1), Value::String(v2)) => v1.partial_cmp(v2),
+            _ => None
         }
     }
 }
 
 // Value Subtraction
 impl Sub for Value {
-    type Output = Value;
+    type Output = Result<Value, RuntimeError>;
 
     fn sub(self, rhs: Value) -> <Self as Sub<Value>>::Output {
-        match (self, rhs) {
-            (Value::Integer(v1), Value::Integer(v2)) => Value::Integer(v1 - v2),
+        #[cfg(feature = "bigint")]
+        if let Some(result) = exact::apply(Operation::Sub, &self, &rhs) {
+            return result;
+        }
+        let value = match (self, rhs) {
+            (Value::Integer(v1), Value::Integer(v2)) => Value::Integer(v1.checked_sub(v2).ok_or(RuntimeError::IntegerOverflow)?),
             (Value::Integer(v1), Value::Float(v2)) => Value::Float(v1 as f32 - v2),
             (Value::Float(v1), Value::Integer(v2)) => Value::Float(v1 - v2 as f32),
             (Value::Float(v1), Value::Float(v2)) => Value::Float(v1 - v2),
-            _ => unreachable!("can not subtract values")
-        }
+            (v1, v2) => return Err(RuntimeError::TypeError(format!("can not subtract {} and {}", v1.type_name(), v2.type_name())))
+        };
+        Ok(value)
     }
 
 }
@@ -86,25 +381,31 @@ impl Sub for Value {
 
 // Value Addition
 impl Add for Value {
-    type Output = Value;
+    type Output = Result<Value, RuntimeError>;
 
     fn add(self, rhs: Value) -> <Self as Add<Value>>::Output {
-        match (self, rhs) {
+        #[cfg(feature = "bigint")]
+        if let Some(result) = exact::apply(Operation::Add, &self, &rhs) {
+            return result;
+        }
+        let value = match (self, rhs) {
 
             // add integers together
-            (Value::Integer(v1), Value::Integer(v2)) => Value::Integer(v1 + v2),
+            (Value::Integer(v1), Value::Integer(v2)) => Value::Integer(v1.checked_add(v2).ok_or(RuntimeError::IntegerOverflow)?),
             (Value::Integer(v1), Value::Float(v2)) => Value::Float(v1 as f32 + v2),
-            (Value::Integer(v1), Value::String(v2)) => Value::String(v1.to_string().add(&*v2)),
+            (Value::Integer(v1), Value::String(v2)) => Value::from(v1.to_string().add(&*v2)),
 
             // add floats together
             (Value::Float(v1), Value::Integer(v2)) => Value::Float(v1 + v2 as f32),
             (Value::Float(v1), Value::Float(v2)) => Value::Float(v1 + v2),
+            (Value::Float(v1), Value::String(v2)) => Value::from(v1.to_string().add(&*v2)),
 
             // add strings together
-            (Value::String(v1), Value::String(v2))  => Value::String(v1.add(&*v2)),
-            (Value::String(v1), Value::Bool(v2)) => Value::String(v1.add(&*v2.to_string())),
-            (Value::String(v1), Value::Integer(v2)) => Value::String(v1.add(&*v2.to_string())),
-            (Value::String(v1), Value::Float(v2)) => Value::String(v1.add(&*v2.to_string())),
+            (Value::Bool(v1), Value::String(v2)) => Value::from(v1.to_string().add(&*v2)),
+            (Value::String(v1), Value::String(v2))  => Value::from(Rc::unwrap_or_clone(v1).add(&*v2)),
+            (Value::String(v1), Value::Bool(v2)) => Value::from(Rc::unwrap_or_clone(v1).add(&*v2.to_string())),
+            (Value::String(v1), Value::Integer(v2)) => Value::from(Rc::unwrap_or_clone(v1).add(&*v2.to_string())),
+            (Value::String(v1), Value::Float(v2)) => Value::from(Rc::unwrap_or_clone(v1).add(&*v2.to_string())),
 
             // add arrays together
             (Value::Array(v1), Value::Array(v2)) => {
@@ -115,39 +416,136 @@ impl Add for Value {
             // add booleans together but only true + true = true
             (Value::Bool(v1), Value::Bool(v2)) => Value::Bool(v1 && v2),
 
-            _ => unreachable!("can not add values")
-        }
+            (v1, v2) => return Err(RuntimeError::TypeError(format!("can not add {} and {}", v1.type_name(), v2.type_name())))
+        };
+        Ok(value)
     }
 }
 
 // Value Multiplication
 impl Mul for Value {
-    type Output = Value;
+    type Output = Result<Value, RuntimeError>;
 
     fn mul(self, rhs: Value) -> <Self as Mul<Value>>::Output {
-        match (self, rhs) {
-            (Value::Integer(v1), Value::Integer(v2)) => Value::Integer(v1 * v2),
+        #[cfg(feature = "bigint")]
+        if let Some(result) = exact::apply(Operation::Mul, &self, &rhs) {
+            return result;
+        }
+        let value = match (self, rhs) {
+            (Value::Integer(v1), Value::Integer(v2)) => Value::Integer(v1.checked_mul(v2).ok_or(RuntimeError::IntegerOverflow)?),
             (Value::Integer(v1), Value::Float(v2)) => Value::Float(v1 as f32 * v2),
             (Value::Float(v1), Value::Integer(v2)) => Value::Float(v1 * v2 as f32),
             (Value::Float(v1), Value::Float(v2)) => Value::Float(v1 * v2),
-            _ => unreachable!("can not multiply values")
-        }
+            (v1, v2) => return Err(RuntimeError::TypeError(format!("can not multiply {} and {}", v1.type_name(), v2.type_name())))
+        };
+        Ok(value)
     }
 }
 
 // Value Division
+//
+// dividing always gives a float, so nothing is lost when integers do not divide exactly.
+// integers can not be divided by zero, but a float follows the usual rules and gives
+// infinity or nan
 impl Div for Value {
-    type Output = Value;
+    type Output = Result<Value, RuntimeError>;
 
     fn div(self, rhs: Value) -> <Self as Div<Value>>::Output {
-        match (self, rhs) {
-            (Value::Integer(v1), Value::Integer(v2)) => Value::Integer(v1 / v2),
+        #[cfg(feature = "bigint")]
+        if let Some(result) = exact::apply(Operation::Div, &self, &rhs) {
+            return result;
+        }
+        let value = match (self, rhs) {
+            (Value::Integer(_), Value::Integer(0)) => return Err(RuntimeError::DivisionByZero),
+            (Value::Integer(v1), Value::Integer(v2)) => Value::Float((v1 as f64 / v2 as f64) as f32),
             (Value::Integer(v1), Value::Float(v2)) => Value::Float(v1 as f32 / v2),
             (Value::Float(v1), Value::Integer(v2)) => Value::Float(v1 / v2 as f32),
             (Value::Float(v1), Value::Float(v2)) => Value::Float(v1 / v2),
-            _ => unreachable!("can not divide values")
+            (v1, v2) => return Err(RuntimeError::TypeError(format!("can not divide {} and {}", v1.type_name(), v2.type_name())))
+        };
+        Ok(value)
+    }
+}
+
+impl Value {
+
+    // divide and round down, integers stay integers
+    pub fn floor_div(self, rhs: Value) -> Result<Value, RuntimeError> {
+        #[cfg(feature = "bigint")]
+        if let Some(result) = exact::apply(Operation::FloorDiv, &self, &rhs) {
+            return result;
         }
+        let value = match (self, rhs) {
+            (Value::Integer(_), Value::Integer(0)) => return Err(RuntimeError::DivisionByZero),
+            (Value::Integer(v1), Value::Integer(v2)) => {
+                let quotient = v1.checked_div(v2).ok_or(RuntimeError::IntegerOverflow)?;
+                // division truncates towards zero, so a negative quotient with a remainder is one too high
+                if v1 % v2 != 0 && (v1 < 0) != (v2 < 0) {
+                    Value::Integer(quotient - 1)
+                } else {
+                    Value::Integer(quotient)
+                }
+            }
+            (Value::Integer(v1), Value::Float(v2)) => Value::Float(math::floor(v1 as f32 / v2)),
+            (Value::Float(v1), Value::Integer(v2)) => Value::Float(math::floor(v1 / v2 as f32)),
+            (Value::Float(v1), Value::Float(v2)) => Value::Float(math::floor(v1 / v2)),
+            (v1, v2) => return Err(RuntimeError::TypeError(format!("can not divide {} and {}", v1.type_name(), v2.type_name())))
+        };
+        Ok(value)
     }
+
+}
+
+// Checked Arithmetic
+//
+// integer overflow is always an error, checked mode also fails where floats would give an
+// answer that is silently wrong
+impl Value {
+
+    // integers that become floats must keep their exact value, they become floats when mixed
+    // with a float or when the operation gives a float anyway
+    pub fn check_coercion(&self, rhs: &Value, to_float: bool) -> Result<(), RuntimeError> {
+        let coerced = match (self, rhs) {
+            (Value::Integer(_), Value::Integer(_)) => to_float,
+            (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) => true,
+            _ => false
+        };
+        match [self, rhs] {
+            [Value::Integer(num), _] | [_, Value::Integer(num)] if coerced && *num as f32 as f64 != *num as f64 => Err(RuntimeError::InexactFloat(*num)),
+            _ => Ok(())
+        }
+    }
+
+    // nan is not equal to or ordered against anything, so comparing with it is an error
+    pub fn check_comparable(&self, rhs: &Value) -> Result<(), RuntimeError> {
+        match [self, rhs] {
+            [Value::Float(num), _] | [_, Value::Float(num)] if num.is_nan() => Err(RuntimeError::NotANumber),
+            _ => Ok(())
+        }
+    }
+
+}
+
+// Value Power
+impl Value {
+
+    // a negative power of an integer is a float, as it is a fraction
+    pub fn pow(self, rhs: Value) -> Result<Value, RuntimeError> {
+        #[cfg(feature = "bigint")]
+        if let Some(result) = exact::pow(&self, &rhs) {
+            return result;
+        }
+        let value = match (self, rhs) {
+            (Value::Integer(v1), Value::Integer(v2)) if v2 >= 0 => Value::Integer(v1.checked_pow(v2 as u32).ok_or(RuntimeError::IntegerOverflow)?),
+            (Value::Integer(v1), Value::Integer(v2)) => Value::Float(math::powi(v1 as f32, v2)),
+            (Value::Integer(v1), Value::Float(v2)) => Value::Float(math::powf(v1 as f32, v2)),
+            (Value::Float(v1), Value::Integer(v2)) => Value::Float(math::powi(v1, v2)),
+            (Value::Float(v1), Value::Float(v2)) => Value::Float(math::powf(v1, v2)),
+            (v1, v2) => return Err(RuntimeError::TypeError(format!("can not raise {} to the power of {}", v1.type_name(), v2.type_name())))
+        };
+        Ok(value)
+    }
+
 }
 
 // Value Negation
@@ -163,74 +561,307 @@ impl Not for Value {
     }
 }
 
+// Value Conversions
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::Integer(value)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::from(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(Rc::new(value))
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Self {
+        Value::Array(Rc::new(RefCell::new(values.into_iter().map(Into::into).collect())))
+    }
+}
+
+impl From<HashMap<String, Value>> for Value {
+    fn from(map: HashMap<String, Value>) -> Self {
+        Value::Dictionary(Rc::new(RefCell::new(map)))
+    }
+}
+
+// none becomes null
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(Value::Null)
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use std::cell::RefCell;
+    use std::cmp::Ordering;
+    use std::rc::Rc;
+
+    use proptest::prelude::*;
+
+    use crate::collections::HashMap;
+    use crate::vm::error::RuntimeError;
     use crate::vm::value::Value;
 
+    #[test]
+    fn test_get_path() {
+        let items = Value::Array(Rc::new(RefCell::new(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)])));
+        let inner = Value::Dictionary(Rc::new(RefCell::new(HashMap::from([(String::from("items"), items), (String::from("a.b"), Value::Bool(true))]))));
+        let outer = Value::Object(Rc::new(RefCell::new(HashMap::from([(String::from("inner"), inner)]))));
+
+        assert_eq!(outer.get_path("inner.items[1]"), Value::Integer(2));
+        assert_eq!(outer.get_path("inner.items[-1]"), Value::Integer(3));
+        assert_eq!(outer.get_path("inner[\"a.b\"]"), Value::Bool(true));
+        assert_eq!(outer.get_path("inner.items[3]"), Value::Null);
+        assert_eq!(outer.get_path("inner.missing.deeper"), Value::Null);
+        assert_eq!(outer.get_path("inner.items.length"), Value::Null);
+        assert_eq!(outer.get_path("inner..items"), Value::Null);
+        assert_eq!(outer.get_path("inner.items[x]"), Value::Null);
+        assert_eq!(outer.get_path(""), outer);
+    }
+
     #[test]
     fn test_add() {
 
         // integers
-        assert_eq!(Value::Integer(2) + Value::Integer(3), Value::Integer(5));
-        assert_eq!(Value::Integer(2) + Value::Float(3.3), Value::Float(5.3));
+        assert_eq!(Value::Integer(2) + Value::Integer(3), Ok(Value::Integer(5)));
+        assert_eq!(Value::Integer(2) + Value::Float(3.3), Ok(Value::Float(5.3)));
 
         // floats
-        assert_eq!(Value::Float(2.2) + Value::Float(3.3), Value::Float(5.5));
-        assert_eq!(Value::Float(2.2) + Value::Integer(3), Value::Float(5.2));
+        assert_eq!(Value::Float(2.2) + Value::Float(3.3), Ok(Value::Float(5.5)));
+        assert_eq!(Value::Float(2.2) + Value::Integer(3), Ok(Value::Float(5.2)));
 
         // strings
-        assert_eq!(Value::String(String::from("x = ")) + Value::Integer(3), Value::String(String::from("x = 3")));
-        assert_eq!(Value::String(String::from("x = ")) + Value::Float(3.1), Value::String(String::from("x = 3.1")));
-        assert_eq!(Value::String(String::from("x = ")) + Value::Bool(true), Value::String(String::from("x = true")));
+        assert_eq!(Value::from("x = ") + Value::Integer(3), Ok(Value::from("x = 3")));
+        assert_eq!(Value::from("x = ") + Value::Float(3.1), Ok(Value::from("x = 3.1")));
+        assert_eq!(Value::from("x = ") + Value::Bool(true), Ok(Value::from("x = true")));
+        assert_eq!(Value::Float(3.1) + Value::from(" = x"), Ok(Value::from("3.1 = x")));
+        assert_eq!(Value::Bool(true) + Value::from(" = x"), Ok(Value::from("true = x")));
 
         // true and false booleans should return false
-        assert_eq!(Value::Bool(true) + Value::Bool(false), Value::Bool(false));
-        assert_eq!(Value::Bool(false) + Value::Bool(true), Value::Bool(false));
-        assert_eq!(Value::Bool(false) + Value::Bool(false), Value::Bool(false));
-        assert_eq!(Value::Bool(true) + Value::Bool(true), Value::Bool(true));
+        assert_eq!(Value::Bool(true) + Value::Bool(false), Ok(Value::Bool(false)));
+        assert_eq!(Value::Bool(false) + Value::Bool(true), Ok(Value::Bool(false)));
+        assert_eq!(Value::Bool(false) + Value::Bool(false), Ok(Value::Bool(false)));
+        assert_eq!(Value::Bool(true) + Value::Bool(true), Ok(Value::Bool(true)));
     }
 
     #[test]
     fn test_sub() {
-        assert_eq!(Value::Integer(7) - Value::Integer(3), Value::Integer(4));
-        assert_eq!(Value::Integer(5) - Value::Float(3.3), Value::Float(1.7));
-        assert_eq!(Value::Float(2.4) - Value::Float(1.3), Value::Float(1.1000001));
-        assert_eq!(Value::Float(5.2) - Value::Integer(3), Value::Float(2.1999998));
+        assert_eq!(Value::Integer(7) - Value::Integer(3), Ok(Value::Integer(4)));
+        assert_eq!(Value::Integer(5) - Value::Float(3.3), Ok(Value::Float(1.7)));
+        assert_eq!(Value::Float(2.4) - Value::Float(1.3), Ok(Value::Float(1.1000001)));
+        assert_eq!(Value::Float(5.2) - Value::Integer(3), Ok(Value::Float(2.1999998)));
     }
 
     #[test]
     fn test_mul() {
-        assert_eq!(Value::Integer(7) * Value::Integer(3), Value::Integer(21));
-        assert_eq!(Value::Integer(5) * Value::Float(1.1), Value::Float(5.5));
-        assert_eq!(Value::Float(2.4) * Value::Float(1.3), Value::Float(3.1200001));
-        assert_eq!(Value::Float(5.2) *  Value::Integer(3), Value::Float(15.599999));
+        assert_eq!(Value::Integer(7) * Value::Integer(3), Ok(Value::Integer(21)));
+        assert_eq!(Value::Integer(5) * Value::Float(1.1), Ok(Value::Float(5.5)));
+        assert_eq!(Value::Float(2.4) * Value::Float(1.3), Ok(Value::Float(3.1200001)));
+        assert_eq!(Value::Float(5.2) *  Value::Integer(3), Ok(Value::Float(15.599999)));
     }
 
     #[test]
     fn test_div() {
-        assert_eq!(Value::Integer(21) / Value::Integer(3), Value::Integer(7));
-        assert_eq!(Value::Integer(22) / Value::Float(1.1), Value::Float(20.0));
-        assert_eq!(Value::Float(2.4) / Value::Float(1.3), Value::Float(1.84615396));
-        assert_eq!(Value::Float(5.2) /  Value::Integer(3), Value::Float(1.7333332));
+        assert!(matches!(Value::Integer(21) / Value::Integer(3), Ok(Value::Float(7.0))));
+        assert_eq!(Value::Integer(7) / Value::Integer(2), Ok(Value::Float(3.5)));
+        assert_eq!(Value::Integer(22) / Value::Float(1.1), Ok(Value::Float(20.0)));
+        assert_eq!(Value::Float(2.4) / Value::Float(1.3), Ok(Value::Float(1.846154)));
+        assert_eq!(Value::Float(5.2) /  Value::Integer(3), Ok(Value::Float(1.7333332)));
+        assert_eq!(Value::Float(1.0) / Value::Integer(0), Ok(Value::Float(f32::INFINITY)));
+        assert_eq!(Value::Integer(1) / Value::Integer(0), Err(RuntimeError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_floor_div() {
+        assert_eq!(Value::Integer(7).floor_div(Value::Integer(2)), Ok(Value::Integer(3)));
+        assert_eq!(Value::Integer(-7).floor_div(Value::Integer(2)), Ok(Value::Integer(-4)));
+        assert_eq!(Value::Integer(7).floor_div(Value::Integer(-2)), Ok(Value::Integer(-4)));
+        assert_eq!(Value::Integer(-8).floor_div(Value::Integer(2)), Ok(Value::Integer(-4)));
+        assert!(matches!(Value::Float(7.5).floor_div(Value::Integer(2)), Ok(Value::Float(3.0))));
+        assert_eq!(Value::Integer(1).floor_div(Value::Integer(0)), Err(RuntimeError::DivisionByZero));
+        assert_eq!(Value::Integer(i32::MIN).floor_div(Value::Integer(-1)), Err(RuntimeError::IntegerOverflow));
+        assert!(Value::from("x").floor_div(Value::Integer(1)).is_err());
     }
 
     #[test]
     fn test_eq() {
-        assert_eq!(Value::Integer(3) == Value::Integer(3), true);
-        assert_eq!(Value::Integer(21) == Value::Integer(3), false);
-        assert_eq!(Value::Float(2.0) == Value::Integer(2), false);
-        assert_eq!(Value::Float(2.0) == Value::Float(2.0), true);
-        assert_eq!(Value::Bool(true) == Value::Bool(true), true);
-        assert_eq!(Value::Bool(false) != Value::Bool(true), true);
-        assert_eq!(Value::String("hello world".parse().unwrap()) == Value::String("hello world".parse().unwrap()), true);
-        assert_eq!(Value::String("hello world".parse().unwrap()) == Value::String("goodbye world".parse().unwrap()), false);
+        assert!(Value::Integer(3) == Value::Integer(3));
+        assert!(Value::Integer(21) != Value::Integer(3));
+        assert!(Value::Float(2.0) == Value::Integer(2));
+        assert!(Value::Float(2.5) != Value::Integer(2));
+        assert!(Value::Float(16777216.0) != Value::Integer(16777217));
+        assert!(Value::Float(2.0) == Value::Float(2.0));
+        assert!(Value::Float(f32::NAN) != Value::Float(f32::NAN));
+        assert!(Value::Integer(1) != Value::Bool(true));
+        assert!(Value::Bool(true) == Value::Bool(true));
+        assert!(Value::Bool(false) != Value::Bool(true));
+        assert!(Value::from("hello world") == Value::from("hello world"));
+        assert!(Value::from("hello world") != Value::from("goodbye world"));
     }
 
     #[test]
     fn test_cmp() {
-        assert_eq!(Value::Integer(6) > Value::Integer(3), true);
-        assert_eq!(Value::Integer(6) < Value::Integer(30), true);
-        assert_eq!(Value::Float(6.1) > Value::Float(3.5), true);
+        assert!(Value::Integer(6) > Value::Integer(3));
+        assert!(Value::Integer(6) < Value::Integer(30));
+        assert!(Value::Float(6.1) > Value::Float(3.5));
+        assert!(Value::Integer(6) < Value::Float(6.5));
+        assert!(Value::Float(-0.5) < Value::Integer(0));
+        assert!(Value::from("apple") < Value::from("banana"));
+        assert_eq!(Value::Integer(6).partial_cmp(&Value::Bool(true)), None);
+        assert_eq!(Value::Integer(6).partial_cmp(&Value::from("6")), None);
+    }
+
+    #[test]
+    fn test_invalid_operation() {
+        assert!((Value::Bool(true) - Value::Integer(1)).is_err());
+        assert!((Value::Null + Value::Integer(1)).is_err());
+        assert_eq!((Value::Integer(1) * Value::from("x")).unwrap_err().to_string(), "type error: can not multiply integer and string");
+    }
+
+    // values are cloned by most instructions, so every variant holds at most one pointer
+    #[test]
+    fn test_size() {
+        assert_eq!(core::mem::size_of::<Value>(), 2 * core::mem::size_of::<usize>());
+    }
+
+    #[test]
+    fn test_integer_overflow() {
+        assert_eq!(Value::Integer(i32::MAX) + Value::Integer(1), Err(RuntimeError::IntegerOverflow));
+        assert_eq!(Value::Integer(i32::MIN) - Value::Integer(1), Err(RuntimeError::IntegerOverflow));
+        assert_eq!(Value::Integer(i32::MAX) * Value::Integer(2), Err(RuntimeError::IntegerOverflow));
+        assert_eq!(Value::Integer(i32::MIN).floor_div(Value::Integer(-1)), Err(RuntimeError::IntegerOverflow));
+        assert_eq!(Value::Integer(1) / Value::Integer(0), Err(RuntimeError::DivisionByZero));
+        assert_eq!(Value::Integer(2).pow(Value::Integer(40)), Err(RuntimeError::IntegerOverflow));
+    }
+
+    #[test]
+    fn test_pow() {
+        assert_eq!(Value::Integer(2).pow(Value::Integer(10)), Ok(Value::Integer(1024)));
+        assert_eq!(Value::Integer(2).pow(Value::Integer(-1)), Ok(Value::Float(0.5)));
+        assert_eq!(Value::Float(4.0).pow(Value::Float(0.5)), Ok(Value::Float(2.0)));
+        assert!(Value::Bool(true).pow(Value::Integer(1)).is_err());
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_checked() {
+        assert_eq!(Value::Integer(16777217).check_coercion(&Value::Float(0.0), false), Err(RuntimeError::InexactFloat(16777217)));
+        assert_eq!(Value::Float(0.0).check_coercion(&Value::Integer(16777217), false), Err(RuntimeError::InexactFloat(16777217)));
+        assert_eq!(Value::Integer(1).check_coercion(&Value::Integer(16777217), true), Err(RuntimeError::InexactFloat(16777217)));
+        assert_eq!(Value::Integer(1).check_coercion(&Value::Integer(16777217), false), Ok(()));
+        assert_eq!(Value::Integer(16777216).check_coercion(&Value::Float(0.5), false), Ok(()));
+        assert_eq!(Value::from("x").check_coercion(&Value::Integer(16777217), true), Ok(()));
+
+        assert_eq!(Value::Float(f32::NAN).check_comparable(&Value::Integer(1)), Err(RuntimeError::NotANumber));
+        assert_eq!(Value::Integer(1).check_comparable(&Value::Float(f32::NAN)), Err(RuntimeError::NotANumber));
+        assert_eq!(Value::Float(f32::INFINITY).check_comparable(&Value::Float(1.0)), Ok(()));
+    }
+
+    // mostly small integers, with the edges that overflow
+    fn integer() -> impl Strategy<Value = i32> {
+        prop_oneof![
+            6 => -1000..=1000,
+            1 => any::<i32>(),
+            1 => prop::sample::select(vec![0, 1, -1, i32::MAX, i32::MIN, 16777217]),
+        ]
+    }
+
+    // any finite float, or a small one that integers can meet exactly
+    fn float() -> impl Strategy<Value = f32> {
+        prop_oneof![
+            3 => (-1000..=1000).prop_map(|quarters| quarters as f32 / 4.0),
+            1 => prop::num::f32::NORMAL | prop::num::f32::SUBNORMAL | prop::num::f32::ZERO,
+        ]
+    }
+
+    fn string() -> impl Strategy<Value = String> {
+        "[abZ1 é]{0,3}"
+    }
+
+    fn number() -> impl Strategy<Value = Value> {
+        prop_oneof![integer().prop_map(Value::Integer), float().prop_map(Value::Float)]
+    }
+
+    fn value() -> impl Strategy<Value = Value> {
+        prop_oneof![integer().prop_map(Value::Integer), float().prop_map(Value::Float), string().prop_map(Value::from)]
+    }
+
+    proptest! {
+
+        #[test]
+        fn test_property_add_commutes(a in number(), b in number(), x: bool, y: bool) {
+            prop_assert_eq!(a.clone() + b.clone(), b.clone() + a.clone());
+            prop_assert_eq!(a.clone() * b.clone(), b * a);
+            prop_assert_eq!(Value::Bool(x) + Value::Bool(y), Value::Bool(y) + Value::Bool(x));
+        }
+
+        #[test]
+        fn test_property_add_strings_joins_text(a in value(), b in value()) {
+            prop_assume!(matches!((&a, &b), (Value::String(_), _) | (_, Value::String(_))));
+            let joined = format!("{}{}", a, b);
+            prop_assert_eq!(a + b, Ok(Value::from(joined)));
+        }
+
+        #[test]
+        fn test_property_identity(integer in integer().prop_map(Value::Integer), float in float().prop_map(Value::Float), string in string().prop_map(Value::from)) {
+            prop_assert_eq!(integer.clone() + Value::Integer(0), Ok(integer.clone()));
+            prop_assert_eq!(integer.clone() - Value::Integer(0), Ok(integer.clone()));
+            prop_assert_eq!(integer.clone() * Value::Integer(1), Ok(integer.clone()));
+            prop_assert_eq!(integer.clone().floor_div(Value::Integer(1)), Ok(integer));
+
+            prop_assert_eq!(float.clone() + Value::Float(0.0), Ok(float.clone()));
+            prop_assert_eq!(float.clone() * Value::Float(1.0), Ok(float.clone()));
+            prop_assert_eq!(float.clone() / Value::Float(1.0), Ok(float));
+
+            prop_assert_eq!(string.clone() + Value::from(""), Ok(string.clone()));
+            prop_assert_eq!(Value::from("") + string.clone(), Ok(string));
+        }
+
+        #[test]
+        fn test_property_ordering_is_consistent(a in value(), b in value(), c in value()) {
+            // equality agrees with ordering, and ordering swaps with its operands
+            prop_assert_eq!(a == b, a.partial_cmp(&b) == Some(Ordering::Equal));
+            prop_assert_eq!(a.partial_cmp(&b), b.partial_cmp(&a).map(Ordering::reverse));
+
+            // numbers order against numbers and strings against strings, but never each other
+            prop_assert_eq!(a.partial_cmp(&b).is_some(), a.is_number() == b.is_number());
+
+            // ordering and equality are transitive
+            if a <= b && b <= c {
+                prop_assert!(a <= c, "{:?} <= {:?} <= {:?}", a, b, c);
+            }
+            if a == b && b == c {
+                prop_assert_eq!(&a, &c);
+            }
+
+            // integers order the same way as their difference
+            if let (Value::Integer(x), Value::Integer(y)) = (&a, &b) {
+                if let Some(difference) = x.checked_sub(*y) {
+                    prop_assert_eq!(a.partial_cmp(&b), difference.partial_cmp(&0));
+                }
+            }
+        }
+
+    }
+
+}
@@ -0,0 +1,125 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
+use crate::vm::value::Value;
+
+// ArrayView
+//
+// part of an array that is read in place, so a script can work through a large array from the
+// host a piece at a time without copying it. the part is fixed when the view is made, and if the
+// array shrinks afterwards the view only covers the items still there. changing items through a
+// view is refused, as the array may be shared with the host
+#[derive(Clone, Debug)]
+pub struct ArrayView {
+    items: Rc<RefCell<Vec<Value>>>,
+    start: usize,
+    end: usize,
+}
+
+impl ArrayView {
+
+    // the items from start up to but not including end
+    pub fn new(items: Rc<RefCell<Vec<Value>>>, start: i32, end: i32) -> Result<ArrayView, RuntimeError> {
+        let len = items.borrow().len();
+        let (start, end) = bounds(start, end, len)?;
+        Ok(ArrayView { items, start, end })
+    }
+
+    // a view of part of this view, reading the same array
+    pub fn view(&self, start: i32, end: i32) -> Result<ArrayView, RuntimeError> {
+        let (start, end) = bounds(start, end, self.len())?;
+        Ok(ArrayView { items: self.items.clone(), start: self.start + start, end: self.start + end })
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.borrow().len().min(self.end).saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<Value> {
+        self.with_items(|items| items.get(index).cloned())
+    }
+
+    // work on the items without copying them
+    pub fn with_items<R>(&self, f: impl FnOnce(&[Value]) -> R) -> R {
+        let items = self.items.borrow();
+        f(items.get(self.start..self.end.min(items.len())).unwrap_or_default())
+    }
+
+    pub fn array(&self) -> &Rc<RefCell<Vec<Value>>> {
+        &self.items
+    }
+
+    pub fn range(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+
+    // a view of a saved array, without checking the array as it may not be filled in yet
+    pub fn restore(items: Rc<RefCell<Vec<Value>>>, start: usize, end: usize) -> ArrayView {
+        ArrayView { items, start, end }
+    }
+
+}
+
+fn bounds(start: i32, end: i32, len: usize) -> Result<(usize, usize), RuntimeError> {
+    let start_at = usize::try_from(start).ok().filter(|start| *start <= len).ok_or(RuntimeError::IndexOutOfBounds(start))?;
+    let end_at = usize::try_from(end).ok().filter(|end| *end <= len && *end >= start_at).ok_or(RuntimeError::IndexOutOfBounds(end))?;
+    Ok((start_at, end_at))
+}
+
+// views are equal when they show the same items
+impl PartialEq for ArrayView {
+    fn eq(&self, other: &Self) -> bool {
+        self.with_items(|items| other.with_items(|other| items == other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    use crate::vm::error::RuntimeError;
+    use crate::vm::view::ArrayView;
+    use crate::vm::value::Value;
+
+    fn numbers(count: i32) -> Rc<RefCell<Vec<Value>>> {
+        Rc::new(RefCell::new((0..count).map(Value::Integer).collect()))
+    }
+
+    #[test]
+    fn test_reads_through() {
+        let items = numbers(10);
+        let view = ArrayView::new(items.clone(), 2, 6).unwrap();
+        assert_eq!(view.len(), 4);
+        assert_eq!(view.get(0), Some(Value::Integer(2)));
+        assert_eq!(view.get(4), None);
+
+        // changes to the array show through the view
+        items.borrow_mut()[3] = Value::from("three");
+        assert_eq!(view.get(1), Some(Value::from("three")));
+
+        let inner = view.view(1, 3).unwrap();
+        assert_eq!(inner.with_items(<[Value]>::to_vec), vec![Value::from("three"), Value::Integer(4)]);
+
+        // and a shorter array shortens the view
+        items.borrow_mut().truncate(4);
+        assert_eq!(view.len(), 2);
+        assert_eq!(inner.len(), 1);
+    }
+
+    #[test]
+    fn test_bounds() {
+        let items = numbers(3);
+        assert_eq!(ArrayView::new(items.clone(), 0, 4).unwrap_err(), RuntimeError::IndexOutOfBounds(4));
+        assert_eq!(ArrayView::new(items.clone(), -1, 2).unwrap_err(), RuntimeError::IndexOutOfBounds(-1));
+        assert_eq!(ArrayView::new(items.clone(), 2, 1).unwrap_err(), RuntimeError::IndexOutOfBounds(1));
+        assert!(ArrayView::new(items, 3, 3).unwrap().is_empty());
+    }
+
+}
@@ -0,0 +1,79 @@
+use alloc::rc::{Rc, Weak};
+use core::cell::RefCell;
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
+use crate::vm::value::Value;
+
+// WeakRef
+//
+// a handle to an array, dictionary or object that does not keep it alive, so an observer can
+// point back at what it watches without the two holding each other forever. once nothing else
+// holds the collection the handle gives null
+#[derive(Clone, Debug)]
+pub enum WeakRef {
+    Array(Weak<RefCell<Vec<Value>>>),
+    Dictionary(Weak<RefCell<HashMap<String, Value>>>),
+    Object(Weak<RefCell<HashMap<String, Value>>>),
+}
+
+impl WeakRef {
+
+    pub fn new(value: &Value) -> Result<WeakRef, RuntimeError> {
+        match value {
+            Value::Array(items) => Ok(WeakRef::Array(Rc::downgrade(items))),
+            Value::Dictionary(entries) => Ok(WeakRef::Dictionary(Rc::downgrade(entries))),
+            Value::Object(members) => Ok(WeakRef::Object(Rc::downgrade(members))),
+            value => Err(RuntimeError::TypeError(format!("can not make a weak reference to {}", value.type_name())))
+        }
+    }
+
+    // the collection, or null once it has been dropped
+    pub fn get(&self) -> Value {
+        let value = match self {
+            WeakRef::Array(items) => items.upgrade().map(Value::Array),
+            WeakRef::Dictionary(entries) => entries.upgrade().map(Value::Dictionary),
+            WeakRef::Object(members) => members.upgrade().map(Value::Object),
+        };
+        value.unwrap_or(Value::Null)
+    }
+
+}
+
+// a handle to nothing, as any handle is once its collection has been dropped
+impl Default for WeakRef {
+    fn default() -> Self {
+        WeakRef::Array(Weak::new())
+    }
+}
+
+// handles are equal when they point at the same collection
+impl PartialEq for WeakRef {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (WeakRef::Array(v1), WeakRef::Array(v2)) => v1.ptr_eq(v2),
+            (WeakRef::Dictionary(v1), WeakRef::Dictionary(v2)) | (WeakRef::Object(v1), WeakRef::Object(v2)) => v1.ptr_eq(v2),
+            _ => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::weak::WeakRef;
+    use crate::vm::value::Value;
+
+    #[test]
+    fn test_get_until_dropped() {
+        let target = Value::from(vec![Value::Integer(1)]);
+        let weak = WeakRef::new(&target).unwrap();
+        assert_eq!(weak.get(), target);
+        assert_eq!(weak, WeakRef::new(&target).unwrap());
+
+        drop(target);
+        assert_eq!(weak.get(), Value::Null);
+        assert!(WeakRef::new(&Value::Integer(1)).is_err());
+    }
+
+}
@@ -0,0 +1,144 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+use yaml_rust2::{Yaml, YamlLoader};
+
+use crate::collections::HashMap;
+use crate::prelude::*;
+use crate::vm::error::RuntimeError;
+use crate::vm::value::Value;
+
+// parse yaml text into a value, taking the first document if there are several
+//
+// integers that do not fit in 32 bits become floats, and keys of mappings must be scalars
+pub fn parse(text: &str) -> Result<Value, RuntimeError> {
+    let documents = YamlLoader::load_from_str(text).map_err(|e| error(e.to_string()))?;
+    documents.into_iter().next().map_or(Ok(Value::Null), convert)
+}
+
+fn error(message: String) -> RuntimeError {
+    RuntimeError::TypeError(format!("invalid yaml: {}", message))
+}
+
+fn convert(value: Yaml) -> Result<Value, RuntimeError> {
+    Ok(match value {
+        Yaml::Null => Value::Null,
+        Yaml::Boolean(b) => Value::Bool(b),
+        Yaml::Integer(i) => i32::try_from(i).map(Value::Integer).unwrap_or(Value::Float(i as f32)),
+        Yaml::Real(_) => Value::Float(value.as_f64().ok_or_else(|| error(format!("{:?} is not a number", value)))? as f32),
+        Yaml::String(s) => Value::from(s),
+        Yaml::Array(items) => Value::Array(Rc::new(RefCell::new(items.into_iter().map(convert).collect::<Result<_, _>>()?))),
+        Yaml::Hash(entries) => {
+            let mut map = HashMap::new();
+            for (key, value) in entries {
+                map.insert(key_string(key)?, convert(value)?);
+            }
+            Value::Dictionary(Rc::new(RefCell::new(map)))
+        }
+        Yaml::Alias(_) | Yaml::BadValue => return Err(error(String::from("aliases are not supported")))
+    })
+}
+
+// dictionaries are keyed by strings, so scalar keys are written out as they would be in yaml
+fn key_string(key: Yaml) -> Result<String, RuntimeError> {
+    match key {
+        Yaml::String(s) | Yaml::Real(s) => Ok(s),
+        Yaml::Integer(i) => Ok(i.to_string()),
+        Yaml::Boolean(b) => Ok(b.to_string()),
+        Yaml::Null => Ok(String::from("null")),
+        key => Err(error(format!("{:?} can not be the key of a dictionary", key)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::value::Value;
+    use crate::vm::yaml::parse;
+
+    fn get(value: &Value, path: &str) -> Value {
+        path.split('.').fold(value.clone(), |value, key| match value {
+            Value::Dictionary(d) => d.borrow().get(key).cloned().unwrap_or(Value::Null),
+            Value::Array(a) => a.borrow()[key.parse::<usize>().unwrap()].clone(),
+            _ => Value::Null
+        })
+    }
+
+    fn string(s: &str) -> Value {
+        Value::from(s)
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = parse(r#"
+---
+# server settings
+name: app   # trailing comment
+port: 8080
+ratio: 0.5
+debug: yes
+empty:
+url: "http://localhost:80"
+quote: 'it''s'
+database:
+  host: db
+  pool:
+    size: 4
+tags:
+- a
+- "b"
+servers:
+  - host: one
+    port: 1
+  - host: two
+    ports: [80, 443]
+limits: { read: 10, write: off }
+"#).unwrap();
+
+        assert_eq!(get(&config, "name"), string("app"));
+        assert_eq!(get(&config, "port"), Value::Integer(8080));
+        assert_eq!(get(&config, "ratio"), Value::Float(0.5));
+        assert_eq!(get(&config, "debug"), string("yes"));
+        assert_eq!(get(&config, "empty"), Value::Null);
+        assert_eq!(get(&config, "url"), string("http://localhost:80"));
+        assert_eq!(get(&config, "quote"), string("it's"));
+        assert_eq!(get(&config, "database.host"), string("db"));
+        assert_eq!(get(&config, "database.pool.size"), Value::Integer(4));
+        assert_eq!(get(&config, "tags.1"), string("b"));
+        assert_eq!(get(&config, "servers.0.port"), Value::Integer(1));
+        assert_eq!(get(&config, "servers.1.host"), string("two"));
+        assert_eq!(get(&config, "servers.1.ports.1"), Value::Integer(443));
+        assert_eq!(get(&config, "limits.read"), Value::Integer(10));
+    }
+
+    #[test]
+    fn test_block_scalars() {
+        let config = parse("literal: |\n  one\n    two\n\nfolded: >-\n  one\n  two\nafter: 1\n").unwrap();
+        assert_eq!(get(&config, "literal"), string("one\n  two\n"));
+        assert_eq!(get(&config, "folded"), string("one two"));
+        assert_eq!(get(&config, "after"), Value::Integer(1));
+    }
+
+    #[test]
+    fn test_top_level() {
+        assert_eq!(get(&parse("- 1\n- - 2\n  - 3\n").unwrap(), "1.1"), Value::Integer(3));
+        assert_eq!(parse("").unwrap(), Value::Null);
+        assert_eq!(parse("just text").unwrap(), string("just text"));
+    }
+
+    #[test]
+    fn test_large_numbers() {
+        let config = parse("big: 99999999999\nsmall: -2147483648\n").unwrap();
+        assert_eq!(get(&config, "big"), Value::Float(99999999999.0));
+        assert_eq!(get(&config, "small"), Value::Integer(i32::MIN));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(parse("a: 1\na: 2").is_err());
+        assert!(parse("a: 1\n    b: 2").is_err());
+        assert!(parse("a: \"open").is_err());
+        assert!(parse("a: [1, 2").is_err());
+        assert!(parse("[1]: a").is_err());
+    }
+
+}
@@ -0,0 +1,95 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use tinyscript::{load_with_config, CompilerOptions, Output, Value, VmConfig};
+
+// the function each golden script is run from
+const ENTRY: &str = "Test.main";
+
+// everything a script prints, followed by what it returned or the error it stopped with
+fn run(path: &Path) -> String {
+    let source = fs::read_to_string(path).expect("script should be readable");
+    let output = Output::capture();
+
+    let result = load_with_config(&source, CompilerOptions::default(), VmConfig::new().output(output.clone()))
+        .and_then(|mut vm| Ok(vm.exec(ENTRY, None)?));
+
+    let mut text = output.captured();
+    match result {
+        Ok(Value::Null) => {}
+        Ok(value) => text.push_str(&format!("returned {}\n", value)),
+        Err(e) => text.push_str(&format!("{}\n", e)),
+    }
+    text
+}
+
+// the lines that differ, marked with - for expected and + for actual
+fn diff(expected: &str, actual: &str) -> String {
+    let (expected, actual): (Vec<&str>, Vec<&str>) = (expected.lines().collect(), actual.lines().collect());
+    let mut text = String::new();
+    for line in 0..expected.len().max(actual.len()) {
+        if expected.get(line) != actual.get(line) {
+            if let Some(expected) = expected.get(line) {
+                text.push_str(&format!("  {:>3} - {}\n", line + 1, expected));
+            }
+            if let Some(actual) = actual.get(line) {
+                text.push_str(&format!("  {:>3} + {}\n", line + 1, actual));
+            }
+        }
+    }
+    text
+}
+
+// usage: cargo test --test golden -- [filter] [--bless]
+fn main() -> ExitCode {
+    let mut filter = None;
+    let mut bless = false;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--bless" => bless = true,
+            // options meant for the default test harness
+            _ if arg.starts_with('-') => {}
+            _ => filter = Some(arg),
+        }
+    }
+
+    let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+    let mut scripts: Vec<PathBuf> = fs::read_dir(&directory).expect("golden directory should exist")
+        .map(|entry| entry.expect("golden directory should be readable").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "tny"))
+        .filter(|path| filter.as_ref().is_none_or(|filter| path.to_string_lossy().contains(filter.as_str())))
+        .collect();
+    scripts.sort();
+
+    let mut failed = 0;
+    for script in scripts.iter() {
+        let name = script.file_stem().expect("script should have a name").to_string_lossy();
+        let expected_path = script.with_extension("expected");
+        let actual = run(script);
+
+        if bless {
+            fs::write(&expected_path, &actual).expect("expected output should be written");
+            println!("blessed {}", name);
+            continue;
+        }
+
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => println!("ok      {}", name),
+            Ok(expected) => {
+                failed += 1;
+                println!("FAILED  {}\n{}", name, diff(&expected, &actual));
+            }
+            Err(_) => {
+                failed += 1;
+                println!("FAILED  {} has no {}, run with --bless to create it", name, expected_path.display());
+            }
+        }
+    }
+
+    println!("\n{} golden scripts, {} failed", scripts.len(), failed);
+    if failed > 0 {
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
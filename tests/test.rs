@@ -1,43 +1,94 @@
-use tinyscript::{run};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::Arc;
+use tinyscript::{evaluate_expression, evaluate_expression_with_sandbox, load, load_with_config, load_with_sandbox, render_template, run, run_named, run_with_options, value, CompileError, CompilerOptions, EmbeddedResolver, MemoryResolver, Output, RuntimeError, Sandbox, TinyscriptError, VmConfig};
+use tinyscript::vm::host::HostObject;
+use tinyscript::vm::image::{FrameImage, HeapValue, ImageValue, VmImage};
+use tinyscript::vm::program::Program;
 use tinyscript::vm::value::Value;
+use tinyscript::vm::VM;
 
 // HELLO WORLD
 
 #[test]
 fn hello_world() {
-    assert_eq!(run(include_str!("scripts/hello_world.tny"), "HelloWorld.main", None).unwrap(), Value::Null);
+    assert_eq!(run(include_str!("scripts/hello_world.tny"), "HelloWorld.test", None).unwrap(), Value::Null);
+}
+
+#[test]
+fn print_callback() {
+    let lines = Rc::new(RefCell::new(Vec::new()));
+    let printed = lines.clone();
+    let output = Output::callback(move |line| printed.borrow_mut().push(line.to_string()));
+    let script = r#"class Hello { function main() { print "hello"; print 1 + 2; } }"#;
+    let mut vm = load_with_config(script, CompilerOptions::default(), VmConfig::new().output(output.clone())).unwrap();
+    vm.exec("Hello.main", None).unwrap();
+    assert_eq!(*lines.borrow(), vec![String::from("\"hello\""), String::from("\"3\"")]);
+    assert_eq!(output.captured(), "");
 }
 
 // VARIABLES
 
 #[test]
 fn integers() {
-    assert_eq!(run(include_str!("scripts/var_integers.tny"), "Test.main", None).unwrap(), Value::Null);
+    assert_eq!(run(include_str!("scripts/var_integers.tny"), "Test.test", None).unwrap(), Value::Null);
 }
 
 #[test]
 fn floats() {
-    assert_eq!(run(include_str!("scripts/var_floats.tny"), "Test.main", None).unwrap(), Value::Null);
+    assert_eq!(run(include_str!("scripts/var_floats.tny"), "Test.test", None).unwrap(), Value::Null);
 }
 
 #[test]
 fn booleans() {
-    assert_eq!(run(include_str!("scripts/var_booleans.tny"), "Test.main", None).unwrap(), Value::Null);
+    assert_eq!(run(include_str!("scripts/var_booleans.tny"), "Test.test", None).unwrap(), Value::Null);
 }
 
 #[test]
 fn dictionary() {
-    assert_eq!(run(include_str!("scripts/var_dictionary.tny"), "Test.main", None).unwrap(), Value::Null);
+    assert_eq!(run(include_str!("scripts/var_dictionary.tny"), "Test.test", None).unwrap(), Value::Null);
 }
 
 #[test]
 fn arrays() {
-    assert_eq!(run(include_str!("scripts/var_arrays.tny"), "Test.main", None).unwrap(), Value::Null);
+    assert_eq!(run(include_str!("scripts/var_arrays.tny"), "Test.test", None).unwrap(), Value::Null);
 }
 
 #[test]
 fn strings() {
-    assert_eq!(run(include_str!("scripts/var_strings.tny"), "Test.main", None).unwrap(), Value::Null);
+    assert_eq!(run(include_str!("scripts/var_strings.tny"), "Test.test", None).unwrap(), Value::Null);
+}
+
+#[test]
+fn block_scopes() {
+    assert_eq!(run(include_str!("scripts/var_scopes.tny"), "Test.main", None).unwrap(), Value::Null);
+
+    let error = |body: &str| match run(&format!("class Test {{ function main() {{ {} }} }}", body), "Test.main", None) {
+        Err(TinyscriptError::Compile(CompileError::Function(_, e))) => *e,
+        result => panic!("expected a compile error, got {:?}", result)
+    };
+    assert_eq!(error("if true { var a = 1; } return a;"), CompileError::OutOfScope(String::from("a")));
+    assert_eq!(error("for (var i = 0; i < 2; i = i + 1) { } i = 3;"), CompileError::OutOfScope(String::from("i")));
+    assert_eq!(error("while true { var a; var a; }"), CompileError::DuplicateVariable(String::from("a")));
+
+    // blocks that have ended give their slots to the blocks after them
+    let program = tinyscript::compile("class Test { function main() { if true { var a; } if true { var b; } } }").unwrap();
+    assert_eq!(program.metadata["Test.main"].local_slots, 2);
+    assert_eq!(program.locals["Test.main"], vec![String::from("this"), String::from("a/b")]);
+}
+
+#[test]
+fn temporary_slots() {
+    let program = tinyscript::compile(include_str!("scripts/var_temps.tny")).unwrap();
+    // this, the value matched, two parts of it at a time and the four names bound, with the
+    // loops after the match reusing its slots
+    assert_eq!(program.metadata["Test.main"].local_slots, 8);
+    assert_eq!(VM::new(program).exec("Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+fn string_literals() {
+    assert_eq!(run(include_str!("scripts/var_strings_literals.tny"), "Test.main", None).unwrap(), Value::Null);
 }
 
 #[test]
@@ -45,6 +96,233 @@ fn chain() {
     assert_eq!(run(include_str!("scripts/var_chain.tny"), "Test.main", None).unwrap(), Value::Null);
 }
 
+#[test]
+fn string_builder() {
+    assert_eq!(run(include_str!("scripts/string_builder.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+fn string_methods() {
+    assert_eq!(run(include_str!("scripts/string_methods.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+fn unicode_strings() {
+    assert_eq!(run(include_str!("scripts/unicode_strings.tny"), "Test.main", None).unwrap(), Value::from("**café"));
+    assert_eq!(run(include_str!("scripts/unicode_strings.tny"), "Test.past_end", None), Err(TinyscriptError::Runtime(RuntimeError::IndexOutOfBounds(1))));
+}
+
+#[test]
+#[cfg(feature = "graphemes")]
+fn grapheme_strings() {
+    // an e with a combining accent and a flag are two chars each but one grapheme
+    let text = "e\u{301}\u{1F1EC}\u{1F1E7}!";
+    let mut vm = load_with_config(include_str!("scripts/unicode_strings.tny"), CompilerOptions::default(), VmConfig::new().strings(tinyscript::StringUnits::Graphemes)).unwrap();
+    let result = vm.exec("Test.graphemes", Some(vec![Value::from(text)])).unwrap();
+    assert_eq!(result, value!([3, ["e\u{301}", "\u{1F1EC}\u{1F1E7}", "!"], "e\u{301}"]));
+
+    let mut vm = load(include_str!("scripts/unicode_strings.tny"), CompilerOptions::default()).unwrap();
+    let result = vm.exec("Test.graphemes", Some(vec![Value::from(text)])).unwrap();
+    assert_eq!(result, value!([5, ["e\u{301}", "\u{1F1EC}\u{1F1E7}", "!"], "e"]));
+}
+
+#[test]
+fn array_methods() {
+    assert_eq!(run(include_str!("scripts/array_methods.tny"), "Test.main", None).unwrap(), value!(["banana", "kiwi", "fig"]));
+    assert!(matches!(run(include_str!("scripts/array_methods.tny"), "Test.bad_key", None), Err(TinyscriptError::Runtime(RuntimeError::TypeError(_)))));
+    assert_eq!(run(include_str!("scripts/array_methods.tny"), "Test.unknown", Some(vec![value!(1)])), Err(TinyscriptError::Runtime(RuntimeError::UnknownMethod(String::from("array"), String::from("total")))));
+    assert_eq!(run(include_str!("scripts/array_methods.tny"), "Test.extra_argument", None), Err(TinyscriptError::Runtime(RuntimeError::TypeError(String::from("array sum takes no arguments")))));
+}
+
+#[test]
+fn sets() {
+    assert_eq!(run(include_str!("scripts/sets.tny"), "Test.main", None).unwrap(), Value::Integer(0));
+    assert_eq!(run(include_str!("scripts/sets.tny"), "Test.bad_item", None), Err(TinyscriptError::Runtime(RuntimeError::TypeError(String::from("can not add float to a set")))));
+}
+
+#[test]
+fn deques() {
+    assert_eq!(run(include_str!("scripts/deques.tny"), "Test.main", None).unwrap(), Value::Integer(6));
+    assert_eq!(run(include_str!("scripts/deques.tny"), "Test.ends", None).unwrap(), Value::from(vec![Value::Integer(2)]));
+}
+
+#[test]
+fn priority_queues() {
+    let distances = run(include_str!("scripts/priority_queues.tny"), "Test.main", None).unwrap();
+    assert_eq!(distances.get_path("d"), Value::Integer(4));
+    assert_eq!(distances.get_path("b"), Value::Integer(3));
+    assert_eq!(run(include_str!("scripts/priority_queues.tny"), "Test.ties", None).unwrap(), Value::from(vec![Value::from("first"), Value::from("second"), Value::from("third")]));
+    assert_eq!(run(include_str!("scripts/priority_queues.tny"), "Test.bad_priority", None), Err(TinyscriptError::Runtime(RuntimeError::TypeError(String::from("can not use string as a priority")))));
+}
+
+#[test]
+fn weakref() {
+    // nothing holds the subject once main has returned
+    let Value::WeakRef(subject) = run(include_str!("scripts/weakref.tny"), "Test.main", None).unwrap() else { panic!("expected a weak reference") };
+    assert_eq!(subject.get(), Value::Null);
+    assert_eq!(run(include_str!("scripts/weakref.tny"), "Test.bad_target", None), Err(TinyscriptError::Runtime(RuntimeError::TypeError(String::from("can not make a weak reference to integer")))));
+}
+
+#[test]
+fn identity() {
+    assert_eq!(run(include_str!("scripts/identity.tny"), "Test.main", None).unwrap(), Value::Bool(true));
+    assert_eq!(run(include_str!("scripts/identity.tny"), "Test.bad_id", None), Err(TinyscriptError::Runtime(RuntimeError::TypeError(String::from("integer is not a reference so has no id")))));
+    assert_eq!(run(include_str!("scripts/identity.tny"), "Test.bad_hash", None), Err(TinyscriptError::Runtime(RuntimeError::TypeError(String::from("can not hash array")))));
+}
+
+#[test]
+fn array_views() {
+    let readings = Value::from((0..1000).map(Value::Integer).collect::<Vec<_>>());
+    assert_eq!(run(include_str!("scripts/array_views.tny"), "Test.main", Some(vec![readings])).unwrap(), Value::Integer(24950));
+    assert_eq!(run(include_str!("scripts/array_views.tny"), "Test.write", None), Err(TinyscriptError::Runtime(RuntimeError::TypeError(String::from("can not change an array through a view")))));
+}
+
+#[test]
+fn format() {
+    assert_eq!(run(include_str!("scripts/format.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+fn superinstructions() {
+    assert_eq!(run(include_str!("scripts/superinstructions.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+fn get_path() {
+    assert_eq!(run(include_str!("scripts/get_path.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+fn host_values() {
+    let config = value!({ "server": { "port": 8080, "hosts": ["a", "b"] }, "debug": true });
+    assert_eq!(run(include_str!("scripts/host_values.tny"), "Test.main", Some(vec![config])).unwrap(), value!("a"));
+}
+
+#[test]
+fn named_params() {
+    let params = [(String::from("name"), value!("ada")), (String::from("greeting"), value!("hello"))].into_iter().collect();
+    assert_eq!(run_named(include_str!("scripts/named_params.tny"), "Test.greet", params).unwrap(), value!("hello ada"));
+
+    let params = [(String::from("nme"), value!("ada"))].into_iter().collect();
+    let error = run_named(include_str!("scripts/named_params.tny"), "Test.greet", params).unwrap_err();
+    assert_eq!(error, TinyscriptError::Runtime(RuntimeError::UnknownParameter(String::from("Test.greet"), String::from("nme"))));
+}
+
+#[test]
+fn return_null() {
+    assert_eq!(run(include_str!("scripts/return_null.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+fn return_types() {
+    let mut vm = load(include_str!("scripts/return_types.tny"), CompilerOptions::default()).unwrap();
+    assert_eq!(vm.call_expecting::<i64>("Test.score", Some(vec![value!(2)])), Ok(42));
+    assert_eq!(vm.call_expecting::<String>("Test.name", None), Ok(String::from("ada")));
+    assert_eq!(vm.call_expecting::<Vec<i32>>("Test.scores", None), Ok(vec![1, 2, 3]));
+    assert_eq!(vm.call_expecting::<bool>("Test.name", None), Err(RuntimeError::ReturnTypeMismatch(String::from("bool"), String::from("string"))));
+}
+
+#[test]
+fn call_batch() {
+    let mut vm = load(include_str!("scripts/return_types.tny"), CompilerOptions::default()).unwrap();
+    let results = vm.call_batch(&[
+        ("Test.score", vec![value!(1)]),
+        ("Test.name", vec![]),
+        ("Test.score", vec![value!(null)]),
+        ("Test.score", vec![value!(2)]),
+    ]);
+    assert_eq!(results[0], Ok(value!(41)));
+    assert_eq!(results[1], Ok(value!("ada")));
+    assert!(results[2].is_err());
+    assert_eq!(results[3], Ok(value!(42)));
+}
+
+#[test]
+fn unknown_entry() {
+    let mut vm = load(include_str!("scripts/return_types.tny"), CompilerOptions::default()).unwrap();
+    let unknown = || RuntimeError::UnknownFunction(String::from("Test.missing"));
+    assert_eq!(vm.exec("Test.missing", None), Err(unknown()));
+    assert_eq!(vm.exec_named("Test.missing", Default::default()), Err(unknown()));
+    assert_eq!(vm.call_expecting::<i64>("Test.missing", None), Err(unknown()));
+    assert_eq!(vm.call_batch(&[("Test.missing", vec![]), ("Test.name", vec![])]), vec![Err(unknown()), Ok(value!("ada"))]);
+}
+
+#[test]
+fn literals_are_fresh() {
+    let mut vm = load(include_str!("scripts/arena.tny"), CompilerOptions::default()).unwrap();
+    let first = vm.exec("Test.build", Some(vec![value!(2)])).unwrap();
+    assert_eq!(vm.exec("Test.build", Some(vec![value!(3)])).unwrap(), value!([0, 1, 2]));
+    assert_eq!(first, value!([0, 1]));
+}
+
+#[test]
+fn arena() {
+    let mut vm = load_with_config(include_str!("scripts/arena.tny"), CompilerOptions::default(), VmConfig::new().arena(true)).unwrap();
+    let first = vm.exec("Test.build", Some(vec![value!(5)])).unwrap();
+    let reusable = vm.arena().unwrap().free();
+    assert!(reusable > 0);
+
+    // the returned array escaped so it is kept as it was
+    assert_eq!(vm.exec("Test.build", Some(vec![value!(5)])).unwrap(), value!([0, 1, 2, 3, 4]));
+    assert_eq!(first, value!([0, 1, 2, 3, 4]));
+    assert!(vm.arena().unwrap().free() >= reusable);
+}
+
+#[test]
+fn csv() {
+    assert_eq!(run(include_str!("scripts/csv.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+#[cfg(feature = "hash")]
+fn hash() {
+    assert_eq!(run(include_str!("scripts/hash.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+#[cfg(feature = "uuid")]
+fn uuid() {
+    assert_eq!(run(include_str!("scripts/uuid.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+#[cfg(feature = "toml")]
+fn toml() {
+    assert_eq!(run(include_str!("scripts/toml.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+#[cfg(feature = "yaml")]
+fn yaml() {
+    assert_eq!(run(include_str!("scripts/yaml.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+fn expect() {
+    assert_eq!(run(include_str!("scripts/expect.tny"), "Test.main", None).unwrap(), Value::Null);
+    let failed = run(include_str!("scripts/expect.tny"), "Test.failing", None);
+    assert_eq!(failed, Err(TinyscriptError::Runtime(RuntimeError::ExpectationFailed(String::from(r#"expected ["a", 1] to equal ["a", "1"]"#)))));
+}
+
+#[test]
+#[cfg(feature = "bigint")]
+fn bigint() {
+    assert_eq!(run(include_str!("scripts/bigint.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+#[cfg(feature = "decimal")]
+fn decimal() {
+    assert_eq!(run(include_str!("scripts/decimal.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+#[cfg(not(feature = "bigint"))]
+fn exact_literals_need_feature() {
+    let result = run("class Test { function main() { return 10n; } }", "Test.main", None);
+    assert_eq!(result, Err(TinyscriptError::Compile(CompileError::Function(String::from("Test.main"), Box::new(CompileError::FeatureNotEnabled(String::from("bigint"), String::from("10n")))))));
+}
+
 // IFS
 
 #[test]
@@ -67,11 +345,49 @@ fn if_else_false() {
     assert_eq!(run(include_str!("scripts/if_else_false.tny"), "Test.test", None).unwrap(), Value::Null);
 }
 
+#[test]
+fn if_nested() {
+    assert_eq!(run(include_str!("scripts/if_nested.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+// SPREAD
+
+#[test]
+fn spread() {
+    assert_eq!(run(include_str!("scripts/spread.tny"), "Test.main", None).unwrap(), Value::Null);
+
+    let error = run("class Test { function main() { return [...1]; } }", "Test.main", None);
+    assert_eq!(error, Err(TinyscriptError::Runtime(RuntimeError::TypeError(String::from("can not spread integer into an array")))));
+    let error = run("class Test { function main() { return {...[1]}; } }", "Test.main", None);
+    assert_eq!(error, Err(TinyscriptError::Runtime(RuntimeError::TypeError(String::from("can not spread array into a dictionary")))));
+}
+
+// MATCH
+
+#[test]
+fn match_patterns() {
+    assert_eq!(run(include_str!("scripts/match.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+fn match_invalid_patterns() {
+    let error = |pattern: &str| {
+        let script = format!("class P {{ var x; }} class Test {{ function main(v) {{ return match v {{ {} => 1 }}; }} }}", pattern);
+        match run(&script, "Test.main", None) {
+            Err(TinyscriptError::Compile(CompileError::Function(_, e))) => *e,
+            result => panic!("expected a compile error, got {:?}", result)
+        }
+    };
+    assert_eq!(error("P(a, b)"), CompileError::InvalidPattern(String::from("class 'P' has 1 fields but the pattern has 2")));
+    assert_eq!(error("[.., a]"), CompileError::InvalidPattern(String::from("'..' can only end an array pattern")));
+    assert_eq!(error("Q(a)"), CompileError::UnknownClass(String::from("Q")));
+}
+
 // CLASSES
 
 #[test]
 fn test_class() {
-    assert_eq!(run(include_str!("scripts/class_simple.tny"), "Test.main", None).unwrap(), Value::Null);
+    assert_eq!(run(include_str!("scripts/class_simple.tny"), "FunctionTest.main", None).unwrap(), Value::Null);
 }
 
 #[test]
@@ -79,21 +395,54 @@ fn test_this() {
     assert_eq!(run(include_str!("scripts/class_this.tny"), "Test.main", None).unwrap(), Value::Null);
 }
 
+#[test]
+fn fluent_calls() {
+    assert_eq!(run(include_str!("scripts/fluent.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
 // LOOPS
 
 #[test]
 fn for_i_loop() {
-    assert_eq!(run(include_str!("scripts/loop_for_i.tny"), "Test.main", None).unwrap(), Value::Null);
+    assert_eq!(run(include_str!("scripts/loop_for_i.tny"), "Test.test", None).unwrap(), Value::Null);
 }
 
 #[test]
 fn for_in_loop() {
-    assert_eq!(run(include_str!("scripts/loop_for_in.tny"), "Test.main", None).unwrap(), Value::Null);
+    assert_eq!(run(include_str!("scripts/loop_for_in.tny"), "Test.test", None).unwrap(), Value::Null);
 }
 
 #[test]
 fn while_loop() {
-    assert_eq!(run(include_str!("scripts/loop_while.tny"), "Test.main", None).unwrap(), Value::Null);
+    assert_eq!(run(include_str!("scripts/loop_while.tny"), "Test.test", None).unwrap(), Value::Null);
+}
+
+#[test]
+fn do_while_loop() {
+    assert_eq!(run(include_str!("scripts/loop_do_while.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+fn break_and_continue() {
+    assert_eq!(run(include_str!("scripts/loop_break.tny"), "Test.main", None).unwrap(), Value::Null);
+
+    let error = |body: &str| match run(&format!("class Test {{ function main() {{ {} }} }}", body), "Test.main", None) {
+        Err(TinyscriptError::Compile(CompileError::Function(_, e))) => *e,
+        result => panic!("expected a compile error, got {:?}", result)
+    };
+    assert_eq!(error("break;"), CompileError::OutsideLoop(String::from("break")));
+    assert_eq!(error("if true { continue; }"), CompileError::OutsideLoop(String::from("continue")));
+    assert_eq!(error("a: while true { } while true { break a; }"), CompileError::UnknownLoop(String::from("a")));
+}
+
+#[test]
+fn loop_condition_calls() {
+    let program = tinyscript::compile(include_str!("scripts/loop_calls.tny")).unwrap();
+    let bytes = program.to_bytes();
+    assert_eq!(VM::new(program).exec("Test.main", None).unwrap(), Value::Null);
+
+    // jumps around the calls still land after the program is written and read back
+    assert_eq!(VM::new(Program::from_bytes(&bytes).unwrap()).exec("Test.main", None).unwrap(), Value::Null);
 }
 
 // COMPLEX SCRIPTS
@@ -102,3 +451,803 @@ fn while_loop() {
 fn fibonacci() {
     assert_eq!(run(include_str!("scripts/fib.tny"), "Test.main", None).unwrap(), Value::Null);
 }
+
+// timed with the std clock
+#[cfg(feature = "std")]
+#[test]
+fn fibonacci_profile() {
+    let mut vm = load_with_config(include_str!("scripts/fib.tny"), CompilerOptions::default(), VmConfig::new().profile(true)).unwrap();
+    vm.exec("Test.main", None).unwrap();
+
+    let profile = vm.profile().unwrap();
+    assert_eq!(profile.functions()["Test.main"].calls, 1);
+    assert_eq!(profile.functions()["Fibonacci.fib_quick"].calls, 1);
+    assert_eq!(profile.functions()["Fibonacci.fib"].calls, 177);
+
+    let folded = profile.to_folded();
+    assert!(folded.lines().any(|l| l.starts_with("Test.main;Fibonacci.fib_quick;Fibonacci.fib;Fibonacci.fib ")));
+}
+
+// DOC COMMENTS
+
+#[test]
+fn doc_comments() {
+    let mut vm = load(include_str!("scripts/doc_comments.tny"), CompilerOptions::default()).unwrap();
+    assert_eq!(vm.doc("Greeter"), Some("Greets people.\nUsed by the hover test."));
+    assert_eq!(vm.doc("Greeter.name"), Some("name used when none is given"));
+    assert_eq!(vm.doc("Greeter.constructor"), Some("Set up the greeter."));
+    assert_eq!(vm.doc("Greeter.greet"), Some("   Say hello, keeping  inner  spacing."));
+    assert_eq!(vm.doc("Greeter.main"), None);
+    assert_eq!(vm.doc("Greeter.undocumented"), None);
+    assert_eq!(vm.exec("Greeter.main", None).unwrap(), Value::Null);
+}
+
+// NAMESPACES
+
+#[test]
+fn namespaces() {
+    let script = include_str!("scripts/namespaces.tny");
+    assert_eq!(run(script, "game.ai.Bot.main", None).unwrap(), Value::Bool(true));
+    assert_eq!(run(script, "game.npc.Bot.update", None).unwrap(), Value::from("npc".to_string()));
+}
+
+// IMPORTS
+
+// read from disk
+#[cfg(feature = "fs")]
+#[test]
+fn imports() {
+    assert_eq!(run(include_str!("scripts/imports.tny"), "Test.main", None).unwrap(), Value::Bool(true));
+}
+
+#[cfg(feature = "fs")]
+#[test]
+fn imports_visibility() {
+    let aliased = r#"import "tests/scripts/modules/vector.tny" as V; class Test { function main() { var m = new Maths(); } }"#;
+    match run(aliased, "Test.main", None) {
+        Err(TinyscriptError::Compile(CompileError::Function(_, e))) => assert_eq!(*e, CompileError::UnknownClass("Maths".to_string())),
+        other => panic!("expected unknown class, got {:?}", other),
+    }
+
+    let missing = r#"from "tests/scripts/modules/vector.tny" import Vec3; class Test { function main() { } }"#;
+    match run(missing, "Test.main", None) {
+        Err(TinyscriptError::Compile(CompileError::UnknownImport(_, name))) => assert_eq!(name, "Vec3"),
+        other => panic!("expected unknown import, got {:?}", other),
+    }
+
+    let duplicate = r#"import "tests/scripts/modules/vector.tny"; class Vec2 { }"#;
+    match run(duplicate, "Vec2.constructor", None) {
+        Err(TinyscriptError::Compile(CompileError::Module(_, e))) => assert_eq!(*e, CompileError::DuplicateClass("Vec2".to_string())),
+        other => panic!("expected duplicate class, got {:?}", other),
+    }
+}
+
+#[test]
+fn imports_from_resolvers() {
+    let script = r#"from "vector.tny" import Vec2; class Test { function main() { var v = new Vec2(1, 2); return v.length_squared(); } }"#;
+
+    let memory = MemoryResolver::new().module("vector.tny", include_str!("scripts/modules/vector.tny"));
+    let options = CompilerOptions { resolver: Arc::new(memory), ..Default::default() };
+    assert_eq!(run_with_options(script, "Test.main", None, options).unwrap(), Value::Integer(5));
+
+    const EMBEDDED: EmbeddedResolver = EmbeddedResolver::new(&[("vector.tny", include_str!("scripts/modules/vector.tny"))]);
+    let options = CompilerOptions { resolver: Arc::new(EMBEDDED), ..Default::default() };
+    assert_eq!(run_with_options(script, "Test.main", None, options).unwrap(), Value::Integer(5));
+
+    let options = CompilerOptions { resolver: Arc::new(MemoryResolver::new()), ..Default::default() };
+    match run_with_options(script, "Test.main", None, options) {
+        Err(TinyscriptError::Compile(CompileError::ImportFailed(path, _))) => assert_eq!(path, "vector.tny"),
+        other => panic!("expected failed import, got {:?}", other),
+    }
+}
+
+// PROJECTS
+
+#[cfg(feature = "fs")]
+#[test]
+fn build_project() {
+    let bundle = tinyscript::build_project("tests/projects/greeter").unwrap();
+    assert_eq!(bundle.entry_points, vec!["Greeter.main", "Farewell.main"]);
+    assert_eq!(tinyscript::build_project("tests/projects/greeter/tinyscript.toml").unwrap(), bundle);
+
+    let mut vm = bundle.instantiate(VmConfig::new()).unwrap();
+    assert_eq!(vm.exec("Greeter.main", None).unwrap(), value!("hello ada"));
+    assert_eq!(vm.exec("Farewell.main", None).unwrap(), value!("bye"));
+
+    assert!(matches!(tinyscript::build_project("tests/projects/missing"), Err(CompileError::InvalidManifest(message)) if message.starts_with("unable to read")));
+}
+
+#[cfg(feature = "fs")]
+#[test]
+fn strip_project() {
+    let bundle = tinyscript::build_project("tests/projects/greeter").unwrap();
+    let stripped = bundle.stripped().unwrap();
+    assert_eq!(stripped.modules.len(), 1);
+    assert_eq!(stripped.entry_points, bundle.entry_points);
+    assert!(stripped.to_bytes().len() < bundle.to_bytes().len());
+
+    let mut vm = stripped.instantiate(VmConfig::new()).unwrap();
+    assert_eq!(vm.exec("Greeter.main", None).unwrap(), value!("hello ada"));
+    assert_eq!(vm.exec("Farewell.main", None).unwrap(), value!("bye"));
+}
+
+// STANDARD LIBRARY
+
+#[test]
+fn stdlib() {
+    assert_eq!(run(include_str!("scripts/stdlib.tny"), "Test.main", None).unwrap(), Value::Bool(true));
+}
+
+#[test]
+fn stdlib_compiled_once() {
+    use tinyscript::compile;
+
+    // each program links in the same compiled standard library
+    let first = compile("class A { function main() { return Arrays.sum([1, 2]); } }").unwrap();
+    let second = compile("class B { function main() { return Arrays.sum([3]); } }").unwrap();
+    let arrays = |program: &Program| program.globals[program.global_symbols["std.Arrays"]].clone();
+    assert!(arrays(&first).identical(&arrays(&second)));
+    assert_eq!(VM::new(second).exec("B.main", None), Ok(Value::Integer(3)));
+}
+
+#[test]
+fn stdlib_disabled() {
+    let script = "class Test { function main() { return Arrays.sum([1, 2]); } }";
+    let options = CompilerOptions { no_std: true, ..Default::default() };
+    assert!(run_with_options(script, "Test.main", None, options).is_err());
+}
+
+#[test]
+fn stdlib_shadowed() {
+    let script = "class Arrays { function sum(array) { return 0; } } class Test { function main() { return Arrays.sum([1, 2]); } }";
+    assert_eq!(run(script, "Test.main", None).unwrap(), Value::Integer(0));
+}
+
+#[test]
+fn stdlib_result_values() {
+    assert_eq!(run(include_str!("scripts/result_values.tny"), "Test.main", None).unwrap(), Value::Bool(true));
+    assert_eq!(run("class Test { function main() { var e = Err(1); return e.unwrap(); } }", "Test.main", None), Err(TinyscriptError::Runtime(RuntimeError::AssertionFailed)));
+}
+
+#[test]
+fn defer() {
+    assert_eq!(run(include_str!("scripts/defer.tny"), "Test.main", None).unwrap(), Value::Bool(true));
+
+    // an error runs the deferred statements of each function it leaves
+    let mut vm = load(include_str!("scripts/defer.tny"), CompilerOptions::default()).unwrap();
+    let counts = value!({"released": 0});
+    assert_eq!(vm.exec("Test.fail", Some(vec![counts.clone()])), Err(RuntimeError::AssertionFailed));
+    assert_eq!(counts.get_path("released"), Value::Integer(11));
+
+    let error = |source: &str| match run(source, "Test.main", None) {
+        Err(TinyscriptError::Compile(CompileError::Function(_, e))) => *e,
+        result => panic!("expected a compile error, got {:?}", result)
+    };
+    assert_eq!(error("class Test { function main() { if true { defer print 1; } } }"), CompileError::InvalidDefer(String::from("defer can only be used in the body of a function, not inside a block")));
+    assert_eq!(error("class Test { function* main() { defer print 1; yield 1; } }"), CompileError::InvalidDefer(String::from("a generator can not defer statements")));
+}
+
+// GENERATORS
+
+#[test]
+fn generators() {
+    assert_eq!(run(include_str!("scripts/generators.tny"), "Test.main", None).unwrap(), Value::Bool(true));
+}
+
+#[test]
+fn generator_from_host() {
+    let mut vm = load(include_str!("scripts/generators.tny"), CompilerOptions::default()).unwrap();
+    assert!(matches!(vm.exec("Test.counter", Some(vec![Value::Integer(3)])).unwrap(), Value::Generator(_)));
+}
+
+#[test]
+fn yield_outside_generator() {
+    match run("class Test { function main() { yield 1; } }", "Test.main", None) {
+        Err(TinyscriptError::Compile(CompileError::Function(_, e))) => assert_eq!(*e, CompileError::YieldOutsideGenerator),
+        other => panic!("expected yield error, got {:?}", other),
+    }
+}
+
+// EXPRESSIONS
+
+#[test]
+fn expressions() {
+    assert_eq!(run(include_str!("scripts/expressions.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+fn untrusted_expressions() {
+    let row = || [(String::from("price"), value!(4)), (String::from("qty"), value!(3)), (String::from("name"), value!("bolts"))].into_iter().collect();
+
+    assert_eq!(evaluate_expression("price * qty + 1", row()).unwrap(), Value::Integer(13));
+    assert_eq!(evaluate_expression("qty > 2 && name == \"bolts\"", row()).unwrap(), Value::Bool(true));
+    assert_eq!(evaluate_expression("format(\"{} {}\", qty, name)", row()).unwrap(), Value::from("3 bolts"));
+
+    // anything but a single expression of the bindings is refused
+    assert!(matches!(evaluate_expression("qty = 1", row()), Err(TinyscriptError::Compile(CompileError::Parse(_)))));
+    assert!(matches!(evaluate_expression("total * 2", row()), Err(TinyscriptError::Compile(_))));
+    assert_eq!(evaluate_expression("on(\"tick\", name)", row()), Err(TinyscriptError::Runtime(RuntimeError::BuiltinNotAllowed(String::from("on")))));
+    assert!(evaluate_expression("delete(name)", row()).is_err());
+
+    // strings are bounded by the memory of the sandbox, and formats by their own limits
+    let limit = RuntimeError::MemoryLimitExceeded(1024 * 1024);
+    assert_eq!(evaluate_expression("\"x\".repeat(2000000000)", row()), Err(TinyscriptError::Runtime(limit.clone())));
+    assert_eq!(evaluate_expression("name.pad_start(2000000000)", row()), Err(TinyscriptError::Runtime(limit.clone())));
+    assert_eq!(evaluate_expression("name.repeat(200000) + name.repeat(200000)", row()), Err(TinyscriptError::Runtime(limit)));
+    assert!(matches!(evaluate_expression("format(\"{:.70000}\", 1.5)", row()), Err(TinyscriptError::Runtime(RuntimeError::TypeError(_)))));
+    assert!(matches!(evaluate_expression("format_number(1.5, 70000, \",\", \".\")", row()), Err(TinyscriptError::Runtime(RuntimeError::TypeError(_)))));
+
+    let tiny = Sandbox { max_instructions: Some(3), ..Sandbox::expression() };
+    assert_eq!(evaluate_expression_with_sandbox("price + qty + price + qty", row(), tiny), Err(TinyscriptError::Runtime(RuntimeError::InstructionLimitExceeded(3))));
+}
+
+#[test]
+fn templates() {
+    let context = || value!({ "user": { "name": "ada", "visits": 3 }, "items": [1, 2] });
+
+    assert_eq!(render_template("Hello ${user.name}!", context()).unwrap(), "Hello ada!");
+    assert_eq!(render_template("${user.visits + 1} visits, ${items[1]} ${format(\"{}\", \"}\")}", context()).unwrap(), "4 visits, 2 }");
+    assert_eq!(render_template("no placeholders, \\${user.name}", context()).unwrap(), "no placeholders, ${user.name}");
+
+    assert_eq!(render_template("${user.name", context()), Err(TinyscriptError::Compile(CompileError::InvalidTemplate(1, String::from("placeholder is not closed")))));
+    assert!(matches!(render_template("${user.name} ${user.name = 1}", context()), Err(TinyscriptError::Compile(CompileError::Parse(_)))));
+    assert!(matches!(render_template("${password}", context()), Err(TinyscriptError::Compile(_))));
+    assert!(matches!(render_template("${name}", value!("ada")), Err(TinyscriptError::Runtime(RuntimeError::TypeError(_)))));
+}
+
+// STRICT MODE
+
+#[test]
+fn strict_mode() {
+    assert_eq!(run(include_str!("scripts/strict_mode.tny"), "Test.main", None).unwrap(), Value::Null);
+}
+
+#[test]
+fn strict_mode_implicit_this() {
+    let script = include_str!("scripts/strict_implicit_this.tny");
+    assert_eq!(run(script, "Test.main", None).unwrap(), Value::Null);
+    assert!(run_with_options(script, "Test.main", None, CompilerOptions { strict: true, ..Default::default() }).is_err());
+}
+
+#[test]
+fn strict_mode_undeclared_variable() {
+    let script = "#strict\nclass Test {\n function main() {\n total = 1;\n }\n}";
+    let result = run(script, "Test.main", None);
+    assert!(matches!(result, Err(TinyscriptError::Compile(CompileError::Function(_, e))) if matches!(*e, CompileError::Strict(_))));
+}
+
+#[test]
+fn strict_mode_string_add() {
+    let result = run(include_str!("scripts/strict_string_add.tny"), "Test.main", None);
+    assert!(matches!(result, Err(TinyscriptError::Runtime(RuntimeError::TypeError(_)))));
+}
+
+// CONDITIONAL COMPILATION
+
+#[test]
+fn conditional_compilation() {
+    let script = include_str!("scripts/conditional.tny");
+    let with = |defines: &[&str]| {
+        let options = CompilerOptions { defines: defines.iter().map(|d| d.to_string()).collect(), ..Default::default() };
+        run_with_options(script, "Test.main", None, options).unwrap()
+    };
+    assert_eq!(with(&[]), Value::from("release native"));
+    assert_eq!(with(&["DEBUG", "WASM"]), Value::from("debug wasm"));
+}
+
+#[test]
+fn conditional_compilation_unclosed() {
+    let result = run("class Test {\n#if DEBUG\n}", "Test.main", None);
+    assert!(matches!(result, Err(TinyscriptError::Compile(CompileError::InvalidDirective(3, _)))));
+}
+
+// DEFINES
+
+#[test]
+fn defines() {
+    assert_eq!(run(include_str!("scripts/defines.tny"), "Test.main", None).unwrap(), Value::Integer(50));
+}
+
+#[test]
+fn define_used_before_declaration() {
+    let script = "class Test {\n function main() {\n return LIMIT;\n }\n}\ndefine LIMIT = 3;";
+    let result = run(script, "Test.main", None);
+    assert!(matches!(result, Err(TinyscriptError::Compile(CompileError::Function(_, e))) if *e == CompileError::DefineUsedBeforeDeclaration(String::from("LIMIT"))));
+}
+
+// PARALLEL COMPILATION
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_compilation() {
+    use tinyscript::compile_with_options;
+
+    let mut script = String::from("define STEP = 2;\n");
+    for i in 0..300 {
+        script.push_str(&format!("class C{i} {{\n function next(n) {{ return n + STEP; }}\n function chain(n) {{ var c = new C{next}(); return c.next(n) + {i}; }}\n}}\n", next = (i + 1) % 300));
+    }
+    let compile = |threads: usize| compile_with_options(&script, CompilerOptions { threads, ..Default::default() }).unwrap();
+
+    let parallel = compile(4);
+    assert_eq!(parallel.to_bytes(), compile(1).to_bytes());
+    assert_eq!(VM::new(parallel).exec("C7.chain", Some(vec![Value::Integer(1)])).unwrap(), Value::Integer(10));
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_compilation_error() {
+    use tinyscript::compile_with_options;
+
+    let mut script = String::new();
+    for i in 0..300 {
+        let body = if i == 250 { "var x = 1; var x = 2;" } else { "return 1;" };
+        script.push_str(&format!("class C{i} {{ function f() {{ {body} }} }}\n"));
+    }
+    let result = compile_with_options(&script, CompilerOptions { threads: 4, ..Default::default() });
+    assert!(matches!(result, Err(CompileError::Function(name, _)) if name == "C250.f"));
+}
+
+// ARITHMETIC ERRORS
+
+#[test]
+fn integer_overflow_and_division_by_zero() {
+    assert_eq!(run("class Test { function main() { return 2147483647 + 1; } }", "Test.main", None), Err(TinyscriptError::Runtime(RuntimeError::IntegerOverflow)));
+    assert_eq!(run("class Test { function main() { var zero = 0; return 1 / zero; } }", "Test.main", None), Err(TinyscriptError::Runtime(RuntimeError::DivisionByZero)));
+    assert!(matches!(run("class Test { function main() { return 99999999999; } }", "Test.main", None), Err(TinyscriptError::Compile(CompileError::Parse(_)))));
+}
+
+#[test]
+fn mixed_comparisons() {
+    assert_eq!(run("class Test { function main() { return 1 < 1.5 && 2 == 2.0 && \"apple\" < \"banana\"; } }", "Test.main", None).unwrap(), Value::Bool(true));
+    assert_eq!(run("class Test { function main() { return 1.5 + \" apples\"; } }", "Test.main", None).unwrap(), Value::from(String::from("1.5 apples")));
+}
+
+#[test]
+fn checked_arithmetic() {
+    let inexact = "class Test { function main() { return 16777217 + 0.5; } }";
+    assert_eq!(run(inexact, "Test.main", None).unwrap(), Value::Float(16777216.0));
+    let checked = CompilerOptions { checked: true, ..Default::default() };
+    assert_eq!(run_with_options(inexact, "Test.main", None, checked), Err(TinyscriptError::Runtime(RuntimeError::InexactFloat(16777217))));
+
+    let nan = "class Test { function main() { var nan = 0.0 / 0; return nan == nan; } }";
+    assert_eq!(run(nan, "Test.main", None).unwrap(), Value::Bool(false));
+    assert_eq!(run(&format!("#checked\n{}", nan), "Test.main", None), Err(TinyscriptError::Runtime(RuntimeError::NotANumber)));
+    assert_eq!(run("#checked\nclass Test { function main() { return 1 / 4 + 2; } }", "Test.main", None).unwrap(), Value::Float(2.25));
+}
+
+#[test]
+fn duplicate_function() {
+    let result = run("class Test { function main() { } function main() { } }", "Test.main", None);
+    assert_eq!(result, Err(TinyscriptError::Compile(CompileError::DuplicateFunction(String::from("Test.main")))));
+}
+
+// EVENTS
+
+#[test]
+fn events() {
+    let mut vm = load(include_str!("scripts/events.tny"), CompilerOptions::default()).unwrap();
+    assert_eq!(vm.exec("Plugin.main", None).unwrap(), Value::Null);
+    assert_eq!(vm.dispatch_event("tick", Value::Integer(5)).unwrap(), vec![Value::Integer(6), Value::Integer(10)]);
+    assert_eq!(vm.dispatch_event("stop", Value::Null).unwrap(), vec![Value::from(String::from("stopped"))]);
+    assert_eq!(vm.dispatch_event("missing", Value::Null).unwrap(), vec![]);
+}
+
+// BYTECODE
+
+#[test]
+fn bytecode_compatibility() {
+    let bytes = include_bytes!("bytecode/counter-1.0.tsbc");
+    assert_eq!(Program::bytecode_version(bytes).unwrap().to_string(), "1.0");
+
+    let mut vm = VM::new(Program::load_any(bytes).unwrap());
+    assert_eq!(vm.exec("Test.main", None).unwrap(), value!([42, "done", { "half": 1.5 }]));
+}
+
+#[test]
+fn reproducible_bytecode() {
+    let shapes = "class Square { var side = 2; function area() { return this.side * this.side; } }\nclass Circle { var r = 1; }";
+    let source = "import \"shapes.tny\";\nclass Zebra { function main() { var s = new Square(); return s.area(); } }\nclass Apple { }";
+    let build = || {
+        let options = CompilerOptions { resolver: Arc::new(MemoryResolver::new().module("shapes.tny", shapes)), ..Default::default() };
+        tinyscript::compile_with_options(source, options).unwrap()
+    };
+
+    // each compile, and each thread, hashes with different keys
+    let first = build();
+    let bytes = first.to_bytes();
+    assert!((0..10).all(|_| build().to_bytes() == bytes));
+    assert_eq!(std::thread::spawn(move || build().to_bytes()).join().unwrap(), bytes);
+
+    // globals are laid out in the order classes are declared, the script before its imports
+    let index = |name: &str| first.global_symbols[name];
+    assert!(index("Zebra") < index("Apple") && index("Apple") < index("Square") && index("Square") < index("Circle"));
+}
+
+#[test]
+fn build_metadata() {
+    let shapes = "class Square { var side = 2; }";
+    let source = "import \"shapes.tny\";\nclass Test { function main() { return new Square().side; } }";
+    let options = || CompilerOptions { resolver: Arc::new(MemoryResolver::new().module("shapes.tny", shapes)), ..Default::default() };
+
+    let program = tinyscript::compile_with_options(source, options()).unwrap();
+    let build = program.metadata();
+    assert!(build.is_current_compiler());
+    assert_eq!(build.compiled_at, None);
+    assert_eq!(build.optimization_level, 1);
+    let mut paths: Vec<&String> = build.sources.keys().collect();
+    paths.sort();
+    assert_eq!(paths, vec!["main", "shapes.tny"]);
+    assert!(build.source_matches("main", source) && build.source_matches("shapes.tny", shapes));
+    assert!(!build.source_matches("shapes.tny", "class Square { var side = 3; }"));
+
+    // the time is only recorded when it is given, and survives being written out
+    let program = tinyscript::compile_with_options(source, CompilerOptions { compiled_at: Some(1_700_000_000), optimize: false, ..options() }).unwrap();
+    let loaded = Program::from_bytes(&program.to_bytes()).unwrap();
+    assert_eq!(loaded.metadata(), program.metadata());
+    assert_eq!(loaded.metadata().compiled_at, Some(1_700_000_000));
+    assert_eq!(loaded.metadata().optimization_level, 0);
+    assert_eq!(VM::new(loaded).exec("Test.main", None).unwrap(), Value::Integer(2));
+
+    // bytecode from before metadata was written has none
+    let old = Program::load_any(include_bytes!("bytecode/counter-1.0.tsbc")).unwrap();
+    assert!(old.metadata().compiler_version.is_empty() && old.metadata().sources.is_empty());
+}
+
+// SNAPSHOTS
+
+#[test]
+fn timers() {
+    let mut vm = load(include_str!("scripts/timers.tny"), CompilerOptions::default()).unwrap();
+    let blinker = vm.exec("Test.main", None).unwrap();
+    assert_eq!(vm.run_until_idle(50).unwrap(), Some(100));
+    assert_eq!(blinker.get_path("blinks"), Value::Integer(0));
+
+    // a snapshot keeps the timers still to come
+    assert_eq!(vm.run_until_idle(220).unwrap(), Some(250));
+    let mut restored = load(include_str!("scripts/timers.tny"), CompilerOptions::default()).unwrap();
+    restored.restore(VmImage::from_bytes(&vm.snapshot().unwrap().to_bytes()).unwrap()).unwrap();
+    assert_eq!(restored.snapshot(), vm.snapshot());
+
+    // stopping at 250 cancels the blinks and schedules a goodbye for 300
+    assert_eq!(vm.run_until_idle(1000).unwrap(), None);
+    assert_eq!(blinker.get_path("log"), value!(["blink", "blink", "stop", "goodbye"]));
+    assert_eq!(restored.run_until_idle(1000).unwrap(), None);
+
+    assert!(matches!(vm.exec("Test.bad_delay", None), Err(RuntimeError::TypeError(_))));
+}
+
+#[test]
+fn coroutines() {
+    let result = run(include_str!("scripts/coroutines.tny"), "Test.main", None).unwrap();
+    assert_eq!(result, value!([
+        ["a starts with 0", "b starts with 1", "a got 2", "b got 3", "a got 4"],
+        ["a 1", "b 1", "a 2", "b done", "a done"]
+    ]));
+
+    // a suspended coroutine is saved with the frames it yielded from
+    let mut vm = load(include_str!("scripts/coroutines.tny"), CompilerOptions::default()).unwrap();
+    let worker = vm.exec("Test.worker", None).unwrap();
+    let args = [worker.clone(), Value::Integer(0)];
+    assert_eq!(vm.exec("Test.step", Some(args.to_vec())).unwrap(), Value::from("e 1"));
+    vm.set_global_member("Test", "saved", worker).unwrap();
+    let mut restored = load(include_str!("scripts/coroutines.tny"), CompilerOptions::default()).unwrap();
+    restored.restore(VmImage::from_bytes(&vm.snapshot().unwrap().to_bytes()).unwrap()).unwrap();
+
+    let args = [restored.global("Test").unwrap().get_path("saved"), Value::Integer(1)];
+    assert_eq!(restored.exec("Test.step", Some(args.to_vec())).unwrap(), Value::from("e 2"));
+    assert_eq!(restored.exec("Test.step", Some(args.to_vec())).unwrap(), Value::from("e done"));
+    let Value::Deque(log) = args[0].get_path("log") else { panic!("expected a deque") };
+    assert_eq!(log.borrow().iter().map(Value::to_string).collect::<Vec<_>>(), ["e starts with 0", "e got 1", "e got 1"]);
+
+    assert!(matches!(vm.exec("Test.resume_dead", None), Err(RuntimeError::TypeError(_))));
+    assert!(matches!(vm.exec("Test.yield_outside", None), Err(RuntimeError::TypeError(_))));
+}
+
+#[test]
+fn actors() {
+    let mut vm = load(include_str!("scripts/actors.tny"), CompilerOptions::default()).unwrap();
+    let mut first = vm.spawn_actor("Counter", vec![Value::from("first"), Value::Integer(10)]).unwrap();
+    let mut second = vm.spawn_actor("Counter", vec![Value::from("second"), Value::Integer(0)]).unwrap();
+
+    // messages are copied, so changing one after sending it changes nothing
+    let message = value!({ "kind": "add", "amount": 5 });
+    first.send(&message).unwrap();
+    let Value::Dictionary(entries) = &message else { panic!("expected a dictionary") };
+    entries.borrow_mut().insert(String::from("amount"), Value::Integer(500));
+    first.send(&value!({ "kind": "peer", "handle": second.handle() })).unwrap();
+    first.send(&value!({ "kind": "forward" })).unwrap();
+    assert_eq!(first.pending(), 3);
+    assert_eq!(first.pump().unwrap(), vec![Value::from("first 15"), Value::from("first 15"), Value::from("first 15")]);
+
+    // actors and scripts of the vm that spawned them send through handles
+    let args = [second.handle(), Value::Integer(1)];
+    vm.exec("Test.notify", Some(args.to_vec())).unwrap();
+    assert_eq!(second.pump().unwrap(), vec![Value::from("second 15"), Value::from("second 16")]);
+    assert_eq!(second.instance().get_path("total"), Value::Integer(16));
+    assert!(second.pump().unwrap().is_empty());
+
+    assert!(matches!(vm.spawn_actor("Missing", vec![]), Err(RuntimeError::UnknownGlobal(_))));
+    let mut test = vm.spawn_actor("Test", vec![]).unwrap();
+    test.send(&Value::Null).unwrap();
+    assert!(matches!(test.pump(), Err(RuntimeError::UnknownMethod(_, _))));
+}
+
+#[test]
+fn snapshot_restore() {
+    let script = include_str!("scripts/events.tny");
+    let mut vm = load(script, CompilerOptions::default()).unwrap();
+    vm.exec("Plugin.main", None).unwrap();
+    let bytes = vm.snapshot().unwrap().to_bytes();
+
+    let mut restored = load(script, CompilerOptions::default()).unwrap();
+    assert_eq!(restored.dispatch_event("tick", Value::Integer(5)).unwrap(), vec![]);
+    restored.restore(VmImage::from_bytes(&bytes).unwrap()).unwrap();
+    assert_eq!(restored.snapshot(), vm.snapshot());
+    assert_eq!(restored.dispatch_event("tick", Value::Integer(5)).unwrap(), vec![Value::Integer(6), Value::Integer(10)]);
+
+    // the host holds the state of its objects, so a vm that can reach one can not be saved
+    vm.set_global_member("Plugin", "window", Value::HostObject(Rc::new(HostObject::new(7)))).unwrap();
+    assert_eq!(vm.snapshot(), Err(RuntimeError::TypeError(String::from("can not save a host object in an image"))));
+}
+
+#[test]
+fn restore_checks_image() {
+    let script = include_str!("scripts/generators.tny");
+    let mut vm = load(script, CompilerOptions::default()).unwrap();
+    let squares = vm.exec("Test.squares", Some(vec![Value::Integer(4)])).unwrap();
+    assert_eq!(vm.exec("Test.advance", Some(vec![squares.clone()])).unwrap(), Value::Integer(0));
+    assert_eq!(vm.exec("Test.advance", Some(vec![squares.clone()])).unwrap(), Value::Integer(1));
+    vm.set_global_member("Test", "saved", squares).unwrap();
+    let image = vm.snapshot().unwrap();
+
+    // a suspended generator carries on where it was saved
+    let mut restored = load(script, CompilerOptions::default()).unwrap();
+    restored.restore(image.clone()).unwrap();
+    let saved = restored.global("Test").unwrap().get_path("saved");
+    assert_eq!(restored.exec("Test.advance", Some(vec![saved])).unwrap(), Value::Integer(4));
+
+    // an image only goes back into the program it was taken from
+    let mut other = load(include_str!("scripts/events.tny"), CompilerOptions::default()).unwrap();
+    assert_eq!(other.restore(image.clone()), Err(RuntimeError::InvalidImage(String::from("image was taken from another program"))));
+
+    // frames that would leave the vm without an instruction, a slot or a value are refused
+    let corrupt = |change: fn(&mut FrameImage, &mut usize)| {
+        let mut image = image.clone();
+        let Some(HeapValue::Generator(Some(frame), ip, _)) = image.heap.iter_mut().find(|value| matches!(value, HeapValue::Generator(Some(_), _, _))) else { panic!("expected a suspended generator") };
+        change(frame, ip);
+        load(script, CompilerOptions::default()).unwrap().restore(image)
+    };
+    for change in [
+        (|_, ip| *ip = usize::MAX) as fn(&mut FrameImage, &mut usize),
+        |frame, _| frame.return_position = Some(usize::MAX),
+        |frame, _| frame.variables.clear(),
+        |frame, _| frame.data.push(ImageValue::Null),
+        |frame, _| frame.name = String::from("Test.missing[1]"),
+    ] {
+        assert!(matches!(corrupt(change), Err(RuntimeError::InvalidImage(_))));
+    }
+}
+
+// GLOBALS
+
+#[test]
+fn host_globals() {
+    let script = r#"
+        class Settings { var difficulty = 1; }
+        class Game { function damage(base) { return base * Settings.difficulty; } }
+    "#;
+    let mut vm = load(script, CompilerOptions { no_std: true, ..Default::default() }).unwrap();
+    assert_eq!(vm.globals().iter().map(|(name, _)| *name).collect::<Vec<&str>>(), ["Game", "Settings"]);
+
+    vm.set_global_member("Settings", "difficulty", Value::Integer(3)).unwrap();
+    assert_eq!(vm.exec("Game.damage", Some(vec![Value::Integer(2)])).unwrap(), Value::Integer(6));
+
+    vm.set_global("Settings", value!({ "difficulty": 5 })).unwrap();
+    assert_eq!(vm.exec("Game.damage", Some(vec![Value::Integer(2)])).unwrap(), Value::Integer(10));
+
+    // a borrow the host still holds is an error rather than a panic
+    let Some(Value::Dictionary(settings)) = vm.global("Settings").cloned() else { panic!("settings should be a dictionary") };
+    let held = settings.borrow();
+    assert_eq!(vm.set_global_member("Settings", "difficulty", Value::Integer(1)), Err(RuntimeError::GlobalBorrowed(String::from("Settings"))));
+    drop(held);
+    assert_eq!(vm.set_global("Missing", Value::Null), Err(RuntimeError::UnknownGlobal(String::from("Missing"))));
+    vm.set_global("Settings", Value::Integer(1)).unwrap();
+    assert!(matches!(vm.set_global_member("Settings", "difficulty", Value::Null), Err(RuntimeError::TypeError(_))));
+}
+
+// SEQUENCES
+
+fn rows(count: i32, asked: Rc<Cell<i32>>) -> Value {
+    Value::sequence((1..=count).map(move |i| {
+        asked.set(asked.get() + 1);
+        value!({ "id": i, "amount": i * 10 })
+    }))
+}
+
+#[test]
+fn host_sequence() {
+    let mut vm = load(include_str!("scripts/sequence.tny"), CompilerOptions::default()).unwrap();
+    let asked = Rc::new(Cell::new(0));
+    assert_eq!(vm.exec("Rows.total", Some(vec![rows(4, asked.clone())])).unwrap(), Value::Integer(100));
+    assert_eq!(asked.get(), 4);
+
+    // a loop that returns early leaves the rest of the rows unread
+    let asked = Rc::new(Cell::new(0));
+    assert_eq!(vm.exec("Rows.first_over", Some(vec![rows(1000, asked.clone()), Value::Integer(25)])).unwrap(), Value::Integer(3));
+    assert_eq!(asked.get(), 3);
+
+    let numbers = Value::sequence([1, 2].into_iter().map(Value::Integer));
+    assert_eq!(vm.exec("Rows.step", Some(vec![numbers])).unwrap(), Value::Integer(3));
+}
+
+#[test]
+fn host_chunks() {
+    let mut vm = load(include_str!("scripts/sequence.tny"), CompilerOptions::default()).unwrap();
+
+    // pages of 100 rows, fetched only when the script reaches them
+    let fetched = Rc::new(Cell::new(0));
+    let pages = |fetched: Rc<Cell<usize>>| Value::chunked(move |page| {
+        fetched.set(fetched.get() + 1);
+        (page < 10).then(|| (page * 100..(page + 1) * 100).map(|i| value!({ "id": i as i32, "amount": 1 })).collect())
+    });
+    assert_eq!(vm.exec("Rows.total", Some(vec![pages(fetched.clone())])).unwrap(), Value::Integer(1000));
+    assert_eq!(fetched.get(), 11);
+
+    let fetched = Rc::new(Cell::new(0));
+    assert_eq!(vm.exec("Rows.first_over", Some(vec![pages(fetched.clone()), Value::Integer(0)])).unwrap(), Value::Integer(0));
+    assert_eq!(fetched.get(), 1);
+}
+
+#[test]
+fn host_sequence_without_end() {
+    let sandbox = Sandbox { max_instructions: Some(1000), ..Sandbox::strict() };
+    let mut vm = load_with_sandbox(include_str!("scripts/sequence.tny"), CompilerOptions::default(), sandbox).unwrap();
+    let endless = Value::sequence(std::iter::repeat_with(|| value!({ "id": 0, "amount": 1 })));
+    assert_eq!(vm.exec("Rows.total", Some(vec![endless])), Err(RuntimeError::InstructionLimitExceeded(1000)));
+}
+
+// HOST OBJECTS
+
+struct File {
+    id: i32,
+}
+
+#[test]
+fn host_objects() {
+    let mut vm = load(include_str!("scripts/host_objects.tny"), CompilerOptions::default()).unwrap();
+    let closed = Rc::new(Cell::new(0));
+    let counter = closed.clone();
+    let file = Value::HostObject(Rc::new(HostObject::with_finalizer(File { id: 4 }, move |file| counter.set(file.id))));
+    let connection = Value::host(String::from("db"));
+
+    let picked = vm.exec("Handles.pick", Some(vec![file.clone(), connection.clone(), Value::Bool(true)])).unwrap();
+    assert_eq!(picked, file);
+    assert_eq!(picked.downcast_host::<File>().map(|file| file.id), Ok(4));
+    assert!(matches!(connection.downcast_host::<File>(), Err(RuntimeError::TypeError(_))));
+    assert_eq!(vm.exec("Handles.same", Some(vec![file.clone(), connection])).unwrap(), Value::Bool(false));
+
+    // the finalizer runs once neither the host nor the script holds the handle
+    drop(file);
+    assert_eq!(closed.get(), 0);
+    drop(picked);
+    assert_eq!(closed.get(), 4);
+}
+
+struct Sprite {
+    x: i32,
+    y: i32,
+}
+
+#[test]
+fn host_methods() {
+    let mut vm = load(include_str!("scripts/host_objects.tny"), CompilerOptions::default()).unwrap();
+    vm.register_type::<Sprite>()
+        .method("x", |sprite, _| Ok(Value::Integer(sprite.x)))
+        .method("y", |sprite, _| Ok(Value::Integer(sprite.y)))
+        .method_mut("move", |sprite, args| match args {
+            [Value::Integer(dx), Value::Integer(dy)] => {
+                sprite.x += dx;
+                sprite.y += dy;
+                Ok(Value::Null)
+            }
+            _ => Err(RuntimeError::TypeError(String::from("move expects two integers")))
+        });
+
+    let sprite = Value::host(Sprite { x: 10, y: 20 });
+    assert_eq!(vm.exec("Game.step", Some(vec![sprite.clone()])).unwrap(), Value::Integer(39));
+    assert_eq!(sprite.downcast_host::<Sprite>().map(|sprite| (sprite.x, sprite.y)), Ok((13, 26)));
+
+    let file = Value::host(File { id: 1 });
+    assert_eq!(vm.exec("Game.poke", Some(vec![file])), Err(RuntimeError::UnknownMethod(String::from("host object test::File"), String::from("move"))));
+}
+
+#[test]
+fn host_properties() {
+    let mut vm = load(include_str!("scripts/host_objects.tny"), CompilerOptions::default()).unwrap();
+    vm.register_type::<Sprite>()
+        .getter("x", |sprite| Ok(Value::Integer(sprite.x)))
+        .getter("y", |sprite| Ok(Value::Integer(sprite.y)))
+        .setter("x", |sprite, value| match value {
+            Value::Integer(x) => {
+                sprite.x = x;
+                Ok(())
+            }
+            value => Err(RuntimeError::TypeError(format!("x can not be {}", value.type_name())))
+        });
+
+    let sprite = Value::host(Sprite { x: 10, y: 20 });
+    assert_eq!(vm.exec("Game.slide", Some(vec![sprite.clone()])).unwrap(), Value::Integer(35));
+    assert_eq!(sprite.downcast_host::<Sprite>().map(|sprite| sprite.x), Ok(15));
+
+    // a property without a setter can not be written
+    assert_eq!(vm.exec("Game.hide", Some(vec![sprite])), Err(RuntimeError::KeyNotFound(String::from("visible"))));
+}
+
+// SANDBOX
+
+fn run_sandboxed(main: &str, sandbox: Sandbox) -> Result<Value, TinyscriptError> {
+    let mut vm = load_with_sandbox(include_str!("scripts/sandbox.tny"), CompilerOptions::default(), sandbox).unwrap();
+    Ok(vm.exec(main, None)?)
+}
+
+#[test]
+fn sandbox_strict() {
+    assert_eq!(run_sandboxed("Sandboxed.deep", Sandbox::strict()), Err(TinyscriptError::Runtime(RuntimeError::CallDepthExceeded(64))));
+    assert_eq!(run_sandboxed("Sandboxed.subscribe", Sandbox::strict()), Err(TinyscriptError::Runtime(RuntimeError::BuiltinNotAllowed(String::from("on")))));
+    assert_eq!(run_sandboxed("Sandboxed.build", Sandbox::strict()).unwrap(), Value::from(String::from("ok")));
+}
+
+#[test]
+fn sandbox_instructions() {
+    let sandbox = Sandbox { max_instructions: Some(1000), ..Sandbox::strict() };
+    assert_eq!(run_sandboxed("Sandboxed.spin", sandbox), Err(TinyscriptError::Runtime(RuntimeError::InstructionLimitExceeded(1000))));
+}
+
+#[test]
+fn sandbox_memory() {
+    let sandbox = Sandbox { max_memory: Some(4 * 1024), ..Sandbox::trusted() };
+    assert_eq!(run_sandboxed("Sandboxed.grow", sandbox), Err(TinyscriptError::Runtime(RuntimeError::MemoryLimitExceeded(4 * 1024))));
+
+    // strings count too, and are checked before a long one is made
+    let limit = 16 * 1024 * 1024;
+    assert_eq!(run_sandboxed("Sandboxed.double", Sandbox::strict()), Err(TinyscriptError::Runtime(RuntimeError::MemoryLimitExceeded(limit))));
+    assert_eq!(run_sandboxed("Sandboxed.repeat", Sandbox::strict()), Err(TinyscriptError::Runtime(RuntimeError::MemoryLimitExceeded(limit))));
+
+    // so does text appended to a builder
+    let sandbox = Sandbox { max_memory: Some(64 * 1024), ..Sandbox::strict() };
+    let error = run_sandboxed("Sandboxed.builder", sandbox).unwrap_err();
+    assert_eq!(error, TinyscriptError::Runtime(RuntimeError::MemoryLimitExceeded(64 * 1024)));
+    assert!(error.to_string().contains("memory limit"));
+}
+
+#[test]
+fn sandbox_trusted() {
+    assert!(run_sandboxed("Sandboxed.subscribe", Sandbox::trusted()).is_ok());
+}
+
+// ERRORS
+
+#[test]
+fn error_parse() {
+    let result = run("class Test {\n function main() {\n var x = ;\n }\n}", "Test.main", None);
+    assert!(matches!(result, Err(TinyscriptError::Compile(CompileError::Parse(_)))));
+}
+
+#[test]
+fn error_unknown_class() {
+    let result = run("class Test {\n function main() {\n var x = new Missing();\n }\n}", "Test.main", None);
+    let error = result.unwrap_err();
+    assert_eq!(error.to_string(), "compile error: in Test.main: class 'Missing' does not exist");
+}
+
+#[test]
+fn error_assertion_failed() {
+    let result = run("class Test {\n function main() {\n assert 1 == 2;\n }\n}", "Test.main", None);
+    assert_eq!(result, Err(TinyscriptError::Runtime(RuntimeError::AssertionFailed)));
+}
+
+#[test]
+fn error_key_not_found() {
+    let result = run("class Test {\n function main() {\n var d = {\"a\": 1};\n var x = d[\"b\"];\n }\n}", "Test.main", None);
+    assert_eq!(result, Err(TinyscriptError::Runtime(RuntimeError::KeyNotFound(String::from("b")))));
+}
@@ -0,0 +1,53 @@
+use js_sys::Function;
+use wasm_bindgen::prelude::*;
+
+use tinyscript::vm::VM;
+use tinyscript::{compile_with_options, load_with_config, CompilerOptions, Output, TinyscriptError, Value, VmConfig};
+
+// check that a script compiles, throwing the compile error if it does not
+#[wasm_bindgen]
+pub fn compile(source: &str) -> Result<(), JsError> {
+    compile_with_options(source, CompilerOptions::default()).map(|_| ()).map_err(|e| error(e.into()))
+}
+
+// a compiled script whose print statements are passed to a javascript function
+#[wasm_bindgen]
+pub struct Script {
+    vm: VM,
+}
+
+#[wasm_bindgen]
+impl Script {
+
+    #[wasm_bindgen(constructor)]
+    pub fn new(source: &str, print: Function) -> Result<Script, JsError> {
+        let output = Output::callback(move |line| {
+            let _ = print.call1(&JsValue::NULL, &JsValue::from_str(line));
+        });
+        let vm = load_with_config(source, CompilerOptions::default(), VmConfig::new().output(output)).map_err(error)?;
+        Ok(Script { vm })
+    }
+
+    // call the entry function, such as "Game.main", and return what it returns
+    pub fn run(&mut self, main: &str) -> Result<JsValue, JsError> {
+        let value = self.vm.exec(main, None).map_err(|e| error(e.into()))?;
+        Ok(to_js(&value))
+    }
+
+}
+
+fn error(e: TinyscriptError) -> JsError {
+    JsError::new(&e.to_string())
+}
+
+// values without a javascript equivalent are given as the text a script would print
+fn to_js(value: &Value) -> JsValue {
+    match value {
+        Value::Null => JsValue::NULL,
+        Value::Bool(b) => JsValue::from_bool(*b),
+        Value::Integer(i) => JsValue::from_f64(*i as f64),
+        Value::Float(f) => JsValue::from_f64(*f as f64),
+        Value::String(s) => JsValue::from_str(s),
+        value => JsValue::from_str(&value.to_string())
+    }
+}